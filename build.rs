@@ -0,0 +1,15 @@
+//! Records the git commit ewok is built from, for run manifests.
+
+use std::process::Command;
+
+fn main() {
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output();
+    if let Ok(output) = output {
+        if output.status.success() {
+            let hash = String::from_utf8_lossy(&output.stdout);
+            println!("cargo:rustc-env=EWOK_GIT_HASH={}", hash.trim());
+        }
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+}
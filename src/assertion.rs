@@ -0,0 +1,301 @@
+//! Assertions about the state of a run, which turn scenario files into executable acceptance
+//! tests of the protocol's behaviour:
+//!
+//! ```text
+//! assert at 500: sections == {00,01,1}
+//! assert eventually: node 3f2a0c member of section 01
+//! ```
+//!
+//! An assertion `at` a step is checked at the end of that step, and fails if the run ends before
+//! it. One which holds `eventually` passes as soon as it holds at the end of any step, and fails
+//! if it never does.
+//!
+//! Sections are those of the global state, as in `state_trace`: each node counts towards the
+//! section it considers itself in, so `sections ==` only holds once no node is left behind in a
+//! section which has since split or merged. A node is written as the leading hex digits of its
+//! name, as in the logs; names are picked by the seeded RNG, so they're the same on every run of
+//! a scenario. It's a member of a section if it's among the members of the latest block for the
+//! section.
+
+use blocks::Blocks;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use scenario::{format_prefix, parse_prefix};
+use state_trace::{Sections, global_sections};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// When an assertion is checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// At the end of the step.
+    At(u64),
+    /// At the end of every step, until it holds.
+    Eventually,
+}
+
+/// A condition on the global state of a run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// The sections are exactly those with the prefixes.
+    Sections(BTreeSet<Prefix>),
+    /// A node whose name starts with the hex digits is a member of the section with the prefix.
+    MemberOf(String, Prefix),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assertion {
+    pub timing: Timing,
+    pub condition: Condition,
+}
+
+impl Assertion {
+    /// Parse an assertion written as in a scenario line without its leading `assert`, e.g.
+    /// `at 500: sections == {0,1}`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let colon = text.find(':').ok_or_else(
+            || format!("assertion {:?} has no ':'", text),
+        )?;
+        let (timing, condition) = (&text[..colon], &text[colon + 1..]);
+        let timing = match *timing.split_whitespace().collect::<Vec<_>>() {
+            ["at", step] => {
+                Timing::At(step.parse().map_err(|_| format!("invalid step {}", step))?)
+            }
+            ["eventually"] => Timing::Eventually,
+            _ => return Err(format!("unknown assertion timing {:?}", timing.trim())),
+        };
+        let words: Vec<&str> = condition.split_whitespace().collect();
+        let condition = match *words {
+            ["sections", "==", ..] => {
+                let set: String = words[2..].concat();
+                if !set.starts_with('{') || !set.ends_with('}') {
+                    return Err(format!("invalid set of prefixes {}", set));
+                }
+                let mut prefixes = BTreeSet::new();
+                for word in set[1..set.len() - 1].split(',').filter(|word| !word.is_empty()) {
+                    let _ = prefixes.insert(parse_prefix(word)?);
+                }
+                Condition::Sections(prefixes)
+            }
+            ["node", name, "member", "of", "section", prefix] => {
+                if name.len() > 16 || !name.chars().all(|digit| digit.is_ascii_hexdigit()) {
+                    return Err(format!("invalid node name {}", name));
+                }
+                Condition::MemberOf(name.to_ascii_lowercase(), parse_prefix(prefix)?)
+            }
+            _ => return Err(format!("unknown assertion condition {:?}", condition.trim())),
+        };
+        Ok(Assertion { timing, condition })
+    }
+
+    /// Check the condition against `sections`, describing how it was violated if it was.
+    fn check(&self, sections: &Sections) -> Result<(), String> {
+        match self.condition {
+            Condition::Sections(ref expected) => {
+                // Inserted one at a time, as collecting them sorts them by `Prefix`'s partial
+                // order, which disagrees with its total order for sections at different depths.
+                let mut actual = BTreeSet::new();
+                for prefix in sections.keys() {
+                    let _ = actual.insert(*prefix);
+                }
+                if actual == *expected {
+                    Ok(())
+                } else {
+                    Err(format!("sections were {}", format_prefixes(&actual)))
+                }
+            }
+            Condition::MemberOf(ref name, prefix) => {
+                let members = match sections.get(&prefix) {
+                    Some(members) => members,
+                    None => return Err(format!("no section {}", format_prefix(&prefix))),
+                };
+                if members.iter().any(|member| format!("{:016x}", member.0).starts_with(name)) {
+                    Ok(())
+                } else {
+                    Err(format!("members were {:?}", members))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.timing {
+            Timing::At(step) => write!(f, "at {}: ", step)?,
+            Timing::Eventually => write!(f, "eventually: ")?,
+        }
+        match self.condition {
+            Condition::Sections(ref prefixes) => write!(f, "sections == {}", format_prefixes(prefixes)),
+            Condition::MemberOf(ref name, prefix) => {
+                write!(f, "node {} member of section {}", name, format_prefix(&prefix))
+            }
+        }
+    }
+}
+
+fn format_prefixes(prefixes: &BTreeSet<Prefix>) -> String {
+    let prefixes: BTreeSet<_> = prefixes.iter().map(format_prefix).collect();
+    let prefixes: Vec<_> = prefixes.into_iter().collect();
+    format!("{{{}}}", prefixes.join(","))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Outcome {
+    Pending,
+    Passed,
+    /// Didn't hold when checked, as described.
+    Failed(String),
+}
+
+/// Checks a run against a list of assertions.
+#[derive(Clone, Debug)]
+pub struct AssertionChecker {
+    assertions: Vec<(Assertion, Outcome)>,
+}
+
+impl AssertionChecker {
+    pub fn new(assertions: Vec<Assertion>) -> Self {
+        AssertionChecker {
+            assertions: assertions
+                .into_iter()
+                .map(|assertion| (assertion, Outcome::Pending))
+                .collect(),
+        }
+    }
+
+    /// Check the assertions due at the end of `step`.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        let due = |(assertion, outcome): &(Assertion, Outcome)| {
+            *outcome == Outcome::Pending &&
+                match assertion.timing {
+                    Timing::At(at) => at == step,
+                    Timing::Eventually => true,
+                }
+        };
+        if !self.assertions.iter().any(due) {
+            return;
+        }
+        let sections = global_sections(blocks, nodes);
+        for entry in &mut self.assertions {
+            if !due(entry) {
+                continue;
+            }
+            let (ref assertion, ref mut outcome) = *entry;
+            match (assertion.check(&sections), assertion.timing) {
+                (Ok(()), _) => *outcome = Outcome::Passed,
+                (Err(description), Timing::At(_)) => *outcome = Outcome::Failed(description),
+                (Err(_), Timing::Eventually) => (),
+            }
+        }
+    }
+
+    /// Descriptions of the assertions which failed, or which were never checked or satisfied
+    /// before the run ended, in the order they were given.
+    pub fn failures(&self) -> Vec<String> {
+        self.assertions
+            .iter()
+            .filter_map(|(assertion, outcome)| match *outcome {
+                Outcome::Passed => None,
+                Outcome::Failed(ref description) => {
+                    Some(format!("{}: {}", assertion, description))
+                }
+                Outcome::Pending => {
+                    match assertion.timing {
+                        Timing::At(_) => Some(format!("{}: run ended before the step", assertion)),
+                        Timing::Eventually => Some(format!("{}: never held", assertion)),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn parse_round_trip() {
+        for text in &["at 500: sections == {00,01,1}", "eventually: node 3f2a member of section 01"] {
+            assert_eq!(Assertion::parse(text).unwrap().to_string(), *text);
+        }
+        let spaced = Assertion::parse(" at 7 : sections == { 0, 1 } ").unwrap();
+        assert_eq!(spaced.to_string(), "at 7: sections == {0,1}");
+
+        assert!(Assertion::parse("at 7 sections == {0}").is_err());
+        assert!(Assertion::parse("soon: sections == {0}").is_err());
+        assert!(Assertion::parse("eventually: sections == 0").is_err());
+        assert!(Assertion::parse("eventually: node xyz member of section 0").is_err());
+    }
+
+    #[test]
+    fn checked_when_due() {
+        let mut blocks = Blocks::new();
+        let members = btreeset!{Name(0x3f2a << 48), Name(0x8000 << 48)};
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: members.clone(),
+        });
+        let nodes: BTreeMap<Name, Node> = members
+            .iter()
+            .map(|&name| {
+                let node = Node::new(name, &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+                (name, node)
+            })
+            .collect();
+        let parse = |text| Assertion::parse(text).unwrap();
+
+        let mut checker = AssertionChecker::new(vec![
+            parse("at 2: sections == {-}"),
+            parse("at 2: sections == {0,1}"),
+            parse("at 9: sections == {-}"),
+            parse("eventually: node 3f2a member of section -"),
+            parse("eventually: node 3f2b member of section -"),
+        ]);
+        for step in 0..5 {
+            checker.observe(step, &blocks, &nodes);
+        }
+        assert_eq!(
+            checker.failures(),
+            vec![
+                "at 2: sections == {0,1}: sections were {-}",
+                "at 9: sections == {-}: run ended before the step",
+                "eventually: node 3f2b member of section -: never held",
+            ]
+        );
+    }
+
+    #[test]
+    fn sections_at_different_depths() {
+        let mut blocks = Blocks::new();
+        let mut nodes = BTreeMap::new();
+        for &(bits, name) in &[(2, 0x00), (2, 0x40), (1, 0x80)] {
+            let name = Name(name << 56);
+            let id = blocks.insert(Block {
+                prefix: Prefix::new(bits, name),
+                version: 1,
+                members: btreeset!{name},
+            });
+            let node = Node::new(name, &blocks, btreeset!{id}, NodeParams::default(), 0);
+            let _ = nodes.insert(name, node);
+        }
+
+        let mut checker = AssertionChecker::new(vec![
+            Assertion::parse("at 5: sections == {00,01,1}").unwrap(),
+            Assertion::parse("at 5: sections == {0,1}").unwrap(),
+        ]);
+        for step in 0..6 {
+            checker.observe(step, &blocks, &nodes);
+        }
+        assert_eq!(
+            checker.failures(),
+            vec!["at 5: sections == {0,1}: sections were {00,01,1}"]
+        );
+    }
+}
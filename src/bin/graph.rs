@@ -3,12 +3,27 @@
 //! graph ewok_log_file -o output_file
 //! dot -Tsvg -O output_file
 //!
+//! Logs ending in `.gz` are decompressed with `gzip` as they're read, and `-` reads the log from
+//! standard input, so it can be piped straight from a running simulation:
+//!
+//! RUST_LOG=debug ewok | graph - -o output_file
+//!
+//! Given several logs, e.g. of the same scenario run with different parameters, the block graphs
+//! of all the runs are overlaid in one graph:
+//!
+//! graph before.log after.log -o output_file
+//!
+//! Blocks and votes found in every run are drawn in black. Votes found in only some runs are drawn
+//! once in the colour of each of those runs, as listed in the graph's legend, and blocks in the
+//! colour of the run they're found in, or in grey if they're found in several.
+//!
 //! (The resulting images are large, so the SVG format is recommended for
 //! quality-conserving zooming.)
 //! The 'dot' utility can be found in the 'graphviz' package.
 
 #![cfg_attr(feature="cargo-clippy", allow(doc_markdown))]
 
+extern crate ewok;
 extern crate regex;
 extern crate clap;
 #[macro_use]
@@ -16,56 +31,128 @@ extern crate lazy_static;
 
 mod utils;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use ewok::Error;
 use std::collections::{BTreeSet, BTreeMap};
 use std::fs::File;
 use std::io::{Write, BufWriter};
+use std::process;
+use utils::chain::{Block, Vote};
 use utils::log_parse::{LogData, LogIterator};
 
+/// Colours distinguishing overlaid runs, in the order their logs are given.
+const RUN_COLOURS: &[&str] = &[
+    "#1F77B4",
+    "#D62728",
+    "#2CA02C",
+    "#FF7F0E",
+    "#9467BD",
+    "#8C564B",
+    "#E377C2",
+    "#17BECF",
+];
+
+fn run_colour(run: usize) -> &'static str {
+    RUN_COLOURS[run % RUN_COLOURS.len()]
+}
+
 fn main() {
     let matches = App::new("ewok_graph")
         .about("This tool takes a log output from an Ewok simulation and generates a file \
                describing a graph of blocks in the DOT language. The resulting file can then be \
-               converted into a graphics file using the 'dot' utility from the 'graphviz' toolset.")
+               converted into a graphics file using the 'dot' utility from the 'graphviz' toolset. \
+               Given several logs, it overlays their graphs, colouring what differs by run.")
         .arg(Arg::with_name("output")
                  .short("o")
                  .long("output")
                  .value_name("FILE")
                  .help("The name for the output file."))
         .arg(Arg::with_name("INPUT")
-                 .help("Sets the input file to use")
+                 .help("Sets the input files to use: logs, gzipped logs ending in .gz, or - \
+                        for standard input")
                  .required(true)
+                 .multiple(true)
                  .index(1))
         .get_matches();
-    let input = matches.value_of("INPUT").unwrap();
+
+    if let Err(err) = run(&matches) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<(), Error> {
+    // INPUT is a required argument, so clap guarantees it's present.
+    let inputs: Vec<&str> = matches.values_of("INPUT").unwrap().collect();
     let output = matches.value_of("output").unwrap_or("output.dot");
-    let mut blocks = BTreeMap::new();
-    let mut votes = BTreeSet::new();
+    // The blocks and votes agreed in any run, with the runs they were agreed in.
+    let mut blocks: BTreeMap<String, (Block, BTreeSet<usize>)> = BTreeMap::new();
+    let mut votes: BTreeMap<Vote, BTreeSet<usize>> = BTreeMap::new();
 
-    let file = File::open(input).unwrap();
-    let log_iter = LogIterator::new(file);
+    for (run, input) in inputs.iter().enumerate() {
+        let log_iter = LogIterator::open(input)?;
 
-    println!("Reading log...");
-    for data in log_iter {
-        if let LogData::VoteAgreement(vote, block_from, block_to) = data {
-            blocks.insert(block_from.get_id(), block_from);
-            blocks.insert(block_to.get_id(), block_to);
-            votes.insert(vote);
+        println!("Reading log {}...", input);
+        for data in log_iter {
+            if let LogData::VoteAgreement(vote, block_from, block_to) = data? {
+                for block in vec![block_from, block_to] {
+                    let entry = blocks.entry(block.get_id()).or_insert_with(
+                        || (block, BTreeSet::new()),
+                    );
+                    entry.1.insert(run);
+                }
+                votes.entry(vote).or_insert_with(BTreeSet::new).insert(run);
+            }
         }
     }
 
     println!("Reading finished. Outputting the dot file...");
-    let file = File::create(output).unwrap();
+    let file = File::create(output)?;
     let mut writer = BufWriter::new(file);
-    let _ = write!(writer, "digraph {{\n");
-    for (b, block) in blocks {
-        let _ = write!(writer,
-                       "{} [label = {}; shape=box];\n",
-                       b,
-                       block.get_label());
+    // Whatever all the runs agreed on is left uncoloured, so only the differences stand out.
+    let in_all_runs = |runs: &BTreeSet<usize>| runs.len() == inputs.len();
+    writeln!(writer, "digraph {{")?;
+    for (b, (block, runs)) in blocks {
+        if in_all_runs(&runs) {
+            writeln!(writer, "{} [label = {}; shape=box];", b, block.get_label())?;
+        } else {
+            let colour = if runs.len() == 1 {
+                run_colour(*runs.iter().next().unwrap())
+            } else {
+                "#888888"
+            };
+            writeln!(writer,
+                     "{} [label = {}; shape=box; color=\"{}\"; penwidth=3];",
+                     b,
+                     block.get_label(),
+                     colour)?;
+        }
+    }
+    for (vote, runs) in votes {
+        if in_all_runs(&runs) {
+            writeln!(writer, "{}->{}", vote.from, vote.to)?;
+        } else {
+            for run in runs {
+                writeln!(writer,
+                         "{}->{} [color=\"{}\"]",
+                         vote.from,
+                         vote.to,
+                         run_colour(run))?;
+            }
+        }
     }
-    for vote in votes {
-        let _ = write!(writer, "{}->{}\n", vote.from, vote.to);
+    if inputs.len() > 1 {
+        writeln!(writer, "subgraph cluster_legend {{")?;
+        writeln!(writer, "label = \"Runs\";")?;
+        for (run, input) in inputs.iter().enumerate() {
+            writeln!(writer,
+                     "run{} [label = \"{}\"; shape=plaintext; fontcolor=\"{}\"];",
+                     run,
+                     input,
+                     run_colour(run))?;
+        }
+        writeln!(writer, "}}")?;
     }
-    let _ = write!(writer, "}}\n");
+    writeln!(writer, "}}")?;
+    Ok(())
 }
@@ -1,5 +1,6 @@
 #![cfg_attr(feature="cargo-clippy", allow(doc_markdown))]
 
+extern crate ewok;
 extern crate regex;
 extern crate clap;
 #[macro_use]
@@ -8,17 +9,20 @@ extern crate lazy_static;
 mod utils;
 
 use clap::{App, Arg};
+use ewok::Error;
 use std::collections::{BTreeSet, BTreeMap};
 use std::fs::File;
 use std::io::{Write, BufWriter};
 use std::mem;
 use utils::log_parse::{LogData, LogIterator};
-use std::process::Command;
+use std::process::{self, Command};
 
 struct StepData {
     pub msgs_sent: BTreeMap<String, u64>,
     pub msgs_queue: u64,
     pub network_size: u64,
+    pub min_health: Option<f64>,
+    pub section_health: BTreeMap<String, f64>,
 }
 
 fn gnuplot_command(input: &str,
@@ -27,7 +31,9 @@ fn gnuplot_command(input: &str,
                    queue_size: bool,
                    total_sent: bool,
                    avg_sent: bool,
-                   max_sent: bool)
+                   max_sent: bool,
+                   min_health: bool,
+                   sections: &[String])
                    -> String {
     let mut column_counter = 2;
     let mut params = vec![];
@@ -60,6 +66,21 @@ fn gnuplot_command(input: &str,
         params.push(format!("'{}' u 1:{} title 'Max messages sent' lt rgb '#222222'",
                             input,
                             column_counter));
+        column_counter += 1;
+    }
+    if min_health {
+        params.push(format!("'{}' u 1:(${} * 100) title 'Lowest section health * 100' \
+                             lt rgb '#FF8000'",
+                            input,
+                            column_counter));
+        column_counter += 1;
+    }
+    for prefix in sections {
+        params.push(format!("'{}' u 1:(${} * 100) title 'Health of Prefix({}) * 100'",
+                            input,
+                            column_counter,
+                            prefix));
+        column_counter += 1;
     }
 
     format!("set terminal png size 1920,1080; set output '{}';\
@@ -68,6 +89,48 @@ fn gnuplot_command(input: &str,
             params.join(","))
 }
 
+/// The data of each step in the log at `input`, and the sections which were ever scored.
+fn read_log(input: &str) -> Result<(Vec<StepData>, BTreeSet<String>), Error> {
+    let mut sent_msgs = BTreeMap::new();
+    let mut result = Vec::new();
+    let mut msgs_in_queue = 0;
+    let mut step_min_health: Option<f64> = None;
+    let mut step_section_health = BTreeMap::new();
+    let mut sections = BTreeSet::new();
+
+    let log_iter = LogIterator::open(input)?;
+
+    println!("Reading log...");
+    for data in log_iter {
+        match data? {
+            LogData::SentMsgs(name, sent) => {
+                let count = sent_msgs.entry(name).or_insert(0);
+                *count += sent;
+            }
+            LogData::MsgsInQueue(count) => {
+                msgs_in_queue = count;
+            }
+            LogData::SectionHealth(prefix, score) => {
+                step_min_health = Some(step_min_health.map_or(score, |min| min.min(score)));
+                sections.insert(prefix.clone());
+                step_section_health.insert(prefix, score);
+            }
+            LogData::Step(s, n) if s > 0 => {
+                let data = StepData {
+                    msgs_sent: mem::replace(&mut sent_msgs, BTreeMap::new()),
+                    msgs_queue: msgs_in_queue,
+                    network_size: n,
+                    min_health: step_min_health.take(),
+                    section_health: mem::replace(&mut step_section_health, BTreeMap::new()),
+                };
+                result.push(data);
+            }
+            _ => (),
+        }
+    }
+    Ok((result, sections))
+}
+
 fn main() {
     let matches = App::new("ewok_graph_msgs")
         .about("This tool takes a log output from an Ewok simulation and generates a file \
@@ -76,7 +139,9 @@ fn main() {
             in queue and valid blocks for prefix for the latest version over time.\n\n\
             Output file row format:\n\n\
             step_number [network_size] [queue_size] [total_messages_sent] [avg_messages_sent] \
-            [max_messages_sent_per_node]")
+            [max_messages_sent_per_node] [min_section_health] [section_health...]\n\n\
+            With --section-health, there is one column for each section which was ever scored, \
+            in order of prefix.")
         .arg(Arg::with_name("output")
                  .short("o")
                  .long("output")
@@ -107,13 +172,26 @@ fn main() {
                  .long("max-sent")
                  .takes_value(false)
                  .help("Include the maximum number of messages sent per node in the output"))
+        .arg(Arg::with_name("include_min_health")
+                 .short("H")
+                 .long("min-health")
+                 .takes_value(false)
+                 .help("Include the lowest section health score in the output, from a log of a \
+                        run with section health being monitored"))
+        .arg(Arg::with_name("include_section_health")
+                 .short("S")
+                 .long("section-health")
+                 .takes_value(false)
+                 .help("Include the health score of each section in the output, from a log of a \
+                        run with section health being monitored"))
         .arg(Arg::with_name("plot")
                  .short("p")
                  .long("plot")
                  .value_name("PLOT")
                  .help("Plot the graph using gnuplot to the given file"))
         .arg(Arg::with_name("INPUT")
-                 .help("Sets the input file to use")
+                 .help("Sets the input file to use: a log, a gzipped log ending in .gz, or - \
+                        for standard input")
                  .required(true)
                  .index(1))
         .get_matches();
@@ -124,37 +202,17 @@ fn main() {
     let total_sent = matches.is_present("include_total_sent");
     let avg_sent = matches.is_present("include_avg_sent");
     let max_sent = matches.is_present("include_max_sent");
+    let min_health = matches.is_present("include_min_health");
+    let section_health = matches.is_present("include_section_health");
     let plot = matches.value_of("plot");
-    let mut sent_msgs = BTreeMap::new();
-    let mut node_names = BTreeSet::new();
-    let mut result = Vec::new();
-    let mut msgs_in_queue = 0;
 
-    let file = File::open(input).unwrap();
-    let log_iter = LogIterator::new(file);
-
-    println!("Reading log...");
-    for data in log_iter {
-        match data {
-            LogData::SentMsgs(name, sent) => {
-                node_names.insert(name.clone());
-                let count = sent_msgs.entry(name).or_insert(0);
-                *count += sent;
-            }
-            LogData::MsgsInQueue(count) => {
-                msgs_in_queue = count;
-            }
-            LogData::Step(s, n) if s > 0 => {
-                let data = StepData {
-                    msgs_sent: mem::replace(&mut sent_msgs, BTreeMap::new()),
-                    msgs_queue: msgs_in_queue,
-                    network_size: n,
-                };
-                result.push(data);
-            }
-            _ => (),
+    let (result, sections) = match read_log(input) {
+        Ok(log) => log,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
         }
-    }
+    };
 
     println!("Reading finished. Generating output...");
     let file = File::create(&output).unwrap();
@@ -191,17 +249,40 @@ fn main() {
                        .unwrap_or(0))
                     .unwrap();
         }
+        if min_health {
+            // Steps with no sections scored are left out of the plot.
+            match data.min_health {
+                Some(score) => write!(writer, "\t{}", score).unwrap(),
+                None => write!(writer, "\tNaN").unwrap(),
+            }
+        }
+        if section_health {
+            // Sections which don't exist at a step are left out of its plot.
+            for prefix in &sections {
+                match data.section_health.get(prefix) {
+                    Some(score) => write!(writer, "\t{}", score).unwrap(),
+                    None => write!(writer, "\tNaN").unwrap(),
+                }
+            }
+        }
         write!(writer, "\n").unwrap();
     }
 
     if let Some(plot_output) = plot {
+        let plotted_sections: Vec<_> = if section_health {
+            sections.into_iter().collect()
+        } else {
+            vec![]
+        };
         let command = gnuplot_command(&output,
                                       &plot_output,
                                       network_size,
                                       queue_size,
                                       total_sent,
                                       avg_sent,
-                                      max_sent);
+                                      max_sent,
+                                      min_health,
+                                      &plotted_sections);
         let mut child = Command::new("gnuplot")
             .args(&["-e", &command])
             .spawn()
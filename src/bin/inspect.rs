@@ -0,0 +1,90 @@
+//! Recommended usage:
+//!
+//! EWOK_JOURNAL_DIR=journals ewok
+//! inspect journals 3f2a0c
+//!
+//! replays the decisions of the node whose name starts with `3f2a0c`, as journaled by a run with
+//! EWOK_JOURNAL_DIR or EWOK_JOURNAL_FILE set. Pass `--indexed` to read a journal written with
+//! EWOK_JOURNAL_FILE, and `--from` and `--to` to limit the steps replayed.
+
+#![allow(clippy::doc_markdown)]
+
+extern crate ewok;
+extern crate clap;
+
+use clap::{App, Arg, ArgMatches};
+use ewok::Error;
+use ewok::journal::{JournalEvent, JournalLayout, read_journals};
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    let matches = App::new("ewok_inspect")
+        .about("This tool replays the decision history of single nodes from the journals written \
+               by an Ewok simulation: the messages each handled, the votes it cast and the \
+               blocks it agreed, step by step.")
+        .arg(Arg::with_name("indexed")
+                 .short("i")
+                 .long("indexed")
+                 .help("Read a single indexed journal file rather than a directory."))
+        .arg(Arg::with_name("from")
+                 .long("from")
+                 .value_name("STEP")
+                 .help("The first step to replay."))
+        .arg(Arg::with_name("to")
+                 .long("to")
+                 .value_name("STEP")
+                 .help("The last step to replay."))
+        .arg(Arg::with_name("JOURNAL")
+                 .help("Sets the journal directory, or file with --indexed")
+                 .required(true)
+                 .index(1))
+        .arg(Arg::with_name("NODE")
+                 .help("The start of the node's name in hex, as in the log")
+                 .required(true)
+                 .index(2))
+        .get_matches();
+
+    if let Err(err) = run(&matches) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<(), Error> {
+    // JOURNAL and NODE are required arguments, so clap guarantees they're present.
+    let path = PathBuf::from(matches.value_of("JOURNAL").unwrap());
+    let node = matches.value_of("NODE").unwrap().trim_end_matches("..");
+    let step = |arg: &str, default: u64| match matches.value_of(arg) {
+        Some(value) => {
+            value.parse().map_err(|_| {
+                Error::Config(format!("--{} must be a step number, not {:?}", arg, value))
+            })
+        }
+        None => Ok(default),
+    };
+    let (from, to) = (step("from", 0)?, step("to", u64::MAX)?);
+    let layout = if matches.is_present("indexed") {
+        JournalLayout::Indexed(path)
+    } else {
+        JournalLayout::PerNode(path)
+    };
+
+    let journals = read_journals(&layout, node)?;
+    if journals.is_empty() {
+        return Err(Error::Config(format!("no journal for a node named {}..", node)));
+    }
+    for (name, entries) in journals {
+        println!("-- Node({}) --", name);
+        let (mut replayed, mut agreed) = (0, 0);
+        for entry in entries.iter().filter(|entry| entry.step >= from && entry.step <= to) {
+            if let JournalEvent::Agreed { .. } = entry.event {
+                agreed += 1;
+            }
+            replayed += 1;
+            println!("{}", entry);
+        }
+        println!("-- {} entries, {} blocks agreed --", replayed, agreed);
+    }
+    Ok(())
+}
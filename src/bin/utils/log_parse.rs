@@ -1,14 +1,19 @@
+use ewok::Error;
+use ewok::format::{Compatibility, FormatKind};
+use ewok::run_id;
 use regex::Regex;
 use super::chain::{Block, Vote, Members};
 use std::convert::AsRef;
 use std::fs::File;
-use std::io::{BufReader, BufRead};
+use std::io::{self, BufReader, BufRead};
+use std::process::{Child, Command, Stdio};
 
 lazy_static!{
     static ref AGREEMENT_RE: Regex = Regex::new(r"^Node\((?P<node>[0-9a-f]{6}\.\.)\): new valid vote: DebugVote \{ from: Block \{ prefix: Prefix\((?P<pfrom>[01]*)\), version: (?P<vfrom>\d+), members: \{(?P<mfrom>[0-9a-f]{6}\.\.(, [0-9a-f]{6}\.\.)*)\} \}, to: Block \{ prefix: Prefix\((?P<pto>[01]*)\), version: (?P<vto>\d+), members: \{(?P<mto>[0-9a-f]{6}\.\.(, [0-9a-f]{6}\.\.)*)\} \} \}").unwrap();
     static ref STEP_RE: Regex = Regex::new(r"^-- step (?P<step>\d+) \(.+\) (?P<nodes>\d+) nodes --").unwrap();
     static ref SENT_RE: Regex = Regex::new(r"^Network: sent (?P<sent>\d+) messages from (?P<name>[0-9a-f]{6})\.\.").unwrap();
     static ref QUEUE_RE: Regex = Regex::new(r"^- (?P<count>\d+) messages still in queue").unwrap();
+    static ref HEALTH_RE: Regex = Regex::new(r"^Health: Prefix\((?P<prefix>[01]*)\) score (?P<score>[0-9.]+)").unwrap();
 }
 
 pub enum LogData {
@@ -16,6 +21,7 @@ pub enum LogData {
     Step(u64, u64),
     SentMsgs(String, u64),
     MsgsInQueue(u64),
+    SectionHealth(String, f64),
 }
 
 impl LogData {
@@ -50,6 +56,9 @@ impl LogData {
         } else if let Some(caps) = QUEUE_RE.captures(line) {
             let count = caps["count"].parse().expect("invalid message count");
             Some(LogData::MsgsInQueue(count))
+        } else if let Some(caps) = HEALTH_RE.captures(line) {
+            let score = caps["score"].parse().expect("invalid health score");
+            Some(LogData::SectionHealth(caps["prefix"].to_owned(), score))
         } else {
             None
         }
@@ -57,32 +66,113 @@ impl LogData {
 }
 
 pub struct LogIterator {
-    file: BufReader<File>,
+    file: Box<dyn BufRead>,
     line: String,
+    /// Whether the log's format version has been checked yet.
+    version_checked: bool,
+    /// The `gzip` process decompressing the log, if it's compressed.
+    gzip: Option<Child>,
+    /// Whether the whole log has been read, or reading it failed.
+    finished: bool,
 }
 
 impl LogIterator {
     pub fn new(file: File) -> LogIterator {
+        Self::from_reader(Box::new(BufReader::new(file)))
+    }
+
+    fn from_reader(file: Box<dyn BufRead>) -> LogIterator {
         LogIterator {
-            file: BufReader::new(file),
+            file,
             line: String::new(),
+            version_checked: false,
+            gzip: None,
+            finished: false,
         }
     }
-}
 
-impl Iterator for LogIterator {
-    type Item = LogData;
+    /// Read the log at `path`: standard input if it's `-`, decompressed with `gzip` if it ends in
+    /// `.gz`, or else the file as it is.
+    pub fn open(path: &str) -> io::Result<LogIterator> {
+        if path == "-" {
+            return Ok(Self::from_reader(Box::new(BufReader::new(io::stdin()))));
+        }
+        if !path.ends_with(".gz") {
+            return Ok(Self::new(File::open(path)?));
+        }
+        // Check the file exists first, for the same error as an uncompressed log.
+        let _ = File::open(path)?;
+        let mut gzip = Command::new("gzip")
+            .args(["-dc", path])
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = gzip.stdout.take().expect("gzip's output is piped");
+        let mut log = Self::from_reader(Box::new(BufReader::new(stdout)));
+        log.gzip = Some(gzip);
+        Ok(log)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Fail if `gzip` failed to decompress the whole log.
+    fn check_gzip(&mut self) -> Result<(), Error> {
+        if let Some(mut gzip) = self.gzip.take() {
+            let status = gzip.wait()?;
+            if !status.success() {
+                let msg = format!("can't decompress the log: gzip exited with {}", status);
+                return Err(Error::Io(io::Error::new(io::ErrorKind::Other, msg)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that we can read a log of the given format version.
+    fn check_version(&mut self, found: Option<u32>) -> Result<(), Error> {
+        self.version_checked = true;
+        if let Compatibility::Upgraded { from } = FormatKind::Log.check_version(found)? {
+            println!("Reading a log in the older format version {}.", from);
+        }
+        Ok(())
+    }
+
+    /// The next entry of interest in the log, or `None` at its end.
+    fn read_next(&mut self) -> Result<Option<LogData>, Error> {
         self.line.clear();
-        while self.file.read_line(&mut self.line).unwrap() > 0 {
-            let result = LogData::from_line(&self.line);
+        while self.file.read_line(&mut self.line)? > 0 {
+            let line = run_id::strip_from_line(&self.line);
+            if !self.version_checked {
+                if let Some(version) = FormatKind::Log.parse_header(line.trim_end()) {
+                    self.check_version(Some(version))?;
+                    self.line.clear();
+                    continue;
+                }
+            }
+            let result = LogData::from_line(line);
             if result.is_none() {
                 self.line.clear();
                 continue;
             }
-            return result;
+            if !self.version_checked {
+                self.check_version(None)?;
+            }
+            return Ok(result);
+        }
+        self.check_gzip()?;
+        Ok(None)
+    }
+}
+
+/// Yields the entries of interest in the log, or the error which stopped it being read.
+impl Iterator for LogIterator {
+    type Item = Result<LogData, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
         }
-        None
+        let result = self.read_next().transpose();
+        self.finished = match result {
+            Some(Ok(_)) => false,
+            _ => true,
+        };
+        result
     }
 }
@@ -1,9 +1,9 @@
 use name::{Prefix, Name};
 use blocks::Blocks;
+use hash::stable_hash;
+use params::SplitPolicy;
 
 use std::collections::BTreeSet;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 
 #[derive(Clone, Debug, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct BlockId(u64);
@@ -37,24 +37,52 @@ impl Block {
         }
     }
 
-    pub fn should_split(&self, min_split_size: usize) -> bool {
+    /// Number of members that would end up in the 0 and 1 halves of this section on a split.
+    pub fn split_sizes(&self) -> (usize, usize) {
         let p0 = self.prefix.pushed(false);
-        let mut len0 = 0;
-        let mut len1 = 0;
-        for name in &self.members {
-            if p0.matches(*name) {
-                len0 += 1;
-            } else {
-                len1 += 1;
-            }
+        let len0 = self.members.iter().filter(|name| p0.matches(**name)).count();
+        (len0, self.members.len() - len0)
+    }
+
+    pub fn should_split(&self, policy: SplitPolicy, min_split_size: usize) -> bool {
+        let (len0, len1) = self.split_sizes();
+        match policy {
+            SplitPolicy::Balanced => len0 >= min_split_size && len1 >= min_split_size,
+            SplitPolicy::TotalSize => len0 + len1 >= 2 * min_split_size,
         }
-        len0 >= min_split_size && len1 >= min_split_size
+    }
+
+    /// Whether the section has reached `max_section_size` and can split into halves of at least
+    /// `min_section_size`, which it must do whatever the split policy.
+    pub fn must_split(&self, max_section_size: Option<usize>, min_section_size: usize) -> bool {
+        let (len0, len1) = self.split_sizes();
+        max_section_size.is_some_and(|max| self.members.len() >= max) &&
+            len0 >= min_section_size && len1 >= min_section_size
     }
 
     pub fn get_id(&self) -> BlockId {
-        let mut s = DefaultHasher::new();
-        self.hash(&mut s);
-        BlockId(s.finish())
+        BlockId(stable_hash(self))
+    }
+}
+
+/// The kind of change to a section that a vote is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoteKind {
+    /// A node being added to or removed from a section, which keeps its prefix.
+    Membership,
+    /// A section splitting into two.
+    Split,
+    /// Two sibling sections merging into one.
+    Merge,
+    /// A node relocated by its section being removed from it under its old name, or added to
+    /// the section covering its new name.
+    Relocation,
+}
+
+impl VoteKind {
+    /// Whether the vote adds or removes a node, keeping the section's prefix.
+    pub fn changes_members(self) -> bool {
+        self == VoteKind::Membership || self == VoteKind::Relocation
     }
 }
 
@@ -72,23 +100,42 @@ impl Vote {
         }
     }
 
+    pub fn kind(&self, blocks: &Blocks) -> VoteKind {
+        let from = self.from.into_block(blocks);
+        let to = self.to.into_block(blocks);
+        if to.prefix.bit_count() < from.prefix.bit_count() {
+            VoteKind::Merge
+        } else if to.prefix.bit_count() > from.prefix.bit_count() {
+            VoteKind::Split
+        } else if blocks.is_relocation(from, to) {
+            VoteKind::Relocation
+        } else {
+            VoteKind::Membership
+        }
+    }
+
     pub fn is_witnessing(&self, blocks: &Blocks) -> bool {
         !self.to.into_block(blocks).is_admissible_after(
             self.from.into_block(blocks),
         )
     }
 
-    pub fn is_quorum(&self, blocks: &Blocks, voters: &BTreeSet<Name>) -> bool {
+    /// The members whose votes count towards a quorum: those of the block being voted from,
+    /// except for the node being removed if the vote removes one.
+    pub fn voting_members<'a>(&self, blocks: &'a Blocks) -> &'a BTreeSet<Name> {
         let from = self.from.into_block(blocks);
         let to = self.to.into_block(blocks);
-        let members = if to.members.len() == from.members.len() - 1 &&
+        if to.members.len() == from.members.len() - 1 &&
             from.members.difference(&to.members).count() == 1
         {
             &to.members
         } else {
             &from.members
-        };
-        is_quorum_of(voters, members)
+        }
+    }
+
+    pub fn is_quorum(&self, blocks: &Blocks, voters: &BTreeSet<Name>) -> bool {
+        is_quorum_of(voters, self.voting_members(blocks))
     }
 }
 
@@ -204,3 +251,56 @@ fn is_quorum_of(voters: &BTreeSet<Name>, members: &BTreeSet<Name>) -> bool {
     assert_eq!(voters.len(), valid_voters.len());
     valid_voters.len() * 2 > members.len()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn section(prefix: Prefix, num0: u64, num1: u64) -> Block {
+        let p0 = prefix.pushed(false);
+        let p1 = prefix.pushed(true);
+        let members = (0..num0)
+            .map(|i| p0.substituted_in(Name(i)))
+            .chain((0..num1).map(|i| p1.substituted_in(Name(i))))
+            .collect();
+        Block {
+            prefix,
+            version: 0,
+            members,
+        }
+    }
+
+    #[test]
+    fn balanced_split() {
+        let block = section(Prefix::empty(), 9, 9);
+        assert_eq!(block.split_sizes(), (9, 9));
+        assert!(block.should_split(SplitPolicy::Balanced, 9));
+        assert!(block.should_split(SplitPolicy::TotalSize, 9));
+    }
+
+    #[test]
+    fn unbalanced_refuses_to_split() {
+        let block = section(Prefix::short(1, 0), 14, 4);
+        assert_eq!(block.split_sizes(), (14, 4));
+        assert!(!block.should_split(SplitPolicy::Balanced, 9));
+        assert!(block.should_split(SplitPolicy::TotalSize, 9));
+    }
+
+    #[test]
+    fn too_small_to_split() {
+        let block = section(Prefix::empty(), 8, 9);
+        assert!(!block.should_split(SplitPolicy::Balanced, 9));
+        assert!(!block.should_split(SplitPolicy::TotalSize, 9));
+    }
+
+    #[test]
+    fn forced_split_at_max_size() {
+        let block = section(Prefix::empty(), 12, 8);
+        assert!(!block.should_split(SplitPolicy::Balanced, 9));
+        assert!(block.must_split(Some(20), 8));
+        assert!(!block.must_split(Some(21), 8));
+        assert!(!block.must_split(None, 8));
+        // Splitting would leave a half below the minimum section size.
+        assert!(!section(Prefix::empty(), 13, 7).must_split(Some(20), 8));
+    }
+}
@@ -11,27 +11,65 @@ pub type CurrentBlocks = BTreeSet<BlockId>;
 /// Mapping from votes to voters: (vote.from -> (vote.to -> names)).
 pub type VoteCounts = BTreeMap<BlockId, BTreeMap<BlockId, BTreeSet<Name>>>;
 
-pub struct Blocks(HashMap<BlockId, Block>);
+#[derive(Clone)]
+pub struct Blocks {
+    blocks: HashMap<BlockId, Block>,
+    /// Old names of the nodes relocated by a quorum of their section, so the votes removing them
+    /// count as relocations.
+    relocated_from: BTreeSet<Name>,
+    /// New names of the same nodes, so the votes adding them count as relocations.
+    relocated_to: BTreeSet<Name>,
+}
 
 impl Deref for Blocks {
     type Target = HashMap<BlockId, Block>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.blocks
     }
 }
 
 impl Blocks {
     pub fn new() -> Blocks {
-        Blocks(HashMap::new())
+        Blocks {
+            blocks: HashMap::new(),
+            relocated_from: BTreeSet::new(),
+            relocated_to: BTreeSet::new(),
+        }
     }
 
     pub fn insert(&mut self, block: Block) -> BlockId {
         let id = block.get_id();
-        self.0.insert(id, block);
+        self.blocks.insert(id, block);
         id
     }
 
+    /// Record the relocation of `node` under the name `to`, once a quorum of its section has
+    /// signed it.
+    pub fn insert_relocation(&mut self, node: Name, to: Name) {
+        self.relocated_from.insert(node);
+        self.relocated_to.insert(to);
+    }
+
+    /// Whether the change from `from` to `to` is a relocated node leaving its section, or being
+    /// added to its new one.
+    pub fn is_relocation(&self, from: &Block, to: &Block) -> bool {
+        let mut removed = from.members.difference(&to.members);
+        let mut added = to.members.difference(&from.members);
+        match (removed.next(), added.next()) {
+            (Some(node), None) => removed.next().is_none() && self.relocated_from.contains(node),
+            (None, Some(node)) => added.next().is_none() && self.relocated_to.contains(node),
+            _ => false,
+        }
+    }
+
+    /// Remove all blocks that aren't in `keep`, returning the number of blocks removed.
+    pub fn retain(&mut self, keep: &BTreeSet<BlockId>) -> usize {
+        let before = self.blocks.len();
+        self.blocks.retain(|id, _| keep.contains(id));
+        before - self.blocks.len()
+    }
+
     /// Compute the set of blocks that become valid as a result of adding `new_vote`.
     ///
     /// * `valid_blocks`: the set of valid blocks.
@@ -0,0 +1,124 @@
+//! Fluent construction of simulations, for embedding ewok in other code.
+//!
+//! Anything left unset falls back to the defaults: `SimulationParams::default()`,
+//! `NodeParams::default()`, the genesis section given by the parameters, an empty event schedule
+//! and the current seed.
+
+use cohort::Cohort;
+use error::Result;
+use event_schedule::EventSchedule;
+use name::Prefix;
+use node::Node;
+use params::{NodeParams, SimulationParams};
+use random::reseed;
+use simulation::{Simulation, StepHook, StepSummary};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Builder for a `Simulation`, returned by `Simulation::builder`.
+pub struct SimulationBuilder {
+    seed: Option<[u32; 4]>,
+    params: SimulationParams,
+    node_params: NodeParams,
+    sections: Option<BTreeMap<Prefix, usize>>,
+    schedule: EventSchedule,
+    hooks: Vec<StepHook>,
+    metrics_path: Option<PathBuf>,
+    cohorts: Vec<Cohort>,
+}
+
+impl Default for SimulationBuilder {
+    fn default() -> Self {
+        SimulationBuilder {
+            seed: None,
+            params: SimulationParams::default(),
+            node_params: NodeParams::default(),
+            sections: None,
+            schedule: EventSchedule::empty(),
+            hooks: vec![],
+            metrics_path: None,
+            cohorts: vec![],
+        }
+    }
+}
+
+impl SimulationBuilder {
+    /// Seed the random number generator before generating the network, so that the whole run
+    /// can be reproduced.
+    pub fn seed(mut self, seed: [u32; 4]) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn params(mut self, params: SimulationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn node_params(mut self, node_params: NodeParams) -> Self {
+        self.node_params = node_params;
+        self
+    }
+
+    /// Start from sections with the given prefixes and numbers of nodes, instead of the genesis
+    /// section given by the parameters.
+    pub fn initial_sections(mut self, sections: BTreeMap<Prefix, usize>) -> Self {
+        self.sections = Some(sections);
+        self
+    }
+
+    pub fn schedule(mut self, schedule: EventSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Call `hook` with the summary of every step. See `Simulation::add_hook`.
+    pub fn hook<F: FnMut(&StepSummary) + 'static>(mut self, hook: F) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Write every node's activity counters to `path` as CSV when the run finishes.
+    pub fn metrics_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.metrics_path = Some(path.into());
+        self
+    }
+
+    /// Run the given share of the nodes with `node_params` in a cohort called `name`, instead of
+    /// the parameters set with `node_params`. See `Simulation::assign_cohorts`.
+    pub fn cohort(mut self, name: &str, share: f64, node_params: NodeParams) -> Self {
+        self.cohorts.push(Cohort {
+            name: name.to_string(),
+            share,
+            params: node_params,
+        });
+        self
+    }
+
+    /// Create the simulation, or return an error if the parameters or sections are invalid.
+    pub fn build(self) -> Result<Simulation<Node>> {
+        if let Some(seed) = self.seed {
+            reseed(seed);
+        }
+        let mut simulation = match self.sections {
+            Some(sections) => Simulation::try_from_sections(
+                sections,
+                self.schedule,
+                self.params,
+                self.node_params,
+            )?,
+            None => Simulation::try_from_genesis(self.schedule, self.params, self.node_params)?,
+        };
+        for hook in self.hooks {
+            simulation.add_hook(hook);
+        }
+        if let Some(path) = self.metrics_path {
+            simulation.write_metrics_to(path);
+        }
+        if !self.cohorts.is_empty() {
+            simulation.assign_cohorts(self.cohorts)?;
+        }
+        Ok(simulation)
+    }
+}
@@ -0,0 +1,222 @@
+//! Lamport-style causality tracking for messages, to reconstruct which messages led to which.
+//!
+//! Every node has a logical clock, which ticks whenever it sends a message and jumps past the
+//! clock of each message it handles. Each message sent is recorded with its sender's clock and
+//! with the message the sender handled last, which is taken as its cause. Messages sent by the
+//! simulation on a node's behalf, like disconnects, are recorded the same way.
+//!
+//! The clocks aren't carried by the messages themselves, as message equality decides which
+//! messages nodes filter out as already sent. Delivery is in order on each connection, so the
+//! records of messages in flight are kept in the same order instead.
+
+use block::BlockId;
+use blocks::VoteCounts;
+use message::{Message, MessageContent};
+use message::MessageContent::*;
+use name::Name;
+use run_id;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Write};
+
+/// Index of a message in the order messages were sent.
+pub type MessageId = usize;
+
+/// A message sent during the run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CausalRecord {
+    pub sender: Name,
+    pub recipient: Name,
+    /// Kind of message, such as `VoteMsg`.
+    pub kind: &'static str,
+    /// Blocks the message carries votes for, if any.
+    pub blocks: BTreeSet<BlockId>,
+    pub step: u64,
+    /// The sender's logical clock when it sent the message.
+    pub clock: u64,
+    /// The message the sender handled last before sending this one, if any.
+    pub cause: Option<MessageId>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CausalityTracker {
+    /// Logical clock of each node.
+    clocks: BTreeMap<Name, u64>,
+    /// Last message handled by each node.
+    last_handled: BTreeMap<Name, MessageId>,
+    /// Every message sent, in order.
+    records: Vec<CausalRecord>,
+    /// Messages in flight on each connection, in the order they'll be delivered.
+    in_flight: BTreeMap<(Name, Name), VecDeque<MessageId>>,
+}
+
+impl CausalityTracker {
+    /// Record a message sent at `step`.
+    pub fn sent(&mut self, step: u64, message: &Message) {
+        let clock = self.clocks.entry(message.sender).or_insert(0);
+        *clock += 1;
+        let blocks = voted_blocks(&message.content);
+        let id = self.records.len();
+        self.records.push(CausalRecord {
+            sender: message.sender,
+            recipient: message.recipient,
+            kind: message.content.kind(),
+            blocks,
+            step,
+            clock: *clock,
+            cause: self.last_handled.get(&message.sender).cloned(),
+        });
+        self.in_flight
+            .entry((message.sender, message.recipient))
+            .or_default()
+            .push_back(id);
+    }
+
+    /// Record that the recipient of `message` is handling it, so it causes whatever the recipient
+    /// sends next.
+    pub fn handled(&mut self, message: &Message) {
+        let conn = (message.sender, message.recipient);
+        let id = match self.in_flight.get_mut(&conn).and_then(VecDeque::pop_front) {
+            Some(id) => id,
+            None => return,
+        };
+        let clock = self.clocks.entry(message.recipient).or_insert(0);
+        *clock = (*clock).max(self.records[id].clock) + 1;
+        let _ = self.last_handled.insert(message.recipient, id);
+    }
+
+    /// Record that the oldest `count` messages in flight from `sender` to `recipient` were dropped.
+    pub fn expired(&mut self, sender: Name, recipient: Name, count: usize) {
+        if let Some(in_flight) = self.in_flight.get_mut(&(sender, recipient)) {
+            let count = count.min(in_flight.len());
+            let _ = in_flight.drain(..count);
+        }
+    }
+
+    pub fn records(&self) -> &[CausalRecord] {
+        &self.records
+    }
+
+    /// The messages carrying votes for `block`, and all the messages which led to them, in the
+    /// order they were sent.
+    pub fn agreement_history(&self, block: BlockId) -> BTreeSet<MessageId> {
+        let mut history = BTreeSet::new();
+        let mut to_visit: Vec<MessageId> = (0..self.records.len())
+            .filter(|&id| self.records[id].blocks.contains(&block))
+            .collect();
+        while let Some(id) = to_visit.pop() {
+            if history.insert(id) {
+                to_visit.extend(self.records[id].cause);
+            }
+        }
+        history
+    }
+
+    /// Write the history of the agreement of `block` as a graph in the DOT language, with an
+    /// edge from each message to those it caused.
+    pub fn write_agreement_graph<W: Write>(
+        &self,
+        block: BlockId,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        if let Some(run_id) = run_id::current() {
+            writeln!(writer, "// run {}", run_id)?;
+        }
+        writeln!(writer, "digraph {{")?;
+        let history = self.agreement_history(block);
+        for &id in &history {
+            let record = &self.records[id];
+            let shape = if record.blocks.contains(&block) {
+                "box"
+            } else {
+                "ellipse"
+            };
+            writeln!(
+                writer,
+                "m{} [label=\"{} {}->{}\\nstep {} clock {}\"; shape={}];",
+                id,
+                record.kind,
+                record.sender,
+                record.recipient,
+                record.step,
+                record.clock,
+                shape
+            )?;
+            if let Some(cause) = record.cause {
+                writeln!(writer, "m{}->m{}", cause, id)?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+/// The blocks which `content` carries votes for.
+fn voted_blocks(content: &MessageContent) -> BTreeSet<BlockId> {
+    let from_counts = |vote_counts: &VoteCounts| -> BTreeSet<BlockId> {
+        vote_counts.values().flat_map(|map| map.keys().cloned()).collect()
+    };
+    match *content {
+        VoteMsg(ref vote) => btreeset!{vote.to},
+        VoteBatch(ref votes) => votes.iter().map(|vote| vote.to).collect(),
+        VoteAgreedMsg(ref agreed) => btreeset!{agreed.0.to},
+        VoteBundle(ref bundle) |
+        ConnectWithVotes(ref bundle) |
+        WelcomeMsg(ref bundle) => bundle.iter().map(|(vote, _)| vote.to).collect(),
+        BootstrapMsg(ref vote_counts) => from_counts(vote_counts),
+        SnapshotBootstrapMsg(ref bootstrap) => from_counts(&bootstrap.1),
+        _ => BTreeSet::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::{Block, Vote};
+    use blocks::Blocks;
+    use name::Prefix;
+
+    fn message(sender: u64, recipient: u64, content: MessageContent) -> Message {
+        Message {
+            sender: Name(sender),
+            recipient: Name(recipient),
+            content,
+        }
+    }
+
+    #[test]
+    fn clocks_and_causes() {
+        let mut blocks = Blocks::new();
+        let block = Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: btreeset!{Name(1), Name(2)},
+        };
+        let from = blocks.insert(block.clone());
+        let to = blocks.insert(block.add_node(Name(3)));
+        let vote = VoteMsg(Vote { from, to });
+
+        let mut tracker = CausalityTracker::default();
+        // 3 connects to 1 and 2, then 1 votes to add it, and 2 follows once it has the vote.
+        let connect = message(3, 1, Connect);
+        tracker.sent(0, &connect);
+        tracker.sent(0, &message(3, 2, Connect));
+        tracker.handled(&connect);
+        let vote_1 = message(1, 2, vote.clone());
+        tracker.sent(1, &vote_1);
+        tracker.handled(&vote_1);
+        tracker.sent(2, &message(2, 1, vote));
+
+        let records = tracker.records();
+        let clocks: Vec<_> = records.iter().map(|record| record.clock).collect();
+        assert_eq!(clocks, vec![1, 2, 3, 5]);
+        let causes: Vec<_> = records.iter().map(|record| record.cause).collect();
+        assert_eq!(causes, vec![None, None, Some(0), Some(2)]);
+        // The connect from 3 to 2 was never handled, so it isn't part of the agreement.
+        assert_eq!(tracker.agreement_history(to), btreeset!{0, 2, 3});
+
+        let mut graph = vec![];
+        tracker.write_agreement_graph(to, &mut graph).unwrap();
+        let graph = String::from_utf8(graph).unwrap();
+        assert!(graph.contains("m0->m2") && graph.contains("m2->m3"));
+    }
+}
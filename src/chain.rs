@@ -0,0 +1,255 @@
+//! Export and import of chains in a canonical binary format, so that a network reached in one
+//! run can be saved and used to start others, e.g. as a fixture for tests.
+//!
+//! A chain is a set of blocks, some of which are marked as current: together, the current blocks
+//! form the routing table, describing every section of the network.
+//!
+//! The file starts with the usual `# ewok chain format <version>` header line, followed by the
+//! number of blocks and then each block in order. Integers are little-endian. Each block is:
+//!
+//! * 1 byte: 1 if the block is current, 0 otherwise
+//! * 1 byte: number of bits in the prefix
+//! * 8 bytes: the prefix's name, with the bits past the prefix cleared
+//! * 8 bytes: the version
+//! * 4 bytes: the number of members, followed by 8 bytes for each member in ascending order
+//!
+//! Blocks are written in ascending order, so equal chains are always written identically.
+
+use block::Block;
+use blocks::{Blocks, CurrentBlocks, ValidBlocks};
+use error::{Error, Result};
+use format::FormatKind;
+use name::{Name, Prefix};
+use pretty;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Read, Write};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Chain {
+    /// Every block in the chain, with whether it's current.
+    blocks: BTreeMap<Block, bool>,
+}
+
+impl Chain {
+    /// The chain of the blocks in each of the sets in `valid`, of which those in `current` are
+    /// current. Blocks in `current` which aren't among `valid` are included too.
+    pub fn new<'a, I>(blocks: &Blocks, valid: I, current: &CurrentBlocks) -> Self
+    where
+        I: IntoIterator<Item = &'a ValidBlocks>,
+    {
+        let mut chain = Chain::default();
+        for block_ids in valid {
+            for block_id in block_ids {
+                let _ = chain.blocks.insert(block_id.into_block(blocks).clone(), false);
+            }
+        }
+        for block_id in current {
+            let _ = chain.blocks.insert(block_id.into_block(blocks).clone(), true);
+        }
+        chain
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The current blocks, one for each section if the network had converged.
+    pub fn routing_table(&self) -> Vec<&Block> {
+        self.blocks
+            .iter()
+            .filter(|&(_, &current)| current)
+            .map(|(block, _)| block)
+            .collect()
+    }
+
+    /// A table with a row for each prefix: the versions of its current blocks, of which there's
+    /// more than one if nodes disagree, the size of the latest, the number of blocks held for it,
+    /// and the versions of valid blocks newer than any current one.
+    pub fn pretty(&self) -> String {
+        let mut prefixes: BTreeMap<Prefix, Vec<(&Block, bool)>> = BTreeMap::new();
+        for (block, &current) in &self.blocks {
+            prefixes.entry(block.prefix).or_default().push((block, current));
+        }
+        let versions = |versions: BTreeSet<u64>| -> String {
+            let versions: Vec<_> = versions.iter().map(|version| format!("v{}", version)).collect();
+            versions.join(", ")
+        };
+        let rows: Vec<_> = prefixes
+            .iter()
+            .map(|(prefix, blocks)| {
+                let latest = blocks
+                    .iter()
+                    .filter(|&&(_, current)| current)
+                    .map(|&(block, _)| block)
+                    .max_by_key(|block| block.version);
+                let current = blocks
+                    .iter()
+                    .filter(|&&(_, current)| current)
+                    .map(|&(block, _)| block.version)
+                    .collect();
+                let latest_version = latest.map(|block| block.version);
+                let pending = blocks
+                    .iter()
+                    .filter(|&&(block, _)| Some(block.version) > latest_version)
+                    .map(|&(block, _)| block.version)
+                    .collect();
+                vec![
+                    format!("{:?}", prefix),
+                    versions(current),
+                    latest.map_or("-".to_string(), |block| block.members.len().to_string()),
+                    blocks.len().to_string(),
+                    versions(pending),
+                ]
+            })
+            .collect();
+        pretty::table(&["prefix", "current", "members", "blocks", "pending"], &rows)
+    }
+
+    /// Add every block to `blocks`, returning the ids of the current ones.
+    pub fn insert_into(&self, blocks: &mut Blocks) -> CurrentBlocks {
+        let mut current_blocks = BTreeSet::new();
+        for (block, &current) in &self.blocks {
+            let block_id = blocks.insert(block.clone());
+            if current {
+                let _ = current_blocks.insert(block_id);
+            }
+        }
+        current_blocks
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", FormatKind::Chain.run_header())?;
+        writer.write_all(&(self.blocks.len() as u32).to_le_bytes())?;
+        for (block, &current) in &self.blocks {
+            writer.write_all(&[current as u8, block.prefix.bit_count() as u8])?;
+            writer.write_all(&block.prefix.lower_bound().0.to_le_bytes())?;
+            writer.write_all(&block.version.to_le_bytes())?;
+            writer.write_all(&(block.members.len() as u32).to_le_bytes())?;
+            for member in &block.members {
+                writer.write_all(&member.0.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a chain written by `write`, checking that its version is supported.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self> {
+        let mut header = String::new();
+        let _ = reader.read_line(&mut header)?;
+        let version = FormatKind::Chain.parse_header(header.trim_end());
+        if version.is_none() {
+            return Err(Error::Serialization("missing chain format header".to_string()));
+        }
+        let _ = FormatKind::Chain.check_version(version)?;
+
+        let mut chain = Chain::default();
+        for _ in 0..read_u32(reader)? {
+            let mut flags = [0; 2];
+            read_exact(reader, &mut flags)?;
+            let bit_count = flags[1] as usize;
+            if flags[0] > 1 || bit_count > 64 {
+                return Err(Error::Serialization(format!("invalid block flags {:?}", flags)));
+            }
+            let prefix = Prefix::new(bit_count, Name(read_u64(reader)?));
+            let version = read_u64(reader)?;
+            let mut members = BTreeSet::new();
+            for _ in 0..read_u32(reader)? {
+                let _ = members.insert(Name(read_u64(reader)?));
+            }
+            let block = Block {
+                prefix,
+                version,
+                members,
+            };
+            let _ = chain.blocks.insert(block, flags[0] == 1);
+        }
+        Ok(chain)
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|err| if err.kind() == io::ErrorKind::UnexpectedEof {
+        Error::Serialization("chain ends in the middle of a block".to_string())
+    } else {
+        Error::Io(err)
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    read_exact(reader, &mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut buf = [0; 8];
+    read_exact(reader, &mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn canonical_round_trip() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let genesis = Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: btreeset!{p0.substituted_in(Name(1)), p1.substituted_in(Name(2))},
+        };
+        let split0 = Block {
+            prefix: p0,
+            version: 1,
+            members: btreeset!{p0.substituted_in(Name(1))},
+        };
+        let split1 = Block {
+            prefix: p1,
+            version: 1,
+            members: btreeset!{p1.substituted_in(Name(2))},
+        };
+
+        let mut blocks = Blocks::new();
+        let ids: Vec<_> = vec![genesis, split1.clone(), split0.clone()]
+            .into_iter()
+            .map(|block| blocks.insert(block))
+            .collect();
+        let current = btreeset!{ids[1], ids[2]};
+        // Blocks valid at several nodes are only included once, whichever node comes first.
+        let valid = vec![btreeset!{ids[0], ids[1]}, btreeset!{ids[2], ids[0]}];
+        let chain = Chain::new(&blocks, &valid, &current);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.routing_table(), vec![&split0, &split1]);
+        assert_eq!(
+            chain.pretty(),
+            "prefix     current  members  blocks  pending\n\
+             Prefix()            -        1       v0\n\
+             Prefix(0)  v1       1        1\n\
+             Prefix(1)  v1       1        1\n"
+        );
+
+        let mut bytes = vec![];
+        chain.write(&mut bytes).unwrap();
+        let mut reversed = vec![];
+        Chain::new(&blocks, valid.iter().rev(), &current)
+            .write(&mut reversed)
+            .unwrap();
+        assert_eq!(bytes, reversed);
+
+        let read = Chain::read(&mut &bytes[..]).unwrap();
+        assert_eq!(read, chain);
+        let mut new_blocks = Blocks::new();
+        assert_eq!(read.insert_into(&mut new_blocks), current);
+        assert_eq!(new_blocks.len(), 3);
+
+        assert!(Chain::read(&mut &bytes[..bytes.len() - 1]).is_err());
+        assert!(Chain::read(&mut &b"# ewok chain format 2\n\0\0\0\0"[..]).is_err());
+    }
+}
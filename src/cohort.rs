@@ -0,0 +1,153 @@
+//! Cohorts of nodes running with different parameters side by side in one network, to compare a
+//! change to the parameters against the baseline without the noise between separate runs.
+//!
+//! Each cohort takes a share of the nodes, picked by a hash of their names so that it's spread
+//! evenly over the sections and a name always falls in the same cohort. Nodes outside every share
+//! are in the default cohort, running with the simulation's own parameters. Statistics are kept
+//! per cohort, for nodes which have left as well as those still running.
+
+use error::{Error, Result};
+use hash::stable_hash;
+use name::Name;
+use params::NodeParams;
+use stats::{JoinStats, NodeStats};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Name of the cohort of nodes which aren't in any other.
+pub const DEFAULT_COHORT: &str = "default";
+
+#[derive(Clone, Debug)]
+pub struct Cohort {
+    pub name: String,
+    /// Fraction of all nodes in the cohort.
+    pub share: f64,
+    pub params: NodeParams,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cohorts {
+    cohorts: Vec<Cohort>,
+    /// Outcomes of finished join attempts, by cohort.
+    joins: BTreeMap<String, JoinStats>,
+}
+
+impl Cohorts {
+    /// Check that the cohorts have distinct names, valid parameters and shares adding up to at
+    /// most 1.
+    pub fn new(cohorts: Vec<Cohort>) -> Result<Self> {
+        let mut names = BTreeSet::new();
+        let mut total_share = 0.0;
+        for cohort in &cohorts {
+            if cohort.name == DEFAULT_COHORT || !names.insert(&cohort.name) {
+                return Err(Error::Config(format!("duplicate cohort name {:?}", cohort.name)));
+            }
+            if !(0.0..=1.0).contains(&cohort.share) {
+                return Err(Error::Config(format!(
+                    "share of cohort {:?} must be between 0 and 1, got {}",
+                    cohort.name,
+                    cohort.share
+                )));
+            }
+            cohort.params.validate()?;
+            total_share += cohort.share;
+        }
+        if total_share > 1.0 {
+            return Err(Error::Config(
+                format!("cohort shares add up to {}, more than 1", total_share),
+            ));
+        }
+        Ok(Cohorts {
+            cohorts,
+            joins: BTreeMap::new(),
+        })
+    }
+
+    fn find(&self, name: Name) -> Option<&Cohort> {
+        // FNV leaves the high bits poorly mixed between names differing only in a few bytes, so
+        // they're mixed further before picking a cohort with them.
+        let mut hash = stable_hash(&name);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        let position = hash as f64 / u64::MAX as f64;
+        let mut upper = 0.0;
+        self.cohorts.iter().find(|cohort| {
+            upper += cohort.share;
+            position < upper
+        })
+    }
+
+    /// The name of the cohort the node with `name` is in.
+    pub fn cohort_of(&self, name: Name) -> &str {
+        self.find(name).map_or(DEFAULT_COHORT, |cohort| &cohort.name)
+    }
+
+    /// The parameters for the node with `name`: its cohort's, or `default` in the default cohort.
+    pub fn params_for(&self, name: Name, default: &NodeParams) -> NodeParams {
+        self.find(name).map_or(default, |cohort| &cohort.params).clone()
+    }
+
+    /// Record the outcome of a join attempt: the steps it took, or `None` if it failed.
+    pub fn record_join(&mut self, name: Name, latency: Option<u64>) {
+        let cohort = self.cohort_of(name).to_string();
+        let stats = self.joins.entry(cohort).or_default();
+        match latency {
+            Some(latency) => {
+                stats.joined += 1;
+                stats.total_latency += latency;
+            }
+            None => stats.rejected += 1,
+        }
+    }
+
+    /// Outcomes of finished join attempts, by cohort.
+    pub fn join_stats(&self) -> &BTreeMap<String, JoinStats> {
+        &self.joins
+    }
+
+    /// Totals of the given per-node statistics, and the number of nodes, by cohort.
+    pub fn node_stats(
+        &self,
+        per_node: &BTreeMap<Name, NodeStats>,
+    ) -> BTreeMap<String, (usize, NodeStats)> {
+        let mut result: BTreeMap<String, (usize, NodeStats)> = BTreeMap::new();
+        for (name, stats) in per_node {
+            let entry = result.entry(self.cohort_of(*name).to_string()).or_default();
+            entry.0 += 1;
+            entry.1 += stats.clone();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cohort(name: &str, share: f64) -> Cohort {
+        Cohort {
+            name: name.to_string(),
+            share,
+            params: NodeParams::default(),
+        }
+    }
+
+    #[test]
+    fn nodes_split_by_share() {
+        let cohorts = Cohorts::new(vec![cohort("patched", 0.25)]).unwrap();
+        let names: Vec<Name> = (0..1000u64).map(|i| Name(i << 54)).collect();
+        let patched = names.iter().filter(|name| cohorts.cohort_of(**name) == "patched").count();
+        assert!(patched > 150 && patched < 350, "{} patched nodes", patched);
+        assert_eq!(cohorts.cohort_of(names[0]), cohorts.cohort_of(names[0]));
+
+        let mut cohorts = cohorts;
+        let patched = names.iter().cloned().find(|name| cohorts.cohort_of(*name) == "patched");
+        cohorts.record_join(patched.unwrap(), Some(4));
+        assert_eq!(cohorts.join_stats()["patched"].joined, 1);
+
+        assert!(Cohorts::new(vec![cohort("a", 0.6), cohort("b", 0.6)]).is_err());
+        assert!(Cohorts::new(vec![cohort("a", 0.1), cohort("a", 0.1)]).is_err());
+        assert!(Cohorts::new(vec![cohort(DEFAULT_COHORT, 0.1)]).is_err());
+    }
+}
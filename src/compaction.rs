@@ -0,0 +1,198 @@
+//! Compaction of long chains of agreed blocks into snapshots.
+//!
+//! A snapshot stands in for a section's history up to some agreed block, the snapshot's head.
+//! It records the head's membership and a hash link over the votes it replaces, chained onto the
+//! link of the snapshot before it, so the compacted history can still be checked against a full
+//! copy of the chain. Nodes holding a snapshot drop the votes it replaces, and joining nodes are
+//! bootstrapped from the snapshot rather than from the genesis block.
+
+use block::{BlockId, Vote};
+use blocks::{Blocks, ValidBlocks, VoteCounts};
+use hash::stable_hash;
+use name::{Name, Prefix};
+
+use std::collections::BTreeSet;
+
+/// A synthetic block capturing a section's history up to and including `head`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Snapshot {
+    /// The newest block of the compacted history.
+    pub head: BlockId,
+    pub prefix: Prefix,
+    pub version: u64,
+    /// Members of the section as of `head`.
+    pub members: BTreeSet<Name>,
+    /// Hash over the previous snapshot's link and the votes compacted into this one.
+    pub link: u64,
+    /// Total number of votes compacted into this snapshot and the ones before it.
+    pub num_votes: usize,
+}
+
+impl Snapshot {
+    /// Compact the history of `block` that's more than `keep_versions` versions older than it,
+    /// following the agreed votes in `rev_votes` back to `previous`, or as far as they go. Only
+    /// history since the section last split or merged is compacted, so that the blocks that
+    /// neighbouring sections' chains start from are kept.
+    ///
+    /// Returns the new snapshot and the blocks it replaces, or `None` if there's nothing old
+    /// enough to compact.
+    pub fn compact(
+        blocks: &Blocks,
+        rev_votes: &VoteCounts,
+        block: BlockId,
+        keep_versions: u64,
+        previous: Option<&Snapshot>,
+    ) -> Option<(Snapshot, BTreeSet<BlockId>)> {
+        let newest = block.into_block(blocks);
+        let cutoff = newest.version.checked_sub(keep_versions)?;
+
+        // Walk back along the chain of agreed, non-witnessing votes, newest first.
+        let mut chain = vec![block];
+        let mut votes = vec![];
+        let mut oldest = block;
+        while previous.is_none_or(|snapshot| snapshot.head != oldest) {
+            let predecessor = blocks
+                .predecessors(&oldest, rev_votes)
+                .into_iter()
+                .find(|(from, vote, _)| {
+                    !vote.is_witnessing(blocks) && from.into_block(blocks).prefix == newest.prefix
+                });
+            match predecessor {
+                Some((from, vote, _)) => {
+                    votes.push(vote);
+                    chain.push(from);
+                    oldest = from;
+                }
+                None => break,
+            }
+        }
+
+        // The head is the newest block at or below the cutoff, and must have history behind it.
+        let head_index = chain
+            .iter()
+            .position(|id| id.into_block(blocks).version <= cutoff)?;
+        if head_index + 1 >= chain.len() {
+            return None;
+        }
+        let compacted: BTreeSet<BlockId> = chain[head_index + 1..].iter().cloned().collect();
+        let mut compacted_votes: Vec<Vote> = votes[head_index..].to_vec();
+        compacted_votes.reverse();
+
+        let head = chain[head_index].into_block(blocks);
+        let snapshot = Snapshot {
+            head: chain[head_index],
+            prefix: head.prefix,
+            version: head.version,
+            members: head.members.clone(),
+            link: stable_hash(&(previous.map(|snapshot| snapshot.link), &compacted_votes)),
+            num_votes: previous.map_or(0, |snapshot| snapshot.num_votes) + compacted_votes.len(),
+        };
+        Some((snapshot, compacted))
+    }
+
+    /// Check that the compacted votes give the same agreed history from the head onwards as the
+    /// full votes they were compacted from.
+    pub fn verify(&self, blocks: &Blocks, compacted: &VoteCounts, full: &VoteCounts) -> bool {
+        self.valid_after(blocks, compacted) == self.valid_after(blocks, full)
+    }
+
+    /// All blocks that become valid starting from the head, given `vote_counts`.
+    fn valid_after(&self, blocks: &Blocks, vote_counts: &VoteCounts) -> ValidBlocks {
+        let valid = btreeset!{self.head};
+        let new_votes = vote_counts
+            .get(&self.head)
+            .into_iter()
+            .flat_map(|map| map.keys())
+            .map(|to| {
+                Vote {
+                    from: self.head,
+                    to: *to,
+                }
+            })
+            .collect();
+        blocks
+            .new_valid_blocks(&valid, vote_counts, new_votes)
+            .into_iter()
+            .map(|(vote, _)| vote.to)
+            .chain(Some(self.head))
+            .collect()
+    }
+}
+
+/// Remove all votes from or to the `compacted` blocks.
+pub fn remove_votes(
+    vote_counts: &mut VoteCounts,
+    rev_vote_counts: &mut VoteCounts,
+    compacted: &BTreeSet<BlockId>,
+) -> usize {
+    let _ = remove_blocks(rev_vote_counts, compacted);
+    remove_blocks(vote_counts, compacted)
+}
+
+/// Remove all entries for the `compacted` blocks from one direction of the vote counts, returning
+/// the number of votes removed.
+fn remove_blocks(map: &mut VoteCounts, compacted: &BTreeSet<BlockId>) -> usize {
+    let mut num_removed = 0;
+    for block in compacted {
+        if let Some(inner) = map.remove(block) {
+            num_removed += inner.len();
+        }
+    }
+    for inner in map.values_mut() {
+        let before = inner.len();
+        inner.retain(|id, _| !compacted.contains(id));
+        num_removed += before - inner.len();
+    }
+    map.retain(|_, inner| !inner.is_empty());
+    num_removed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn compact_and_verify() {
+        let names: Vec<Name> = (0..6).map(|i| Name(i << 32)).collect();
+        let mut blocks = Blocks::new();
+        let ids: Vec<BlockId> = (0..5)
+            .map(|version| {
+                blocks.insert(Block {
+                    prefix: Prefix::empty(),
+                    version: version as u64,
+                    members: names[..version + 1].iter().cloned().collect(),
+                })
+            })
+            .collect();
+
+        let mut vote_counts = VoteCounts::new();
+        let mut rev_vote_counts = VoteCounts::new();
+        for pair in ids.windows(2) {
+            let voters = pair[0].into_block(&blocks).members.clone();
+            let _ = vote_counts
+                .entry(pair[0])
+                .or_insert_with(BTreeMap::new)
+                .insert(pair[1], voters.clone());
+            let _ = rev_vote_counts
+                .entry(pair[1])
+                .or_insert_with(BTreeMap::new)
+                .insert(pair[0], voters);
+        }
+
+        let (snapshot, compacted) =
+            Snapshot::compact(&blocks, &rev_vote_counts, ids[4], 2, None).unwrap();
+        assert_eq!(snapshot.head, ids[2]);
+        assert_eq!(snapshot.num_votes, 2);
+        assert_eq!(compacted, btreeset!{ids[0], ids[1]});
+
+        let full = vote_counts.clone();
+        assert_eq!(remove_votes(&mut vote_counts, &mut rev_vote_counts, &compacted), 2);
+        assert!(snapshot.verify(&blocks, &vote_counts, &full));
+
+        // Nothing more to compact until the chain grows.
+        let previous = Some(&snapshot);
+        assert!(Snapshot::compact(&blocks, &rev_vote_counts, ids[4], 2, previous).is_none());
+    }
+}
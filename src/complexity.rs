@@ -0,0 +1,186 @@
+//! Analysis of the messages it takes to agree a block, by kind of message and by section size, to
+//! quantify how the protocol scales as sections grow, e.g. with every vote broadcast to the whole
+//! section, and to compare that against gossip or aggregation variants.
+//!
+//! Each message sent by a section member counts against the size bucket of the sender's section
+//! at the time. Each block counts as agreed from the first step at which it's current at any
+//! node, against the bucket of its own size. Messages sent by nodes which aren't members of a
+//! section yet aren't counted.
+
+use block::BlockId;
+use blocks::Blocks;
+use error::{Error, Result};
+use name::Name;
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Messages sent and blocks agreed by sections within a range of sizes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BucketStats {
+    pub blocks_agreed: u64,
+    /// Number of messages sent, by kind.
+    pub messages: BTreeMap<&'static str, u64>,
+}
+
+impl BucketStats {
+    pub fn messages_sent(&self) -> u64 {
+        self.messages.values().sum()
+    }
+
+    pub fn messages_per_block(&self) -> f64 {
+        self.messages_sent() as f64 / self.blocks_agreed.max(1) as f64
+    }
+
+    /// Number of messages of the given kind sent per block agreed.
+    pub fn per_block(&self, kind: &str) -> f64 {
+        let sent = self.messages.get(kind).cloned().unwrap_or(0);
+        sent as f64 / self.blocks_agreed.max(1) as f64
+    }
+}
+
+#[derive(Clone)]
+pub struct ComplexityTracker {
+    /// Number of section sizes in each bucket.
+    bucket_width: usize,
+    /// Blocks already agreed, or current when tracking started.
+    seen: BTreeSet<BlockId>,
+    /// Stats of each bucket, by the smallest section size in it.
+    buckets: BTreeMap<usize, BucketStats>,
+}
+
+impl ComplexityTracker {
+    pub fn new(bucket_width: usize) -> Result<Self> {
+        if bucket_width == 0 {
+            return Err(Error::Config("bucket width must be at least 1".to_string()));
+        }
+        Ok(ComplexityTracker {
+            bucket_width,
+            seen: BTreeSet::new(),
+            buckets: BTreeMap::new(),
+        })
+    }
+
+    /// Don't count the blocks which are current at any node as agreed, as when tracking starts
+    /// part way through a run.
+    pub fn skip_current<N: NodeTrait>(&mut self, nodes: &BTreeMap<Name, N>) {
+        for node in nodes.values() {
+            self.seen.extend(node.current_blocks().iter().cloned());
+        }
+    }
+
+    /// Count the messages each node has sent, by kind, since the last call, and any blocks which
+    /// are current at some node for the first time.
+    pub fn observe<N: NodeTrait>(
+        &mut self,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+        sent: &BTreeMap<(Name, &'static str), u64>,
+    ) {
+        for (&(sender, kind), &count) in sent {
+            let size = match nodes.get(&sender).and_then(|node| {
+                node.our_current_blocks(blocks).first().map(|block| block.members.len())
+            }) {
+                Some(size) => size,
+                None => continue,
+            };
+            let bucket = self.bucket(size);
+            *bucket.messages.entry(kind).or_insert(0) += count;
+        }
+
+        for node in nodes.values() {
+            for id in node.current_blocks() {
+                if self.seen.insert(*id) {
+                    let size = id.into_block(blocks).members.len();
+                    self.bucket(size).blocks_agreed += 1;
+                }
+            }
+        }
+    }
+
+    fn bucket(&mut self, size: usize) -> &mut BucketStats {
+        let lowest = size / self.bucket_width * self.bucket_width;
+        self.buckets.entry(lowest).or_default()
+    }
+
+    /// Stats of each bucket, by the range of section sizes in it.
+    pub fn buckets(&self) -> Vec<((usize, usize), &BucketStats)> {
+        self.buckets
+            .iter()
+            .map(|(&lowest, stats)| ((lowest, lowest + self.bucket_width - 1), stats))
+            .collect()
+    }
+}
+
+impl fmt::Display for ComplexityTracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ((lowest, highest), stats) in self.buckets() {
+            write!(
+                f,
+                "sections of {}-{} nodes: {} blocks agreed, {:.1} messages per block (",
+                lowest,
+                highest,
+                stats.blocks_agreed,
+                stats.messages_per_block()
+            )?;
+            for (index, kind) in stats.messages.keys().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{} {:.1}", kind, stats.per_block(kind))?;
+            }
+            writeln!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use name::Prefix;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn messages_per_block_by_section_size() {
+        let mut blocks = Blocks::new();
+        let genesis = Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: (0..5).map(Name).collect(),
+        };
+        let genesis_id = blocks.insert(genesis.clone());
+        let grown_id = blocks.insert(genesis.add_node(Name(5)));
+        let mut nodes = BTreeMap::new();
+        for name in (0..5).map(Name) {
+            let node = Node::new(name, &blocks, btreeset!{genesis_id}, NodeParams::default(), 0);
+            let _ = nodes.insert(name, node);
+        }
+        assert!(ComplexityTracker::new(0).is_err());
+        let mut tracker = ComplexityTracker::new(4).unwrap();
+        tracker.skip_current(&nodes);
+
+        // Votes among the five members, and a message from a node which isn't a member yet.
+        let sent = btreemap! {
+            (Name(0), "VoteMsg") => 4,
+            (Name(1), "VoteMsg") => 4,
+            (Name(1), "Connect") => 2,
+            (Name(5), "NodeJoined") => 5,
+        };
+        tracker.observe(&blocks, &nodes, &sent);
+        assert_eq!(tracker.buckets()[0].1.blocks_agreed, 0);
+
+        let _ = nodes.get_mut(&Name(0)).unwrap().current_blocks.insert(grown_id);
+        tracker.observe(&blocks, &nodes, &BTreeMap::new());
+        let buckets = tracker.buckets();
+        assert_eq!(buckets.len(), 1);
+        let ((lowest, highest), stats) = buckets[0];
+        assert_eq!((lowest, highest), (4, 7));
+        assert_eq!((stats.blocks_agreed, stats.messages_sent()), (1, 10));
+        assert_eq!(stats.per_block("VoteMsg"), 8.0);
+        assert_eq!(stats.per_block("NodeJoined"), 0.0);
+    }
+}
@@ -0,0 +1,147 @@
+//! Tracking of conflicting blocks: several blocks which a node holds valid for the same prefix
+//! and version.
+//!
+//! A few conflicting blocks are expected while a section is under stress, but a growing number
+//! means the section is failing to converge. The worst case for each prefix is kept for the final
+//! report. A warning is logged the first time a prefix and version reach the soft threshold, and
+//! the run is aborted once any reach the hard threshold.
+
+use name::{Name, Prefix};
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Conflicting blocks held by a single node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub prefix: Prefix,
+    pub version: u64,
+    /// Number of valid blocks for the prefix and version.
+    pub count: usize,
+    pub node: Name,
+    /// Step after which the node held them.
+    pub step: u64,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Node({}) has {} valid blocks for {:?} with version {} at step {}",
+            self.node,
+            self.count,
+            self.prefix,
+            self.version,
+            self.step
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct ConflictTracker {
+    /// Number of conflicting blocks at which to log a warning.
+    warn_threshold: usize,
+    /// Number of conflicting blocks at which to abort the run.
+    abort_threshold: usize,
+    /// Worst conflict seen for each prefix.
+    worst: BTreeMap<Prefix, Conflict>,
+    /// Prefixes and versions which have been warned about.
+    warned: BTreeSet<(Prefix, u64)>,
+    aborted: Option<Conflict>,
+}
+
+impl ConflictTracker {
+    pub fn new(warn_threshold: usize, abort_threshold: usize) -> Self {
+        ConflictTracker {
+            warn_threshold,
+            abort_threshold,
+            worst: BTreeMap::new(),
+            warned: BTreeSet::new(),
+            aborted: None,
+        }
+    }
+
+    /// Record the number of valid blocks `node` holds for each prefix and version after `step`.
+    /// Returns the conflict which aborts the run, if it has just been found.
+    pub fn observe(
+        &mut self,
+        step: u64,
+        node: Name,
+        counts: &BTreeMap<(Prefix, u64), usize>,
+    ) -> Option<&Conflict> {
+        let already_aborted = self.aborted.is_some();
+        for (&(prefix, version), &count) in counts {
+            if count < 2 {
+                continue;
+            }
+            let conflict = Conflict {
+                prefix,
+                version,
+                count,
+                node,
+                step,
+            };
+            if count >= self.warn_threshold && self.warned.insert((prefix, version)) {
+                warn!("conflicting blocks: {}", conflict);
+            }
+            if count >= self.abort_threshold && self.aborted.is_none() {
+                self.aborted = Some(conflict.clone());
+            }
+            let worst = self.worst.entry(prefix).or_insert_with(|| conflict.clone());
+            if count > worst.count {
+                *worst = conflict;
+            }
+        }
+        if already_aborted {
+            None
+        } else {
+            self.aborted.as_ref()
+        }
+    }
+
+    /// The conflict which aborted the run, if any.
+    pub fn aborted(&self) -> Option<&Conflict> {
+        self.aborted.as_ref()
+    }
+
+    /// The worst conflicts of up to `max` prefixes, most conflicting blocks first.
+    pub fn worst_offenders(&self, max: usize) -> Vec<&Conflict> {
+        let mut worst: Vec<&Conflict> = self.worst.values().collect();
+        worst.sort_by_key(|conflict| Reverse(conflict.count));
+        worst.truncate(max);
+        worst
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn worst_kept_and_abort_once() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let mut tracker = ConflictTracker::new(3, 5);
+
+        let counts = btreemap!{ (p0, 1) => 1, (p1, 1) => 2 };
+        assert!(tracker.observe(0, Name(1), &counts).is_none());
+        let counts = btreemap!{ (p0, 2) => 4 };
+        assert!(tracker.observe(1, Name(2), &counts).is_none());
+        assert_eq!(tracker.warned, btreeset!{(p0, 2)});
+
+        let counts = btreemap!{ (p0, 2) => 3, (p1, 2) => 5 };
+        let aborted = tracker.observe(2, Name(3), &counts).cloned().unwrap();
+        assert_eq!((aborted.prefix, aborted.count, aborted.node), (p1, 5, Name(3)));
+        assert!(tracker.observe(3, Name(3), &counts).is_none());
+        assert_eq!(tracker.aborted(), Some(&aborted));
+
+        let worst: Vec<_> = tracker
+            .worst_offenders(5)
+            .into_iter()
+            .map(|conflict| (conflict.prefix, conflict.count))
+            .collect();
+        assert_eq!(worst, vec![(p1, 5), (p0, 4)]);
+        assert_eq!(tracker.worst_offenders(1).len(), 1);
+    }
+}
@@ -0,0 +1,379 @@
+//! A node's connections to its peers, each following a small state machine:
+//!
+//! ```text
+//! Disconnected --request--> Connecting --accept--> Connected
+//!       ^                    |      |                  |
+//!       +-disconnect/timeout-+      |                  |
+//!       +--------------disconnect---+------------------+
+//!       |                                              |
+//!       +---forgive--- Refused <-------dropped by------+
+//! ```
+//!
+//! Connecting to a peer takes both sides: we send our connection request, and the connection is
+//! up once we accept the peer's own. A request the peer doesn't answer in time is given up, and
+//! made again. With the handshake model, our request is held back until a handshake with the
+//! peer completes, and a failed handshake isn't retried until its timeout has passed. Randomness
+//! is left to the caller, so the transitions here are deterministic.
+//!
+//! There's no `Disconnecting` state between `Connected` and `Disconnected`: a disconnect is a
+//! single message which nothing acknowledges, so the connection is down as soon as we send or
+//! receive one, and there's nothing to wait for. `Refused` takes its place, for the one case where
+//! a dropped connection still needs remembering.
+
+use name::Name;
+use params::HandshakeParams;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Where we are with a connection to a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected, nor trying to connect.
+    Disconnected,
+    /// We've sent the peer our connection request, or will once our handshake completes, and are
+    /// waiting for its own request.
+    Connecting,
+    /// We've accepted the peer's connection request.
+    Connected,
+    /// The peer dropped or refused our connection, and we don't ask it again until we forgive it.
+    Refused,
+}
+
+/// Our connection to a single peer.
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub state: ConnectionState,
+    /// Whether we've sent the peer our own connection request, or will once our handshake
+    /// completes.
+    requested: bool,
+    /// Step at which we last requested the connection.
+    requested_at: u64,
+    /// Step at which our handshake with the peer completes, if one is under way.
+    handshake_due: Option<u64>,
+    /// Step at which our last handshake with the peer failed, until we retry it.
+    failed_at: Option<u64>,
+    /// Number of times we've retried connecting after a failed handshake or an unanswered
+    /// request since we were last connected.
+    pub retries: u64,
+}
+
+impl Connection {
+    fn new(state: ConnectionState) -> Self {
+        Connection {
+            state,
+            requested: false,
+            requested_at: 0,
+            handshake_due: None,
+            failed_at: None,
+            retries: 0,
+        }
+    }
+
+    /// Whether there's nothing to remember about the connection.
+    fn is_idle(&self) -> bool {
+        self.state == ConnectionState::Disconnected && self.failed_at.is_none() &&
+            self.handshake_due.is_none()
+    }
+}
+
+/// Our connections to all our peers.
+#[derive(Clone, Debug, Default)]
+pub struct Connections {
+    peers: BTreeMap<Name, Connection>,
+}
+
+impl Connections {
+    /// Start out connected to `peers`, without having requested any of the connections.
+    pub fn new(peers: BTreeSet<Name>) -> Self {
+        Connections {
+            peers: peers
+                .into_iter()
+                .map(|peer| (peer, Connection::new(ConnectionState::Connected)))
+                .collect(),
+        }
+    }
+
+    pub fn state(&self, peer: &Name) -> ConnectionState {
+        self.peers.get(peer).map_or(
+            ConnectionState::Disconnected,
+            |connection| connection.state,
+        )
+    }
+
+    pub fn get(&self, peer: &Name) -> Option<&Connection> {
+        self.peers.get(peer)
+    }
+
+    pub fn is_connected(&self, peer: &Name) -> bool {
+        self.state(peer) == ConnectionState::Connected
+    }
+
+    /// Whether we've requested a connection to `peer`.
+    pub fn is_requested(&self, peer: &Name) -> bool {
+        self.peers.get(peer).map_or(false, |connection| connection.requested)
+    }
+
+    /// Peers we're connected to.
+    pub fn connected(&self) -> BTreeSet<Name> {
+        self.peers
+            .iter()
+            .filter(|&(_, connection)| connection.state == ConnectionState::Connected)
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    pub fn num_connected(&self) -> usize {
+        self.peers
+            .values()
+            .filter(|connection| connection.state == ConnectionState::Connected)
+            .count()
+    }
+
+    /// Peers we're connected to or have requested a connection to, which count towards our
+    /// capacity.
+    pub fn held(&self) -> BTreeSet<Name> {
+        self.peers
+            .iter()
+            .filter(|&(_, connection)| {
+                connection.state == ConnectionState::Connected || connection.requested
+            })
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    /// Whether we can start connecting to `peer` at `step`: we're neither connected nor trying
+    /// to connect, it hasn't refused us, and any failed handshake with it has timed out.
+    pub fn may_connect(&self, peer: &Name, step: u64, handshake: Option<&HandshakeParams>) -> bool {
+        let connection = match self.peers.get(peer) {
+            Some(connection) => connection,
+            None => return true,
+        };
+        if connection.state != ConnectionState::Disconnected {
+            return false;
+        }
+        match (connection.failed_at, handshake) {
+            (Some(failed), Some(handshake)) => step >= failed + handshake.retry_timeout,
+            _ => true,
+        }
+    }
+
+    /// Record that we requested a connection to `peer` at `step`.
+    pub fn request(&mut self, peer: Name, step: u64) {
+        let connection = self.peers.entry(peer).or_insert_with(
+            || Connection::new(ConnectionState::Disconnected),
+        );
+        connection.requested = true;
+        connection.requested_at = step;
+        if connection.state != ConnectionState::Connected {
+            connection.state = ConnectionState::Connecting;
+        }
+    }
+
+    /// Accept a connection request from `peer`, returning whether we weren't already connected.
+    pub fn accept(&mut self, peer: Name) -> bool {
+        let connection = self.peers.entry(peer).or_insert_with(
+            || Connection::new(ConnectionState::Disconnected),
+        );
+        connection.retries = 0;
+        if connection.state == ConnectionState::Connected {
+            return false;
+        }
+        connection.state = ConnectionState::Connected;
+        true
+    }
+
+    /// Drop our connection to `peer`, or our attempt at one.
+    pub fn disconnect(&mut self, peer: &Name) {
+        self.set_disconnected(peer, ConnectionState::Disconnected);
+    }
+
+    /// Note that `peer` dropped or refused our connection. If `remember` is set, we don't ask it
+    /// again until we forgive it.
+    pub fn dropped_by(&mut self, peer: &Name, remember: bool) {
+        let state = if remember {
+            ConnectionState::Refused
+        } else {
+            ConnectionState::Disconnected
+        };
+        self.set_disconnected(peer, state);
+    }
+
+    fn set_disconnected(&mut self, peer: &Name, state: ConnectionState) {
+        let idle = match self.peers.get_mut(peer) {
+            Some(connection) => {
+                connection.state = state;
+                connection.requested = false;
+                connection.handshake_due = None;
+                connection.is_idle()
+            }
+            None if state == ConnectionState::Disconnected => false,
+            None => {
+                let _ = self.peers.insert(*peer, Connection::new(state));
+                false
+            }
+        };
+        if idle {
+            let _ = self.peers.remove(peer);
+        }
+    }
+
+    /// Let the peers which dropped or refused our connections be asked again.
+    pub fn forgive(&mut self) {
+        for connection in self.peers.values_mut() {
+            if connection.state == ConnectionState::Refused {
+                connection.state = ConnectionState::Disconnected;
+            }
+        }
+        self.peers.retain(|_, connection| !connection.is_idle());
+    }
+
+    /// Give up the connection requests which have gone unanswered for `timeout` steps by `step`,
+    /// returning the peers they were sent to, which can be asked again straight away.
+    pub fn expire_requests(&mut self, step: u64, timeout: u64) -> Vec<Name> {
+        let mut expired = vec![];
+        for (peer, connection) in &mut self.peers {
+            if connection.state == ConnectionState::Connecting &&
+                connection.requested_at + timeout <= step
+            {
+                connection.state = ConnectionState::Disconnected;
+                connection.requested = false;
+                connection.handshake_due = None;
+                connection.retries += 1;
+                expired.push(*peer);
+            }
+        }
+        expired
+    }
+
+    /// Retry connecting to `peer`, returning whether a previous handshake with it failed.
+    pub fn retry(&mut self, peer: &Name) -> bool {
+        match self.peers.get_mut(peer) {
+            Some(connection) if connection.failed_at.is_some() => {
+                connection.failed_at = None;
+                connection.retries += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record that our handshake with `peer` completes at step `due`.
+    pub fn start_handshake(&mut self, peer: Name, due: u64) {
+        if let Some(connection) = self.peers.get_mut(&peer) {
+            connection.handshake_due = Some(due);
+        }
+    }
+
+    /// Record that our handshake with `peer` failed at `step`, withdrawing our request.
+    pub fn fail_handshake(&mut self, peer: Name, step: u64) {
+        let connection = self.peers.entry(peer).or_insert_with(
+            || Connection::new(ConnectionState::Disconnected),
+        );
+        connection.requested = false;
+        connection.failed_at = Some(step);
+        if connection.state == ConnectionState::Connecting {
+            connection.state = ConnectionState::Disconnected;
+        }
+    }
+
+    /// Finish the handshakes due by `step`, returning the peers whose connection requests can now
+    /// be sent.
+    pub fn complete_handshakes(&mut self, step: u64) -> BTreeSet<Name> {
+        let mut completed = BTreeSet::new();
+        for (peer, connection) in &mut self.peers {
+            if connection.handshake_due.map_or(false, |due| due <= step) {
+                connection.handshake_due = None;
+                let _ = completed.insert(*peer);
+            }
+        }
+        completed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::ConnectionState::*;
+
+    fn handshake() -> HandshakeParams {
+        HandshakeParams {
+            max_delay: 3,
+            prob_fail: 0.5,
+            retry_timeout: 5,
+        }
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let (a, b) = (Name(1), Name(2));
+        let mut connections = Connections::new(btreeset!{a});
+        assert_eq!(connections.state(&a), Connected);
+        assert!(!connections.is_requested(&a));
+        assert!(!connections.may_connect(&a, 0, None));
+
+        connections.request(b, 0);
+        assert_eq!(connections.state(&b), Connecting);
+        assert_eq!(connections.held(), btreeset!{a, b});
+        assert!(connections.accept(b));
+        assert!(!connections.accept(b));
+        assert_eq!(connections.connected(), btreeset!{a, b});
+
+        connections.disconnect(&a);
+        connections.dropped_by(&b, true);
+        assert_eq!(connections.state(&a), Disconnected);
+        assert_eq!(connections.state(&b), Refused);
+        assert!(connections.may_connect(&a, 0, None));
+        assert!(!connections.may_connect(&b, 0, None));
+        assert_eq!(connections.num_connected(), 0);
+
+        connections.forgive();
+        assert!(connections.may_connect(&b, 0, None));
+        assert!(connections.peers.is_empty());
+    }
+
+    #[test]
+    fn failed_handshakes_retried_after_timeout() {
+        let (a, b) = (Name(1), Name(2));
+        let handshake = handshake();
+        let mut connections = Connections::default();
+
+        connections.request(a, 0);
+        connections.start_handshake(a, 3);
+        connections.request(b, 0);
+        connections.fail_handshake(b, 1);
+        assert_eq!(connections.state(&b), Disconnected);
+        assert!(!connections.is_requested(&b));
+        assert!(!connections.may_connect(&b, 5, Some(&handshake)));
+        assert!(connections.may_connect(&b, 6, Some(&handshake)));
+
+        assert!(connections.complete_handshakes(2).is_empty());
+        assert_eq!(connections.complete_handshakes(3), btreeset!{a});
+        assert!(connections.complete_handshakes(4).is_empty());
+
+        assert!(connections.retry(&b));
+        assert!(!connections.retry(&b));
+        connections.request(b, 6);
+        assert_eq!(connections.state(&b), Connecting);
+        assert_eq!(connections.get(&b).unwrap().retries, 1);
+        // The retry count only runs until we're connected again.
+        assert!(connections.accept(b));
+        assert_eq!(connections.get(&b).unwrap().retries, 0);
+    }
+
+    #[test]
+    fn unanswered_requests_time_out() {
+        let (a, b) = (Name(1), Name(2));
+        let mut connections = Connections::new(btreeset!{a});
+        connections.request(a, 0);
+        connections.request(b, 2);
+
+        assert!(connections.expire_requests(11, 10).is_empty());
+        // Only requests still waiting for an answer time out.
+        assert_eq!(connections.expire_requests(12, 10), vec![b]);
+        assert_eq!(connections.state(&a), Connected);
+        assert_eq!(connections.state(&b), Disconnected);
+        assert!(!connections.held().contains(&b));
+        assert!(connections.may_connect(&b, 12, None));
+        assert_eq!(connections.get(&b).unwrap().retries, 1);
+    }
+}
@@ -0,0 +1,250 @@
+//! Metrics of the connection graph between live nodes, to tell when it has silently fragmented.
+//!
+//! Two nodes are linked if each of them is connected to the other. The metrics are:
+//!
+//! * the number of connected components, and the size of the largest
+//! * an estimate of the largest component's diameter, in hops, by a double sweep: a
+//!   breadth-first search from its first node, then another from the farthest node that reached.
+//!   This is a lower bound, and exact for trees
+//! * articulation points among section members: members whose loss would split the component
+//!   they're in
+
+use blocks::Blocks;
+use name::Name;
+use node::NodeTrait;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnectivityMetrics {
+    /// Number of connected components.
+    pub components: usize,
+    /// Number of nodes in the largest component.
+    pub largest_component: usize,
+    /// Lower bound on the diameter of the largest component, in hops.
+    pub diameter_estimate: usize,
+    /// Section members whose loss would split the component they're in.
+    pub articulation_points: BTreeSet<Name>,
+}
+
+impl ConnectivityMetrics {
+    /// The metrics of the graph of connections between `nodes`, with the members of the blocks
+    /// current at any node as section members.
+    pub fn of<N: NodeTrait>(nodes: &BTreeMap<Name, N>, blocks: &Blocks) -> Self {
+        let mut links: BTreeMap<Name, BTreeSet<Name>> =
+            nodes.keys().map(|name| (*name, BTreeSet::new())).collect();
+        let names: Vec<Name> = nodes.keys().cloned().collect();
+        for (i, name) in names.iter().enumerate() {
+            for peer in &names[i + 1..] {
+                if !nodes[name].is_disconnected_from(peer) &&
+                    !nodes[peer].is_disconnected_from(name)
+                {
+                    let _ = links.entry(*name).or_default().insert(*peer);
+                    let _ = links.entry(*peer).or_default().insert(*name);
+                }
+            }
+        }
+        let members: BTreeSet<Name> = nodes
+            .values()
+            .flat_map(|node| blocks.block_contents(node.current_blocks()))
+            .flat_map(|block| block.members.iter().cloned())
+            .collect();
+        Self::of_links(&links, &members)
+    }
+
+    /// The metrics of the undirected graph given by each node's set of neighbours.
+    pub fn of_links(links: &BTreeMap<Name, BTreeSet<Name>>, members: &BTreeSet<Name>) -> Self {
+        let graph = Graph::new(links);
+        let components = graph.components();
+        let largest = components.iter().max_by_key(|component| component.len());
+        let diameter_estimate = largest.map_or(0, |component| {
+            let (farthest, _) = graph.farthest_from(component[0]);
+            graph.farthest_from(farthest).1
+        });
+        let articulation_points = graph
+            .articulation_points()
+            .into_iter()
+            .map(|index| graph.names[index])
+            .filter(|name| members.contains(name))
+            .collect();
+        ConnectivityMetrics {
+            components: components.len(),
+            largest_component: largest.map_or(0, Vec::len),
+            diameter_estimate,
+            articulation_points,
+        }
+    }
+
+    /// Whether every node can reach every other.
+    pub fn is_connected(&self) -> bool {
+        self.components <= 1
+    }
+}
+
+/// The graph with nodes numbered in name order.
+struct Graph {
+    names: Vec<Name>,
+    adjacent: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn new(links: &BTreeMap<Name, BTreeSet<Name>>) -> Self {
+        let names: Vec<Name> = links.keys().cloned().collect();
+        let index: BTreeMap<Name, usize> =
+            names.iter().enumerate().map(|(i, name)| (*name, i)).collect();
+        let adjacent = links
+            .values()
+            .map(|peers| peers.iter().filter_map(|peer| index.get(peer).cloned()).collect())
+            .collect();
+        Graph { names, adjacent }
+    }
+
+    /// Distances in hops from `start` to every node, `None` for those it can't reach.
+    fn distances_from(&self, start: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.names.len()];
+        distances[start] = Some(0);
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(node) = queue.pop_front() {
+            let next = distances[node].map(|distance| distance + 1);
+            for &peer in &self.adjacent[node] {
+                if distances[peer].is_none() {
+                    distances[peer] = next;
+                    queue.push_back(peer);
+                }
+            }
+        }
+        distances
+    }
+
+    /// The node farthest from `start` which it can reach, and how far it is.
+    fn farthest_from(&self, start: usize) -> (usize, usize) {
+        self.distances_from(start)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(node, distance)| distance.map(|distance| (node, distance)))
+            .max_by_key(|&(node, distance)| (distance, cmp::Reverse(node)))
+            .unwrap_or((start, 0))
+    }
+
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.names.len()];
+        let mut components = vec![];
+        for start in 0..self.names.len() {
+            if seen[start] {
+                continue;
+            }
+            let component: Vec<usize> = self.distances_from(start)
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, distance)| distance.is_some())
+                .map(|(node, _)| node)
+                .collect();
+            for &node in &component {
+                seen[node] = true;
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    /// Nodes whose removal disconnects their component, by Tarjan's low-link method.
+    fn articulation_points(&self) -> BTreeSet<usize> {
+        let mut search = LowLink {
+            graph: self,
+            order: vec![None; self.names.len()],
+            low: vec![0; self.names.len()],
+            next: 0,
+            points: BTreeSet::new(),
+        };
+        for root in 0..self.names.len() {
+            if search.order[root].is_none() {
+                search.visit(root, None);
+            }
+        }
+        search.points
+    }
+}
+
+/// State of a depth-first search for articulation points.
+struct LowLink<'a> {
+    graph: &'a Graph,
+    /// Order in which each node was first visited.
+    order: Vec<Option<usize>>,
+    /// Earliest visited node reachable from each node's subtree by at most one back edge.
+    low: Vec<usize>,
+    next: usize,
+    points: BTreeSet<usize>,
+}
+
+impl<'a> LowLink<'a> {
+    fn visit(&mut self, node: usize, parent: Option<usize>) {
+        let order = self.next;
+        self.next += 1;
+        self.order[node] = Some(order);
+        self.low[node] = order;
+        let mut children = 0;
+        for &peer in &self.graph.adjacent[node] {
+            match self.order[peer] {
+                None => {
+                    children += 1;
+                    self.visit(peer, Some(node));
+                    self.low[node] = cmp::min(self.low[node], self.low[peer]);
+                    if parent.is_some() && self.low[peer] >= order {
+                        let _ = self.points.insert(node);
+                    }
+                }
+                Some(peer_order) if Some(peer) != parent => {
+                    self.low[node] = cmp::min(self.low[node], peer_order);
+                }
+                Some(_) => (),
+            }
+        }
+        if parent.is_none() && children > 1 {
+            let _ = self.points.insert(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn links(names: &[Name], edges: &[(usize, usize)]) -> BTreeMap<Name, BTreeSet<Name>> {
+        let mut links: BTreeMap<Name, BTreeSet<Name>> =
+            names.iter().map(|name| (*name, BTreeSet::new())).collect();
+        for &(a, b) in edges {
+            let _ = links.get_mut(&names[a]).unwrap().insert(names[b]);
+            let _ = links.get_mut(&names[b]).unwrap().insert(names[a]);
+        }
+        links
+    }
+
+    #[test]
+    fn path_and_triangle() {
+        let names: Vec<Name> = (0..7).map(|i| Name(i << 56)).collect();
+        let members = names.iter().cloned().collect();
+        // A path 0-1-2-3 and, apart from it, a triangle 4-5-6.
+        let metrics = ConnectivityMetrics::of_links(
+            &links(&names, &[(0, 1), (1, 2), (2, 3), (4, 5), (5, 6), (6, 4)]),
+            &members,
+        );
+        assert_eq!(metrics.components, 2);
+        assert!(!metrics.is_connected());
+        assert_eq!(metrics.largest_component, 4);
+        assert_eq!(metrics.diameter_estimate, 3);
+        assert_eq!(metrics.articulation_points, btreeset!{names[1], names[2]});
+
+        // Joining them through 3-4 makes 3 and 4 cut points too, and only counts members.
+        let members = names[..4].iter().cloned().collect();
+        let metrics = ConnectivityMetrics::of_links(
+            &links(&names, &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6), (6, 4)]),
+            &members,
+        );
+        assert!(metrics.is_connected());
+        assert_eq!(metrics.largest_component, 7);
+        assert_eq!(metrics.diameter_estimate, 5);
+        assert_eq!(metrics.articulation_points, btreeset!{names[1], names[2], names[3]});
+    }
+}
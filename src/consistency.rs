@@ -1,75 +1,139 @@
 use name::{Name, Prefix};
-use node::Node;
+use node::NodeTrait;
 use blocks::Blocks;
 use block::Block;
-use std::collections::{BTreeMap, BTreeSet};
-use itertools::Itertools;
+use std::collections::BTreeMap;
+use coverage::{check_coverage, CoverageViolation};
+use error::{Error, Result};
+use params::quorum;
+use random::seed;
+use routing::RoutingTable;
 
 /// Check that all the nodes have a consistent view of the network.
-pub fn check_consistency(
+pub fn check_consistency<N: NodeTrait>(
     blocks: &Blocks,
-    nodes: &BTreeMap<Name, Node>,
+    nodes: &BTreeMap<Name, N>,
     min_section_size: usize,
-) -> Result<BTreeMap<Prefix, Block>, ()> {
+    max_section_size: Option<usize>,
+) -> Result<BTreeMap<Prefix, Block>> {
     let mut sections = btreemap!{};
     let mut result = btreemap!{};
-    let mut failed = false;
+    let mut problems = vec![];
 
+    // Each version of a section's block, with the first node holding it.
     for node in nodes.values() {
-        for block in blocks.block_contents(&node.current_blocks) {
-            let section_versions = sections.entry(block.prefix).or_insert_with(BTreeSet::new);
-            section_versions.insert(block.clone());
+        for block in blocks.block_contents(node.current_blocks()) {
+            let section_versions = sections.entry(block.prefix).or_insert_with(BTreeMap::new);
+            section_versions.entry(block.clone()).or_insert_with(|| node.name());
         }
     }
 
     let num_sections = sections.len();
 
-    for (prefix, blocks) in sections {
-        if blocks.len() > 1 {
-            failed = true;
-            error!("multiple versions of {:?}, they are: {:#?}", prefix, blocks);
+    for (prefix, versions) in sections {
+        if versions.len() > 1 {
+            let holders: Vec<_> = versions.values().take(2).collect();
+            let tables: Vec<_> = holders
+                .iter()
+                .map(|name| RoutingTable::of(&nodes[*name], blocks))
+                .collect();
+            error!(
+                "multiple versions of {:?}, {} of them; {:?} and {:?} differ in {}",
+                prefix,
+                versions.len(),
+                holders[0],
+                holders[1],
+                tables[0].diff(&tables[1])
+            );
+            problems.push(format!("multiple versions of {:?}", prefix));
             continue;
         }
 
-        let block = blocks.into_iter().next().unwrap();
+        let block = versions.into_iter().next().unwrap().0;
 
         // Allow any size if we have only one section, otherwise require `min_section_size`.
         if num_sections > 1 && block.members.len() < min_section_size {
-            failed = true;
             error!(
                 "section too small: {:?} with members {:?}",
                 prefix,
                 block.members
             );
+            problems.push(format!("section too small: {:?}", prefix));
+        }
+        if max_section_size.is_some_and(|max| block.members.len() > max) {
+            error!(
+                "section too large: {:?} with members {:?}",
+                prefix,
+                block.members
+            );
+            problems.push(format!("section too large: {:?}", prefix));
         }
 
         // Check that all members are alive.
         for member in &block.members {
             if !nodes.contains_key(member) {
-                failed = true;
-                error!(
+                let problem = format!(
                     "node {:?} is dead but appears in the block for {:?}",
                     member,
                     prefix
                 );
+                error!("{}", problem);
+                problems.push(problem);
             }
         }
 
         result.insert(prefix, block);
     }
 
-    for (p1, p2) in result.keys().tuple_combinations() {
-        if p1.is_compatible(p2) {
-            failed = true;
-            error!("prefixes {:?} and {:?} overlap", p1, p2);
-        }
+    let prefixes = result.keys().cloned().collect();
+    for violation in check_coverage(&prefixes) {
+        let problem = match violation {
+            CoverageViolation::Gap(prefixes) => {
+                format!("prefixes {:?} don't cover the whole namespace", prefixes)
+            }
+            CoverageViolation::Overlap(p1, p2) => format!("prefixes {:?} and {:?} overlap", p1, p2),
+        };
+        error!("{}", problem);
+        problems.push(problem);
     }
 
-    if failed {
+    if !problems.is_empty() {
         error!("network not consistent: see above");
-        Err(())
+        Err(Error::InvariantViolation {
+            seed: seed(),
+            description: format!("network not consistent: {}", problems.join("; ")),
+        })
     } else {
         info!("network is consistent!");
         Ok(result)
     }
 }
+
+/// Members of each section which aren't connected to enough of the other members for their votes
+/// to reach a quorum, as when nodes can only hold a limited number of connections.
+pub fn unreachable_quorums<N: NodeTrait>(
+    sections: &BTreeMap<Prefix, Block>,
+    nodes: &BTreeMap<Name, N>,
+) -> Vec<(Prefix, Name)> {
+    let mut unreachable = vec![];
+    for (prefix, block) in sections {
+        let needed = quorum(block.members.len());
+        for member in &block.members {
+            let node = match nodes.get(member) {
+                Some(node) => node,
+                None => continue,
+            };
+            // Our own vote counts towards the quorum.
+            let reachable = 1 +
+                block
+                    .members
+                    .iter()
+                    .filter(|peer| *peer != member && !node.is_disconnected_from(peer))
+                    .count();
+            if reachable < needed {
+                unreachable.push((*prefix, *member));
+            }
+        }
+    }
+    unreachable
+}
@@ -0,0 +1,98 @@
+//! Checks that sections' prefixes cover the whole namespace exactly once.
+
+use blocks::Blocks;
+use name::{Name, Prefix};
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+use itertools::Itertools;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CoverageViolation {
+    /// Some part of the namespace isn't covered by any of these prefixes.
+    Gap(BTreeSet<Prefix>),
+    /// Two distinct prefixes cover some of the same names.
+    Overlap(Prefix, Prefix),
+}
+
+/// Check that `prefixes` cover the namespace without any overlaps.
+pub fn check_coverage(prefixes: &BTreeSet<Prefix>) -> Vec<CoverageViolation> {
+    let mut violations = vec![];
+    if !Prefix::empty().is_covered_by(prefixes) {
+        violations.push(CoverageViolation::Gap(prefixes.clone()));
+    }
+    for (p1, p2) in prefixes.iter().tuple_combinations() {
+        if p1.is_compatible(p2) {
+            violations.push(CoverageViolation::Overlap(*p1, *p2));
+        }
+    }
+    violations
+}
+
+/// Checks the prefixes of the sections that nodes believe they belong to after every step.
+///
+/// While splits and merges are in progress nodes legitimately disagree, so violations are only
+/// recorded once the network has converged, i.e. when no messages are in flight. Violations while
+/// messages are in flight are just counted.
+#[derive(Clone, Default)]
+pub struct CoverageChecker {
+    /// Violations found in converged states, with the step they were found at.
+    pub violations: Vec<(u64, CoverageViolation)>,
+    /// Number of steps with a violation while the network hadn't converged.
+    pub transient_steps: u64,
+}
+
+impl CoverageChecker {
+    pub fn check_step<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+        converged: bool,
+    ) {
+        // Inserted one at a time, as collecting them sorts them by `Prefix`'s partial order,
+        // which disagrees with its total order exactly when sections overlap.
+        let mut prefixes = BTreeSet::new();
+        for block in nodes.values().flat_map(|node| node.our_current_blocks(blocks)) {
+            let _ = prefixes.insert(block.prefix);
+        }
+        if prefixes.is_empty() {
+            return;
+        }
+
+        let violations = check_coverage(&prefixes);
+        if violations.is_empty() {
+            return;
+        }
+        if !converged {
+            self.transient_steps += 1;
+            return;
+        }
+        for violation in violations {
+            warn!("step {}: namespace coverage violated: {:?}", step, violation);
+            self.violations.push((step, violation));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn coverage_gaps_and_overlaps() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let p10 = Prefix::short(2, 0b10000000);
+
+        assert!(check_coverage(&btreeset!{ p0, p1 }).is_empty());
+        assert_eq!(
+            check_coverage(&btreeset!{ p0, p10 }),
+            vec![CoverageViolation::Gap(btreeset!{ p0, p10 })]
+        );
+        assert_eq!(
+            check_coverage(&btreeset!{ p0, p1, p10 }),
+            vec![CoverageViolation::Overlap(p1, p10)]
+        );
+    }
+}
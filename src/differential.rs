@@ -0,0 +1,145 @@
+//! Differential testing of two node implementations.
+//!
+//! Both implementations are run from the same seed, initial sections, event schedule and
+//! parameters, in lock-step: each run steps in turn with its own copy of the random number
+//! generators, and the blocks agreed by both are compared after every step. Events, message delays
+//! and names are drawn from separate random streams (see `random::in_stream`), so both runs see the
+//! same random events throughout. Once the implementations behave differently (e.g. send a
+//! different number of messages), the two runs draw different message delays from then on, and
+//! nodes' own random choices can differ too. The first divergence is still meaningful.
+
+use block::{Block, BlockId};
+use blocks::Blocks;
+use event_schedule::EventSchedule;
+use name::Prefix;
+use node::NodeTrait;
+use params::{NodeParams, SimulationParams};
+use random::{reseed, restore_rng, rng_state, RngState};
+use simulation::Simulation;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The first step at which the two implementations' agreed blocks differed.
+///
+/// If one run had already finished by then, its final blocks are compared with the other's.
+#[derive(Debug)]
+pub struct Divergence {
+    pub step: u64,
+    /// Whether each run had already finished by this step while the other carried on.
+    pub a_finished: bool,
+    pub b_finished: bool,
+    /// Blocks agreed by the first implementation but not the second.
+    pub only_in_a: BTreeSet<Block>,
+    /// Blocks agreed by the second implementation but not the first.
+    pub only_in_b: BTreeSet<Block>,
+}
+
+#[derive(Debug)]
+pub struct DifferentialResult {
+    /// Whether each run finished in a consistent state.
+    pub a_consistent: bool,
+    pub b_consistent: bool,
+    /// Number of steps each run took.
+    pub a_steps: u64,
+    pub b_steps: u64,
+    /// The first step where the runs disagreed, or `None` if they never did.
+    pub first_divergence: Option<Divergence>,
+}
+
+/// Run node implementations `A` and `B` against the same scenario and compare them step by step.
+pub fn run_differential<A: NodeTrait, B: NodeTrait>(
+    seed: [u32; 4],
+    sections: &BTreeMap<Prefix, usize>,
+    event_schedule: &EventSchedule,
+    params: &SimulationParams,
+    node_params: &NodeParams,
+) -> DifferentialResult {
+    let (mut sim_a, mut rng_a) = start::<A>(seed, sections, event_schedule, params, node_params);
+    let (mut sim_b, mut rng_b) = start::<B>(seed, sections, event_schedule, params, node_params);
+
+    let mut first_divergence = None;
+    let (mut a_finished, mut b_finished) = (false, false);
+    let (mut a_steps, mut b_steps) = (0, 0);
+    while !a_finished || !b_finished {
+        let step = cmp::max(a_steps, b_steps);
+        a_finished = a_finished || !step_with(&mut sim_a, &mut rng_a);
+        b_finished = b_finished || !step_with(&mut sim_b, &mut rng_b);
+        a_steps = sim_a.agreed_history().map_or(0, <[_]>::len) as u64;
+        b_steps = sim_b.agreed_history().map_or(0, <[_]>::len) as u64;
+        if first_divergence.is_some() || (a_finished && b_finished) {
+            continue;
+        }
+
+        let empty = BTreeSet::new();
+        let a = sim_a.agreed_history().and_then(<[_]>::last).unwrap_or(&empty);
+        let b = sim_b.agreed_history().and_then(<[_]>::last).unwrap_or(&empty);
+        if a != b || a_finished != b_finished {
+            let divergence = Divergence {
+                step,
+                a_finished,
+                b_finished,
+                only_in_a: contents(sim_a.blocks(), a.difference(b)),
+                only_in_b: contents(sim_b.blocks(), b.difference(a)),
+            };
+            warn!("Implementations diverged at step {}: {:#?}", step, divergence);
+            first_divergence = Some(divergence);
+        }
+    }
+
+    // Both runs have finished stepping, so this only checks their final states.
+    restore_rng(&rng_a);
+    let a_consistent = sim_a.run().is_ok();
+    restore_rng(&rng_b);
+    let b_consistent = sim_b.run().is_ok();
+
+    DifferentialResult {
+        a_consistent,
+        b_consistent,
+        a_steps,
+        b_steps,
+        first_divergence,
+    }
+}
+
+/// Create a simulation recording its agreed blocks, along with the random number generators it
+/// starts from.
+fn start<N: NodeTrait>(
+    seed: [u32; 4],
+    sections: &BTreeMap<Prefix, usize>,
+    event_schedule: &EventSchedule,
+    params: &SimulationParams,
+    node_params: &NodeParams,
+) -> (Simulation<N>, RngState) {
+    reseed(seed);
+    let mut simulation = Simulation::<N>::from_sections(
+        sections.clone(),
+        event_schedule.clone(),
+        params.clone(),
+        node_params.clone(),
+    );
+    simulation.record_agreed_blocks();
+    (simulation, rng_state())
+}
+
+/// Run a single step of `simulation` with its own random number generators, returning false if it
+/// has finished.
+fn step_with<N: NodeTrait>(simulation: &mut Simulation<N>, rng: &mut RngState) -> bool {
+    restore_rng(rng);
+    let stepped = simulation.steps().next().is_some();
+    *rng = rng_state();
+    stepped
+}
+
+fn contents<'a, I>(blocks: &Blocks, ids: I) -> BTreeSet<Block>
+where
+    I: IntoIterator<Item = &'a BlockId>,
+{
+    // Inserted one at a time, as collecting them sorts them by `Block`'s derived partial order,
+    // which goes through `Prefix`'s and so disagrees with the total order across a split.
+    let mut contents = BTreeSet::new();
+    for block in blocks.block_contents(ids) {
+        let _ = contents.insert(block.clone());
+    }
+    contents
+}
@@ -0,0 +1,192 @@
+//! Detection of nodes whose view of the network stays different from the rest of their section's,
+//! and optionally their quarantine: having the rest of the section vote them out, as a real
+//! network would treat stuck nodes.
+//!
+//! Each node's section is the prefix it believes it belongs to which the most nodes agree on, so
+//! that a node stuck on a prefix from before a split or merge still counts as part of the section
+//! that took its place. The section's consensus is the routing table held by most of its members.
+//! Tables differ for a few steps whenever a block is agreed, so a node is only marked divergent
+//! once its table has differed from the consensus for more than a given number of steps.
+
+use blocks::Blocks;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use routing::RoutingTable;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DivergenceReport {
+    /// Nodes marked divergent, with the step they were marked at.
+    pub divergent: BTreeMap<Name, u64>,
+    /// Number of quarantined nodes the rest of their section has since voted out.
+    pub voted_out: usize,
+}
+
+#[derive(Clone)]
+pub struct DivergenceMonitor {
+    /// Number of steps a node's table may differ from its section's consensus for.
+    max_steps: u64,
+    /// Whether divergent nodes are quarantined.
+    vote_out: bool,
+    /// Step since which each node's table has differed from the consensus.
+    differing_since: BTreeMap<Name, u64>,
+    /// Quarantined nodes which are still members of a section.
+    quarantined: BTreeSet<Name>,
+    report: DivergenceReport,
+}
+
+impl DivergenceMonitor {
+    pub fn new(max_steps: u64, vote_out: bool) -> Self {
+        DivergenceMonitor {
+            max_steps,
+            vote_out,
+            differing_since: BTreeMap::new(),
+            quarantined: BTreeSet::new(),
+            report: DivergenceReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &DivergenceReport {
+        &self.report
+    }
+
+    /// Compare every node's table with its section's consensus after `step`. Returns the nodes
+    /// to quarantine, if any have just been marked divergent and quarantine is enabled.
+    pub fn observe<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> Vec<Name> {
+        let mut views = BTreeMap::new();
+        for (name, node) in nodes {
+            if let Some(block) = node.our_current_blocks(blocks).first() {
+                let _ = views.insert(*name, (block.prefix, RoutingTable::of(node, blocks)));
+            }
+        }
+        self.update(step, &views)
+    }
+
+    fn update(&mut self, step: u64, views: &BTreeMap<Name, (Prefix, RoutingTable)>) -> Vec<Name> {
+        let mut section_sizes: BTreeMap<Prefix, usize> = BTreeMap::new();
+        for &(prefix, _) in views.values() {
+            *section_sizes.entry(prefix).or_insert(0) += 1;
+        }
+        let section_of = |name: &Name| {
+            section_sizes
+                .iter()
+                .filter(|&(prefix, _)| prefix.matches(*name))
+                .max_by_key(|&(_, size)| *size)
+                .map(|(prefix, _)| *prefix)
+        };
+        let mut consensus = BTreeMap::new();
+        for &prefix in section_sizes.keys() {
+            let members: Vec<&RoutingTable> = views
+                .values()
+                .filter(|&&(own, _)| own == prefix)
+                .map(|(_, table)| table)
+                .collect();
+            let most_held = members
+                .iter()
+                .max_by_key(|table| members.iter().filter(|other| other == table).count());
+            if let Some(table) = most_held {
+                let _ = consensus.insert(prefix, *table);
+            }
+        }
+
+        let mut differing_since = BTreeMap::new();
+        let mut to_quarantine = vec![];
+        for (name, (_, table)) in views {
+            let differs = section_of(name)
+                .and_then(|section| consensus.get(&section))
+                .is_some_and(|consensus| consensus.latest() != table.latest());
+            if !differs {
+                continue;
+            }
+            let since = self.differing_since.get(name).cloned().unwrap_or(step);
+            let _ = differing_since.insert(*name, since);
+            if step - since > self.max_steps && !self.report.divergent.contains_key(name) {
+                warn!(
+                    "Node({}) has differed from its section's view since step {}: {}",
+                    name,
+                    since,
+                    table.diff(consensus[&section_of(name).unwrap()])
+                );
+                let _ = self.report.divergent.insert(*name, step);
+                if self.vote_out {
+                    let _ = self.quarantined.insert(*name);
+                    to_quarantine.push(*name);
+                }
+            }
+        }
+        self.differing_since = differing_since;
+
+        // A quarantined node is out once no section's consensus lists it as a member.
+        let voted_out: Vec<Name> = self.quarantined
+            .iter()
+            .filter(|name| {
+                !consensus.values().any(|table| {
+                    table.latest().values().any(|block| block.members.contains(name))
+                })
+            })
+            .cloned()
+            .collect();
+        for name in voted_out {
+            debug!("Node({}) voted out of its section after quarantine", name);
+            let _ = self.quarantined.remove(&name);
+            self.report.voted_out += 1;
+        }
+        to_quarantine
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+
+    #[test]
+    fn stuck_node_marked_and_quarantined() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let names: Vec<_> = (0..4).map(|i| p0.substituted_in(Name(i))).collect();
+        let mut blocks = Blocks::new();
+        let block = |members: &[Name]| Block {
+            prefix: p0,
+            version: members.len() as u64,
+            members: members.iter().cloned().collect(),
+        };
+        let other = blocks.insert(Block {
+            prefix: p1,
+            version: 0,
+            members: btreeset!{p1.substituted_in(Name(9))},
+        });
+        let old = blocks.insert(block(&names[..3]));
+        let new = blocks.insert(block(&names));
+        let without_stuck = blocks.insert(block(&names[1..]));
+        let table = |current| RoutingTable::new(&blocks, &btreeset!{current, other}, &btreeset!{});
+
+        // Node 0 never learns that node 3 joined.
+        let mut views = BTreeMap::new();
+        for (i, name) in names.iter().enumerate() {
+            let current = if i == 0 { old } else { new };
+            let _ = views.insert(*name, (p0, table(current)));
+        }
+        let mut monitor = DivergenceMonitor::new(2, true);
+        for step in 0..3 {
+            assert!(monitor.update(step, &views).is_empty());
+        }
+        assert_eq!(monitor.update(3, &views), vec![names[0]]);
+        assert_eq!(monitor.report().divergent, btreemap!{ names[0] => 3 });
+        assert!(monitor.update(4, &views).is_empty());
+
+        // The rest of the section votes it out.
+        let _ = views.remove(&names[0]);
+        for name in &names[1..] {
+            let _ = views.insert(*name, (p0, table(without_stuck)));
+        }
+        assert!(monitor.update(5, &views).is_empty());
+        assert_eq!(monitor.report().voted_out, 1);
+    }
+}
@@ -0,0 +1,87 @@
+//! Measurement of votes to remove section members, and how many of them were against members
+//! which were still running, e.g. because they were only briefly disconnected.
+
+use block::Vote;
+use blocks::Blocks;
+use message::Message;
+use name::Name;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, Default)]
+pub struct DropTracker {
+    /// Removal votes seen so far.
+    votes: BTreeSet<Vote>,
+    /// Number of distinct votes to remove a member.
+    pub drops_proposed: u64,
+    /// Number of those votes against members which were still running.
+    pub false_drops: u64,
+}
+
+impl DropTracker {
+    /// Record the votes to remove members among `messages`, given the nodes still running.
+    pub fn observe<N>(
+        &mut self,
+        blocks: &Blocks,
+        messages: &[Message],
+        nodes: &BTreeMap<Name, N>,
+    ) {
+        for vote in messages.iter().flat_map(|message| message.content.plain_votes()) {
+            let from = vote.from.into_block(blocks);
+            let to = vote.to.into_block(blocks);
+            if from.prefix != to.prefix || !to.members.is_subset(&from.members) ||
+                to.members.len() == from.members.len() || !self.votes.insert(vote.clone())
+            {
+                continue;
+            }
+            for removed in from.members.difference(&to.members) {
+                self.drops_proposed += 1;
+                if nodes.contains_key(removed) {
+                    self.false_drops += 1;
+                }
+            }
+        }
+    }
+
+    /// Fraction of removal votes against members which were still running.
+    pub fn false_drop_rate(&self) -> f64 {
+        self.false_drops as f64 / self.drops_proposed.max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use message::MessageContent::VoteMsg;
+    use name::Prefix;
+
+    #[test]
+    fn counts_removals_of_running_nodes() {
+        let mut blocks = Blocks::new();
+        let block = Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: (0..4).map(Name).collect(),
+        };
+        let from = blocks.insert(block.clone());
+        let lost = blocks.insert(block.remove_node(Name(2)));
+        let disconnected = blocks.insert(block.remove_node(Name(3)));
+        let added = blocks.insert(block.add_node(Name(4)));
+        let vote_msg = |sender: u64, to| Message {
+            sender: Name(sender),
+            recipient: Name(1),
+            content: VoteMsg(Vote { from, to }),
+        };
+        // Node 2 has gone, node 3 is still running.
+        let nodes = btreemap!{ Name(0) => (), Name(1) => (), Name(3) => () };
+
+        let mut tracker = DropTracker::default();
+        let messages = [vote_msg(0, lost), vote_msg(0, disconnected), vote_msg(0, added)];
+        tracker.observe(&blocks, &messages, &nodes);
+        // The same votes from another member aren't counted again.
+        tracker.observe(&blocks, &[vote_msg(1, lost), vote_msg(1, disconnected)], &nodes);
+        assert_eq!((tracker.drops_proposed, tracker.false_drops), (2, 1));
+        assert_eq!(tracker.false_drop_rate(), 0.5);
+    }
+}
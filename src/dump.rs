@@ -0,0 +1,321 @@
+//! Dumps of the network's state when an invariant first fails.
+//!
+//! The dump is a bundle directory holding a description of the failure, the full state of every
+//! node in the affected sections, and the most recent messages sent to or from those nodes, so
+//! that a failure can be analysed without re-running with trace logging.
+//!
+//! If the nodes still disagree once a failed run has finished, a separate non-convergence bundle
+//! is written too: besides the state of the nodes under the disputed prefixes, it holds each of
+//! their chains for those prefixes in the chain format, the messages still in flight, and the
+//! events which were still to occur.
+
+use blocks::{Blocks, ValidBlocks};
+use chain::Chain;
+use message::Message;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use routing::{RoutingTable, disputed_prefixes};
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub struct FailureDump {
+    /// Directory that the bundle is created in.
+    dir: PathBuf,
+    /// Number of recent messages to keep for each node.
+    max_messages: usize,
+    /// Messages most recently delivered to or from each node, with the step of delivery.
+    recent: BTreeMap<Name, VecDeque<(u64, Message)>>,
+    /// The bundle, once written.
+    written: Option<PathBuf>,
+    /// The non-convergence bundle, once written.
+    non_convergence: Option<PathBuf>,
+}
+
+impl FailureDump {
+    pub fn new(dir: PathBuf, max_messages: usize) -> Self {
+        FailureDump {
+            dir,
+            max_messages,
+            recent: BTreeMap::new(),
+            written: None,
+            non_convergence: None,
+        }
+    }
+
+    /// The bundle directory, if a failure has been dumped.
+    pub fn path(&self) -> Option<&Path> {
+        self.written.as_deref()
+    }
+
+    /// The non-convergence bundle directory, if one has been written.
+    pub fn non_convergence_path(&self) -> Option<&Path> {
+        self.non_convergence.as_deref()
+    }
+
+    /// Remember messages delivered at `step`.
+    pub fn record_delivered(&mut self, step: u64, messages: &[Message]) {
+        for message in messages {
+            for name in &[message.sender, message.recipient] {
+                let recent = self.recent.entry(*name).or_default();
+                if recent.len() == self.max_messages {
+                    let _ = recent.pop_front();
+                }
+                recent.push_back((step, message.clone()));
+            }
+        }
+    }
+
+    /// Write a bundle describing a failure at `step` involving the sections with the `affected`
+    /// prefixes. Only the first failure is dumped; later calls do nothing.
+    pub fn dump<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        description: &str,
+        affected: &[Prefix],
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> io::Result<()> {
+        if self.written.is_some() {
+            return Ok(());
+        }
+        let bundle = self.dir.join(format!("failure-step-{}", step));
+        fs::create_dir_all(&bundle)?;
+        self.written = Some(bundle.clone());
+
+        let is_affected = |name: &Name| affected.iter().any(|prefix| prefix.matches(*name));
+
+        let mut summary = File::create(bundle.join("summary.txt"))?;
+        writeln!(summary, "step: {}", step)?;
+        writeln!(summary, "failure: {}", description)?;
+        writeln!(summary, "affected prefixes: {:?}", affected)?;
+
+        write_nodes(&bundle, nodes.values().filter(|node| is_affected(&node.name())), blocks)?;
+
+        // Messages between two affected nodes are held twice, once for each of them.
+        let messages: BTreeSet<_> = self.recent
+            .iter()
+            .filter(|&(name, _)| is_affected(name))
+            .flat_map(|(_, recent)| recent)
+            .collect();
+        let mut writer = BufWriter::new(File::create(bundle.join("messages.txt"))?);
+        for &(step, ref message) in messages {
+            writeln!(writer, "{}: {:?}", step, message)?;
+        }
+        writer.flush()?;
+
+        error!("state at first failure dumped to {}", bundle.display());
+        Ok(())
+    }
+
+    /// If the nodes disagree once a failed run finished at `step`, write a bundle describing their
+    /// disagreement, with the messages still `in_flight` and the events which were still to
+    /// occur, as given by `EventSchedule::tail`. Returns whether they disagreed. Only one bundle
+    /// is written.
+    pub fn dump_non_convergence<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        description: &str,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+        in_flight: &[(u64, &Message)],
+        schedule_tail: &[String],
+    ) -> io::Result<bool> {
+        let tables: BTreeMap<Name, RoutingTable> = nodes
+            .iter()
+            .map(|(name, node)| (*name, RoutingTable::of(node, blocks)))
+            .collect();
+        let disputed = disputed_prefixes(tables.values());
+        if disputed.is_empty() {
+            return Ok(false);
+        }
+        if self.non_convergence.is_some() {
+            return Ok(true);
+        }
+        let bundle = self.dir.join(format!("non-convergence-step-{}", step));
+        fs::create_dir_all(bundle.join("chains"))?;
+        self.non_convergence = Some(bundle.clone());
+
+        let is_disputed = |prefix: &Prefix| {
+            disputed.iter().any(|other| other.is_compatible(prefix))
+        };
+        let afflicted: Vec<&N> = nodes
+            .values()
+            .filter(|node| disputed.iter().any(|prefix| prefix.matches(node.name())))
+            .collect();
+
+        let mut summary = File::create(bundle.join("summary.txt"))?;
+        writeln!(summary, "step: {}", step)?;
+        writeln!(summary, "failure: {}", description)?;
+        writeln!(summary, "disputed prefixes: {:?}", disputed)?;
+        // Each latest block any node holds for a disputed prefix, with the number holding it.
+        let mut held = BTreeMap::new();
+        for table in tables.values() {
+            for (prefix, block) in table.latest() {
+                if is_disputed(prefix) {
+                    *held.entry(block.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+        for (block, count) in held {
+            writeln!(summary, "held by {} nodes: {:?}", count, block)?;
+        }
+
+        write_nodes(&bundle, afflicted.iter().cloned(), blocks)?;
+        for node in &afflicted {
+            let in_dispute = |ids: &ValidBlocks| -> ValidBlocks {
+                ids.iter()
+                    .filter(|id| is_disputed(&id.into_block(blocks).prefix))
+                    .cloned()
+                    .collect()
+            };
+            let chain = Chain::new(
+                blocks,
+                Some(&in_dispute(node.valid_blocks())),
+                &in_dispute(node.current_blocks()),
+            );
+            let path = bundle.join("chains").join(format!("{:016x}.chain", node.name().0));
+            let mut writer = BufWriter::new(File::create(path)?);
+            chain.write(&mut writer)?;
+            writer.flush()?;
+        }
+
+        let mut writer = BufWriter::new(File::create(bundle.join("in_flight.txt"))?);
+        for &(step, message) in in_flight {
+            writeln!(writer, "{}: {:?}", step, message)?;
+        }
+        writer.flush()?;
+
+        let mut writer = BufWriter::new(File::create(bundle.join("schedule.txt"))?);
+        for event in schedule_tail {
+            writeln!(writer, "{}", event)?;
+        }
+        writer.flush()?;
+
+        error!("nodes' disagreement dumped to {}", bundle.display());
+        Ok(true)
+    }
+}
+
+/// Write the full state of each of `nodes` to `nodes.txt` within `bundle`.
+fn write_nodes<'a, N, I>(bundle: &Path, nodes: I, blocks: &Blocks) -> io::Result<()>
+where
+    N: NodeTrait + 'a,
+    I: IntoIterator<Item = &'a N>,
+{
+    let mut writer = BufWriter::new(File::create(bundle.join("nodes.txt"))?);
+    for node in nodes {
+        writeln!(writer, "{}\n", node.dump_state(blocks))?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use message::MessageContent::*;
+    use node::Node;
+    use params::NodeParams;
+    use std::env;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::process;
+
+    #[test]
+    fn dump_written_once() {
+        let dir = env::temp_dir().join(format!("ewok-dump-test-{}", process::id()));
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let (a, b) = (p0.substituted_in(Name(1)), p1.substituted_in(Name(2)));
+
+        let mut dump = FailureDump::new(dir.clone(), 2);
+        let messages: Vec<_> = (0..3)
+            .map(|_| {
+                Message {
+                    sender: b,
+                    recipient: b,
+                    content: Connect,
+                }
+            })
+            .chain(Some(Message {
+                sender: a,
+                recipient: a,
+                content: Disconnect,
+            }))
+            .collect();
+        dump.record_delivered(7, &messages);
+
+        let nodes: BTreeMap<Name, Node> = BTreeMap::new();
+        dump.dump(10, "test failure", &[p0], &Blocks::new(), &nodes)
+            .unwrap();
+        dump.dump(11, "later failure", &[p1], &Blocks::new(), &nodes)
+            .unwrap();
+
+        let bundle = dir.join("failure-step-10");
+        assert_eq!(dump.path(), Some(bundle.as_path()));
+        assert!(!dir.join("failure-step-11").exists());
+        let messages = fs::read_to_string(bundle.join("messages.txt")).unwrap();
+        assert_eq!(messages.lines().count(), 1);
+        assert!(messages.contains("Disconnect"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn non_convergence_bundle() {
+        let dir = env::temp_dir().join(format!("ewok-non-convergence-test-{}", process::id()));
+        let (a, b) = (Name(0), Name(1));
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: btreeset!{a},
+        });
+        let next = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: btreeset!{a, b},
+        });
+
+        // Node a never learns that b joined.
+        let mut nodes = BTreeMap::new();
+        for &(name, current) in &[(a, genesis), (b, next)] {
+            let node = Node::new(name, &blocks, btreeset!{current}, NodeParams::default(), 0);
+            let _ = nodes.insert(name, node);
+        }
+        let message = Message {
+            sender: b,
+            recipient: a,
+            content: Connect,
+        };
+        let mut dump = FailureDump::new(dir.clone(), 2);
+        let dumped = dump.dump_non_convergence(
+            20,
+            "test failure",
+            &blocks,
+            &nodes,
+            &[(18, &message)],
+            &["step 25: RemoveNode(..)".to_string()],
+        );
+        assert!(dumped.unwrap());
+
+        let bundle = dir.join("non-convergence-step-20");
+        assert_eq!(dump.non_convergence_path(), Some(bundle.as_path()));
+        assert_eq!(dump.path(), None);
+        let summary = fs::read_to_string(bundle.join("summary.txt")).unwrap();
+        assert!(summary.contains(&format!("disputed prefixes: {:?}\n", vec![Prefix::empty()])));
+        assert_eq!(summary.lines().filter(|line| line.starts_with("held by 1 nodes")).count(), 2);
+        let file = File::open(bundle.join("chains").join("0000000000000000.chain")).unwrap();
+        let chain = Chain::read(&mut BufReader::new(file)).unwrap();
+        assert_eq!(chain.routing_table(), vec![genesis.into_block(&blocks)]);
+        let in_flight = fs::read_to_string(bundle.join("in_flight.txt")).unwrap();
+        assert_eq!(in_flight, format!("18: {:?}\n", message));
+        let schedule = fs::read_to_string(bundle.join("schedule.txt")).unwrap();
+        assert_eq!(schedule, "step 25: RemoveNode(..)\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+//! How well nodes catch equivocators, which send different members of their section votes for
+//! conflicting sibling blocks: how long after a node starts equivocating it's first caught, and
+//! how many honest nodes are wrongly taken for equivocators.
+
+use fault::Fault;
+use name::Name;
+use node::NodeTrait;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EquivocationReport {
+    /// Number of nodes which equivocated.
+    pub equivocators: u64,
+    /// Number of those which another node caught equivocating.
+    pub detected: u64,
+    /// Total number of steps from each detected node starting to equivocate to it first being
+    /// caught.
+    pub total_detection_latency: u64,
+    /// Number of honest nodes which another node took for equivocators.
+    pub false_positives: u64,
+}
+
+impl EquivocationReport {
+    /// Mean number of steps between a node starting to equivocate and it first being caught.
+    pub fn mean_detection_latency(&self) -> f64 {
+        self.total_detection_latency as f64 / cmp::max(self.detected, 1) as f64
+    }
+}
+
+/// Follows which nodes equivocate, and which nodes the others catch equivocating.
+#[derive(Clone, Debug, Default)]
+pub struct EquivocationTracker {
+    /// Nodes which have equivocated, with the step each started at.
+    started: BTreeMap<Name, u64>,
+    /// Nodes which another node has taken for an equivocator.
+    accused: BTreeSet<Name>,
+    report: EquivocationReport,
+}
+
+impl EquivocationTracker {
+    pub fn report(&self) -> &EquivocationReport {
+        &self.report
+    }
+
+    /// Note the nodes equivocating at `step`, and those newly caught doing so.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, nodes: &BTreeMap<Name, N>) {
+        for (name, node) in nodes {
+            if node.has_fault(Fault::Equivocate) && !self.started.contains_key(name) {
+                let _ = self.started.insert(*name, step);
+                self.report.equivocators += 1;
+            }
+        }
+        for node in nodes.values() {
+            for suspect in node.equivocators() {
+                if !self.accused.insert(suspect) {
+                    continue;
+                }
+                match self.started.get(&suspect) {
+                    Some(&start_step) => {
+                        self.report.detected += 1;
+                        self.report.total_detection_latency += step - start_step;
+                    }
+                    None => self.report.false_positives += 1,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use blocks::Blocks;
+    use name::Prefix;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn latency_and_false_positives() {
+        let mut blocks = Blocks::new();
+        let names: Vec<Name> = (0..3).map(Name).collect();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: names.iter().cloned().collect(),
+        });
+        let mut nodes: BTreeMap<Name, Node> = names
+            .iter()
+            .map(|&name| {
+                let node = Node::new(name, &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+                (name, node)
+            })
+            .collect();
+        let mut tracker = EquivocationTracker::default();
+
+        tracker.observe(1, &nodes);
+        nodes.get_mut(&names[0]).unwrap().set_fault(Fault::Equivocate, true);
+        tracker.observe(2, &nodes);
+        let _ = nodes.get_mut(&names[1]).unwrap().equivocators.insert(names[0]);
+        tracker.observe(5, &nodes);
+        // Each node only counts once, however many others catch it.
+        let _ = nodes.get_mut(&names[2]).unwrap().equivocators.insert(names[0]);
+        let _ = nodes.get_mut(&names[0]).unwrap().equivocators.insert(names[1]);
+        tracker.observe(6, &nodes);
+
+        assert_eq!(
+            *tracker.report(),
+            EquivocationReport {
+                equivocators: 1,
+                detected: 1,
+                total_detection_latency: 3,
+                false_positives: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Errors reported by ewok's public API.
+
+use format::FormatError;
+use params::Limit;
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid parameters or network layout.
+    Config(String),
+    /// The network finished in a state which breaks the protocol's invariants. `seed` reproduces
+    /// the run.
+    InvariantViolation { seed: [u32; 4], description: String },
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// The run was stopped while paused, at the start of the given step.
+    Stopped { step: u64 },
+    /// The run reached a safety limit at the end of the given step, with that many nodes.
+    LimitReached {
+        limit: Limit,
+        step: u64,
+        num_nodes: usize,
+    },
+    /// A log or output file couldn't be read.
+    Serialization(String),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Config(ref msg) => write!(f, "invalid configuration: {}", msg),
+            Error::InvariantViolation {
+                ref seed,
+                ref description,
+            } => write!(f, "{} (seed {:?})", description, seed),
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Stopped { step } => write!(f, "stopped while paused at step {}", step),
+            Error::LimitReached {
+                limit,
+                step,
+                num_nodes,
+            } => {
+                write!(
+                    f,
+                    "limit reached: {} at step {}, with {} nodes",
+                    limit,
+                    step,
+                    num_nodes
+                )
+            }
+            Error::Serialization(ref msg) => write!(f, "malformed data: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<FormatError> for Error {
+    fn from(err: FormatError) -> Self {
+        Error::Serialization(err.to_string())
+    }
+}
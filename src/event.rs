@@ -1,7 +1,10 @@
+use fault::Fault;
 use name::{Name, Prefix};
-use node::Node;
 use message::Message;
 use message::MessageContent::*;
+use node::NodeTrait;
+use params::BootstrapStrategy;
+use random::{random_name, sample};
 use std::collections::BTreeMap;
 use self::Event::*;
 
@@ -10,52 +13,229 @@ pub enum Event {
     AddNode(Name),
     RemoveNode(Name),
     RemoveNodeFrom(Prefix),
+    /// A node's section relocates it to the section covering the new name: once a quorum of the
+    /// section has signed the relocation, the destination section is told to expect the node,
+    /// the old section votes it out and the destination votes it in with relocation votes, and
+    /// the node restarts under the new name.
+    Relocate { node: Name, to: Name },
+    /// Relocate some node with the first prefix to a random name with the second.
+    RelocateFrom(Prefix, Prefix),
+    /// A node leaves its section and joins again under the same name after the given number of
+    /// steps.
+    Rejoin { node: Name, after: u64 },
+    /// Some node with the prefix leaves and rejoins under the same name after the given number
+    /// of steps.
+    RejoinFrom(Prefix, u64),
+    /// Start or stop a node behaving with a fault.
+    SetFault {
+        node: Name,
+        fault: Fault,
+        enabled: bool,
+    },
+    /// Start the fault in the first node with the prefix which isn't behaving with it yet, or
+    /// stop it in the first which is.
+    SetFaultIn {
+        prefix: Prefix,
+        fault: Fault,
+        enabled: bool,
+    },
+    /// Drop the next message of the given kind, as named by `MessageContent::kind`, sent from
+    /// one node to another.
+    DropNextMessage {
+        from: Name,
+        to: Name,
+        content_kind: String,
+    },
+    /// Drop the next message of the given kind from the first node with the first prefix to the
+    /// first other node with the second.
+    DropNextMessageIn {
+        from: Prefix,
+        to: Prefix,
+        content_kind: String,
+    },
+    /// A node forgets the given fraction of its cached votes.
+    ForgetVotes { node: Name, fraction: f64 },
+    /// The first node with the prefix forgets the given fraction of its cached votes.
+    ForgetVotesIn { prefix: Prefix, fraction: f64 },
+    /// The members of the section with the prefix vote to split it, whatever its size.
+    SectionSplit(Prefix),
+    /// The members of the two sections one bit longer than the prefix vote to merge them into
+    /// it, whatever their sizes.
+    SectionMerge(Prefix),
+    /// Cut every connection between nodes under one prefix and nodes under the other, and keep
+    /// them from reconnecting, or let them reconnect again.
+    PartitionPrefixes {
+        a: Prefix,
+        b: Prefix,
+        partitioned: bool,
+    },
+    /// Every node under the prefix drops all its connections, which are then reconnected like
+    /// any others which break.
+    RewireConnections(Prefix),
     //Reconnect(Name, Name)
     //Disconnect(Name, Name)
 }
 
+/// A relocation which has been applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    pub step: u64,
+    /// The node's name before relocating.
+    pub from: Name,
+    /// The node's name after relocating.
+    pub to: Name,
+}
+
 impl Event {
     /// Convert the event into a vec of notifications for all the nodes it should be sent to.
-    pub fn broadcast(&self, nodes: &BTreeMap<Name, Node>) -> Vec<Message> {
+    ///
+    /// Joining nodes contact the nodes chosen by `bootstrap`.
+    pub fn broadcast<N>(
+        &self,
+        nodes: &BTreeMap<Name, N>,
+        bootstrap: &BootstrapStrategy,
+    ) -> Vec<Message> {
         match *self {
-            AddNode(name) => add_node(name, nodes),
-            RemoveNode(name) => remove_node(name, nodes),
-            RemoveNodeFrom(_) => panic!("you need to normalise events before broadcasting"),
+            AddNode(name) => add_node(name, nodes, bootstrap),
+            RemoveNode(name) | Rejoin { node: name, .. } => remove_node(name, nodes),
+            Relocate { .. } |
+            SetFault { .. } |
+            DropNextMessage { .. } |
+            ForgetVotes { .. } |
+            SectionSplit(_) |
+            SectionMerge(_) |
+            PartitionPrefixes { .. } |
+            RewireConnections(_) => vec![],
+            RemoveNodeFrom(_) |
+            RelocateFrom(..) |
+            RejoinFrom(..) |
+            SetFaultIn { .. } |
+            DropNextMessageIn { .. } |
+            ForgetVotesIn { .. } => panic!("you need to normalise events before broadcasting"),
         }
     }
 
+    /// Whether the event adds or removes nodes, as opposed to changing how they behave or
+    /// connect.
+    pub fn changes_membership(&self) -> bool {
+        matches!(
+            *self,
+            AddNode(_) | RemoveNode(_) | Relocate { .. } | Rejoin { .. }
+        )
+    }
+
     /// If this is an event about a prefix, transform it into an event about a specific node.
-    pub fn normalise(self, nodes: &BTreeMap<Name, Node>) -> Option<Self> {
-        if let RemoveNodeFrom(prefix) = self {
-            select_node_to_remove(prefix, nodes).map(RemoveNode)
-        } else {
-            Some(self)
+    pub fn normalise<N: NodeTrait>(self, nodes: &BTreeMap<Name, N>) -> Option<Self> {
+        match self {
+            RemoveNodeFrom(prefix) => select_node_to_remove(prefix, nodes).map(RemoveNode),
+            RelocateFrom(from, to) => {
+                select_node_to_remove(from, nodes).map(|node| {
+                    Relocate {
+                        node,
+                        to: to.substituted_in(random_name()),
+                    }
+                })
+            }
+            RejoinFrom(prefix, after) => {
+                select_node_to_remove(prefix, nodes).map(|node| Rejoin { node, after })
+            }
+            SetFaultIn {
+                prefix,
+                fault,
+                enabled,
+            } => {
+                nodes
+                    .iter()
+                    .find(|&(name, node)| prefix.matches(*name) && node.has_fault(fault) != enabled)
+                    .map(|(&node, _)| {
+                        SetFault {
+                            node,
+                            fault,
+                            enabled,
+                        }
+                    })
+            }
+            DropNextMessageIn {
+                from,
+                to,
+                content_kind,
+            } => {
+                let sender = nodes.keys().find(|name| from.matches(**name)).cloned()?;
+                nodes
+                    .keys()
+                    .find(|name| **name != sender && to.matches(**name))
+                    .map(|&recipient| {
+                        DropNextMessage {
+                            from: sender,
+                            to: recipient,
+                            content_kind,
+                        }
+                    })
+            }
+            ForgetVotesIn { prefix, fraction } => {
+                nodes.keys().find(|name| prefix.matches(**name)).map(|&node| {
+                    ForgetVotes { node, fraction }
+                })
+            }
+            _ => Some(self),
         }
     }
 }
 
-fn add_node(joining_node: Name, nodes: &BTreeMap<Name, Node>) -> Vec<Message> {
-    // TODO: send only to this node's section(s).
-    nodes
-        .iter()
-        .map(|(&neighbour, _)| {
+fn add_node<N>(
+    joining_node: Name,
+    nodes: &BTreeMap<Name, N>,
+    bootstrap: &BootstrapStrategy,
+) -> Vec<Message> {
+    let (contacts, content) = match *bootstrap {
+        // TODO: send only to this node's section(s).
+        BootstrapStrategy::AllNodes => (nodes.keys().cloned().collect(), NodeJoined),
+        BootstrapStrategy::SingleProxy => (sample(nodes.keys().cloned(), 1), JoinRequest),
+        BootstrapStrategy::RandomContacts(count) => {
+            (sample(nodes.keys().cloned(), count), JoinRequest)
+        }
+        BootstrapStrategy::BootstrapList(ref names) => {
+            let live: Vec<Name> = names
+                .iter()
+                .filter(|name| nodes.contains_key(name))
+                .cloned()
+                .collect();
+            (live, JoinRequest)
+        }
+    };
+    contacts
+        .into_iter()
+        .map(|contact| {
             Message {
                 sender: joining_node,
-                recipient: neighbour,
-                content: NodeJoined,
+                recipient: contact,
+                content: content.clone(),
             }
         })
         .collect()
 }
 
-fn select_node_to_remove(prefix: Prefix, nodes: &BTreeMap<Name, Node>) -> Option<Name> {
+/// Messages sent by a relocated node restarting under its new name: it disconnects from every
+/// node under its old name, and contacts the nodes chosen by `bootstrap` under the new one.
+pub fn relocation_messages<N>(
+    node: Name,
+    to: Name,
+    nodes: &BTreeMap<Name, N>,
+    bootstrap: &BootstrapStrategy,
+) -> Vec<Message> {
+    let mut messages = remove_node(node, nodes);
+    messages.extend(add_node(to, nodes, bootstrap));
+    messages
+}
+
+fn select_node_to_remove<N>(prefix: Prefix, nodes: &BTreeMap<Name, N>) -> Option<Name> {
     nodes
         .iter()
         .find(move |&(name, _)| prefix.matches(*name))
         .map(|(name, _)| *name)
 }
 
-fn remove_node(to_remove: Name, nodes: &BTreeMap<Name, Node>) -> Vec<Message> {
+fn remove_node<N>(to_remove: Name, nodes: &BTreeMap<Name, N>) -> Vec<Message> {
     // TODO: only send to this node's connected peers.
     // TODO: consider connections again?
     nodes
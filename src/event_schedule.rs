@@ -1,30 +1,143 @@
 //! Tools for specifying events in advance.
 
 use event::Event;
+use simulation::Phase;
 use std::collections::BTreeMap;
+use std::mem;
+
+/// A condition, other than reaching a fixed step, upon which events should occur.
+///
+/// Phases are compared by kind only, so e.g. `Phase::Stable { since_step: 0 }` matches any stable
+/// phase.
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    /// The given number of steps after the network enters the phase.
+    AfterPhaseStart(Phase, u64),
+    /// The given number of steps after the network leaves the phase.
+    AfterPhaseEnd(Phase, u64),
+    /// The first step at which the network has more than this many nodes.
+    NetworkSizeAbove(usize),
+    /// The first step at which the network has fewer than this many nodes.
+    NetworkSizeBelow(usize),
+}
+
+#[derive(Clone)]
+struct TriggeredEvents {
+    trigger: Trigger,
+    events: Vec<Event>,
+    fired: bool,
+}
 
 /// A schedule for the occurrence of events like node additions and removals.
 ///
-/// You specify the event, and the step number at which you'd like it to occur.
+/// You specify the event, and the step number at which you'd like it to occur. Events can also be
+/// tied to triggers like phase changes, which occur once, at the first step the trigger is met.
+#[derive(Clone)]
 pub struct EventSchedule {
     pub schedule: BTreeMap<u64, Vec<Event>>,
+    triggered: Vec<TriggeredEvents>,
+    /// Phases seen so far, with the steps they started at.
+    phases: Vec<(Phase, u64)>,
 }
 
 impl EventSchedule {
     pub fn new(schedule: BTreeMap<u64, Vec<Event>>) -> Self {
-        EventSchedule { schedule }
+        EventSchedule {
+            schedule,
+            triggered: vec![],
+            phases: vec![],
+        }
     }
 
     pub fn empty() -> Self {
-        EventSchedule { schedule: BTreeMap::new() }
+        Self::new(BTreeMap::new())
+    }
+
+    /// Add events to occur when `trigger` is first met.
+    pub fn on(mut self, trigger: Trigger, events: Vec<Event>) -> Self {
+        self.triggered.push(TriggeredEvents {
+            trigger,
+            events,
+            fired: false,
+        });
+        self
     }
 
     pub fn is_empty(&self) -> bool {
-        self.schedule.is_empty()
+        self.schedule.is_empty() && self.triggered.is_empty()
     }
 
     /// Fetch events occuring at the given step.
     pub fn get_events(&self, step: u64) -> Vec<Event> {
         self.schedule.get(&step).cloned().unwrap_or_else(Vec::new)
     }
+
+    /// Events still to occur after the given step: those scheduled for later steps, and those
+    /// whose triggers haven't been met yet.
+    pub fn tail(&self, step: u64) -> Vec<String> {
+        let scheduled = self.schedule
+            .range(step + 1..)
+            .flat_map(|(step, events)| {
+                events.iter().map(move |event| format!("step {}: {:?}", step, event))
+            });
+        let triggered = self.triggered
+            .iter()
+            .filter(|triggered| !triggered.fired)
+            .flat_map(|triggered| {
+                triggered.events.iter().map(move |event| {
+                    format!("on {:?}: {:?}", triggered.trigger, event)
+                })
+            });
+        scheduled.chain(triggered).collect()
+    }
+
+    /// Fetch events whose triggers are met at the given step, given the network's state.
+    pub fn get_triggered_events(
+        &mut self,
+        step: u64,
+        phase: Phase,
+        num_nodes: usize,
+    ) -> Vec<Event> {
+        let phase_changed = self.phases
+            .last()
+            .map(|&(last, _)| !same_kind(last, phase))
+            .unwrap_or(true);
+        if phase_changed {
+            self.phases.push((phase, step));
+        }
+
+        let mut events = vec![];
+        for triggered in &mut self.triggered {
+            if !triggered.fired && is_met(&triggered.trigger, &self.phases, step, num_nodes) {
+                triggered.fired = true;
+                events.extend(triggered.events.iter().cloned());
+            }
+        }
+        events
+    }
+}
+
+fn same_kind(p1: Phase, p2: Phase) -> bool {
+    mem::discriminant(&p1) == mem::discriminant(&p2)
+}
+
+fn is_met(trigger: &Trigger, phases: &[(Phase, u64)], step: u64, num_nodes: usize) -> bool {
+    match *trigger {
+        Trigger::AfterPhaseStart(phase, delay) => {
+            phases
+                .iter()
+                .find(|&&(p, _)| same_kind(p, phase))
+                .map(|&(_, start)| step >= start + delay)
+                .unwrap_or(false)
+        }
+        Trigger::AfterPhaseEnd(phase, delay) => {
+            phases
+                .windows(2)
+                .find(|pair| same_kind(pair[0].0, phase))
+                .map(|pair| step >= pair[1].1 + delay)
+                .unwrap_or(false)
+        }
+        Trigger::NetworkSizeAbove(size) => num_nodes > size,
+        Trigger::NetworkSizeBelow(size) => num_nodes < size,
+    }
 }
@@ -0,0 +1,61 @@
+//! Faults in a node's logic, such as bugs or overload, which can be switched on and off by
+//! events. Most are benign: the faulty node doesn't lie, it just stops doing part of its job. An
+//! equivocating node does lie, telling different members different things.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Fault {
+    /// The node stops sending its own votes, though it still accepts others'.
+    NoVotes,
+    /// The node ignores connection requests.
+    IgnoreConnects,
+    /// The node drops the bootstrap messages it receives.
+    DropBootstrap,
+    /// The node sends each of its membership votes to only half of its recipients, and the other
+    /// half a vote for a conflicting sibling block, which removes another member instead.
+    Equivocate,
+}
+
+/// Names of all the faults, as used in scenario files.
+pub const FAULTS: &[&str] = &["no-votes", "ignore-connects", "drop-bootstrap", "equivocate"];
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Fault::NoVotes => FAULTS[0],
+            Fault::IgnoreConnects => FAULTS[1],
+            Fault::DropBootstrap => FAULTS[2],
+            Fault::Equivocate => FAULTS[3],
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Fault, String> {
+        match name {
+            "no-votes" => Ok(Fault::NoVotes),
+            "ignore-connects" => Ok(Fault::IgnoreConnects),
+            "drop-bootstrap" => Ok(Fault::DropBootstrap),
+            "equivocate" => Ok(Fault::Equivocate),
+            _ => Err(format!("unknown fault {:?}, expected one of {}", name, FAULTS.join(", "))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for name in FAULTS {
+            assert_eq!(name.parse::<Fault>().unwrap().to_string(), *name);
+        }
+        assert!("slow".parse::<Fault>().is_err());
+    }
+}
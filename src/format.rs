@@ -0,0 +1,173 @@
+//! Versioning of the files and logs that ewok produces for other tools to read.
+//!
+//! Each output starts with a header line of the form `# ewok <kind> format <version>`, so that
+//! readers can tell which layout they're looking at. Outputs from before versioning have no
+//! header; they're treated as version 0. Outputs of a single run follow the version with
+//! ` run <id>`, the run's identifier.
+
+use run_id;
+use std::fmt;
+
+/// Kinds of output whose format is versioned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatKind {
+    /// The simulation log, as parsed by the `graph` and `graph_msgs` tools.
+    Log,
+    /// Soak mode metrics files.
+    SoakMetrics,
+    /// Node lifecycle timelines.
+    Lifecycle,
+    /// Per-node activity counters.
+    NodeStats,
+    /// Summaries of runs across seeds and parameter settings.
+    SweepSummary,
+    /// Regression scenario descriptions.
+    Scenario,
+    /// Golden reports of regression scenario runs.
+    ScenarioReport,
+    /// Exported chains.
+    Chain,
+    /// Manifests of runs, with their parameters and reports.
+    Manifest,
+    /// Per-node journals and their index.
+    Journal,
+    /// Traces of the global state, for checking against a specification.
+    StateTrace,
+}
+
+impl FormatKind {
+    fn tag(&self) -> &'static str {
+        match *self {
+            FormatKind::Log => "log",
+            FormatKind::SoakMetrics => "soak-metrics",
+            FormatKind::Lifecycle => "lifecycle",
+            FormatKind::NodeStats => "node-stats",
+            FormatKind::SweepSummary => "sweep-summary",
+            FormatKind::Scenario => "scenario",
+            FormatKind::ScenarioReport => "scenario-report",
+            FormatKind::Chain => "chain",
+            FormatKind::Manifest => "manifest",
+            FormatKind::Journal => "journal",
+            FormatKind::StateTrace => "state-trace",
+        }
+    }
+
+    /// The version of this format currently written.
+    pub fn current_version(&self) -> u32 {
+        match *self {
+            FormatKind::Log | FormatKind::NodeStats | FormatKind::SoakMetrics => 2,
+            FormatKind::Lifecycle |
+            FormatKind::SweepSummary |
+            FormatKind::Scenario |
+            FormatKind::ScenarioReport |
+            FormatKind::Chain |
+            FormatKind::Manifest |
+            FormatKind::Journal |
+            FormatKind::StateTrace => 1,
+        }
+    }
+
+    /// The header line identifying the current version of this format (without a newline).
+    pub fn header(&self) -> String {
+        format!("# ewok {} format {}", self.tag(), self.current_version())
+    }
+
+    /// The header line for an output of the run in progress, with its identifier if it has one.
+    pub fn run_header(&self) -> String {
+        match run_id::current() {
+            Some(run_id) => format!("{} run {}", self.header(), run_id),
+            None => self.header(),
+        }
+    }
+
+    /// If `line` is a header for this kind of format, the version it declares.
+    pub fn parse_header(&self, line: &str) -> Option<u32> {
+        let prefix = format!("# ewok {} format ", self.tag());
+        if line.starts_with(&prefix) {
+            line[prefix.len()..].split_whitespace().next()?.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// Check whether a reader of the current version can read a capture of the given version.
+    /// `None` means no header was found.
+    ///
+    /// Version 1 only added the header to the version 0 layout, and version 2 of the log only
+    /// started each line with the run's identifier, so older captures are read as they are.
+    /// Version 2 of the node stats added a cohort column, which is taken to be the default
+    /// cohort for older captures. Captures from newer versions are refused rather than risk
+    /// misparsing them.
+    pub fn check_version(&self, found: Option<u32>) -> Result<Compatibility, FormatError> {
+        let version = found.unwrap_or(0);
+        if version == self.current_version() {
+            Ok(Compatibility::Current)
+        } else if version < self.current_version() {
+            Ok(Compatibility::Upgraded { from: version })
+        } else {
+            Err(FormatError {
+                kind: *self,
+                found: version,
+            })
+        }
+    }
+}
+
+/// How a capture relates to the current format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    Current,
+    /// An older format, which is converted to the current one while being read.
+    Upgraded { from: u32 },
+}
+
+/// A capture was written by a newer version of ewok than this one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FormatError {
+    pub kind: FormatKind,
+    pub found: u32,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} format version {} is newer than the supported version {}",
+            self.kind.tag(),
+            self.found,
+            self.kind.current_version()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let kind = FormatKind::SoakMetrics;
+        assert_eq!(kind.parse_header(&kind.header()), Some(kind.current_version()));
+        assert_eq!(FormatKind::Log.parse_header(&kind.header()), None);
+
+        run_id::set_current(Some("0123abcd".to_string()));
+        let header = kind.run_header();
+        run_id::set_current(None);
+        assert!(header.ends_with(" run 0123abcd"));
+        assert_eq!(kind.parse_header(&header), Some(kind.current_version()));
+    }
+
+    #[test]
+    fn version_checks() {
+        let kind = FormatKind::Log;
+        assert_eq!(
+            kind.check_version(Some(kind.current_version())),
+            Ok(Compatibility::Current)
+        );
+        assert_eq!(
+            kind.check_version(None),
+            Ok(Compatibility::Upgraded { from: 0 })
+        );
+        assert!(kind.check_version(Some(kind.current_version() + 1)).is_err());
+    }
+}
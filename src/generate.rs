@@ -1,35 +1,64 @@
 //! Functions for generating sections of a certain size.
 
 use block::{Block, BlockId};
+use chain::Chain;
+use error::{Error, Result};
 use blocks::{Blocks, CurrentBlocks};
 use name::{Name, Prefix};
-use node::Node;
-use params::NodeParams;
-use random::random;
+use node::NodeTrait;
+use params::{GenesisNodes, NodeParams};
+use random::random_name;
 
 use std::collections::{BTreeMap, BTreeSet};
 
 /// Generate a bunch of nodes based on sizes specified for sections.
 ///
 /// `sections`: map from prefix to desired size for that section.
-pub fn generate_network(
+pub fn generate_network<N: NodeTrait>(
     blocks: &mut Blocks,
     sections: &BTreeMap<Prefix, usize>,
     params: &NodeParams,
-) -> (BTreeMap<Name, Node>, BTreeSet<BlockId>) {
+) -> Result<(BTreeMap<Name, N>, BTreeSet<BlockId>)> {
     // Check that the supplied prefixes describe a whole network.
-    assert!(
-        Prefix::empty().is_covered_by(sections.keys()),
-        "Prefixes should cover the whole namespace"
-    );
+    if !Prefix::empty().is_covered_by(sections.keys()) {
+        return Err(Error::Config(format!(
+            "prefixes {:?} don't cover the whole namespace",
+            sections.keys().collect::<Vec<_>>()
+        )));
+    }
 
     let mut nodes_by_section = btreemap!{};
 
     for (prefix, &size) in sections {
-        let node_names: BTreeSet<_> = (0..size).map(|_| prefix.substituted_in(random())).collect();
+        let node_names: BTreeSet<_> = (0..size)
+            .map(|_| prefix.substituted_in(random_name()))
+            .collect();
         nodes_by_section.insert(*prefix, node_names);
     }
 
+    Ok(network_of(blocks, nodes_by_section, params))
+}
+
+/// Generate the members of a single genesis section covering the whole namespace.
+pub fn genesis_network<N: NodeTrait>(
+    blocks: &mut Blocks,
+    genesis: &GenesisNodes,
+    params: &NodeParams,
+) -> (BTreeMap<Name, N>, BTreeSet<BlockId>) {
+    let names = match *genesis {
+        GenesisNodes::Count(count) => (0..count).map(|_| random_name()).collect(),
+        GenesisNodes::Names(ref names) => names.iter().cloned().collect(),
+    };
+    network_of(blocks, btreemap!{ Prefix::empty() => names }, params)
+}
+
+/// Create a fully connected node for each member of the given sections, which all start with
+/// the sections' blocks as current.
+fn network_of<N: NodeTrait>(
+    blocks: &mut Blocks,
+    nodes_by_section: BTreeMap<Prefix, BTreeSet<Name>>,
+    params: &NodeParams,
+) -> (BTreeMap<Name, N>, BTreeSet<BlockId>) {
     let current_blocks: CurrentBlocks = construct_blocks(nodes_by_section.clone())
         .into_iter()
         .map(|b| blocks.insert(b))
@@ -41,7 +70,7 @@ pub fn generate_network(
         .map(|name| {
             (
                 name,
-                Node::new(name, blocks, current_blocks.clone(), params.clone(), 0),
+                N::new(name, blocks, current_blocks.clone(), params.clone(), 0),
             )
         })
         .collect();
@@ -49,6 +78,60 @@ pub fn generate_network(
     (nodes, current_blocks)
 }
 
+/// Create a node for each member of the current blocks of `chain`, adding all its blocks to
+/// `blocks`.
+pub fn network_from_chain<N: NodeTrait>(
+    blocks: &mut Blocks,
+    chain: &Chain,
+    params: &NodeParams,
+) -> Result<(BTreeMap<Name, N>, BTreeSet<BlockId>)> {
+    let routing_table = chain.routing_table();
+    let prefixes: Vec<_> = routing_table.iter().map(|block| block.prefix).collect();
+    if !Prefix::empty().is_covered_by(&prefixes) {
+        return Err(Error::Config(format!(
+            "the chain's current prefixes {:?} don't cover the whole namespace",
+            prefixes
+        )));
+    }
+
+    let current_blocks = chain.insert_into(blocks);
+    let nodes = routing_table
+        .into_iter()
+        .flat_map(|block| block.members.iter().cloned())
+        .map(|name| {
+            (
+                name,
+                N::new(name, blocks, current_blocks.clone(), params.clone(), 0),
+            )
+        })
+        .collect();
+
+    Ok((nodes, current_blocks))
+}
+
+/// Divide `num_nodes` nodes into sections the way a network grown to that size would be, with
+/// each section split evenly once both halves would be large enough to split.
+pub fn converged_sections(num_nodes: usize, params: &NodeParams) -> BTreeMap<Prefix, usize> {
+    let mut sections = btreemap!{};
+    add_sections(&mut sections, Prefix::empty(), num_nodes, params);
+    sections
+}
+
+fn add_sections(
+    sections: &mut BTreeMap<Prefix, usize>,
+    prefix: Prefix,
+    size: usize,
+    params: &NodeParams,
+) {
+    let min_split_size = params.min_section_size + params.split_buffer;
+    if size / 2 >= min_split_size {
+        add_sections(sections, prefix.pushed(false), size / 2, params);
+        add_sections(sections, prefix.pushed(true), size - size / 2, params);
+    } else {
+        sections.insert(prefix, size);
+    }
+}
+
 /// Construct a set of blocks to describe the given sections.
 fn construct_blocks(nodes: BTreeMap<Prefix, BTreeSet<Name>>) -> BTreeSet<Block> {
     nodes
@@ -0,0 +1,272 @@
+//! Per-section health scores, computed every step to show how close each section is to stalling.
+//!
+//! A section's score is the mean of three measures, each between 0 and 1:
+//!
+//! * connectivity: the fraction of ordered pairs of members in which the first is connected to
+//!   the second, counting members which are no longer running as disconnected
+//! * backlog: `1 / (1 + n)`, where `n` is the number of blocks for the section still pending at
+//!   any of its members
+//! * participation: the fraction of members voting, rather than waiting for their handover or
+//!   withholding their votes due to a fault
+//!
+//! A section which stays below a threshold score for long enough raises an alert.
+
+use block::Block;
+use blocks::Blocks;
+use fault::Fault;
+use name::{Name, Prefix};
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SectionHealth {
+    /// Fraction of ordered pairs of members which are connected.
+    pub connectivity: f64,
+    /// Number of blocks for the section pending at any of its members.
+    pub backlog: usize,
+    /// Fraction of members voting.
+    pub participation: f64,
+}
+
+impl SectionHealth {
+    /// The health of the section described by `block`.
+    pub fn of<N: NodeTrait>(
+        block: &Block,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+        step: u64,
+    ) -> Self {
+        let members: Vec<_> = block.members.iter().map(|name| nodes.get(name)).collect();
+        let num_pairs = members.len() * members.len().saturating_sub(1);
+        let connected = members
+            .iter()
+            .filter_map(|node| *node)
+            .map(|node| {
+                block
+                    .members
+                    .iter()
+                    .filter(|&&name| name != node.name() && nodes.contains_key(&name))
+                    .filter(|name| !node.is_disconnected_from(name))
+                    .count()
+            })
+            .sum::<usize>();
+
+        let mut pending = BTreeSet::new();
+        for node in members.iter().filter_map(|node| *node) {
+            pending.extend(node.pending_blocks().into_iter().filter(|id| {
+                id.into_block(blocks).prefix.is_compatible(&block.prefix)
+            }));
+        }
+
+        let voting = members
+            .iter()
+            .filter(|node| {
+                node.is_some_and(|node| node.is_voting(step) && !node.has_fault(Fault::NoVotes))
+            })
+            .count();
+
+        SectionHealth {
+            connectivity: ratio(connected, num_pairs),
+            backlog: pending.len(),
+            participation: ratio(voting, members.len()),
+        }
+    }
+
+    /// The overall score, between 0 and 1.
+    pub fn score(&self) -> f64 {
+        (self.connectivity + 1.0 / (1.0 + self.backlog as f64) + self.participation) / 3.0
+    }
+}
+
+/// The fraction `num / denom`, or 1 if there's nothing to count.
+fn ratio(num: usize, denom: usize) -> f64 {
+    if denom == 0 {
+        1.0
+    } else {
+        num as f64 / denom as f64
+    }
+}
+
+impl fmt::Display for SectionHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "score {:.3} (connectivity {:.3}, backlog {}, participation {:.3})",
+            self.score(),
+            self.connectivity,
+            self.backlog,
+            self.participation
+        )
+    }
+}
+
+/// A section whose health stayed below the threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LowHealth {
+    pub prefix: Prefix,
+    pub threshold: f64,
+    /// The section's health when the alert was raised.
+    pub health: SectionHealth,
+    /// Step since which the score has been below the threshold.
+    pub since_step: u64,
+    /// Step at which the alert was raised.
+    pub step: u64,
+}
+
+impl fmt::Display for LowHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "health of {:?} below {} since step {}: {}",
+            self.prefix,
+            self.threshold,
+            self.since_step,
+            self.health
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct HealthMonitor {
+    /// Score below which a section counts as unhealthy, and the number of steps it has to stay
+    /// unhealthy for to raise an alert, if alerts are enabled.
+    alert: Option<(f64, u64)>,
+    /// Health of each section after the latest step.
+    latest: BTreeMap<Prefix, SectionHealth>,
+    /// Step since which each unhealthy section has been below the threshold.
+    unhealthy_since: BTreeMap<Prefix, u64>,
+    /// Lowest score seen, with the section and step it was seen at.
+    lowest: Option<(f64, Prefix, u64)>,
+    detected: Option<LowHealth>,
+}
+
+impl HealthMonitor {
+    /// Raise an alert once a section's score stays below `threshold` for `max_steps` steps.
+    pub fn with_alert(threshold: f64, max_steps: u64) -> Self {
+        HealthMonitor {
+            alert: Some((threshold, max_steps)),
+            ..HealthMonitor::default()
+        }
+    }
+
+    /// Compute the health of every section after `step`. Returns the alert if one has just been
+    /// raised.
+    pub fn observe<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> Option<&LowHealth> {
+        // The latest block for each prefix, in case nodes disagree.
+        let mut sections: BTreeMap<Prefix, &Block> = BTreeMap::new();
+        for node in nodes.values() {
+            for block in node.our_current_blocks(blocks) {
+                let latest = sections.entry(block.prefix).or_insert(block);
+                if block.version > latest.version {
+                    *latest = block;
+                }
+            }
+        }
+        let health = sections
+            .into_iter()
+            .map(|(prefix, block)| (prefix, SectionHealth::of(block, blocks, nodes, step)))
+            .collect();
+        self.update(step, health)
+    }
+
+    fn update(
+        &mut self,
+        step: u64,
+        health: BTreeMap<Prefix, SectionHealth>,
+    ) -> Option<&LowHealth> {
+        let mut alerted = false;
+        let mut unhealthy_since = BTreeMap::new();
+        // Sections which are no longer unhealthy, or no longer exist, start counting again.
+        for (prefix, health) in &health {
+            let score = health.score();
+            debug!("Health: {:?} {}", prefix, health);
+            if self.lowest.is_none_or(|(lowest, _, _)| score < lowest) {
+                self.lowest = Some((score, *prefix, step));
+            }
+            let (threshold, max_steps) = match self.alert {
+                Some(alert) if score < alert.0 => alert,
+                _ => continue,
+            };
+            let since_step = self.unhealthy_since.get(prefix).cloned().unwrap_or(step);
+            let _ = unhealthy_since.insert(*prefix, since_step);
+            if step - since_step >= max_steps && self.detected.is_none() {
+                self.detected = Some(LowHealth {
+                    prefix: *prefix,
+                    threshold,
+                    health: health.clone(),
+                    since_step,
+                    step,
+                });
+                alerted = true;
+            }
+        }
+        self.latest = health;
+        self.unhealthy_since = unhealthy_since;
+        if alerted {
+            self.detected.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// Health of each section after the latest step.
+    pub fn latest(&self) -> &BTreeMap<Prefix, SectionHealth> {
+        &self.latest
+    }
+
+    /// The lowest score seen so far, with the section and step it was seen at.
+    pub fn lowest(&self) -> Option<(f64, Prefix, u64)> {
+        self.lowest
+    }
+
+    /// The first alert raised, if any.
+    pub fn detected(&self) -> Option<&LowHealth> {
+        self.detected.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn health(connectivity: f64, backlog: usize, participation: f64) -> SectionHealth {
+        SectionHealth {
+            connectivity,
+            backlog,
+            participation,
+        }
+    }
+
+    #[test]
+    fn sustained_low_health_alerts() {
+        assert_eq!(health(1.0, 0, 1.0).score(), 1.0);
+        assert_eq!(health(0.5, 1, 1.0).score(), 2.0 / 3.0);
+
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let mut monitor = HealthMonitor::with_alert(0.8, 3);
+        let sick = || btreemap!{ p0 => health(0.5, 1, 1.0), p1 => health(1.0, 0, 1.0) };
+        let well = || btreemap!{ p0 => health(1.0, 1, 1.0), p1 => health(1.0, 0, 1.0) };
+        // Recovering in between resets the count.
+        for step in 0..3 {
+            assert!(monitor.update(step, sick()).is_none());
+        }
+        assert!(monitor.update(3, well()).is_none());
+        for step in 4..7 {
+            assert!(monitor.update(step, sick()).is_none());
+        }
+        let alert = monitor.update(7, sick()).cloned().unwrap();
+        assert_eq!((alert.prefix, alert.since_step, alert.step), (p0, 4, 7));
+        assert!(monitor.update(8, sick()).is_none());
+        assert_eq!(monitor.detected(), Some(&alert));
+        assert_eq!(monitor.lowest(), Some((2.0 / 3.0, p0, 0)));
+        assert_eq!(monitor.latest()[&p1].score(), 1.0);
+    }
+}
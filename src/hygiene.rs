@@ -0,0 +1,143 @@
+//! Audit of node state which should have been cleaned up but wasn't: candidates kept long after
+//! their join timed out, and connections to names which no node's routing table lists. Nodes
+//! never forget either on their own, so both accumulate silently over long runs.
+//!
+//! Each step the audit counts both across all nodes, and with purging enabled has the nodes
+//! forget what it found.
+
+use blocks::Blocks;
+use name::Name;
+use node::NodeTrait;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HygieneReport {
+    /// Stale candidates found at the latest step, summed over all nodes.
+    pub stale_candidates: usize,
+    /// Ghost connections found at the latest step, summed over all nodes.
+    pub ghost_connections: usize,
+    pub max_stale_candidates: usize,
+    pub max_ghost_connections: usize,
+    pub candidates_purged: u64,
+    pub connections_purged: u64,
+}
+
+#[derive(Clone)]
+pub struct HygieneAudit {
+    /// Number of steps past its join timeout after which a candidate is stale.
+    grace: u64,
+    /// Whether nodes forget the stale candidates and ghost connections found.
+    purge: bool,
+    report: HygieneReport,
+}
+
+impl HygieneAudit {
+    pub fn new(grace: u64, purge: bool) -> Self {
+        HygieneAudit {
+            grace,
+            purge,
+            report: HygieneReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &HygieneReport {
+        &self.report
+    }
+
+    pub fn observe<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        blocks: &Blocks,
+        nodes: &mut BTreeMap<Name, N>,
+    ) {
+        let mut routable = BTreeSet::new();
+        for node in nodes.values() {
+            for block in blocks.block_contents(node.current_blocks()) {
+                routable.extend(block.members.iter().cloned());
+            }
+        }
+
+        let (mut stale_candidates, mut ghost_connections) = (0, 0);
+        for node in nodes.values_mut() {
+            let stale = node.stale_candidates(step, self.grace);
+            let ghosts = node.ghost_connections(&routable, step);
+            if stale.is_empty() && ghosts.is_empty() {
+                continue;
+            }
+            trace!(
+                "Node({}): stale candidates {:?}, ghost connections {:?}",
+                node.name(),
+                stale,
+                ghosts
+            );
+            stale_candidates += stale.len();
+            ghost_connections += ghosts.len();
+            if self.purge {
+                node.purge(&stale, &ghosts);
+                self.report.candidates_purged += stale.len() as u64;
+                self.report.connections_purged += ghosts.len() as u64;
+            }
+        }
+
+        let report = &mut self.report;
+        report.stale_candidates = stale_candidates;
+        report.ghost_connections = ghost_connections;
+        report.max_stale_candidates = cmp::max(report.max_stale_candidates, stale_candidates);
+        report.max_ghost_connections = cmp::max(report.max_ghost_connections, ghost_connections);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use message::Message;
+    use message::MessageContent::NodeJoined;
+    use name::Prefix;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn stale_candidates_and_ghosts_purged() {
+        let names: Vec<_> = (0..3).map(Name).collect();
+        let (joining, departed) = (Name(10), Name(11));
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: names.iter().cloned().collect(),
+        });
+        let params = NodeParams::default();
+        let timeout = params.join_timeout;
+        let mut nodes = BTreeMap::new();
+        for name in &names {
+            let node = Node::new(*name, &blocks, btreeset!{genesis}, params.clone(), 0);
+            let _ = nodes.insert(*name, node);
+        }
+
+        // The joining node is never added, and a departed node is still connected to node 0.
+        let join = Message {
+            sender: joining,
+            recipient: names[0],
+            content: NodeJoined,
+        };
+        let _ = nodes.get_mut(&names[0]).unwrap().handle_message(join, &blocks, 0);
+        let _ = nodes.get_mut(&names[0]).unwrap().connections.accept(departed);
+
+        let mut audit = HygieneAudit::new(5, false);
+        audit.observe(timeout, &blocks, &mut nodes);
+        assert_eq!((audit.report().stale_candidates, audit.report().ghost_connections), (0, 1));
+        audit.observe(timeout + 6, &blocks, &mut nodes);
+        assert_eq!((audit.report().stale_candidates, audit.report().ghost_connections), (1, 2));
+
+        let mut audit = HygieneAudit::new(5, true);
+        audit.observe(timeout + 6, &blocks, &mut nodes);
+        audit.observe(timeout + 7, &blocks, &mut nodes);
+        let report = audit.report();
+        assert_eq!((report.stale_candidates, report.ghost_connections), (0, 0));
+        assert_eq!((report.max_stale_candidates, report.max_ghost_connections), (1, 2));
+        assert_eq!((report.candidates_purged, report.connections_purged), (1, 2));
+    }
+}
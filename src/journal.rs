@@ -0,0 +1,475 @@
+//! Per-node journals of the messages each node handled, the votes it cast and the blocks it
+//! agreed, so that a single node's decisions can be replayed without filtering the global log.
+//!
+//! Each journal entry is one line: the step, what happened, and its details, with names in full
+//! hex and prefixes as bits (`-` for the empty prefix):
+//!
+//! ```text
+//! 12 handled VoteMsg 3f2a0c1d9e8b7a65
+//! 12 voted 01 4 01 5 +3f2a0c1d9e8b7a65
+//! 14 agreed 01 5 9
+//! ```
+//!
+//! Journals are either written to a file per node in a directory, or to a single file with an
+//! index alongside it giving the offset and length of each node's chunks. Entries are buffered and
+//! written every `FLUSH_INTERVAL` steps, and when the run finishes.
+
+use block::Vote;
+use blocks::{Blocks, CurrentBlocks};
+use error::{Error, Result};
+use format::FormatKind;
+use message::Message;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use scenario::{format_prefix, parse_prefix};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// Number of steps between writes of the buffered entries.
+pub const FLUSH_INTERVAL: u64 = 100;
+
+/// Where journals are written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JournalLayout {
+    /// A `<name>.journal` file per node in this directory.
+    PerNode(PathBuf),
+    /// A single file, indexed by a `.index` file next to it.
+    Indexed(PathBuf),
+}
+
+impl JournalLayout {
+    /// The index of an indexed journal.
+    fn index_path(path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.index", path.display()))
+    }
+}
+
+/// Something that happened at a node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JournalEvent {
+    /// The node handled a message of this kind from this sender.
+    Handled { kind: String, sender: Name },
+    /// The node voted for the block with the `to` prefix and version to succeed the `from` one,
+    /// adding and removing the given members.
+    Voted {
+        from: (Prefix, u64),
+        to: (Prefix, u64),
+        added: BTreeSet<Name>,
+        removed: BTreeSet<Name>,
+    },
+    /// A block with this prefix, version and number of members became current at the node.
+    Agreed {
+        prefix: Prefix,
+        version: u64,
+        members: usize,
+    },
+}
+
+/// An event at a node, with the step it happened at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub step: u64,
+    pub event: JournalEvent,
+}
+
+impl JournalEntry {
+    /// Parse a line written by the `Display` implementation.
+    pub fn parse(line: &str) -> ::std::result::Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let step = words.first().map_or(Err(String::from("empty entry")), |word| {
+            parse_num(word)
+        })?;
+        let event = match words[1..] {
+            ["handled", kind, sender] => {
+                JournalEvent::Handled {
+                    kind: kind.to_string(),
+                    sender: parse_name(sender)?,
+                }
+            }
+            ["voted", from_prefix, from_version, to_prefix, to_version, ref changes @ ..] => {
+                let mut added = BTreeSet::new();
+                let mut removed = BTreeSet::new();
+                for change in changes {
+                    let _ = match change.split_at(1) {
+                        ("+", name) => added.insert(parse_name(name)?),
+                        ("-", name) => removed.insert(parse_name(name)?),
+                        _ => return Err(format!("invalid member change {}", change)),
+                    };
+                }
+                JournalEvent::Voted {
+                    from: (parse_prefix(from_prefix)?, parse_num(from_version)?),
+                    to: (parse_prefix(to_prefix)?, parse_num(to_version)?),
+                    added,
+                    removed,
+                }
+            }
+            ["agreed", prefix, version, members] => {
+                JournalEvent::Agreed {
+                    prefix: parse_prefix(prefix)?,
+                    version: parse_num(version)?,
+                    members: parse_num(members)?,
+                }
+            }
+            _ => return Err(format!("invalid entry {:?}", line)),
+        };
+        Ok(JournalEntry { step, event })
+    }
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.step)?;
+        match self.event {
+            JournalEvent::Handled { ref kind, sender } => {
+                write!(f, "handled {} {:016x}", kind, sender.0)
+            }
+            JournalEvent::Voted {
+                from,
+                to,
+                ref added,
+                ref removed,
+            } => {
+                write!(
+                    f,
+                    "voted {} {} {} {}",
+                    format_prefix(&from.0),
+                    from.1,
+                    format_prefix(&to.0),
+                    to.1
+                )?;
+                for name in added {
+                    write!(f, " +{:016x}", name.0)?;
+                }
+                for name in removed {
+                    write!(f, " -{:016x}", name.0)?;
+                }
+                Ok(())
+            }
+            JournalEvent::Agreed {
+                prefix,
+                version,
+                members,
+            } => write!(f, "agreed {} {} {}", format_prefix(&prefix), version, members),
+        }
+    }
+}
+
+fn parse_num<T: ::std::str::FromStr>(word: &str) -> ::std::result::Result<T, String> {
+    word.parse().map_err(|_| format!("invalid number {}", word))
+}
+
+fn parse_name(word: &str) -> ::std::result::Result<Name, String> {
+    u64::from_str_radix(word, 16).map(Name).map_err(|_| format!("invalid name {}", word))
+}
+
+/// Journals being recorded during a run.
+pub struct Journals {
+    layout: JournalLayout,
+    /// Entries not yet written, for each node.
+    pending: BTreeMap<Name, Vec<JournalEntry>>,
+    /// Nodes whose journal file has been started, with the per-node layout.
+    started: BTreeSet<Name>,
+    /// Length of the indexed journal written so far.
+    offset: u64,
+    /// Each node's current blocks when last observed.
+    current: BTreeMap<Name, CurrentBlocks>,
+}
+
+/// The part of `Journals` held in memory, which is all a checkpoint can restore.
+#[derive(Clone)]
+pub struct JournalsCheckpoint {
+    pending: BTreeMap<Name, Vec<JournalEntry>>,
+    current: BTreeMap<Name, CurrentBlocks>,
+}
+
+impl Journals {
+    /// Start journals in `layout`, replacing any previous indexed journal at the same path.
+    pub fn new(layout: JournalLayout) -> io::Result<Self> {
+        let mut offset = 0;
+        match layout {
+            JournalLayout::PerNode(ref dir) => fs::create_dir_all(dir)?,
+            JournalLayout::Indexed(ref path) => {
+                let header = format!("{}\n", FormatKind::Journal.run_header());
+                File::create(path)?.write_all(header.as_bytes())?;
+                let mut index = File::create(JournalLayout::index_path(path))?;
+                writeln!(index, "{}", FormatKind::Journal.run_header())?;
+                offset = header.len() as u64;
+            }
+        }
+        Ok(Journals {
+            layout,
+            pending: BTreeMap::new(),
+            started: BTreeSet::new(),
+            offset,
+            current: BTreeMap::new(),
+        })
+    }
+
+    pub fn checkpoint(&self) -> JournalsCheckpoint {
+        JournalsCheckpoint {
+            pending: self.pending.clone(),
+            current: self.current.clone(),
+        }
+    }
+
+    /// Go back to `checkpoint`. Entries already written stay in the files, so steps re-run after
+    /// this are journalled a second time.
+    pub fn restore(&mut self, checkpoint: JournalsCheckpoint) {
+        self.pending = checkpoint.pending;
+        self.current = checkpoint.current;
+    }
+
+    fn record(&mut self, name: Name, step: u64, event: JournalEvent) {
+        self.pending.entry(name).or_default().push(JournalEntry { step, event });
+    }
+
+    /// Record the messages delivered at `step` which are handled by live nodes.
+    pub fn record_handled<N>(
+        &mut self,
+        step: u64,
+        messages: &[Message],
+        nodes: &BTreeMap<Name, N>,
+    ) {
+        for message in messages.iter().filter(|message| nodes.contains_key(&message.recipient)) {
+            let event = JournalEvent::Handled {
+                kind: message.content.kind().to_string(),
+                sender: message.sender,
+            };
+            self.record(message.recipient, step, event);
+        }
+    }
+
+    /// Record the votes among the messages `name` sent at `step`, once each.
+    pub fn record_votes(&mut self, step: u64, name: Name, messages: &[Message], blocks: &Blocks) {
+        let votes: BTreeSet<&Vote> = messages
+            .iter()
+            .filter(|message| message.sender == name)
+            .flat_map(|message| message.content.plain_votes())
+            .collect();
+        for vote in votes {
+            let from = vote.from.into_block(blocks);
+            let to = vote.to.into_block(blocks);
+            let event = JournalEvent::Voted {
+                from: (from.prefix, from.version),
+                to: (to.prefix, to.version),
+                added: to.members.difference(&from.members).cloned().collect(),
+                removed: from.members.difference(&to.members).cloned().collect(),
+            };
+            self.record(name, step, event);
+        }
+    }
+
+    /// Record blocks which have become current at any node by the end of `step`, and write the
+    /// buffered entries if due.
+    pub fn observe<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> io::Result<()> {
+        for (name, node) in nodes {
+            let current = node.current_blocks();
+            let previous = self.current.remove(name).unwrap_or_default();
+            for block_id in current.difference(&previous) {
+                let block = block_id.into_block(blocks);
+                let event = JournalEvent::Agreed {
+                    prefix: block.prefix,
+                    version: block.version,
+                    members: block.members.len(),
+                };
+                self.record(*name, step, event);
+            }
+            let _ = self.current.insert(*name, current.clone());
+        }
+        self.current.retain(|name, _| nodes.contains_key(name));
+        if step.is_multiple_of(FLUSH_INTERVAL) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write all buffered entries.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let pending = mem::take(&mut self.pending);
+        match self.layout {
+            JournalLayout::PerNode(ref dir) => {
+                for (name, entries) in pending {
+                    let path = dir.join(format!("{:016x}.journal", name.0));
+                    let mut file = if self.started.insert(name) {
+                        let mut file = File::create(path)?;
+                        writeln!(file, "{}", FormatKind::Journal.run_header())?;
+                        file
+                    } else {
+                        OpenOptions::new().append(true).open(path)?
+                    };
+                    file.write_all(chunk(&entries).as_bytes())?;
+                }
+            }
+            JournalLayout::Indexed(ref path) => {
+                let mut file = OpenOptions::new().append(true).open(path)?;
+                let mut index = OpenOptions::new().append(true).open(
+                    JournalLayout::index_path(path),
+                )?;
+                for (name, entries) in pending {
+                    let chunk = chunk(&entries);
+                    file.write_all(chunk.as_bytes())?;
+                    writeln!(index, "{:016x} {} {}", name.0, self.offset, chunk.len())?;
+                    self.offset += chunk.len() as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chunk(entries: &[JournalEntry]) -> String {
+    entries.iter().map(|entry| format!("{}\n", entry)).collect()
+}
+
+/// Read the journals in `layout` of every node whose name, in hex, starts with `name`.
+pub fn read_journals(
+    layout: &JournalLayout,
+    name: &str,
+) -> Result<BTreeMap<Name, Vec<JournalEntry>>> {
+    let name = name.to_lowercase();
+    let mut journals = BTreeMap::new();
+    match *layout {
+        JournalLayout::PerNode(ref dir) => {
+            for dir_entry in fs::read_dir(dir)? {
+                let path = dir_entry?.path();
+                let hex = match path.file_name().and_then(|file| file.to_str()) {
+                    Some(file) if file.ends_with(".journal") => file.trim_end_matches(".journal"),
+                    _ => continue,
+                };
+                if !hex.starts_with(&name) {
+                    continue;
+                }
+                let mut reader = BufReader::new(File::open(&path)?);
+                check_header(&mut reader)?;
+                let node = parse_name(hex).map_err(Error::Serialization)?;
+                read_entries(reader, journals.entry(node).or_default())?;
+            }
+        }
+        JournalLayout::Indexed(ref path) => {
+            let mut index = BufReader::new(File::open(JournalLayout::index_path(path))?);
+            check_header(&mut index)?;
+            let mut file = File::open(path)?;
+            check_header(&mut BufReader::new(&mut file))?;
+            for line in index.lines() {
+                let line = line?;
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let (hex, offset, len): (_, u64, u64) = match fields[..] {
+                    [hex, offset, len] => {
+                        let parse = |word| parse_num(word).map_err(Error::Serialization);
+                        (hex, parse(offset)?, parse(len)?)
+                    }
+                    _ => return Err(Error::Serialization(format!("invalid index line {:?}", line))),
+                };
+                if !hex.starts_with(&name) {
+                    continue;
+                }
+                let node = parse_name(hex).map_err(Error::Serialization)?;
+                let _ = file.seek(SeekFrom::Start(offset))?;
+                read_entries((&mut file).take(len), journals.entry(node).or_default())?;
+            }
+        }
+    }
+    Ok(journals)
+}
+
+fn check_header<R: BufRead>(reader: &mut R) -> Result<()> {
+    let mut header = String::new();
+    let _ = reader.read_line(&mut header)?;
+    let version = FormatKind::Journal.parse_header(header.trim_end());
+    if version.is_none() {
+        return Err(Error::Serialization("missing journal format header".to_string()));
+    }
+    let _ = FormatKind::Journal.check_version(version)?;
+    Ok(())
+}
+
+fn read_entries<R: Read>(reader: R, entries: &mut Vec<JournalEntry>) -> Result<()> {
+    for line in BufReader::new(reader).lines() {
+        entries.push(JournalEntry::parse(&line?).map_err(Error::Serialization)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entries() -> Vec<JournalEntry> {
+        let name = Name(0x3f2a_0c1d_9e8b_7a65);
+        let p01 = Prefix::short(2, 0b01000000);
+        vec![
+            JournalEntry {
+                step: 12,
+                event: JournalEvent::Handled {
+                    kind: "VoteMsg".to_string(),
+                    sender: name,
+                },
+            },
+            JournalEntry {
+                step: 12,
+                event: JournalEvent::Voted {
+                    from: (Prefix::empty(), 4),
+                    to: (p01, 5),
+                    added: btreeset!{name},
+                    removed: btreeset!{Name(1)},
+                },
+            },
+            JournalEntry {
+                step: 14,
+                event: JournalEvent::Agreed {
+                    prefix: p01,
+                    version: 5,
+                    members: 9,
+                },
+            },
+        ]
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        for entry in entries() {
+            assert_eq!(JournalEntry::parse(&entry.to_string()), Ok(entry));
+        }
+        assert_eq!(entries()[0].to_string(), "12 handled VoteMsg 3f2a0c1d9e8b7a65");
+        assert!(JournalEntry::parse("12 voted - 4 01 5 3f2a0c1d9e8b7a65").is_err());
+    }
+
+    #[test]
+    fn layouts_round_trip() {
+        let dir = ::std::env::temp_dir().join(format!("ewok-journal-{}", ::std::process::id()));
+        let (a, b) = (Name(0xaa << 56), Name(0xab << 56));
+        for layout in vec![
+            JournalLayout::PerNode(dir.join("nodes")),
+            JournalLayout::Indexed(dir.join("journal")),
+        ]
+        {
+            fs::create_dir_all(&dir).unwrap();
+            let mut journals = Journals::new(layout.clone()).unwrap();
+            // Two flushes, so that each node's journal is written in two chunks.
+            for _ in 0..2 {
+                for entry in entries() {
+                    journals.record(a, entry.step, entry.event.clone());
+                    journals.record(b, entry.step + 1, entry.event);
+                }
+                journals.flush().unwrap();
+            }
+
+            let read = read_journals(&layout, "AA").unwrap();
+            let expected: Vec<JournalEntry> = entries().into_iter().chain(entries()).collect();
+            assert_eq!(read, btreemap!{a => expected});
+            assert_eq!(read_journals(&layout, "a").unwrap().len(), 2);
+            assert!(read_journals(&layout, "b").unwrap().is_empty());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,10 @@
+//! Ewok simulates an algorithm for establishing an eventually consistent view of the members of a
+//! decentralised network.
+//!
+//! Code embedding ewok should use the types in `prelude`. Only the modules defining those types
+//! are documented; the rest are internals of the model and its tooling, public only for ewok's own
+//! binaries and tests, and may change without notice.
+
 extern crate rand;
 extern crate itertools;
 #[macro_use]
@@ -5,21 +12,132 @@ extern crate maplit;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+extern crate rayon;
+#[cfg(unix)]
+extern crate libc;
 
+#[doc(hidden)]
+pub mod assertion;
+#[doc(hidden)]
 pub mod block;
+#[doc(hidden)]
 pub mod blocks;
+pub mod builder;
+#[doc(hidden)]
+pub mod causality;
+#[doc(hidden)]
+pub mod chain;
+#[doc(hidden)]
+pub mod cohort;
+#[doc(hidden)]
+pub mod compaction;
+#[doc(hidden)]
+pub mod complexity;
+#[doc(hidden)]
+pub mod conflicts;
+#[doc(hidden)]
+pub mod connection;
+#[doc(hidden)]
+pub mod connectivity;
+#[doc(hidden)]
 pub mod consistency;
+#[doc(hidden)]
+pub mod coverage;
+#[doc(hidden)]
+pub mod differential;
+#[doc(hidden)]
+pub mod divergence;
+#[doc(hidden)]
+pub mod drops;
+#[doc(hidden)]
+pub mod dump;
+#[doc(hidden)]
+pub mod equivocation;
+pub mod error;
 pub mod event;
 pub mod event_schedule;
+#[doc(hidden)]
+pub mod fault;
+#[doc(hidden)]
+pub mod format;
+#[doc(hidden)]
 pub mod generate;
+#[doc(hidden)]
+pub mod hash;
+#[doc(hidden)]
+pub mod health;
+#[doc(hidden)]
+pub mod hygiene;
+#[doc(hidden)]
+pub mod journal;
+#[doc(hidden)]
+pub mod lifecycle;
+#[doc(hidden)]
+pub mod livelock;
+#[doc(hidden)]
 pub mod logging;
+#[doc(hidden)]
+pub mod manifest;
+#[doc(hidden)]
+pub mod membership;
+#[doc(hidden)]
+pub mod merge;
+#[doc(hidden)]
 pub mod message;
 pub mod name;
+#[doc(hidden)]
 pub mod network;
+#[doc(hidden)]
 pub mod node;
+#[doc(hidden)]
+pub mod outage;
 pub mod params;
+#[doc(hidden)]
+pub mod pause;
+#[doc(hidden)]
+pub mod prefix_tree;
+pub mod prelude;
+#[doc(hidden)]
+pub mod pretty;
+#[doc(hidden)]
+pub mod prometheus;
+#[doc(hidden)]
+pub mod proxy_failure;
+#[doc(hidden)]
+pub mod races;
+#[doc(hidden)]
 pub mod random;
+#[doc(hidden)]
 pub mod random_events;
+#[doc(hidden)]
+pub mod routing;
+#[doc(hidden)]
+pub mod run_id;
+pub mod scenario;
+#[doc(hidden)]
+pub mod section_message;
+#[doc(hidden)]
+pub mod secure_join;
 pub mod simulation;
+#[doc(hidden)]
+pub mod size_target;
+#[doc(hidden)]
+pub mod soak;
+#[doc(hidden)]
 pub mod split;
-pub mod merge;
+#[doc(hidden)]
+pub mod state_trace;
+#[doc(hidden)]
+pub mod stats;
+#[doc(hidden)]
+pub mod sweep;
+#[doc(hidden)]
+pub mod sybil;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod tune;
+#[doc(hidden)]
+pub mod validity;
+
+pub use error::{Error, Result};
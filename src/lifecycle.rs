@@ -0,0 +1,141 @@
+//! Tracking of the states each node passes through, from joining to death.
+//!
+//! Useful for spotting nodes which linger in one state, e.g. candidates that are never added.
+//!
+//! A relocated node runs on under a new name, so it has a timeline under each. Its old name
+//! passes through leaving to dead like any other node that stops. Its new name starts out
+//! relocating, and stays so until the node is a member of the section it was relocated to,
+//! without passing through joining or candidate.
+
+use blocks::Blocks;
+use format::FormatKind;
+use name::Name;
+use node::{NodeTrait, nodes_in_any};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleState {
+    /// Running, but no section member has connected to it yet.
+    Joining,
+    /// Connected to by a section member, but not yet a member itself.
+    Candidate,
+    /// Running under the name its section relocated it to, but not yet a member of the section
+    /// covering that name.
+    Relocating,
+    /// A member of a section, from its own point of view.
+    Member,
+    /// Stopped, but still a member of a section from the point of view of some other node.
+    Leaving,
+    /// Stopped, and no longer a member of any section known to a live node.
+    Dead,
+}
+
+/// Per-node timelines of lifecycle state transitions.
+#[derive(Clone, Default)]
+pub struct Lifecycles {
+    /// For each node, the steps at which it entered each state, in order.
+    timelines: BTreeMap<Name, Vec<(u64, LifecycleState)>>,
+    /// New names of relocated nodes which aren't members under them yet.
+    relocating: BTreeSet<Name>,
+    /// Last step that was observed.
+    last_step: u64,
+}
+
+impl Lifecycles {
+    /// Record the state of every node at the end of `step`.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        self.last_step = step;
+
+        let known_members: BTreeSet<Name> = nodes
+            .values()
+            .flat_map(|node| nodes_in_any(blocks, node.current_blocks()))
+            .collect();
+
+        let members: Vec<&N> = nodes
+            .values()
+            .filter(|node| !node.our_current_blocks(blocks).is_empty())
+            .collect();
+
+        self.relocating.retain(|name| nodes.contains_key(name));
+        for (name, node) in nodes {
+            let state = if !node.our_current_blocks(blocks).is_empty() {
+                let _ = self.relocating.remove(name);
+                LifecycleState::Member
+            } else if self.relocating.contains(name) {
+                LifecycleState::Relocating
+            } else if members.iter().any(|member| !member.is_disconnected_from(name)) {
+                LifecycleState::Candidate
+            } else {
+                LifecycleState::Joining
+            };
+            self.transition(*name, step, state);
+        }
+
+        let stopped: Vec<Name> = self.timelines
+            .keys()
+            .filter(|name| !nodes.contains_key(name))
+            .cloned()
+            .collect();
+        for name in stopped {
+            let state = if known_members.contains(&name) {
+                LifecycleState::Leaving
+            } else {
+                LifecycleState::Dead
+            };
+            self.transition(name, step, state);
+        }
+    }
+
+    /// Record that a node relocated at `step` runs on under the name `to`.
+    pub fn relocated(&mut self, to: Name, step: u64) {
+        let _ = self.relocating.insert(to);
+        self.transition(to, step, LifecycleState::Relocating);
+    }
+
+    fn transition(&mut self, name: Name, step: u64, state: LifecycleState) {
+        let timeline = self.timelines.entry(name).or_default();
+        match timeline.last() {
+            Some(&(_, LifecycleState::Dead)) => return,
+            Some(&(_, last)) if last == state => return,
+            _ => (),
+        }
+        timeline.push((step, state));
+    }
+
+    /// The state transitions of a single node, if it has been observed.
+    pub fn timeline(&self, name: &Name) -> Option<&[(u64, LifecycleState)]> {
+        self.timelines.get(name).map(|timeline| &timeline[..])
+    }
+
+    /// Nodes which have been stuck as candidates for at least `min_steps` steps.
+    pub fn stuck_candidates(&self, min_steps: u64) -> Vec<Name> {
+        self.timelines
+            .iter()
+            .filter(|&(_, timeline)| match timeline.last() {
+                Some(&(since, LifecycleState::Candidate)) => self.last_step >= since + min_steps,
+                _ => false,
+            })
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Write every node's timeline as CSV, one row per period spent in a state.
+    ///
+    /// The end step is exclusive, and empty for the state a node is still in.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", FormatKind::Lifecycle.run_header())?;
+        writeln!(writer, "node,state,start_step,end_step")?;
+        for (name, timeline) in &self.timelines {
+            for (i, &(start, state)) in timeline.iter().enumerate() {
+                let end = timeline
+                    .get(i + 1)
+                    .map(|&(end, _)| end.to_string())
+                    .unwrap_or_default();
+                writeln!(writer, "{:016x},{:?},{},{}", name.0, state, start, end)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! Watchdog for runs which stop making progress without the network going quiet.
+//!
+//! A prefix is livelocked once the blocks pending for it at any node have stayed the same for a
+//! given number of steps with no votes in flight to change them. Such a run would otherwise only
+//! end once the finishing phase runs out of steps.
+
+use block::BlockId;
+use blocks::Blocks;
+use name::{Name, Prefix};
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::mem;
+
+/// A prefix whose pending blocks stopped changing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Livelock {
+    pub prefix: Prefix,
+    /// Blocks which have been pending since `since_step`.
+    pub pending: BTreeSet<BlockId>,
+    pub since_step: u64,
+    /// Step at which the livelock was detected.
+    pub step: u64,
+}
+
+impl fmt::Display for Livelock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "livelock: {} blocks pending for {:?} with no votes in flight since step {}",
+            self.pending.len(),
+            self.prefix,
+            self.since_step
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct LivelockWatchdog {
+    /// Number of steps without progress after which a prefix counts as livelocked.
+    max_steps: u64,
+    /// Blocks pending for each prefix, and the step since which they've been unchanged.
+    pending: BTreeMap<Prefix, (BTreeSet<BlockId>, u64)>,
+    detected: Option<Livelock>,
+}
+
+impl LivelockWatchdog {
+    pub fn new(max_steps: u64) -> Self {
+        LivelockWatchdog {
+            max_steps,
+            pending: BTreeMap::new(),
+            detected: None,
+        }
+    }
+
+    /// Check the blocks pending at any node after `step`, given whether any votes are still in
+    /// flight. Returns the livelock if one has just been detected.
+    pub fn observe<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+        votes_in_flight: bool,
+    ) -> Option<&Livelock> {
+        let mut pending: BTreeMap<Prefix, BTreeSet<BlockId>> = BTreeMap::new();
+        for node in nodes.values() {
+            for id in node.pending_blocks() {
+                pending.entry(id.into_block(blocks).prefix).or_default().insert(id);
+            }
+        }
+        self.update(step, pending, votes_in_flight)
+    }
+
+    fn update(
+        &mut self,
+        step: u64,
+        pending: BTreeMap<Prefix, BTreeSet<BlockId>>,
+        votes_in_flight: bool,
+    ) -> Option<&Livelock> {
+        if self.detected.is_some() {
+            return None;
+        }
+        let previous = mem::take(&mut self.pending);
+        for (prefix, blocks) in pending {
+            let since_step = match previous.get(&prefix) {
+                Some(&(ref old, since_step)) if *old == blocks && !votes_in_flight => since_step,
+                _ => step,
+            };
+            if step - since_step >= self.max_steps && self.detected.is_none() {
+                self.detected = Some(Livelock {
+                    prefix,
+                    pending: blocks.clone(),
+                    since_step,
+                    step,
+                });
+            }
+            let _ = self.pending.insert(prefix, (blocks, since_step));
+        }
+        self.detected.as_ref()
+    }
+
+    /// The livelock detected, if any.
+    pub fn detected(&self) -> Option<&Livelock> {
+        self.detected.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+
+    #[test]
+    fn stalled_prefix_detected() {
+        let mut blocks = Blocks::new();
+        let block = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: BTreeSet::new(),
+        });
+        let pending = || btreemap!{ Prefix::empty() => btreeset!{block} };
+
+        let mut watchdog = LivelockWatchdog::new(3);
+        for step in 0..5 {
+            // Votes in flight keep resetting the count.
+            assert!(watchdog.update(step, pending(), true).is_none());
+        }
+        for step in 5..7 {
+            assert!(watchdog.update(step, pending(), false).is_none());
+        }
+        let livelock = watchdog.update(7, pending(), false).cloned().unwrap();
+        assert_eq!(livelock.since_step, 4);
+        assert_eq!(livelock.pending, btreeset!{block});
+        assert_eq!(watchdog.detected(), Some(&livelock));
+    }
+}
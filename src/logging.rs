@@ -1,12 +1,13 @@
 use std::env;
 use log::LogRecord;
+use run_id;
 use env_logger::{LogBuilder, LogTarget};
 
 /// If the `RUST_LOG` environment variable is set, enable logging.
 pub fn init_logging() {
     if let Ok(rust_log) = env::var("RUST_LOG") {
-        // Disable extraneous formatting.
-        let format = |record: &LogRecord| format!("{}", record.args());
+        // Disable extraneous formatting, other than the identifier of the run.
+        let format = |record: &LogRecord| run_id::tag_line(record.args());
 
         let mut builder = LogBuilder::new();
         builder.format(format).target(LogTarget::Stdout).parse(
@@ -1,28 +1,425 @@
+extern crate clap;
 extern crate ewok;
 
+use clap::{App, Arg, ArgMatches};
+use ewok::{Error, Result};
+use ewok::chain::Chain;
+use ewok::event_schedule::EventSchedule;
+use ewok::journal::JournalLayout;
 use ewok::simulation::Simulation;
-use ewok::params::{SimulationParams, NodeParams};
+use ewok::params::{self, GenesisNodes, SimulationParams, NodeParams};
+use ewok::scenario::{self, SimulationReport};
 use ewok::logging::init_logging;
+use ewok::manifest::RunManifest;
+use ewok::pause::PauseAction;
+use ewok::size_target::SizeTrajectory;
+use ewok::soak::SoakParams;
+use ewok::trace::ChurnTrace;
+use ewok::tune::{self, Search, Tuner};
+use ewok::random::{random, seed};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
 fn main() {
     init_logging();
 
-    let params = SimulationParams {
-        max_delay: 5,
-        grow_prob_join: 0.1,
-        grow_prob_drop: 0.02,
-        prob_churn: 0.05,
-        shrink_prob_join: 0.02,
-        shrink_prob_drop: 0.1,
-        prob_disconnect: 0.05,
-        // Gives ~95% chance that a pair will reconnect within 5 steps
-        prob_reconnect: 0.45,
-        starting_complete: 16,
-        grow_complete: 30,
-        stable_steps: 100,
+    let matches = App::new("ewok")
+        .about("Simulates a network of nodes agreeing on its sections' membership. Most settings \
+               are read from EWOK_* environment variables.")
+        .arg(Arg::with_name("preset")
+                 .long("preset")
+                 .value_name("NAME")
+                 .possible_values(params::PRESETS)
+                 .help("Starts from one of the named parameter presets"))
+        .arg(Arg::with_name("load_chain")
+                 .long("load-chain")
+                 .value_name("FILE")
+                 .help("Starts from the sections of a chain written with --dump-chain or \
+                        --checkpoint-dir"))
+        .arg(Arg::with_name("dump_chain")
+                 .long("dump-chain")
+                 .value_name("FILE")
+                 .help("Writes the chain the network ended with to FILE"))
+        .arg(Arg::with_name("checkpoint_dir")
+                 .long("checkpoint-dir")
+                 .value_name("DIR")
+                 .help("Saves a checkpoint every EWOK_CHECKPOINT_INTERVAL steps (100 by default), \
+                        writing the chain of each of the last 10 to DIR"))
+        .get_matches();
+
+    if let Err(err) = run(&matches) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let mut params = match matches.value_of("preset") {
+        Some(name) => SimulationParams::preset(name)?,
+        None => SimulationParams::default(),
+    };
+    // Setting EWOK_GENESIS_NODES starts from a genesis section of that many nodes.
+    if let Ok(count) = env::var("EWOK_GENESIS_NODES") {
+        let count = count.parse().map_err(|_| {
+            Error::Config(format!("EWOK_GENESIS_NODES must be a number, not {:?}", count))
+        })?;
+        params.genesis_nodes = GenesisNodes::Count(count);
+    }
+    // Setting EWOK_MAX_NODES or EWOK_MAX_STEPS stops the run once it has more than that many
+    // nodes, or has run that many steps, reporting which limit it reached.
+    if let Ok(max) = env::var("EWOK_MAX_NODES") {
+        params.max_nodes = Some(max.parse().map_err(|_| {
+            Error::Config(format!("EWOK_MAX_NODES must be a number, not {:?}", max))
+        })?);
+    }
+    if let Ok(max) = env::var("EWOK_MAX_STEPS") {
+        params.max_total_steps = Some(max.parse().map_err(|_| {
+            Error::Config(format!("EWOK_MAX_STEPS must be a number, not {:?}", max))
+        })?);
+    }
+
+    let node_params = NodeParams::default();
+    params.validate()?;
+    node_params.validate()?;
+
+    // Setting EWOK_TUNE to `<name>=<value>:<value>...,...` searches those values of the
+    // parameters for the ones minimising EWOK_TUNE_METRIC (join_latency by default) without any
+    // failed run, instead of making a single run. Each configuration is run with EWOK_TUNE_SEEDS
+    // seeds (5 by default). Setting EWOK_TUNE_GRID tries every configuration, rather than hill
+    // climbing through at most EWOK_TUNE_MAX_RUNS (50 by default). The best configuration found
+    // is printed as scenario `param` lines.
+    if let Ok(spec) = env::var("EWOK_TUNE") {
+        return tune_params(&spec, params, node_params);
+    }
+
+    // Setting EWOK_MANIFEST writes the parameters, seed and version of ewok to that file, and
+    // appends the run's report once it finishes.
+    let manifest_path = env::var("EWOK_MANIFEST").ok().map(PathBuf::from);
+    let manifest = manifest_path.as_ref().map(|_| RunManifest::new(&params, &node_params));
+
+    // Setting EWOK_SKIP_WARMUP starts from a converged network of `starting_complete` nodes, unless
+    // a chain is loaded instead.
+    let mut simulation = if let Some(path) = matches.value_of("load_chain") {
+        let chain = Chain::read(&mut BufReader::new(File::open(path)?))?;
+        Simulation::try_from_chain(&chain, EventSchedule::empty(), params, node_params)?
+    } else if env::var("EWOK_SKIP_WARMUP").is_ok() {
+        Simulation::try_warmed_up(
+            params.starting_complete,
+            EventSchedule::empty(),
+            params,
+            node_params,
+        )?
+    } else {
+        Simulation::new(params, node_params)
     };
+    // Written once the simulation is created, so that the header has the run's identifier.
+    if let (Some(path), Some(manifest)) = (manifest_path.as_ref(), manifest) {
+        manifest.create(path)?;
+    }
+
+    // Setting EWOK_SOAK_DIR runs the stable phase indefinitely, writing metrics to that directory.
+    // Setting EWOK_METRICS_PORT as well serves them on that port for Prometheus to scrape.
+    if let Ok(dir) = env::var("EWOK_SOAK_DIR") {
+        let mut soak_params = SoakParams::new(PathBuf::from(dir));
+        if let Ok(port) = env::var("EWOK_METRICS_PORT") {
+            soak_params.metrics_port = Some(port.parse().map_err(|_| {
+                Error::Config(format!("EWOK_METRICS_PORT must be a port number, not {:?}", port))
+            })?);
+        }
+        simulation.enable_soak(soak_params)?;
+    }
+
+    // Setting EWOK_THREADS handles each step's messages on that many threads.
+    if let Ok(threads) = env::var("EWOK_THREADS") {
+        let threads = threads.parse().map_err(|_| {
+            Error::Config(format!("EWOK_THREADS must be a number, not {:?}", threads))
+        })?;
+        simulation.set_num_threads(threads)?;
+    }
+
+    // Setting EWOK_CHURN_TRACE replays the joins and leaves in that CSV file instead of random
+    // ones, with each unit of trace time taking EWOK_TRACE_SCALE steps (1 by default).
+    if let Ok(path) = env::var("EWOK_CHURN_TRACE") {
+        let scale = match env::var("EWOK_TRACE_SCALE") {
+            Ok(scale) => scale.parse().map_err(|_| {
+                Error::Config(format!("EWOK_TRACE_SCALE must be a number, not {:?}", scale))
+            })?,
+            Err(_) => 1.0,
+        };
+        let trace = ChurnTrace::parse(&fs::read_to_string(path)?)?;
+        simulation.replay_churn_trace(trace, scale)?;
+    }
+
+    // Setting EWOK_SIZE_TARGET to `<step>:<size>,...` steers the network's size along that
+    // trajectory during the growth and stable phases instead of drawing random joins and leaves,
+    // joining or removing a node with probability EWOK_SIZE_GAIN (0.1 by default) per node of
+    // difference from the target.
+    if let Ok(trajectory) = env::var("EWOK_SIZE_TARGET") {
+        let gain = match env::var("EWOK_SIZE_GAIN") {
+            Ok(gain) => gain.parse().map_err(|_| {
+                Error::Config(format!("EWOK_SIZE_GAIN must be a number, not {:?}", gain))
+            })?,
+            Err(_) => 0.1,
+        };
+        simulation.target_network_size(SizeTrajectory::parse(&trajectory)?, gain)?;
+    }
+
+    // Setting EWOK_MESSAGE_COMPLEXITY reports the messages sent per block agreed, by kind, for
+    // sections in buckets of that many sizes.
+    if let Ok(width) = env::var("EWOK_MESSAGE_COMPLEXITY") {
+        let bucket_width = width.parse().map_err(|_| {
+            Error::Config(format!("EWOK_MESSAGE_COMPLEXITY must be a number, not {:?}", width))
+        })?;
+        simulation.track_message_complexity(bucket_width)?;
+    }
+
+    // Setting EWOK_DUMP_DIR dumps the state of the affected nodes there when a check first fails,
+    // and a non-convergence bundle if the nodes still disagree once a failed run has finished.
+    if let Ok(dir) = env::var("EWOK_DUMP_DIR") {
+        simulation.dump_on_failure(PathBuf::from(dir), 100);
+    }
+
+    // Setting EWOK_AUDIT_VALIDITY audits blocks becoming valid, reporting blocks which some nodes
+    // still haven't accepted that many steps after the first did.
+    if let Ok(steps) = env::var("EWOK_AUDIT_VALIDITY") {
+        let max_disagreement = steps.parse().map_err(|_| {
+            Error::Config(format!("EWOK_AUDIT_VALIDITY must be a number, not {:?}", steps))
+        })?;
+        simulation.audit_validity(max_disagreement);
+    }
+
+    // Setting EWOK_AUDIT_HYGIENE counts candidates held that many steps past their join timeout,
+    // and connections to names in no node's routing table. Setting EWOK_PURGE_HYGIENE as well
+    // has the nodes forget them.
+    if let Ok(steps) = env::var("EWOK_AUDIT_HYGIENE") {
+        let grace = steps.parse().map_err(|_| {
+            Error::Config(format!("EWOK_AUDIT_HYGIENE must be a number, not {:?}", steps))
+        })?;
+        simulation.audit_hygiene(grace, env::var("EWOK_PURGE_HYGIENE").is_ok());
+    }
+
+    // Setting EWOK_SECTION_HEALTH logs every section's health score each step, for graph_msgs to
+    // chart. Setting EWOK_HEALTH_ALERT to `<threshold>,<steps>` fails the run once a section's
+    // score stays below the threshold for that many steps.
+    if env::var("EWOK_SECTION_HEALTH").is_ok() {
+        simulation.monitor_health();
+    }
+    if let Ok(alert) = env::var("EWOK_HEALTH_ALERT") {
+        let invalid = || {
+            Error::Config(format!(
+                "EWOK_HEALTH_ALERT must be <threshold>,<steps>, not {:?}",
+                alert
+            ))
+        };
+        let mut fields = alert.split(',');
+        let threshold = fields.next().and_then(|field| field.parse().ok()).ok_or_else(
+            &invalid,
+        )?;
+        let max_steps = fields.next().and_then(|field| field.parse().ok()).ok_or_else(
+            &invalid,
+        )?;
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        simulation.alert_on_low_health(threshold, max_steps);
+    }
+
+    // Setting EWOK_LIFECYCLE_CSV writes every node's lifecycle timeline to that file.
+    let lifecycle_path = env::var("EWOK_LIFECYCLE_CSV").ok();
+    if lifecycle_path.is_some() {
+        simulation.record_lifecycles();
+    }
+
+    // Setting EWOK_PREFIX_TREE writes the prefix tree to that file as a graph in the DOT language
+    // each time the sections change.
+    let prefix_tree_path = env::var("EWOK_PREFIX_TREE").ok();
+    if prefix_tree_path.is_some() {
+        simulation.record_prefix_tree();
+    }
+
+    // Setting EWOK_STATE_TRACE writes the membership of every section, each time it changes, to
+    // that file as a trace in the Informal Trace Format, for checking against a TLA+
+    // specification with Apalache.
+    let state_trace_path = env::var("EWOK_STATE_TRACE").ok();
+    if state_trace_path.is_some() {
+        simulation.record_state_trace();
+    }
 
-    let mut simulation = Simulation::new(params, NodeParams::default());
+    // Setting EWOK_VOTE_RACES writes a timeline of the votes arriving at each node for every set
+    // of blocks which competed to follow the same block to that file.
+    let vote_races_path = env::var("EWOK_VOTE_RACES").ok();
+    if vote_races_path.is_some() {
+        simulation.record_vote_races();
+    }
+
+    // Setting EWOK_NODE_STATS_CSV writes every node's activity counters to that file.
+    if let Ok(path) = env::var("EWOK_NODE_STATS_CSV") {
+        simulation.write_metrics_to(PathBuf::from(path));
+    }
+
+    // Setting EWOK_JOURNAL_DIR writes a journal of every node's decisions to a file per node in
+    // that directory, and setting EWOK_JOURNAL_FILE writes them all to that file, indexed by node.
+    // Either can be replayed for a single node with the `inspect` tool.
+    if let Ok(dir) = env::var("EWOK_JOURNAL_DIR") {
+        simulation.write_journals(JournalLayout::PerNode(PathBuf::from(dir)))?;
+    } else if let Ok(path) = env::var("EWOK_JOURNAL_FILE") {
+        simulation.write_journals(JournalLayout::Indexed(PathBuf::from(path)))?;
+    }
+
+    // The last 10 checkpoints are kept, saved every EWOK_CHECKPOINT_INTERVAL steps (100 by
+    // default), if they're written to a directory or there's a debugger to rewind to them.
+    let checkpoint_dir = matches.value_of("checkpoint_dir").map(PathBuf::from);
+    let pause_debug = env::var("EWOK_PAUSE_DIR").is_ok() && env::var("EWOK_PAUSE_DEBUG").is_ok();
+    if checkpoint_dir.is_some() || pause_debug {
+        let interval = match env::var("EWOK_CHECKPOINT_INTERVAL") {
+            Ok(interval) => {
+                match interval.parse() {
+                    Ok(interval) if interval > 0 => interval,
+                    _ => {
+                        return Err(Error::Config(format!(
+                            "EWOK_CHECKPOINT_INTERVAL must be a positive number, not {:?}",
+                            interval
+                        )))
+                    }
+                }
+            }
+            Err(_) => 100,
+        };
+        simulation.record_checkpoints(interval, 10);
+    }
+    if let Some(dir) = checkpoint_dir {
+        simulation.write_checkpoint_chains(dir);
+    }
+
+    // Setting EWOK_PAUSE_DIR pauses the run at the next step on SIGINT or SIGUSR1, writing the
+    // chain to that directory. Setting EWOK_PAUSE_DEBUG as well drops into a debugger on stdin
+    // while paused, rather than waiting for SIGUSR1 to resume or another SIGINT to stop. The
+    // debugger can rewind to any step since the oldest checkpoint kept.
+    if let Ok(dir) = env::var("EWOK_PAUSE_DIR") {
+        simulation.pause_on_signal(PathBuf::from(dir))?;
+        if pause_debug {
+            simulation.set_debugger(debug_paused);
+        }
+    }
+
+    let result = simulation.run();
+
+    if let Some(path) = matches.value_of("dump_chain") {
+        simulation.write_chain(Path::new(path))?;
+    }
+
+    if let (Some(path), Some(lifecycles)) = (lifecycle_path, simulation.lifecycles()) {
+        let mut file = File::create(path)?;
+        lifecycles.write_csv(&mut file)?;
+    }
+
+    if let (Some(path), Some(prefix_tree)) = (prefix_tree_path, simulation.prefix_tree()) {
+        let mut file = File::create(path)?;
+        prefix_tree.write_dot(&mut file)?;
+    }
+
+    if let (Some(path), Some(state_trace)) = (state_trace_path, simulation.state_trace()) {
+        let mut file = File::create(path)?;
+        state_trace.write_itf(&mut file)?;
+    }
+
+    if let (Some(path), Some(vote_races)) = (vote_races_path, simulation.vote_races()) {
+        let mut file = File::create(path)?;
+        vote_races.write_report(simulation.blocks(), &mut file)?;
+    }
+
+    if let Some(ref path) = manifest_path {
+        RunManifest::append_report(path, &SimulationReport::new(&simulation, &result))?;
+    }
+
+    result.map(|_| ())
+}
+
+/// Search for the best values of the parameters in `spec`, starting from the given parameters.
+fn tune_params(spec: &str, params: SimulationParams, node_params: NodeParams) -> Result<()> {
+    let number = |var: &str, default: usize| match env::var(var) {
+        Ok(value) => {
+            value.parse().map_err(|_| {
+                Error::Config(format!("{} must be a number, not {:?}", var, value))
+            })
+        }
+        Err(_) => Ok(default),
+    };
+    let metric = env::var("EWOK_TUNE_METRIC").unwrap_or_else(|_| "join_latency".to_string());
+    let search = if env::var("EWOK_TUNE_GRID").is_ok() {
+        Search::Grid
+    } else {
+        Search::HillClimb { max_evaluations: number("EWOK_TUNE_MAX_RUNS", 50)? }
+    };
+    // Derived from the run's own seed, so that a search can be repeated with EWOK_SEED.
+    println!("# tuning with seed {:?}", seed());
+    let seeds = (0..number("EWOK_TUNE_SEEDS", 5)?).map(|_| random()).collect();
+
+    let mut tuner = Tuner::new(tune::parse_params(spec)?, &metric, seeds)?;
+    let _ = tuner.search(search, |config| {
+        let (mut params, mut node_params) = (params.clone(), node_params.clone());
+        tune::apply(config, &mut params, &mut node_params)?;
+        let mut simulation = Simulation::new(params, node_params);
+        simulation.audit_validity(u64::MAX);
+        let _ = simulation.run()?;
+        Ok(tune::run_metrics(&simulation))
+    });
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    tuner.sweep().write_markdown(None, &mut stdout)?;
+    tuner.write_best(&mut stdout)?;
+    Ok(())
+}
 
-    simulation.run().unwrap();
+/// Inspect a paused simulation with commands read from stdin. `rewind <step>` goes back to an
+/// earlier step, from which the run continues. Any other command is read as an event in the
+/// scenario syntax, e.g. `partition 0 1`, and applied at the next step.
+fn debug_paused(simulation: &mut Simulation) -> PauseAction {
+    println!(
+        "Paused at step {}. Commands: sections, stats, rewind <step>, continue, stop, or a \
+         scenario event",
+        simulation.step()
+    );
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return PauseAction::Resume,
+            Ok(_) => (),
+        }
+        match line.trim() {
+            "sections" => print!("{}", simulation.chain().pretty()),
+            "stats" => println!("{:#?}", simulation.node_stats()),
+            "continue" => return PauseAction::Resume,
+            "stop" => return PauseAction::Stop,
+            "" => (),
+            command if command.starts_with("rewind ") => {
+                let step = command["rewind ".len()..].trim();
+                match step.parse() {
+                    Ok(step) => {
+                        match simulation.rewind(step) {
+                            Ok(()) => println!("Rewound to step {}", step),
+                            Err(err) => println!("{}", err),
+                        }
+                    }
+                    Err(_) => println!("invalid step {:?}", step),
+                }
+            }
+            command => {
+                match scenario::parse_event_line(command) {
+                    Ok(event) => {
+                        println!("{:?} will be applied at the next step", event);
+                        simulation.inject_event(event);
+                    }
+                    Err(_) => println!("unknown command {:?}", command),
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,103 @@
+//! Run manifests: a file written at the start of a run with everything needed to repeat it, to
+//! which the run's report is appended once it finishes, so that a directory of results explains
+//! itself long after the run.
+//!
+//! The manifest starts with the usual `# ewok manifest format <version>` header line, followed by
+//! the version of ewok and the git commit it was built from, the seed, and the full parameter
+//! sets. The report is appended after a blank line, in the scenario report format.
+
+use error::Result;
+use format::FormatKind;
+use params::{NodeParams, SimulationParams};
+use random::seed;
+use scenario::SimulationReport;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Git commit ewok was built from, if it was built from a git checkout.
+pub const GIT_HASH: Option<&str> = option_env!("EWOK_GIT_HASH");
+
+#[derive(Clone, Debug)]
+pub struct RunManifest {
+    /// Version of the ewok crate.
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub seed: [u32; 4],
+    pub params: SimulationParams,
+    pub node_params: NodeParams,
+}
+
+impl RunManifest {
+    /// The manifest of a run with the given parameters, seeded from the current seed.
+    pub fn new(params: &SimulationParams, node_params: &NodeParams) -> Self {
+        RunManifest {
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: GIT_HASH,
+            seed: seed(),
+            params: params.clone(),
+            node_params: node_params.clone(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", FormatKind::Manifest.run_header())?;
+        writeln!(writer, "ewok version: {}", self.version)?;
+        writeln!(writer, "git hash: {}", self.git_hash.unwrap_or("unknown"))?;
+        writeln!(writer, "seed: {:?}", self.seed)?;
+        writeln!(writer, "simulation params: {:#?}", self.params)?;
+        writeln!(writer, "node params: {:#?}", self.node_params)
+    }
+
+    /// Write the manifest to a new file at `path`, replacing any existing one.
+    pub fn create(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path)?;
+        self.write(&mut file)?;
+        Ok(())
+    }
+
+    /// Append the report of the finished run to the manifest at `path`.
+    pub fn append_report(path: &Path, report: &SimulationReport) -> Result<()> {
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        write!(file, "\n{}", report)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn manifest_then_report() {
+        let manifest = RunManifest::new(&SimulationParams::default(), &NodeParams::default());
+        let path = env::temp_dir().join(format!("ewok-manifest-test-{}", process::id()));
+        manifest.create(&path).unwrap();
+        let report = SimulationReport {
+            failure: None,
+            final_step: 10,
+            sections: BTreeMap::new(),
+            joined: 1,
+            rejected: 0,
+            blocks_agreed: 2,
+            failed_assertions: vec![],
+        };
+        RunManifest::append_report(&path, &report).unwrap();
+        let text = fs::read_to_string(&path);
+        let _ = fs::remove_file(&path);
+        let text = text.unwrap();
+
+        let mut lines = text.lines();
+        let version = lines.next().and_then(|line| FormatKind::Manifest.parse_header(line));
+        assert_eq!(version, Some(FormatKind::Manifest.current_version()));
+        assert!(text.contains(&format!("seed: {:?}\n", seed())));
+        assert!(text.contains("    max_delay: "));
+        assert!(text.contains("    min_section_size: "));
+        assert!(text.ends_with(&format!("}}\n\n{}", report)));
+    }
+}
@@ -0,0 +1,128 @@
+//! History of section membership, for asking who was in a section at a given version or step
+//! without replaying logs.
+//!
+//! A block counts as agreed from the first step at which it's current at any node.
+
+use block::{Block, BlockId};
+use blocks::Blocks;
+use name::{Name, Prefix};
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A block of a section's chain, with the step at which it was agreed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AgreedBlock {
+    pub version: u64,
+    pub step: u64,
+    pub members: BTreeSet<Name>,
+}
+
+/// Every block agreed so far, by prefix.
+#[derive(Clone, Default)]
+pub struct MembershipHistory {
+    seen: BTreeSet<BlockId>,
+    /// Blocks of each prefix, in the order they were agreed.
+    chains: BTreeMap<Prefix, Vec<AgreedBlock>>,
+}
+
+impl MembershipHistory {
+    /// Record any blocks which are current at some node for the first time.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        for node in nodes.values() {
+            for id in node.current_blocks() {
+                if !self.seen.contains(id) {
+                    self.record(step, *id, id.into_block(blocks));
+                }
+            }
+        }
+    }
+
+    /// Record that `block` was agreed at `step`, unless it already has been.
+    pub fn record(&mut self, step: u64, id: BlockId, block: &Block) {
+        if !self.seen.insert(id) {
+            return;
+        }
+        self.chains.entry(block.prefix).or_default().push(AgreedBlock {
+            version: block.version,
+            step,
+            members: block.members.clone(),
+        });
+    }
+
+    /// The blocks agreed for the section with exactly this prefix, in order of agreement.
+    pub fn chain(&self, prefix: &Prefix) -> &[AgreedBlock] {
+        self.chains.get(prefix).map_or(&[], Vec::as_slice)
+    }
+
+    /// Members of the section with this prefix as of the given version. If several blocks with
+    /// the version were agreed, the first is used.
+    pub fn members_at_version(&self, prefix: &Prefix, version: u64) -> Option<&BTreeSet<Name>> {
+        self.chain(prefix)
+            .iter()
+            .find(|block| block.version == version)
+            .map(|block| &block.members)
+    }
+
+    /// Members of the section with this prefix at the end of `step`, as of the newest block
+    /// agreed by then: a block losing out to one agreed in the same step may be recorded after
+    /// it. If at that point the prefix was still part of a larger section, or had been merged
+    /// into one, the members of that section which match the prefix are given instead.
+    pub fn members_at_step(&self, prefix: &Prefix, step: u64) -> Option<BTreeSet<Name>> {
+        self.chains
+            .iter()
+            .filter(|&(section, _)| section.is_prefix_of(prefix))
+            .flat_map(|(_, chain)| chain.iter().filter(|block| block.step <= step))
+            .max_by_key(|block| (block.step, block.version))
+            .map(|block| {
+                block
+                    .members
+                    .iter()
+                    .filter(|name| prefix.matches(**name))
+                    .cloned()
+                    .collect()
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn members_over_time() {
+        let p = Prefix::empty();
+        let p0 = p.pushed(false);
+        let names: Vec<Name> = vec![Name(0), Name(1), Name(1 << 63), Name(2)];
+        let block = |prefix: Prefix, version, members: &[Name]| {
+            Block {
+                prefix,
+                version,
+                members: members.iter().cloned().collect(),
+            }
+        };
+
+        let mut history = MembershipHistory::default();
+        let genesis = block(p, 0, &names[..3]);
+        history.record(0, genesis.get_id(), &genesis);
+        let split = block(p0, 1, &names[..2]);
+        history.record(10, split.get_id(), &split);
+        let add = block(p0, 2, &[names[0], names[1], names[3]]);
+        history.record(20, add.get_id(), &add);
+
+        assert_eq!(history.members_at_version(&p0, 1), Some(&split.members));
+        assert_eq!(history.members_at_version(&p0, 5), None);
+        // Before the split, the members of the parent section matching the prefix.
+        assert_eq!(history.members_at_step(&p0, 5), Some(split.members.clone()));
+        assert_eq!(history.members_at_step(&p0, 15), Some(split.members.clone()));
+        assert_eq!(history.members_at_step(&p0, 25), Some(add.members.clone()));
+        assert_eq!(history.members_at_step(&p, 5), Some(genesis.members.clone()));
+
+        // A block losing out to one agreed in the same step, recorded after it.
+        let next = block(p0, 3, &[names[0], names[3]]);
+        history.record(30, next.get_id(), &next);
+        let sibling = block(p0, 2, &names[..2]);
+        history.record(30, sibling.get_id(), &sibling);
+        assert_eq!(history.members_at_step(&p0, 30), Some(next.members.clone()));
+    }
+}
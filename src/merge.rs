@@ -1,4 +1,4 @@
-use name::Name;
+use name::{Name, Prefix};
 use block::{Block, Vote};
 use blocks::{CurrentBlocks, Blocks};
 use std::collections::BTreeSet;
@@ -21,6 +21,36 @@ pub fn merge_blocks(
     result.into_iter().collect()
 }
 
+/// Votes to merge our sections with their siblings, when the prefix they'd merge into is in
+/// `targets`, whatever their sizes.
+pub fn forced_merge_blocks(
+    blocks: &mut Blocks,
+    current_blocks: &CurrentBlocks,
+    our_name: Name,
+    targets: &BTreeSet<Prefix>,
+) -> Vec<Vote> {
+    let mut votes = vec![];
+    let mut blocks_to_insert = vec![];
+    for our_block in blocks.our_blocks(current_blocks, our_name) {
+        let sibling_prefix = match our_block.prefix.sibling() {
+            Some(sibling) if targets.contains(&our_block.prefix.popped()) => sibling,
+            _ => continue,
+        };
+        for sibling_block in blocks.blocks_for_prefix(current_blocks, sibling_prefix) {
+            let target = merged_block(sibling_block, our_block);
+            votes.push(Vote {
+                from: our_block.get_id(),
+                to: target.get_id(),
+            });
+            blocks_to_insert.push(target);
+        }
+    }
+    for block in blocks_to_insert {
+        blocks.insert(block);
+    }
+    votes
+}
+
 fn force_merge_rule(
     blocks: &mut Blocks,
     current_blocks: &CurrentBlocks,
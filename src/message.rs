@@ -1,8 +1,12 @@
-use block::{BlockId, Vote};
+use block::{BlockId, Vote, VoteKind};
 use blocks::{VoteCounts, CurrentBlocks, Blocks};
+use compaction::Snapshot;
 use name::{Name, Prefix};
+use section_message::SectionMessage;
 use self::MessageContent::*;
 use std::collections::BTreeSet;
+use std::slice;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Message {
@@ -11,27 +15,70 @@ pub struct Message {
     pub content: MessageContent,
 }
 
+/// Content of a message.
+///
+/// Large payloads are held in an `Arc` so that broadcasting a message to many recipients shares
+/// one copy of the payload instead of cloning it per recipient. Equality and hashing (and so the
+/// message filter) still go by the payload's contents.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum MessageContent {
     /// Vote for a block to succeed another block.
     VoteMsg(Vote),
+    /// Votes cast over several steps, sent together when batching votes.
+    VoteBatch(Arc<Vec<Vote>>),
     /// Notification that we believe this vote to be agreed by all the listed members.
-    VoteAgreedMsg((Vote, BTreeSet<Name>)),
+    VoteAgreedMsg(Arc<(Vote, BTreeSet<Name>)>),
     /// Collection of agreed votes, sent during a merge.
-    VoteBundle(Vec<(Vote, BTreeSet<Name>)>),
+    VoteBundle(Arc<Vec<(Vote, BTreeSet<Name>)>>),
     /// Request for a proof for the given block
     RequestProof(BlockId, CurrentBlocks),
     /// Means that the node couldn't prove the requested block
     NoProof(BlockId),
     /// Message sent from joining node (sender) to all section members (recipients).
     NodeJoined,
+    /// Request from a joining node (sender) for the recipient to forward its join to the members
+    /// of its section.
+    JoinRequest,
+    /// A join forwarded on behalf of the given node, handled like its `NodeJoined`.
+    ForwardedJoin(Name),
+    /// Notification that the sender is willing to add the given candidate to its section.
+    ApproveCandidate(Name),
+    /// Answer to a join from a member of a busy section, asking the joining node to send its
+    /// join again after the given number of steps.
+    TryLater(u64),
     /// Message sent to a joining node to get it up to date on the current blocks.
-    BootstrapMsg(VoteCounts),
+    BootstrapMsg(Arc<VoteCounts>),
+    /// Bootstrap message from a node which has compacted its history: the joining node starts
+    /// from the snapshots instead of the genesis blocks, then applies the votes.
+    SnapshotBootstrapMsg(Arc<(Vec<Snapshot>, VoteCounts)>),
+    /// Agreed votes leading up to the recipient's addition to our section, sent to it once the
+    /// block adding it is valid.
+    WelcomeMsg(Arc<Vec<(Vote, BTreeSet<Name>)>>),
+    /// Request from a node bootstrapped with part of the history for the history of the sections
+    /// covered by these prefixes, answered with a bootstrap message.
+    RequestChains(Vec<Prefix>),
+    /// The sender's signature share for a message from its section, sent to the rest of the
+    /// section.
+    SectionShare(Arc<SectionMessage>),
+    /// A message from a section, signed by the listed members of its block.
+    SectionMsg(Arc<(SectionMessage, BTreeSet<Name>)>),
+    /// Notification that the sender has been disconnected from the given member of its section
+    /// and suspects it has left.
+    NodeSuspected(Name),
+    /// Notification that the sender has reconnected to the given member, withdrawing its
+    /// suspicion.
+    SuspicionWithdrawn(Name),
+    /// Evidence that the given member of the sender's section equivocated: two votes it signed
+    /// from the same block for conflicting sibling blocks.
+    EquivocationProof(Arc<(Name, Vote, Vote)>),
     /// Connect and disconnect represent the connection or disconnection of two nodes.
     /// Can be sent from node-to-node or from the simulation to a pair of nodes (for disconnects
     /// and reconnects).
     /// See handling in node.rs.
     Connect,
+    /// A connection request carrying the sender's pending votes, with all the voters it knows
+    /// of. Handled like `Connect`, with the votes applied if the connection is accepted.
+    ConnectWithVotes(Arc<Vec<(Vote, BTreeSet<Name>)>>),
     /// ^See above.
     Disconnect,
 }
@@ -42,6 +89,96 @@ fn prefix_dist(p1: &Prefix, p2: &Prefix) -> u64 {
 }
 
 impl MessageContent {
+    /// Whether this message carries votes.
+    pub fn carries_votes(&self) -> bool {
+        matches!(
+            *self,
+            VoteMsg(_) | VoteBatch(_) | VoteAgreedMsg(_) | VoteBundle(_) | ConnectWithVotes(_) |
+                BootstrapMsg(_) | SnapshotBootstrapMsg(_) | WelcomeMsg(_)
+        )
+    }
+
+    /// The votes this message carries on their own, rather than with their voters.
+    pub fn plain_votes(&self) -> &[Vote] {
+        match *self {
+            VoteMsg(ref vote) => slice::from_ref(vote),
+            VoteBatch(ref votes) => votes,
+            _ => &[],
+        }
+    }
+
+    /// Name of the kind of message, for logs and reports.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            VoteMsg(_) => "VoteMsg",
+            VoteBatch(_) => "VoteBatch",
+            VoteAgreedMsg(_) => "VoteAgreedMsg",
+            VoteBundle(_) => "VoteBundle",
+            RequestProof(..) => "RequestProof",
+            NoProof(_) => "NoProof",
+            NodeJoined => "NodeJoined",
+            JoinRequest => "JoinRequest",
+            ForwardedJoin(_) => "ForwardedJoin",
+            ApproveCandidate(_) => "ApproveCandidate",
+            TryLater(_) => "TryLater",
+            BootstrapMsg(_) => "BootstrapMsg",
+            SnapshotBootstrapMsg(_) => "SnapshotBootstrapMsg",
+            WelcomeMsg(_) => "WelcomeMsg",
+            RequestChains(_) => "RequestChains",
+            SectionShare(_) => "SectionShare",
+            SectionMsg(_) => "SectionMsg",
+            NodeSuspected(_) => "NodeSuspected",
+            SuspicionWithdrawn(_) => "SuspicionWithdrawn",
+            EquivocationProof(_) => "EquivocationProof",
+            Connect => "Connect",
+            ConnectWithVotes(_) => "ConnectWithVotes",
+            Disconnect => "Disconnect",
+        }
+    }
+
+    /// All the blocks referred to by this message.
+    pub fn block_ids(&self) -> BTreeSet<BlockId> {
+        match *self {
+            VoteMsg(ref vote) => btreeset!{vote.from, vote.to},
+            VoteBatch(ref votes) => votes.iter().flat_map(|vote| vec![vote.from, vote.to]).collect(),
+            VoteAgreedMsg(ref agreed) => btreeset!{agreed.0.from, agreed.0.to},
+            VoteBundle(ref bundle) |
+            ConnectWithVotes(ref bundle) |
+            WelcomeMsg(ref bundle) => {
+                bundle
+                    .iter()
+                    .flat_map(|(vote, _)| vec![vote.from, vote.to])
+                    .collect()
+            }
+            RequestProof(block, ref current_blocks) => {
+                let mut ids = current_blocks.clone();
+                ids.insert(block);
+                ids
+            }
+            NoProof(block) => btreeset!{block},
+            EquivocationProof(ref proof) => btreeset!{proof.1.from, proof.1.to, proof.2.to},
+            SectionShare(ref message) => message.block_ids(),
+            SectionMsg(ref signed) => signed.0.block_ids(),
+            BootstrapMsg(ref vote_counts) => {
+                vote_counts
+                    .iter()
+                    .flat_map(|(from, map)| map.keys().chain(Some(from)).cloned())
+                    .collect()
+            }
+            SnapshotBootstrapMsg(ref bootstrap) => {
+                let (ref snapshots, ref vote_counts) = **bootstrap;
+                vote_counts
+                    .iter()
+                    .flat_map(|(from, map)| map.keys().chain(Some(from)).cloned())
+                    .chain(snapshots.iter().map(|snapshot| snapshot.head))
+                    .collect()
+            }
+            NodeJoined | JoinRequest | ForwardedJoin(_) | ApproveCandidate(_) | TryLater(_) |
+            NodeSuspected(_) | SuspicionWithdrawn(_) | RequestChains(_) | Connect |
+            Disconnect => btreeset!{},
+        }
+    }
+
     pub fn recipients(
         &self,
         blocks: &Blocks,
@@ -49,13 +186,9 @@ impl MessageContent {
         our_name: Name,
     ) -> BTreeSet<Name> {
         match *self {
-            // Send votes to members of the `from` and `to` blocks.
-            VoteMsg(ref vote) => {
-                let from = vote.from.into_block(blocks);
-                let to = vote.to.into_block(blocks);
-                &from.members | &to.members
-            }
-            VoteAgreedMsg((Vote { ref from, ref to }, _)) => {
+            VoteMsg(ref vote) => vote_recipients(vote, blocks, current_blocks),
+            VoteAgreedMsg(ref agreed) => {
+                let Vote { ref from, ref to } = agreed.0;
                 let from = from.into_block(blocks);
                 let to = to.into_block(blocks);
 
@@ -95,6 +228,18 @@ impl MessageContent {
                     .flat_map(|block| block.members.iter().cloned())
                     .collect()
             }
+            // Send candidate approvals, suspicions and equivocation evidence to the rest of our
+            // own section.
+            ApproveCandidate(_) |
+            NodeSuspected(_) |
+            SuspicionWithdrawn(_) |
+            EquivocationProof(_) => {
+                blocks
+                    .our_blocks(current_blocks, our_name)
+                    .into_iter()
+                    .flat_map(|block| block.members.iter().cloned())
+                    .collect()
+            }
             // Send anything else to all connected neighbours.
             _ => {
                 blocks
@@ -106,3 +251,67 @@ impl MessageContent {
         }
     }
 }
+
+/// Recipients for a vote, depending on the kind of change it's for.
+///
+/// Votes always go to the members of the `from` and `to` blocks. A merge needs votes from both
+/// merging sections, whose membership may have changed since the merged block was constructed, so
+/// votes for a merge also go to the members of every current block within the merged prefix.
+fn vote_recipients(vote: &Vote, blocks: &Blocks, current_blocks: &CurrentBlocks) -> BTreeSet<Name> {
+    let from = vote.from.into_block(blocks);
+    let to = vote.to.into_block(blocks);
+    let mut recipients = &from.members | &to.members;
+    match vote.kind(blocks) {
+        VoteKind::Merge => {
+            for block in blocks.block_contents(current_blocks) {
+                if to.prefix.is_prefix_of(&block.prefix) {
+                    recipients.extend(&block.members);
+                }
+            }
+        }
+        VoteKind::Split | VoteKind::Membership | VoteKind::Relocation => (),
+    }
+    recipients
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+
+    #[test]
+    fn merge_votes_reach_current_sibling() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let members = |prefix: Prefix, range: ::std::ops::Range<u64>| -> BTreeSet<Name> {
+            range.map(|i| prefix.substituted_in(Name(i))).collect()
+        };
+
+        let mut blocks = Blocks::new();
+        let b0 = Block {
+            prefix: p0,
+            version: 1,
+            members: members(p0, 0..2),
+        };
+        // A node has joined section 1 since the merged block was constructed.
+        let b1 = Block {
+            prefix: p1,
+            version: 2,
+            members: members(p1, 0..3),
+        };
+        let merged = Block {
+            prefix: Prefix::empty(),
+            version: 2,
+            members: &b0.members | &members(p1, 0..2),
+        };
+        let vote = Vote {
+            from: blocks.insert(b0.clone()),
+            to: blocks.insert(merged),
+        };
+        let current_blocks = btreeset!{ vote.from, blocks.insert(b1.clone()) };
+
+        assert_eq!(vote.kind(&blocks), VoteKind::Merge);
+        let recipients = VoteMsg(vote).recipients(&blocks, &current_blocks, Name(0));
+        assert_eq!(recipients, &b0.members | &b1.members);
+    }
+}
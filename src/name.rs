@@ -229,6 +229,19 @@ impl Prefix {
                 ))
     }
 
+    /// Returns the largest prefixes within `self` which aren't covered by any of `prefixes`.
+    pub fn uncovered_by(&self, prefixes: &[Prefix]) -> Vec<Prefix> {
+        if prefixes.iter().any(|prefix| prefix.is_prefix_of(self)) {
+            return vec![];
+        }
+        if !prefixes.iter().any(|prefix| self.is_prefix_of(prefix)) {
+            return vec![*self];
+        }
+        let mut uncovered = self.pushed(false).uncovered_by(prefixes);
+        uncovered.extend(self.pushed(true).uncovered_by(prefixes));
+        uncovered
+    }
+
     /// Returns the given `name` with first bits replaced by `self`
     pub fn substituted_in(&self, mut name: Name) -> Name {
         // TODO: is there a more efficient way of doing that?
@@ -321,4 +334,19 @@ mod test {
         assert!(p1.is_sibling_of_ancestor_of(&p000));
         assert!(!p10.is_sibling_of_ancestor_of(&p000));
     }
+
+    #[test]
+    fn uncovered() {
+        let p00 = Prefix::short(2, 0);
+        let p01 = Prefix::short(2, 0b01000000);
+        let p1 = Prefix::short(1, 0b10000000);
+        let p111 = Prefix::short(3, 0b11100000);
+        assert_eq!(Prefix::empty().uncovered_by(&[p00, p01, p1]), vec![]);
+        assert_eq!(Prefix::empty().uncovered_by(&[p01, p111]), vec![
+            p00,
+            Prefix::short(2, 0b10000000),
+            Prefix::short(3, 0b11000000),
+        ]);
+        assert_eq!(p1.uncovered_by(&[p00]), vec![p1]);
+    }
 }
@@ -1,26 +1,82 @@
-use std::collections::BTreeMap;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
 use std::mem;
+use block::BlockId;
+use causality::CausalityTracker;
 use message::Message;
-use name::Name;
+use message::MessageContent;
+use name::{Name, Prefix};
+use params::DelayModel;
+use section_message::SectionMessage;
 
-use random::do_with_probability;
+use random::{do_with_probability, in_stream, random, Stream};
 
 /// Network model with synchronous, in-order delivery.
+#[derive(Clone)]
 pub struct Network {
     /// Maximum delay in steps before a message is guaranteed to have been delivered.
     max_delay: u64,
     /// Probability that a message is delivered on a given step.
     prob_deliver: f64,
+    /// Number of steps after which undelivered messages are dropped, if any.
+    ttl: Option<u64>,
+    /// Number of messages dropped for exceeding the TTL.
+    expired: u64,
+    delay_model: DelayModel,
+    /// Latency of each pair of nodes, smaller name first, with the `PerConnection` delay model.
+    latencies: BTreeMap<(Name, Name), u64>,
     /// Map from a connection between two nodes and step # to messages inserted at that step.
     messages: BTreeMap<(Name, Name), BTreeMap<u64, Vec<Message>>>,
+    /// Nodes which can only be reached by peers they've opened a connection to, as if behind a NAT.
+    inbound_blocked: BTreeSet<Name>,
+    /// Connections opened by nodes in `inbound_blocked`, as (blocked node, peer).
+    opened: BTreeSet<(Name, Name)>,
+    /// Number of messages dropped for being sent to a node which blocks them.
+    blocked: u64,
+    /// Section messages sent to each node so far, which are only delivered to it once.
+    section_messages: BTreeSet<(Name, SectionMessage)>,
+    /// Number of section messages dropped for having been sent to their recipient already.
+    section_duplicates: u64,
+    /// Logical clocks and causes of the messages sent, if being tracked.
+    causality: Option<CausalityTracker>,
+    /// Number of messages of each kind each node has sent since they were last taken, if being
+    /// counted.
+    sent_counts: Option<BTreeMap<(Name, &'static str), u64>>,
+    /// Number of messages of each kind sent since they were last taken.
+    sent_by_kind: BTreeMap<&'static str, u64>,
+    /// Senders, recipients and kinds of the next messages to drop, in the order the drops were
+    /// forced.
+    forced_drops: Vec<(Name, Name, String)>,
+    /// Number of messages dropped because a drop was forced for them.
+    forced: u64,
+    /// Pairs of prefixes which no messages but disconnects get between.
+    partitions: BTreeSet<(Prefix, Prefix)>,
+    /// Number of messages dropped for crossing a partition.
+    partitioned: u64,
 }
 
 impl Network {
-    pub fn new(max_delay: u64) -> Self {
+    pub fn new(max_delay: u64, ttl: Option<u64>, delay_model: DelayModel) -> Self {
         Network {
             max_delay,
             prob_deliver: Self::delivery_probability(max_delay),
+            ttl,
+            expired: 0,
+            delay_model,
+            latencies: BTreeMap::new(),
             messages: BTreeMap::new(),
+            inbound_blocked: BTreeSet::new(),
+            opened: BTreeSet::new(),
+            blocked: 0,
+            section_messages: BTreeSet::new(),
+            section_duplicates: 0,
+            causality: None,
+            sent_counts: None,
+            sent_by_kind: BTreeMap::new(),
+            forced_drops: vec![],
+            forced: 0,
+            partitions: BTreeSet::new(),
+            partitioned: 0,
         }
     }
 
@@ -40,6 +96,21 @@ impl Network {
 
     /// Get messages delivered at the given step (randomised).
     pub fn receive(&mut self, step: u64) -> Vec<Message> {
+        if let Some(ttl) = self.ttl {
+            self.expire(step.saturating_sub(ttl));
+        }
+
+        if self.delay_model == DelayModel::PerConnection {
+            let latencies = &self.latencies;
+            return self.messages
+                .iter_mut()
+                .flat_map(|(&(sender, recipient), messages)| {
+                    let latency = latencies[&Self::pair(sender, recipient)];
+                    Self::receive_sent_before(messages, (step + 1).saturating_sub(latency))
+                })
+                .collect();
+        }
+
         let start_step = step.saturating_sub(self.max_delay);
         let prob_deliver = self.prob_deliver;
         let max_delay = self.max_delay;
@@ -52,6 +123,204 @@ impl Network {
             .collect()
     }
 
+    /// Get all messages on a single connection which were sent before `end_step`.
+    fn receive_sent_before(
+        conn_messages: &mut BTreeMap<u64, Vec<Message>>,
+        end_step: u64,
+    ) -> Vec<Message> {
+        let later = conn_messages.split_off(&end_step);
+        mem::replace(conn_messages, later)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// The latency between two nodes with the `PerConnection` delay model, once they've
+    /// exchanged a message.
+    pub fn latency(&self, a: Name, b: Name) -> Option<u64> {
+        self.latencies.get(&Self::pair(a, b)).cloned()
+    }
+
+    fn pair(a: Name, b: Name) -> (Name, Name) {
+        (cmp::min(a, b), cmp::max(a, b))
+    }
+
+    /// Drop all messages sent before `min_step`.
+    fn expire(&mut self, min_step: u64) {
+        let mut num_expired = 0;
+        for (&(sender, recipient), conn_messages) in &mut self.messages {
+            let keep = conn_messages.split_off(&min_step);
+            let conn_expired = conn_messages.values().map(Vec::len).sum::<usize>();
+            num_expired += conn_expired;
+            if let Some(ref mut causality) = self.causality {
+                causality.expired(sender, recipient, conn_expired);
+            }
+            *conn_messages = keep;
+        }
+        if num_expired > 0 {
+            debug!("Network: dropped {} expired messages", num_expired);
+        }
+        self.expired += num_expired as u64;
+    }
+
+    /// Number of messages dropped so far for exceeding the TTL.
+    pub fn messages_expired(&self) -> u64 {
+        self.expired
+    }
+
+    /// Only deliver messages to `name` from peers it has sent a message to, until it sends them
+    /// a disconnect.
+    pub fn block_inbound(&mut self, name: Name) {
+        let _ = self.inbound_blocked.insert(name);
+    }
+
+    pub fn is_inbound_blocked(&self, name: &Name) -> bool {
+        self.inbound_blocked.contains(name)
+    }
+
+    /// Number of messages dropped so far for being sent to a node which blocks them.
+    pub fn messages_blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    /// Whether `message` gets through to its recipient, opening or closing connections as nodes
+    /// with blocked inbound connections send messages.
+    fn admit(&mut self, message: &Message) -> bool {
+        let disconnect = matches!(message.content, MessageContent::Disconnect);
+        if self.inbound_blocked.contains(&message.sender) {
+            let conn = (message.sender, message.recipient);
+            if disconnect {
+                let _ = self.opened.remove(&conn);
+            } else {
+                let _ = self.opened.insert(conn);
+            }
+        }
+        if !self.inbound_blocked.contains(&message.recipient) {
+            return true;
+        }
+        let admitted = self.opened.contains(&(message.recipient, message.sender));
+        if !admitted {
+            trace!("Network: blocked message from {} to {}", message.sender, message.recipient);
+            self.blocked += 1;
+        }
+        admitted
+    }
+
+    /// Whether `message` is a section message already sent to its recipient by another member of
+    /// the sending section.
+    fn is_duplicate(&mut self, message: &Message) -> bool {
+        let section_message = match message.content {
+            MessageContent::SectionMsg(ref signed) => signed.0.clone(),
+            _ => return false,
+        };
+        if self.section_messages.insert((message.recipient, section_message)) {
+            return false;
+        }
+        self.section_duplicates += 1;
+        true
+    }
+
+    /// Number of section messages dropped so far for having been sent to their recipient already.
+    pub fn section_duplicates(&self) -> u64 {
+        self.section_duplicates
+    }
+
+    /// Drop the next message of the given kind sent from `from` to `to`.
+    pub fn drop_next(&mut self, from: Name, to: Name, content_kind: String) {
+        self.forced_drops.push((from, to, content_kind));
+    }
+
+    /// Drops forced with `drop_next` which no message has matched yet.
+    pub fn pending_forced_drops(&self) -> &[(Name, Name, String)] {
+        &self.forced_drops
+    }
+
+    /// Whether `message` is one a drop was forced for, which then no longer applies.
+    fn is_forced_drop(&mut self, message: &Message) -> bool {
+        let kind = message.content.kind();
+        let index = self.forced_drops.iter().position(|&(from, to, ref content_kind)| {
+            from == message.sender && to == message.recipient && content_kind == kind
+        });
+        let index = match index {
+            Some(index) => index,
+            None => return false,
+        };
+        debug!("Network: forced drop of {} from {} to {}", kind, message.sender, message.recipient);
+        let _ = self.forced_drops.remove(index);
+        self.forced += 1;
+        true
+    }
+
+    /// Number of messages dropped so far because a drop was forced for them.
+    pub fn messages_force_dropped(&self) -> u64 {
+        self.forced
+    }
+
+    /// Cut off, or restore, messages between nodes under `a` and nodes under `b`. Disconnects
+    /// still get through, as the connections between them breaking is what cuts them off.
+    pub fn set_partition(&mut self, a: Prefix, b: Prefix, partitioned: bool) {
+        if partitioned {
+            let _ = self.partitions.insert((a, b));
+        } else {
+            self.partitions.retain(|&pair| pair != (a, b) && pair != (b, a));
+        }
+    }
+
+    /// Whether `x` and `y` are on opposite sides of a partition.
+    pub fn is_partitioned(&self, x: Name, y: Name) -> bool {
+        self.partitions.iter().any(|&(a, b)| {
+            (a.matches(x) && b.matches(y)) || (a.matches(y) && b.matches(x))
+        })
+    }
+
+    /// Whether `message` crosses a partition and isn't a disconnect.
+    fn crosses_partition(&mut self, message: &Message) -> bool {
+        if matches!(message.content, MessageContent::Disconnect) ||
+            !self.is_partitioned(message.sender, message.recipient)
+        {
+            return false;
+        }
+        self.partitioned += 1;
+        true
+    }
+
+    /// Number of messages dropped so far for crossing a partition.
+    pub fn messages_partitioned(&self) -> u64 {
+        self.partitioned
+    }
+
+    /// Count the messages sent from now on, to be collected with `take_sent_counts`.
+    pub fn count_sent(&mut self) {
+        self.sent_counts.get_or_insert_with(BTreeMap::new);
+    }
+
+    /// Number of messages of each kind each node has sent since this was last called, if
+    /// counting.
+    pub fn take_sent_counts(&mut self) -> BTreeMap<(Name, &'static str), u64> {
+        self.sent_counts.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    /// Number of messages of each kind sent since this was last called.
+    pub fn take_sent_by_kind(&mut self) -> BTreeMap<&'static str, u64> {
+        mem::take(&mut self.sent_by_kind)
+    }
+
+    /// Track the logical clocks and causes of messages from now on.
+    pub fn track_causality(&mut self) {
+        self.causality.get_or_insert_with(CausalityTracker::default);
+    }
+
+    pub fn causality(&self) -> Option<&CausalityTracker> {
+        self.causality.as_ref()
+    }
+
+    /// Note that the recipient of a delivered message is handling it, if tracking causality.
+    pub fn handling(&mut self, message: &Message) {
+        if let Some(ref mut causality) = self.causality {
+            causality.handled(message);
+        }
+    }
+
     /// Get messages delivered on a single connection at a given step.
     ///
     /// `conn_messages`: the messages for a single connection as contained in `self.messages`.
@@ -80,7 +349,7 @@ impl Network {
 
             let num_messages = messages.len();
             let num_delivered = (1..messages.len() + 1)
-                .take_while(|_| do_with_probability(prob_deliver))
+                .take_while(|_| in_stream(Stream::Delays, || do_with_probability(prob_deliver)))
                 .last()
                 .unwrap_or(0);
 
@@ -103,8 +372,26 @@ impl Network {
     pub fn send(&mut self, step: u64, messages: Vec<Message>) {
         let mut msg_counts = BTreeMap::new();
         for message in messages {
+            if let Some(ref mut sent_counts) = self.sent_counts {
+                *sent_counts.entry((message.sender, message.content.kind())).or_insert(0) += 1;
+            }
+            *self.sent_by_kind.entry(message.content.kind()).or_insert(0) += 1;
+            if self.is_forced_drop(&message) || self.crosses_partition(&message) ||
+                !self.admit(&message) || self.is_duplicate(&message)
+            {
+                continue;
+            }
+            if let Some(ref mut causality) = self.causality {
+                causality.sent(step, &message);
+            }
             let count = msg_counts.entry(message.sender).or_insert(0);
             *count += 1;
+            if self.delay_model == DelayModel::PerConnection {
+                let max_delay = cmp::max(self.max_delay, 1);
+                let _ = self.latencies
+                    .entry(Self::pair(message.sender, message.recipient))
+                    .or_insert_with(|| 1 + in_stream(Stream::Delays, random::<u64>) % max_delay);
+            }
             let conn_messages = self.messages
                 .entry((message.sender, message.recipient))
                 .or_insert_with(BTreeMap::new);
@@ -123,6 +410,48 @@ impl Network {
         )
     }
 
+    /// Get the set of blocks referred to by messages still in queue.
+    pub fn referenced_blocks(&self) -> BTreeSet<BlockId> {
+        self.messages
+            .values()
+            .flat_map(BTreeMap::values)
+            .flat_map(|messages| messages.iter())
+            .flat_map(|message| message.content.block_ids())
+            .collect()
+    }
+
+    /// Whether any message still in queue carries votes.
+    pub fn votes_in_flight(&self) -> bool {
+        self.messages
+            .values()
+            .flat_map(BTreeMap::values)
+            .flat_map(|messages| messages.iter())
+            .any(|message| message.content.carries_votes())
+    }
+
+    /// Messages still in queue, with the step each was sent at, in the order they were sent.
+    pub fn in_flight(&self) -> Vec<(u64, &Message)> {
+        let mut in_flight: Vec<_> = self.messages
+            .values()
+            .flat_map(|by_step| {
+                by_step.iter().flat_map(|(&step, messages)| {
+                    messages.iter().map(move |message| (step, message))
+                })
+            })
+            .collect();
+        in_flight.sort_by_key(|&(step, _)| step);
+        in_flight
+    }
+
+    /// Number of messages of each kind still in queue.
+    pub fn queued_by_kind(&self) -> BTreeMap<&'static str, u64> {
+        let mut counts = BTreeMap::new();
+        for message in self.messages.values().flat_map(BTreeMap::values).flatten() {
+            *counts.entry(message.content.kind()).or_insert(0) += 1;
+        }
+        counts
+    }
+
     /// Get the number of messages still in queue
     pub fn messages_in_queue(&self) -> usize {
         self.messages
@@ -179,6 +508,44 @@ mod test {
         }
     }
 
+    #[test]
+    fn expired_messages_dropped() {
+        let mut network = Network::new(20, Some(2), DelayModel::PerMessage);
+        network.send(0, vec![test_message(Connect)]);
+        network.send(1, vec![test_message(Disconnect)]);
+
+        // Delivery isn't forced before `max_delay`, so both messages are either delivered or
+        // dropped within the TTL.
+        let mut delivered = vec![];
+        for step in 1..5 {
+            delivered.extend(network.receive(step));
+        }
+        assert!(network.queue_is_empty());
+        assert_eq!(delivered.len() as u64 + network.messages_expired(), 2);
+    }
+
+    #[test]
+    fn per_connection_latency() {
+        let mut network = Network::new(20, None, DelayModel::PerConnection);
+        network.send(0, vec![test_message(Connect)]);
+        let latency = network.latency(Name(1), Name(0)).unwrap();
+        assert!(latency >= 1 && latency <= 20);
+
+        // Messages in both directions take exactly the pair's latency to arrive.
+        let reply = Message {
+            sender: Name(1),
+            recipient: Name(0),
+            content: Disconnect,
+        };
+        network.send(1, vec![reply]);
+        for step in 1..latency {
+            assert!(network.receive(step).is_empty());
+        }
+        assert_eq!(network.receive(latency), vec![test_message(Connect)]);
+        assert_eq!(network.receive(latency + 1).len(), 1);
+        assert!(network.queue_is_empty());
+    }
+
     #[test]
     fn in_order_delivery_same_step() {
         let connect = test_message(Connect);
@@ -209,4 +576,61 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn blocked_inbound_needs_outbound_first() {
+        let mut network = Network::new(1, None, DelayModel::PerMessage);
+        network.block_inbound(Name(1));
+        let reply = Message {
+            sender: Name(1),
+            recipient: Name(0),
+            content: Connect,
+        };
+
+        network.send(0, vec![test_message(Connect)]);
+        assert_eq!(network.messages_blocked(), 1);
+        assert!(network.queue_is_empty());
+
+        network.send(1, vec![reply.clone(), test_message(Connect)]);
+        assert_eq!(network.messages_blocked(), 1);
+        assert_eq!(network.receive(2).len(), 2);
+
+        // Disconnecting closes the connection again.
+        let disconnect = Message {
+            content: Disconnect,
+            ..reply
+        };
+        network.send(3, vec![disconnect, test_message(Connect)]);
+        assert_eq!(network.messages_blocked(), 2);
+    }
+
+    #[test]
+    fn section_messages_delivered_once() {
+        use block::Block;
+        use blocks::Blocks;
+        use name::Prefix;
+        use section_message::{SectionMessage, SectionPayload};
+        use std::sync::Arc;
+
+        let mut network = Network::new(1, None, DelayModel::PerMessage);
+        let src = Blocks::new().insert(Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: btreeset!{Name(0), Name(2)},
+        });
+        let section_message = SectionMessage {
+            src,
+            dst: Prefix::empty(),
+            payload: SectionPayload::Split(src),
+        };
+        let signed = SectionMsg(Arc::new((section_message, btreeset!{Name(0), Name(2)})));
+        let copy = Message {
+            sender: Name(2),
+            ..test_message(signed.clone())
+        };
+
+        network.send(0, vec![test_message(signed), copy]);
+        assert_eq!(network.section_duplicates(), 1);
+        assert_eq!(network.receive(1).len(), 1);
+    }
 }
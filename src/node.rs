@@ -1,21 +1,202 @@
 use message::Message;
 use message::MessageContent;
 use message::MessageContent::*;
-use name::Name;
-use block::{Block, BlockId, Vote};
+use name::{Name, Prefix};
+use block::{Block, BlockId, Vote, VoteKind};
 use blocks::{Blocks, VoteCounts, ValidBlocks, CurrentBlocks};
-use params::NodeParams;
-use split::split_blocks;
-use merge::merge_blocks;
-
+use compaction::{self, Snapshot};
+use connection::Connections;
+use fault::Fault;
+use params::{NodeParams, quorum};
+use split::{forced_split_blocks, split_blocks};
+use stats::NodeStats;
+use hash::stable_hash;
+use merge::{forced_merge_blocks, merge_blocks};
+use section_message::{SectionAccumulator, SectionMessage, SectionPayload};
+use random::{random, do_with_probability, sample};
+use chain::Chain;
+use routing::RoutingTable;
+
+use std::cmp;
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use std::mem;
+use std::sync::Arc;
 use std::fmt;
 
 const MESSAGE_FILTER_LEN: usize = 1024;
+/// Number of steps between sending our approval of a candidate again, until it's added.
+const APPROVAL_RETRY_STEPS: u64 = 5;
+
+/// The interface between the simulation and a node's membership-consensus algorithm.
+///
+/// `Node` is the reference implementation. Alternative algorithms can implement this trait and
+/// be run by `Simulation<N>` under exactly the same event schedules and network model.
+pub trait NodeTrait: fmt::Display + Send {
+    /// Create a new node which starts from a given set of valid and current blocks.
+    fn new(
+        name: Name,
+        blocks: &Blocks,
+        current_blocks: CurrentBlocks,
+        params: NodeParams,
+        step: u64,
+    ) -> Self
+    where
+        Self: Sized;
+
+    /// Handle a message intended for us and return messages we'd like to send.
+    ///
+    /// Nodes may handle their messages on separate threads, which don't share the seeded random
+    /// number streams, so this mustn't draw random numbers: anything random should wait for
+    /// `update_state`. Debug builds panic on a draw made while handling a message.
+    fn handle_message(&mut self, message: Message, blocks: &Blocks, step: u64) -> Vec<Message>;
+
+    /// Update our state once all of this step's messages have been handled.
+    fn update_state(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message>;
+
+    /// Construct and return messages for any new votes.
+    fn broadcast_new_votes(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message>;
+
+    /// Our view of the current blocks for the whole network.
+    fn current_blocks(&self) -> &CurrentBlocks;
+
+    /// Blocks that we can legitimately vote on successors for, because we are part of them.
+    fn our_current_blocks<'a>(&self, blocks: &'a Blocks) -> Vec<&'a Block> {
+        blocks.our_blocks(self.current_blocks(), self.name())
+    }
+
+    fn name(&self) -> Name;
+
+    /// Returns true if we have no connection to the given peer.
+    fn is_disconnected_from(&self, name: &Name) -> bool;
+
+    /// Expect connections to succeed only if we initiate them, as when behind a NAT.
+    fn block_inbound(&mut self) {}
+
+    /// Start or stop behaving with the given fault.
+    fn set_fault(&mut self, _fault: Fault, _enabled: bool) {}
+
+    /// Vote to split our section with the given prefix, whatever its size.
+    fn force_split(&mut self, _prefix: Prefix) {}
+
+    /// Vote to merge our section into the given prefix with its sibling, whatever their sizes.
+    fn force_merge(&mut self, _prefix: Prefix) {}
+
+    /// Sign the relocation of `node`, if it's a member of our section, to the section covering
+    /// `to`, under which name it then joins again.
+    fn relocate(&mut self, _node: Name, _to: Name) {}
+
+    /// Run with the given parameters from now on.
+    fn set_params(&mut self, _params: NodeParams) {}
+
+    /// Forget the given fraction of the votes in our cache, as if restarting without having
+    /// persisted them.
+    fn forget_votes(&mut self, _fraction: f64, _step: u64) {}
+
+    /// Whether we're behaving with the given fault.
+    fn has_fault(&self, _fault: Fault) -> bool {
+        false
+    }
+
+    /// Vote to remove `peer` from our section, even while we're still connected to it.
+    fn quarantine(&mut self, _peer: Name) {}
+
+    /// Peers we've caught equivocating.
+    fn equivocators(&self) -> Vec<Name> {
+        vec![]
+    }
+
+    /// The name our section has relocated us to, once a quorum of it has signed our relocation,
+    /// for us to join again under.
+    fn relocation(&self) -> Option<Name> {
+        None
+    }
+
+    /// Candidates whose join timed out more than `grace` steps ago, which we still hold on to.
+    fn stale_candidates(&self, _step: u64, _grace: u64) -> Vec<Name> {
+        vec![]
+    }
+
+    /// Peers we're connected to which aren't in `routable`, the names in any node's routing
+    /// table, nor candidates still within their join timeout.
+    fn ghost_connections(&self, _routable: &BTreeSet<Name>, _step: u64) -> Vec<Name> {
+        vec![]
+    }
+
+    /// Forget the given candidates, and drop our connections to the given peers.
+    fn purge(&mut self, _candidates: &[Name], _connections: &[Name]) {}
+
+    /// Whether we're voting, rather than waiting for our handover as a new member to finish.
+    fn is_voting(&self, _step: u64) -> bool {
+        true
+    }
+
+    /// Returns true if this node should shutdown because it has failed to join a section.
+    fn should_shutdown(&self, blocks: &Blocks, step: u64) -> bool;
+
+    /// Create a message to get a node with no knowledge of our history up to date.
+    fn construct_bootstrap_msg(&self, joining_node: Name) -> Message;
+
+    /// Treat the given blocks as valid without having seen any votes for them.
+    fn learn_blocks(&mut self, new_blocks: &BTreeSet<BlockId>);
+
+    /// Blocks voted for as successors to our current blocks which we don't yet consider valid.
+    fn pending_blocks(&self) -> BTreeSet<BlockId> {
+        BTreeSet::new()
+    }
+
+    /// Blocks we consider valid.
+    fn valid_blocks(&self) -> &ValidBlocks {
+        self.current_blocks()
+    }
+
+    /// Number of blocks we consider valid.
+    fn num_valid_blocks(&self) -> usize {
+        self.valid_blocks().len()
+    }
+
+    /// Number of blocks we consider valid for each prefix and version.
+    fn conflicting_blocks(&self, _blocks: &Blocks) -> BTreeMap<(Prefix, u64), usize> {
+        BTreeMap::new()
+    }
+
+    /// All the blocks this node holds a reference to.
+    fn referenced_blocks(&self) -> BTreeSet<BlockId> {
+        self.current_blocks().clone()
+    }
+
+    /// Counters for our activity so far.
+    fn stats(&self) -> NodeStats {
+        NodeStats::default()
+    }
+
+    /// Step at which this node was started.
+    fn step_created(&self) -> u64 {
+        0
+    }
+
+    /// Number of peers we're connected to.
+    fn num_connections(&self) -> usize {
+        0
+    }
+
+    /// Number of messages delivered to us which we haven't handled yet.
+    fn backlog(&self) -> usize {
+        0
+    }
+
+    /// Description of our state for debug output.
+    fn debug_state(&self, blocks: &Blocks) -> String {
+        let table = RoutingTable::new(blocks, self.current_blocks(), &self.pending_blocks());
+        format!("{}: routing table:\n{}", self, table.pretty())
+    }
 
+    /// Full description of our state, for dumps taken when an invariant fails.
+    fn dump_state(&self, blocks: &Blocks) -> String {
+        self.debug_state(blocks)
+    }
+}
+
+#[derive(Clone)]
 pub struct Node {
     /// Our node's name.
     pub our_name: Name,
@@ -34,18 +215,88 @@ pub struct Node {
     /// Recently received votes that haven't yet been applied to the sets of valid and current
     /// blocks.
     pub recent_votes: BTreeSet<Vote>,
-    /// Peers that we're currently connected to.
-    pub connections: BTreeSet<Name>,
-    /// Nodes that we've sent connection requests to.
-    pub connect_requests: BTreeSet<Name>,
+    /// Our connections to peers, and those we're trying to make. With limited capacity, peers
+    /// which dropped or refused our connection aren't asked again until our current blocks change.
+    pub connections: Connections,
     /// Candidates who we are waiting to add to our current blocks.
     pub candidates: BTreeMap<Name, Candidate>,
     /// Filter for hashes of recent messages we've already sent and shouldn't resend.
     pub message_filter: VecDeque<u64>,
     /// Network configuration parameters.
     pub params: NodeParams,
-    /// Step that this node was created.
+    /// Step that this node was created, by its own clock.
     pub step_created: u64,
+    /// Difference between our clock and the true step.
+    pub clock_offset: i64,
+    /// Counters for our activity.
+    pub stats: NodeStats,
+    /// Snapshots of our section's compacted history, by prefix.
+    pub snapshots: BTreeMap<Prefix, Snapshot>,
+    /// Whether peers can only reach us over connections we initiated.
+    pub inbound_blocked: bool,
+    /// The first node to send us a bootstrap message, which we ask for any history it left out.
+    pub bootstrap_peer: Option<Name>,
+    /// Whether we've asked for the history left out of our bootstrap, or found none missing.
+    pub chains_requested: bool,
+    /// Shares of messages from our section, collected until a quorum has signed them.
+    pub section_shares: SectionAccumulator,
+    /// Step at which each disconnected member of our section was first seen disconnected.
+    pub disconnected_since: BTreeMap<Name, u64>,
+    /// Members of our section suspected of having left, with the members suspecting them.
+    pub suspicions: BTreeMap<Name, BTreeSet<Name>>,
+    /// Faults we're currently behaving with.
+    pub faults: BTreeSet<Fault>,
+    /// Peers to vote out of our section, whether or not we're connected to them.
+    pub quarantined: BTreeSet<Name>,
+    /// Step from which we vote, once we're a member of a section and our handover is finished.
+    pub voting_from: Option<u64>,
+    /// Messages delivered to us which we haven't handled yet, with the step each arrived at.
+    pub inbox: VecDeque<(Message, u64)>,
+    /// Step we last handled messages on, and the cost of the messages handled and signatures
+    /// verified on it.
+    pub processed: (u64, u64),
+    /// Members of busy sections which asked us to try joining later, with the step to send our
+    /// join to each again.
+    pub join_retries: BTreeMap<Name, u64>,
+    /// Votes we've forgotten and haven't heard about again since.
+    pub forgotten_votes: BTreeSet<Vote>,
+    /// Step we first forgot votes at, until we've heard about them all again.
+    pub forgotten_at: Option<u64>,
+    /// Prefixes of our sections we've been told to split, until we're no longer in them.
+    pub forced_splits: BTreeSet<Prefix>,
+    /// Prefixes we've been told to merge our section into, until we're no longer in one of
+    /// their halves.
+    pub forced_merges: BTreeSet<Prefix>,
+    /// Members of our section we've been told to relocate, with the names they're relocated
+    /// under, until we've signed their relocation.
+    pub pending_relocations: BTreeMap<Name, Name>,
+    /// Members recently removed from the sections we know of, with the step we agreed to remove
+    /// each at, until their rejoin cooldown is over.
+    pub recently_removed: BTreeMap<Name, u64>,
+    /// Votes we've cast which are waiting to be broadcast in a batch, with the step each was
+    /// cast at.
+    pub vote_batch: Vec<(Vote, u64)>,
+    /// The name our section has relocated us to, once a quorum of it has signed our relocation.
+    pub relocated_to: Option<Name>,
+    /// Nodes other sections have relocated to ours. We let them join and connect to us however
+    /// busy we are, and with secure joins we don't relocate them again once they're added.
+    pub relocated_in: BTreeSet<Name>,
+    /// Members of our section a quorum of it has signed the relocation of, with their new names,
+    /// which we vote out of our section until they're gone.
+    pub relocating_out: BTreeMap<Name, Name>,
+    /// New names of the nodes relocated to our section, with their old names and the step we
+    /// learned of each, which we vote into our section until they're added or time out.
+    pub relocating_in: BTreeMap<Name, (Name, u64)>,
+    /// With equivocation detection, the votes each peer has sent us itself.
+    pub direct_votes: BTreeMap<Name, BTreeSet<Vote>>,
+    /// With equivocation detection, votes we've seen peers' signatures on which they haven't
+    /// sent us themselves, with the step we first saw each signature at.
+    pub unsent_votes: BTreeMap<(Name, Vote), u64>,
+    /// Peers we've caught equivocating, which we vote out of our section.
+    pub equivocators: BTreeSet<Name>,
+    /// With ancestry validation, votes held back until the block they're from becomes valid,
+    /// by that block, with the voter and the step we received each at.
+    pub orphan_votes: BTreeMap<BlockId, Vec<(Vote, Name, u64)>>,
 }
 
 impl fmt::Display for Node {
@@ -54,8 +305,16 @@ impl fmt::Display for Node {
     }
 }
 
+#[derive(Clone)]
 pub struct Candidate {
     step_added: u64,
+    /// Members of our section who have approved adding this candidate.
+    approvals: BTreeSet<Name>,
+    /// Step at which we send our approval of this candidate again, if we've had its join.
+    approval_due: Option<u64>,
+    /// Whether we've seen a block adding this candidate, which may yet lose out to one that
+    /// doesn't.
+    added: bool,
 }
 
 impl Candidate {
@@ -64,6 +323,16 @@ impl Candidate {
     }
 }
 
+/// The step shown by a clock which is `offset` steps ahead of the true step.
+fn clock_step(step: u64, offset: i64) -> u64 {
+    cmp::max(step as i64 + offset, 0) as u64
+}
+
+/// Total number of votes in a set of vote counts.
+fn num_votes(vote_counts: &VoteCounts) -> usize {
+    vote_counts.values().map(BTreeMap::len).sum()
+}
+
 /// Compute the set of nodes that are in any current block.
 pub fn nodes_in_any(all_blocks: &Blocks, blocks: &BTreeSet<BlockId>) -> BTreeSet<Name> {
     all_blocks
@@ -72,6 +341,56 @@ pub fn nodes_in_any(all_blocks: &Blocks, blocks: &BTreeSet<BlockId>) -> BTreeSet
         .fold(BTreeSet::new(), |acc, block| &acc | &block.members)
 }
 
+/// Compute the set of nodes we should be connected to: the members of any current block, or with
+/// pruning, only those of our section and its neighbours.
+fn peers_to_connect(
+    all_blocks: &Blocks,
+    blocks: &BTreeSet<BlockId>,
+    our_name: Name,
+    prune: bool,
+) -> BTreeSet<Name> {
+    let current = all_blocks.block_contents(blocks);
+    let ours: Vec<Prefix> = current
+        .iter()
+        .map(|block| block.prefix)
+        .filter(|prefix| prefix.matches(our_name))
+        .collect();
+    if !prune || ours.is_empty() {
+        return nodes_in_any(all_blocks, blocks);
+    }
+    current
+        .into_iter()
+        .filter(|block| {
+            ours.iter().any(|prefix| {
+                *prefix == block.prefix || prefix.is_neighbour(&block.prefix)
+            })
+        })
+        .fold(BTreeSet::new(), |acc, block| &acc | &block.members)
+}
+
+/// How much we want a connection to `peer`, lower being better: members of our section first,
+/// then those of neighbouring sections, then everyone else.
+fn connection_rank(
+    all_blocks: &Blocks,
+    blocks: &BTreeSet<BlockId>,
+    our_name: Name,
+    peer: Name,
+) -> usize {
+    let prefixes: Vec<Prefix> = all_blocks
+        .block_contents(blocks)
+        .into_iter()
+        .map(|block| block.prefix)
+        .collect();
+    let ours: Vec<&Prefix> = prefixes.iter().filter(|p| p.matches(our_name)).collect();
+    if ours.iter().any(|prefix| prefix.matches(peer)) {
+        return 0;
+    }
+    let neighbouring = prefixes.iter().filter(|p| p.matches(peer)).any(|theirs| {
+        ours.iter().any(|prefix| prefix.is_neighbour(theirs))
+    });
+    if neighbouring { 1 } else { 2 }
+}
+
 impl Node {
     /// Create a new node which starts from a given set of valid and current blocks.
     pub fn new(
@@ -81,8 +400,31 @@ impl Node {
         params: NodeParams,
         step: u64,
     ) -> Self {
-        // FIXME: prune connections
-        let connections = nodes_in_any(blocks, &current_blocks);
+        let mut connections =
+            peers_to_connect(blocks, &current_blocks, name, params.prune_connections);
+        let mut stats = NodeStats {
+            connections_pruned: (nodes_in_any(blocks, &current_blocks).len() -
+                                     connections.len()) as u64,
+            ..NodeStats::default()
+        };
+        if let Some(max) = params.max_connections {
+            let mut by_rank: Vec<Name> = connections.iter().cloned().collect();
+            by_rank.sort_by_key(|peer| connection_rank(blocks, &current_blocks, name, *peer));
+            stats.connections_refused = by_rank.len().saturating_sub(max) as u64;
+            connections = by_rank.into_iter().take(max).collect();
+        }
+        let clock_offset = if params.max_clock_skew > 0 {
+            let max_skew = params.max_clock_skew as i64;
+            (random::<u64>() % (2 * params.max_clock_skew + 1)) as i64 - max_skew
+        } else {
+            0
+        };
+        // Nodes which start out as members have nothing to hand over.
+        let voting_from = if connections.contains(&name) {
+            Some(clock_step(step, clock_offset))
+        } else {
+            None
+        };
 
         Node {
             our_name: name,
@@ -90,16 +432,80 @@ impl Node {
             current_blocks: current_blocks.clone(),
             prev_current_blocks: BTreeSet::new(),
             current_candidate_blocks: current_blocks,
-            connections,
-            connect_requests: BTreeSet::new(),
+            connections: Connections::new(connections),
             candidates: BTreeMap::new(),
             vote_counts: BTreeMap::new(),
             rev_vote_counts: BTreeMap::new(),
             recent_votes: BTreeSet::new(),
             message_filter: VecDeque::with_capacity(MESSAGE_FILTER_LEN),
             params,
-            step_created: step,
+            step_created: clock_step(step, clock_offset),
+            clock_offset,
+            stats,
+            snapshots: BTreeMap::new(),
+            inbound_blocked: false,
+            bootstrap_peer: None,
+            chains_requested: false,
+            section_shares: SectionAccumulator::default(),
+            disconnected_since: BTreeMap::new(),
+            suspicions: BTreeMap::new(),
+            faults: BTreeSet::new(),
+            quarantined: BTreeSet::new(),
+            voting_from,
+            inbox: VecDeque::new(),
+            processed: (step, 0),
+            join_retries: BTreeMap::new(),
+            forgotten_votes: BTreeSet::new(),
+            forgotten_at: None,
+            forced_splits: BTreeSet::new(),
+            forced_merges: BTreeSet::new(),
+            pending_relocations: BTreeMap::new(),
+            recently_removed: BTreeMap::new(),
+            vote_batch: vec![],
+            relocated_to: None,
+            relocated_in: BTreeSet::new(),
+            relocating_out: BTreeMap::new(),
+            relocating_in: BTreeMap::new(),
+            direct_votes: BTreeMap::new(),
+            unsent_votes: BTreeMap::new(),
+            equivocators: BTreeSet::new(),
+            orphan_votes: BTreeMap::new(),
+        }
+    }
+
+    /// Treat the given blocks as valid without having seen any votes for them.
+    ///
+    /// Used to make a node aware of the genesis blocks of a network it didn't start in.
+    pub fn learn_blocks(&mut self, new_blocks: &BTreeSet<BlockId>) {
+        self.valid_blocks.extend(new_blocks.iter().cloned());
+        self.current_candidate_blocks.extend(new_blocks.iter().cloned());
+    }
+
+    /// All the blocks this node holds a reference to.
+    pub fn referenced_blocks(&self) -> BTreeSet<BlockId> {
+        let mut referenced = BTreeSet::new();
+        referenced.extend(self.valid_blocks.iter().cloned());
+        referenced.extend(self.current_candidate_blocks.iter().cloned());
+        referenced.extend(self.current_blocks.iter().cloned());
+        referenced.extend(self.prev_current_blocks.iter().cloned());
+        referenced.extend(self.snapshots.values().map(|snapshot| snapshot.head));
+        let votes = self.recent_votes
+            .iter()
+            .chain(&self.forgotten_votes)
+            .chain(self.vote_batch.iter().map(|(vote, _)| vote))
+            .chain(self.direct_votes.values().flatten())
+            .chain(self.unsent_votes.keys().map(|(_, vote)| vote))
+            .chain(self.orphan_votes.values().flatten().map(|(vote, ..)| vote));
+        for vote in votes {
+            referenced.insert(vote.from);
+            referenced.insert(vote.to);
         }
+        referenced.extend(self.orphan_votes.keys().cloned());
+        for (from, map) in self.vote_counts.iter().chain(&self.rev_vote_counts) {
+            referenced.insert(*from);
+            referenced.extend(map.keys().cloned());
+        }
+        referenced
     }
 
     /// Minimum size that all sections must be before splitting.
@@ -112,6 +518,9 @@ impl Node {
     where
         I: IntoIterator<Item = Name>,
     {
+        if self.forgotten_votes.remove(&vote) {
+            self.stats.votes_relearned += 1;
+        }
         self.recent_votes.insert(vote.clone());
         let voters = self.vote_counts
             .entry(vote.from)
@@ -127,6 +536,61 @@ impl Node {
         rev_voters.extend(voters.clone());
     }
 
+    /// Check the simulated signatures of `voters` on `vote`, returning only the voters whose
+    /// signatures are acceptable, i.e. those who are members of the block being voted from.
+    fn verify_voters(
+        &mut self,
+        blocks: &Blocks,
+        vote: &Vote,
+        voters: BTreeSet<Name>,
+    ) -> BTreeSet<Name> {
+        if !self.params.verify_signatures {
+            return voters;
+        }
+        let num_voters = voters.len() as u64;
+        let cost = num_voters * self.params.signature_cost;
+        self.stats.signatures_verified += num_voters;
+        self.stats.verification_cost += cost;
+        // The checks take up some of the work we can do this step, holding back the messages
+        // still in our inbox.
+        if self.params.processing.is_some() {
+            self.processed.1 += cost;
+        }
+
+        let members = &vote.from.into_block(blocks).members;
+        let (valid, invalid): (BTreeSet<_>, BTreeSet<_>) =
+            voters.into_iter().partition(|voter| members.contains(voter));
+        if !invalid.is_empty() {
+            debug!(
+                "{}: rejecting signatures from non-members {:?} on {:?}",
+                self,
+                invalid,
+                vote.as_debug(blocks)
+            );
+            self.stats.signatures_rejected += invalid.len() as u64;
+        }
+        valid
+    }
+
+    /// Check the voters an agreement for `vote` claims: they must all be members of the block
+    /// being voted from, and enough of them to agree the vote. Otherwise the agreement is
+    /// rejected.
+    fn verify_agreement(&mut self, blocks: &Blocks, vote: &Vote, voters: &BTreeSet<Name>) -> bool {
+        let members = vote.voting_members(blocks);
+        if voters.is_subset(members) && vote.is_quorum(blocks, voters) {
+            return true;
+        }
+        debug!(
+            "{}: rejecting agreement for {:?}: {:?} aren't a quorum of its voting members {:?}",
+            self,
+            vote.as_debug(blocks),
+            voters,
+            members
+        );
+        self.stats.agreements_rejected += 1;
+        false
+    }
+
     /// Update valid and current block sets, return set of newly valid blocks to broadcast,
     /// and merge messages to broadcast.
     fn update_valid_blocks(&mut self, blocks: &Blocks) -> BTreeSet<(Vote, BTreeSet<Name>)> {
@@ -134,11 +598,14 @@ impl Node {
         let new_votes = mem::replace(&mut self.recent_votes, btreeset!{});
         let new_valid_votes =
             blocks.new_valid_blocks(&self.valid_blocks, &self.vote_counts, new_votes);
-        self.valid_blocks.extend(new_valid_votes.iter().map(
-            |&(ref vote, _)| {
-                vote.to.clone()
-            },
-        ));
+        // A block can become valid through votes from several of its predecessors at once, and is
+        // only counted once.
+        for &(ref vote, _) in &new_valid_votes {
+            if self.valid_blocks.insert(vote.to) {
+                self.stats.blocks_agreed += 1;
+                self.record_merge(blocks, vote);
+            }
+        }
 
         // Update current blocks.
         self.update_current_blocks(blocks, &new_valid_votes);
@@ -166,6 +633,9 @@ impl Node {
             &mut self.current_blocks,
             blocks.compute_current_blocks(&self.current_candidate_blocks),
         );
+        self.stats.blocks_expired += self.prev_current_blocks
+            .difference(&self.current_blocks)
+            .count() as u64;
     }
 
     /// Drop blocks for sections that we aren't neighbours of.
@@ -186,35 +656,60 @@ impl Node {
             .get(name)
             .map(|candidate| {
                 candidate.is_recent(self.params.join_timeout, step) &&
-                    self.connections.contains(name)
+                    self.connections.is_connected(name)
             })
             .unwrap_or(false)
     }
 
     /// Get connection and disconnection messages for peers.
     fn connects_and_disconnects(&mut self, blocks: &Blocks, step: u64) -> Vec<Message> {
-        let neighbours = nodes_in_any(blocks, &self.current_blocks);
+        let members = nodes_in_any(blocks, &self.current_blocks);
         let our_name = self.our_name;
 
         // FIXME: put this somewhere else?
-        for node in &neighbours {
-            self.candidates.remove(node);
+        // The block adding a candidate may yet lose out to one that doesn't, so it stays a
+        // candidate, connected to us, until its join times out.
+        let join_timeout = self.params.join_timeout;
+        self.candidates.retain(|name, candidate| {
+            candidate.added |= members.contains(name);
+            !members.contains(name) || candidate.is_recent(join_timeout, step)
+        });
+        if self.current_blocks != self.prev_current_blocks {
+            self.connections.forgive();
+        }
+        // A peer which didn't answer our request may think it's still connected to us, so a
+        // disconnect clears that before we ask again.
+        let mut timeouts = vec![];
+        if let Some(timeout) = self.params.connect_timeout {
+            for peer in self.connections.expire_requests(step, timeout) {
+                trace!("{}: connection request to {} timed out", self, peer);
+                self.stats.connects_timed_out += 1;
+                timeouts.push(Message {
+                    sender: our_name,
+                    recipient: peer,
+                    content: Disconnect,
+                });
+            }
         }
 
+        let neighbours = peers_to_connect(
+            blocks,
+            &self.current_blocks,
+            our_name,
+            self.params.prune_connections,
+        );
         let to_disconnect: BTreeSet<Name> = {
             self.connections
-                .iter()
-                .filter(|name| {
-                    !neighbours.contains(&name) && !self.is_candidate(&name, step)
-                })
-                .cloned()
+                .connected()
+                .into_iter()
+                .filter(|name| !neighbours.contains(name) && !self.is_candidate(name, step))
                 .collect()
         };
+        self.stats.connections_pruned += to_disconnect.intersection(&members).count() as u64;
 
         for node in &to_disconnect {
             trace!("{}: disconnecting from {}", self, node);
-            self.connections.remove(node);
-            self.connect_requests.remove(node);
+            self.connections.disconnect(node);
         }
 
         let disconnects = to_disconnect.into_iter().map(|neighbour| {
@@ -225,40 +720,262 @@ impl Node {
             }
         });
 
-        let to_connect: BTreeSet<Name> = {
+        // Candidates we've seen added may have joined through other members while we asked them
+        // to try later, so we connect to them too, to vote for them again if that block loses out.
+        let added_candidates = self.candidates
+            .iter()
+            .filter(|&(_, candidate)| candidate.added && candidate.is_recent(join_timeout, step))
+            .map(|(name, _)| name);
+        let mut to_connect: BTreeSet<Name> = {
             neighbours
                 .iter()
+                .chain(added_candidates)
                 .filter(|name| {
-                    !self.connections.contains(name) && !self.connect_requests.contains(name) &&
-                        **name != our_name
+                    **name != our_name &&
+                        self.connections.may_connect(
+                            name,
+                            step,
+                            self.params.handshake.as_ref(),
+                        )
                 })
                 .cloned()
                 .collect()
         };
 
+        // With limited capacity, connect to the peers we want most while there's room.
+        let mut evictions = vec![];
+        if self.params.max_connections.is_some() {
+            let mut by_rank: Vec<Name> = to_connect.into_iter().collect();
+            by_rank.sort_by_key(|peer| {
+                connection_rank(blocks, &self.current_blocks, our_name, *peer)
+            });
+            to_connect = BTreeSet::new();
+            for peer in by_rank {
+                if !self.make_room(blocks, peer, &mut evictions) {
+                    break;
+                }
+                let _ = to_connect.insert(peer);
+                self.connections.request(peer, step);
+            }
+        }
+
         for node in &to_connect {
             trace!("{}: connecting to {}", self, node);
-            self.connect_requests.insert(*node);
+            self.connections.request(*node, step);
         }
 
-        let connects = to_connect.into_iter().map(|neighbour| {
-            Message {
-                sender: our_name,
-                recipient: neighbour,
-                content: MessageContent::Connect,
+        let to_connect = self.start_handshakes(to_connect, step);
+        self.stats.connects_initiated += to_connect.len() as u64;
+
+        let connects: Vec<Message> = to_connect
+            .into_iter()
+            .flat_map(|neighbour| self.initiate_connection(neighbour))
+            .collect();
+
+        timeouts
+            .into_iter()
+            .chain(connects)
+            .chain(disconnects)
+            .chain(evictions)
+            .collect()
+    }
+
+    /// Whether we have room for a connection to `peer`, if we're limited in how many we can hold.
+    /// At capacity, our least wanted connection is dropped to make room if `peer` is wanted more,
+    /// and a disconnect for it added to `messages`.
+    fn make_room(&mut self, blocks: &Blocks, peer: Name, messages: &mut Vec<Message>) -> bool {
+        let max = match self.params.max_connections {
+            Some(max) => max,
+            None => return true,
+        };
+        let held = self.connections.held();
+        if held.contains(&peer) || held.len() < max {
+            return true;
+        }
+        let rank = |name| connection_rank(blocks, &self.current_blocks, self.our_name, name);
+        let worst = held.iter().map(|name| (rank(*name), *name)).max();
+        match worst {
+            Some((worst_rank, worst)) if worst_rank > rank(peer) => {
+                debug!("{}: dropping {} to make room for {}", self, worst, peer);
+                self.connections.disconnect(&worst);
+                self.stats.connections_evicted += 1;
+                messages.push(Message {
+                    sender: self.our_name,
+                    recipient: worst,
+                    content: Disconnect,
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Run new connection attempts through the handshake model, returning the peers that
+    /// connection requests should be sent to this step.
+    ///
+    /// Failed attempts are forgotten until it's time to retry them. Delayed attempts are held
+    /// until their handshake completes.
+    fn start_handshakes(&mut self, to_connect: BTreeSet<Name>, step: u64) -> BTreeSet<Name> {
+        let (max_delay, prob_fail) = match self.params.handshake {
+            Some(ref handshake) => (handshake.max_delay, handshake.prob_fail),
+            None => return to_connect,
+        };
+
+        for node in to_connect {
+            if self.connections.retry(&node) {
+                self.stats.connect_retries += 1;
+            }
+            if do_with_probability(prob_fail) {
+                trace!("{}: handshake with {} failed", self, node);
+                self.stats.handshakes_failed += 1;
+                self.connections.fail_handshake(node, step);
+            } else {
+                let delay = random::<u64>() % (max_delay + 1);
+                self.connections.start_handshake(node, step + delay);
             }
+        }
+        self.connections.complete_handshakes(step)
+    }
+
+    /// Count a newly valid merge, noting whether it continues a cascade of merges, i.e. whether
+    /// the section being merged was itself formed by a merge. Only called once per block, so a
+    /// merge is counted once, however many of the merged sections' votes for it are agreed.
+    ///
+    /// This is only for the stats: the votes continuing a cascade come from the usual merge rule,
+    /// which has a section merge with its sibling whenever a section it's part of would merge.
+    fn record_merge(&mut self, blocks: &Blocks, vote: &Vote) {
+        let from = vote.from.into_block(blocks);
+        let to = vote.to.into_block(blocks);
+        // Witnessing votes from a neighbour's block to ours can shorten the prefix too.
+        if from.prefix.bit_count() == 0 || to.prefix != from.prefix.popped() {
+            return;
+        }
+        self.stats.merges_agreed += 1;
+
+        // The other section merged is our current block for the sibling prefix, as our current
+        // blocks aren't updated for the merge yet.
+        let mut merged = vec![vote.from];
+        if let Some(sibling) = from.prefix.sibling() {
+            merged.extend(
+                blocks
+                    .blocks_for_prefix(&self.current_blocks, sibling)
+                    .into_iter()
+                    .map(|block| block.get_id()),
+            );
+        }
+        let cascading = merged.iter().any(|merged| {
+            let prefix = merged.into_block(blocks).prefix;
+            blocks
+                .predecessors(merged, &self.rev_vote_counts)
+                .into_iter()
+                .any(|(pred, _, _)| pred.into_block(blocks).prefix.bit_count() > prefix.bit_count())
         });
+        if cascading {
+            debug!(
+                "{}: cascading merge from {:?} to {:?}",
+                self,
+                from.prefix,
+                to.prefix
+            );
+            self.stats.cascading_merges_agreed += 1;
+        }
+    }
 
-        connects.chain(disconnects).collect()
+    /// Remember a member whose removal from its section has just become valid, so that it can't
+    /// rejoin under the same name until the cooldown is over.
+    fn record_removal(&mut self, blocks: &Blocks, vote: &Vote, step: u64) {
+        if self.params.rejoin_cooldown == 0 {
+            return;
+        }
+        let from = vote.from.into_block(blocks);
+        let to = vote.to.into_block(blocks);
+        if from.prefix != to.prefix || to.members.len() + 1 != from.members.len() ||
+            !to.members.is_subset(&from.members)
+        {
+            return;
+        }
+        for &name in from.members.difference(&to.members) {
+            debug!("{}: {} removed, keeping it from rejoining for now", self, name);
+            let _ = self.recently_removed.insert(name, step);
+        }
+    }
+
+    /// Treat the members just added to our section as candidates, if they weren't already: we may
+    /// not have seen their joins, and the block adding them may yet lose out to one that doesn't.
+    /// Their joins time out afresh from here, so that we don't drop a candidate added again late
+    /// in its join before every member has caught up with the block adding it.
+    fn record_addition(&mut self, blocks: &Blocks, vote: &Vote, step: u64) {
+        let from = vote.from.into_block(blocks);
+        let to = vote.to.into_block(blocks);
+        if from.prefix != to.prefix || !to.members.contains(&self.our_name) ||
+            !from.members.is_subset(&to.members)
+        {
+            return;
+        }
+        for &name in to.members.difference(&from.members) {
+            if name != self.our_name {
+                let candidate = self.candidates
+                    .entry(name)
+                    .or_insert_with(|| {
+                        Candidate {
+                            step_added: step,
+                            approvals: BTreeSet::new(),
+                            approval_due: None,
+                            added: true,
+                        }
+                    });
+                candidate.added = true;
+                candidate.step_added = step;
+            }
+        }
     }
 
     /// Called once per step.
     pub fn update_state(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message> {
+        // Work through any backlog left from earlier steps, if nothing was delivered this step.
+        let mut messages = self.process_inbox(blocks, step);
+
+        if self.params.validate_ancestry {
+            self.release_orphan_votes(blocks, step);
+        }
+        if let Some(grace) = self.params.equivocation_grace {
+            messages.extend(self.detect_equivocation(blocks, step, grace));
+        }
+
         // Update valid and current blocks.
         let new_valid_votes = self.update_valid_blocks(blocks);
+        for (vote, _) in &new_valid_votes {
+            self.record_removal(blocks, vote, step);
+            self.record_addition(blocks, vote, step);
+            if vote.kind(blocks) == VoteKind::Relocation && !vote.is_witnessing(blocks) {
+                self.stats.relocations_agreed += 1;
+            }
+        }
+        let cooldown = self.params.rejoin_cooldown;
+        self.recently_removed.retain(|_, removed| *removed + cooldown > step);
+        if self.params.section_messages {
+            messages.extend(self.notify_neighbours_of_splits(blocks, &new_valid_votes, step));
+        }
+        if let Some(depth) = self.params.welcome_joiners {
+            messages.extend(self.welcome_joiners(blocks, &new_valid_votes, depth));
+        }
+        if self.params.secure_join {
+            messages.extend(self.relocate_joiners(blocks, &new_valid_votes, step));
+        }
+        if !self.pending_relocations.is_empty() {
+            messages.extend(self.sign_relocations(blocks, step));
+        }
+        if !self.relocating_out.is_empty() || !self.relocating_in.is_empty() {
+            let members = nodes_in_any(blocks, &self.current_blocks);
+            let join_timeout = self.params.join_timeout;
+            self.relocating_out.retain(|node, _| members.contains(node));
+            self.relocating_in.retain(|to, &mut (_, since)| {
+                !members.contains(to) && since + join_timeout >= step
+            });
+        }
 
         // Broadcast vote agreement messages before pruning the current block set.
-        let mut messages = self.broadcast(
+        messages.extend(self.broadcast(
             blocks,
             new_valid_votes
                 .into_iter()
@@ -270,27 +987,75 @@ impl Node {
                     );
                 })
                 .filter(|&(ref vote, _)| !vote.is_witnessing(blocks))
-                .map(VoteAgreedMsg)
+                .map(|agreed| VoteAgreedMsg(Arc::new(agreed)))
                 .collect(),
             step,
-        );
+        ));
 
         // Prune blocks that are no longer relevant because of splitting.
         self.prune_split_blocks(blocks);
 
+        if !self.forced_splits.is_empty() || !self.forced_merges.is_empty() {
+            let ours: Vec<Prefix> =
+                self.our_current_blocks(blocks).iter().map(|block| block.prefix).collect();
+            self.forced_splits.retain(|prefix| ours.contains(prefix));
+            self.forced_merges.retain(|prefix| {
+                ours.iter().any(|our| our.bit_count() > 0 && our.popped() == *prefix)
+            });
+        }
+
+        if self.params.compaction.is_some() {
+            self.compact_history(blocks);
+        }
+
+        if self.params.partial_bootstrap {
+            messages.extend(self.request_missing_chains(blocks));
+        }
+
         // Generate connect and disconnect messages.
         messages.extend(self.connects_and_disconnects(blocks, step));
 
+        // With a rejoin cooldown, blocks listing us while we've still to send our join again can
+        // be left over from before we were removed, from messages meant for our earlier
+        // incarnation.
+        let rejoin_pending = self.params.rejoin_cooldown > 0 && !self.join_retries.is_empty();
+        if self.voting_from.is_none() && !rejoin_pending &&
+            !self.our_current_blocks(blocks).is_empty()
+        {
+            let voting_from = step + self.params.handover_steps;
+            debug!("{}: became a member, voting from step {}", self, voting_from);
+            self.voting_from = Some(voting_from);
+            self.stats.joins_completed += 1;
+            self.stats.steps_to_join += step.saturating_sub(self.step_created);
+        }
+
+        if self.forgotten_votes.is_empty() {
+            if let Some(forgotten_at) = self.forgotten_at.take() {
+                debug!("{}: heard again about all the votes we forgot", self);
+                self.stats.memory_recoveries += 1;
+                self.stats.steps_to_recover += step - forgotten_at;
+            }
+        }
+
+        if self.params.candidate_approval {
+            messages.extend(self.retry_approvals(blocks, step));
+        }
+        messages.extend(self.retry_joins(step));
         messages
     }
 
+    /// Whether we're voting, rather than waiting for our handover as a new member to finish.
+    pub fn is_voting(&self, step: u64) -> bool {
+        self.voting_from.is_some_and(|voting_from| step >= voting_from)
+    }
+
     /// Create messages for every relevant neighbour for every vote in the given vec.
     pub fn broadcast(&self, blocks: &Blocks, msgs: Vec<MessageContent>, step: u64) -> Vec<Message> {
         msgs.into_iter()
             .flat_map(move |content| {
                 let mut recipients =
                     content.recipients(blocks, &self.current_blocks, self.our_name);
-                recipients.extend(self.nodes_to_add(step));
+                recipients.extend(self.nodes_to_add(blocks, step));
                 recipients.remove(&self.our_name);
 
                 recipients.into_iter().map(move |recipient| {
@@ -304,24 +1069,15 @@ impl Node {
             .collect()
     }
 
-    /// Check we don't have excessive valid blocks for any given (prefix, version) pair.
-    pub fn check_conflicting_block_count(&self, blocks: &Blocks) {
+    /// Count the valid blocks we have for each (prefix, version) pair.
+    pub fn conflicting_blocks(&self, blocks: &Blocks) -> BTreeMap<(Prefix, u64), usize> {
         let mut conflicting_counts = BTreeMap::new();
         for block in self.valid_blocks.iter().map(|b| blocks.get(b).unwrap()) {
-            let count = conflicting_counts
+            *conflicting_counts
                 .entry((block.prefix, block.version))
-                .or_insert(0);
-            *count += 1;
-            if *count == self.params.max_conflicting_blocks {
-                panic!(
-                    "{:?}\nhas {} valid blocks for {:?} with version {}.",
-                    self.as_debug(blocks),
-                    count,
-                    block.prefix,
-                    block.version
-                );
-            }
+                .or_insert(0) += 1;
         }
+        conflicting_counts
     }
 
     /// Blocks that we can legitimately vote on successors for, because we are part of them.
@@ -339,44 +1095,234 @@ impl Node {
     /// True if the given node could be added to the given block
     fn could_be_added(&self, node: Name, block: &Block) -> bool {
         !block.members.contains(&node) && block.prefix.matches(node) &&
-            !block.should_split(self.min_split_size())
+            !block.should_split(self.params.split_policy, self.min_split_size()) &&
+            self.params.max_section_size.is_none_or(
+                |max| block.members.len() < max,
+            )
     }
 
     /// Vote to add the oldest candidate (from our perspective) that hasn't timed out.
-    fn nodes_to_add(&self, step: u64) -> Vec<Name> {
-        self.candidates
+    fn nodes_to_add(&self, blocks: &Blocks, step: u64) -> Vec<Name> {
+        let members = nodes_in_any(blocks, &self.current_blocks);
+        let (stranded, fresh): (Vec<_>, Vec<_>) = self.candidates
             .iter()
             .filter(|&(name, candidate)| {
-                self.connections.contains(name) &&
-                    candidate.is_recent(self.params.join_timeout, step)
+                !members.contains(name) && !self.relocating_in.contains_key(name) &&
+                    self.connections.is_connected(name) &&
+                    candidate.is_recent(self.params.join_timeout, step) &&
+                    self.is_approved(blocks, candidate)
             })
+            .partition(|&(_, candidate)| candidate.added);
+        // Candidates whose blocks lost out are voted for again one at a time, the same one by
+        // every member, or each fork would add them all again and the forks would multiply.
+        fresh
+            .into_iter()
+            .chain(stranded.into_iter().take(1))
             .map(|(name, _)| *name)
             .collect()
     }
 
-    fn nodes_to_drop(&self, current_block: &Block) -> Vec<Name> {
+    /// Whether a quorum of our section has approved the candidate, if approval is required.
+    fn is_approved(&self, blocks: &Blocks, candidate: &Candidate) -> bool {
+        if !self.params.candidate_approval {
+            return true;
+        }
+        self.our_current_blocks(blocks).iter().any(|block| {
+            let approvals = candidate.approvals.intersection(&block.members).count();
+            approvals >= quorum(block.members.len())
+        })
+    }
+
+    /// Approve the joining node if it could be added to our section, and tell the rest of our
+    /// section about it. We try again after `APPROVAL_RETRY_STEPS` steps, as our approval can be
+    /// lost or miss members we aren't connected to yet.
+    fn approve_candidate(
+        &mut self,
+        blocks: &Blocks,
+        joining_node: Name,
+        step: u64,
+    ) -> Vec<Message> {
+        if let Some(candidate) = self.candidates.get_mut(&joining_node) {
+            candidate.approval_due = Some(step + APPROVAL_RETRY_STEPS);
+        }
+        let acceptable = self.our_current_blocks(blocks).iter().any(|block| {
+            self.could_be_added(joining_node, block)
+        });
+        if !acceptable {
+            trace!("{}: not approving candidate {}", self, joining_node);
+            return vec![];
+        }
+
+        let our_name = self.our_name;
+        if let Some(candidate) = self.candidates.get_mut(&joining_node) {
+            candidate.approvals.insert(our_name);
+        }
+        let content = ApproveCandidate(joining_node);
+        content
+            .recipients(blocks, &self.current_blocks, our_name)
+            .into_iter()
+            .filter(|recipient| *recipient != our_name)
+            .map(|recipient| {
+                Message {
+                    sender: our_name,
+                    recipient,
+                    content: content.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Approve again the candidates whose approval is due, until they're added or time out.
+    fn retry_approvals(&mut self, blocks: &Blocks, step: u64) -> Vec<Message> {
+        let join_timeout = self.params.join_timeout;
+        let due: Vec<Name> = self.candidates
+            .iter()
+            .filter(|&(_, candidate)| {
+                candidate.is_recent(join_timeout, step) &&
+                    candidate.approval_due.is_some_and(|due| due <= step)
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        let mut messages = vec![];
+        for name in due {
+            let added = self.our_current_blocks(blocks).iter().any(|block| {
+                block.members.contains(&name)
+            });
+            if added {
+                if let Some(candidate) = self.candidates.get_mut(&name) {
+                    candidate.approval_due = None;
+                }
+                continue;
+            }
+            trace!("{}: approving candidate {} again", self, name);
+            messages.extend(self.approve_candidate(blocks, name, step));
+        }
+        messages
+    }
+
+    fn nodes_to_drop(&self, current_block: &Block, step: u64) -> Vec<Name> {
         current_block
             .members
             .iter()
             .filter(|peer| {
-                **peer != self.our_name && !self.connections.contains(peer) &&
-                    !self.candidates.contains_key(peer)
+                **peer != self.our_name && !self.relocating_out.contains_key(peer) &&
+                    (self.quarantined.contains(peer) ||
+                         !self.connections.is_connected(peer) &&
+                             !self.relocating_in.contains_key(peer) &&
+                             !self.candidates.contains_key(peer) &&
+                             self.past_drop_grace(peer, step) &&
+                             self.suspicion_confirmed(peer))
             })
             .cloned()
             .collect()
     }
 
-    fn witness_votes(&self, blocks: &Blocks) -> Vec<Vote> {
-        let new_current_blocks = self.current_blocks.difference(&self.prev_current_blocks);
-        let mut votes = vec![];
-        for block in blocks
-            .block_contents(new_current_blocks)
+    /// Whether `peer` has been disconnected from us for long enough to vote to remove it.
+    fn past_drop_grace(&self, peer: &Name, step: u64) -> bool {
+        let grace = self.params.drop_grace_steps;
+        grace == 0 ||
+            self.disconnected_since
+                .get(peer)
+                .is_some_and(|&since| step >= since + grace)
+    }
+
+    /// Whether enough members of our section suspect `peer` to vote to remove it.
+    fn suspicion_confirmed(&self, peer: &Name) -> bool {
+        self.params.suspicion_confirmations.is_none_or(|confirmations| {
+            self.suspicions.get(peer).map_or(0, BTreeSet::len) >= confirmations
+        })
+    }
+
+    /// Announce our suspicion of members of our section which have been disconnected from us for
+    /// longer than the grace period, and withdraw it from those we've reconnected to.
+    fn update_suspicions(&mut self, blocks: &Blocks, step: u64) -> Vec<Message> {
+        let our_name = self.our_name;
+        let members: BTreeSet<Name> = self.our_current_blocks(blocks)
             .into_iter()
-            .filter(|b| !b.prefix.matches(self.our_name))
-        {
-            for our_block in self.our_current_blocks(blocks) {
-                votes.push(Vote {
-                    from: our_block.get_id(),
+            .flat_map(|block| block.members.iter().cloned())
+            .filter(|peer| *peer != our_name)
+            .collect();
+        self.suspicions.retain(|peer, _| members.contains(peer));
+
+        let mut contents = vec![];
+        for peer in members {
+            let suspected = !self.connections.is_connected(&peer) &&
+                !self.candidates.contains_key(&peer) &&
+                self.past_drop_grace(&peer, step);
+            let suspecting = self.suspicions.get(&peer).is_some_and(|suspecters| {
+                suspecters.contains(&our_name)
+            });
+            if suspected && !suspecting {
+                debug!("{}: suspect {} has left", self, peer);
+                let _ = self.suspicions.entry(peer).or_default().insert(our_name);
+                self.stats.suspicions_raised += 1;
+                contents.push((peer, NodeSuspected(peer)));
+            } else if !suspected && suspecting {
+                debug!("{}: no longer suspect {}", self, peer);
+                if let Some(suspecters) = self.suspicions.get_mut(&peer) {
+                    let _ = suspecters.remove(&our_name);
+                }
+                self.stats.suspicions_withdrawn += 1;
+                contents.push((peer, SuspicionWithdrawn(peer)));
+            }
+        }
+
+        let mut messages = vec![];
+        for (suspect, content) in contents {
+            messages.extend(
+                content
+                    .recipients(blocks, &self.current_blocks, our_name)
+                    .into_iter()
+                    .filter(|recipient| *recipient != our_name && *recipient != suspect)
+                    .map(|recipient| {
+                        Message {
+                            sender: our_name,
+                            recipient,
+                            content: content.clone(),
+                        }
+                    }),
+            );
+        }
+        messages
+    }
+
+    /// Record a suspicion of `suspect` from `sender`, if both are members of our section.
+    fn add_suspicion(&mut self, blocks: &Blocks, suspect: Name, sender: Name) {
+        let is_member = |name| {
+            self.our_current_blocks(blocks).iter().any(
+                |block| block.members.contains(&name),
+            )
+        };
+        if is_member(suspect) && is_member(sender) {
+            let _ = self.suspicions.entry(suspect).or_default().insert(sender);
+        }
+    }
+
+    /// Note when members of our section disconnect from us, and forget those which have
+    /// reconnected or left the section.
+    fn track_disconnections(&mut self, blocks: &Blocks, step: u64) {
+        let members: BTreeSet<Name> = self.our_current_blocks(blocks)
+            .into_iter()
+            .flat_map(|block| block.members.iter().cloned())
+            .filter(|peer| *peer != self.our_name && !self.connections.is_connected(peer))
+            .collect();
+        self.disconnected_since.retain(|peer, _| members.contains(peer));
+        for peer in members {
+            let _ = self.disconnected_since.entry(peer).or_insert(step);
+        }
+    }
+
+    fn witness_votes(&self, blocks: &Blocks) -> Vec<Vote> {
+        let new_current_blocks = self.current_blocks.difference(&self.prev_current_blocks);
+        let mut votes = vec![];
+        for block in blocks
+            .block_contents(new_current_blocks)
+            .into_iter()
+            .filter(|b| !b.prefix.matches(self.our_name))
+        {
+            for our_block in self.our_current_blocks(blocks) {
+                votes.push(Vote {
+                    from: our_block.get_id(),
                     to: block.get_id(),
                 });
             }
@@ -391,7 +1337,7 @@ impl Node {
         let blocks_to_add = {
             let mut blocks_to_add = BTreeSet::new();
             for block in self.our_current_blocks(blocks) {
-                for node in self.nodes_to_add(step) {
+                for node in self.nodes_to_add(blocks, step) {
                     if self.could_be_added(node, block) {
                         trace!("{}: voting to add {} to: {:?}", self, node, block);
                         let added = block.add_node(node);
@@ -413,7 +1359,7 @@ impl Node {
         let blocks_to_add = {
             let mut blocks_to_add = BTreeSet::new();
             for block in self.our_current_blocks(blocks) {
-                for node in self.nodes_to_drop(&block) {
+                for node in self.nodes_to_drop(&block, step) {
                     trace!("{}: voting to remove {} from: {:?}", self, node, block);
                     let removed = block.remove_node(node);
                     let removed_id = removed.get_id();
@@ -430,11 +1376,47 @@ impl Node {
             blocks.insert(block);
         }
 
+        // Relocations signed by a quorum of the section a node leaves are voted for by both
+        // sections, whether or not it has left the one or joined the other yet.
+        let mut relocations = vec![];
+        let blocks_to_add = {
+            let mut blocks_to_add = BTreeSet::new();
+            for block in self.our_current_blocks(blocks) {
+                let leaving = self.relocating_out
+                    .iter()
+                    .filter(|&(node, _)| block.members.contains(node))
+                    .map(|(&node, &to)| (node, to, block.remove_node(node)));
+                let arriving = self.relocating_in
+                    .iter()
+                    .filter(|&(to, _)| !block.members.contains(to) && block.prefix.matches(*to))
+                    .map(|(&to, &(node, _))| (node, to, block.add_node(to)));
+                for (node, to, relocated) in leaving.chain(arriving) {
+                    trace!("{}: voting to relocate {} to {}: {:?}", self, node, to, relocated);
+                    votes.push(Vote {
+                        from: block.get_id(),
+                        to: relocated.get_id(),
+                    });
+                    blocks_to_add.insert(relocated);
+                    relocations.push((node, to));
+                }
+            }
+            blocks_to_add
+        };
+        for block in blocks_to_add {
+            blocks.insert(block);
+        }
+        for (node, to) in relocations {
+            blocks.insert_relocation(node, to);
+        }
+
         for vote in split_blocks(
             blocks,
             &self.current_blocks,
             self.our_name,
+            self.params.split_policy,
             self.min_split_size(),
+            self.params.max_section_size,
+            self.params.min_section_size,
         )
         {
             trace!(
@@ -449,7 +1431,7 @@ impl Node {
         for vote in merge_blocks(
             blocks,
             &self.current_blocks,
-            &self.connections,
+            &self.connections.connected(),
             self.our_name,
             self.params.min_section_size,
         )
@@ -463,6 +1445,28 @@ impl Node {
             votes.push(vote);
         }
 
+        for vote in forced_split_blocks(
+            blocks,
+            &self.current_blocks,
+            self.our_name,
+            &self.forced_splits,
+        )
+        {
+            trace!("{}: forced to vote to split to: {:?}", self, vote.to.into_block(blocks));
+            votes.push(vote);
+        }
+
+        for vote in forced_merge_blocks(
+            blocks,
+            &self.current_blocks,
+            self.our_name,
+            &self.forced_merges,
+        )
+        {
+            trace!("{}: forced to vote to merge to: {:?}", self, vote.to.into_block(blocks));
+            votes.push(vote);
+        }
+
         for vote in self.witness_votes(blocks) {
             trace!(
                 "{}: witnessing from: {:?} to: {:?}",
@@ -478,31 +1482,161 @@ impl Node {
 
     /// Returns new votes to be broadcast after filtering them.
     pub fn broadcast_new_votes(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message> {
+        if self.params.drop_grace_steps > 0 {
+            self.track_disconnections(blocks, step);
+        }
+        // Suspicions can be withdrawn and raised again, so they bypass the message filter.
+        let suspicions = if self.params.suspicion_confirmations.is_some() {
+            self.update_suspicions(blocks, step)
+        } else {
+            vec![]
+        };
         let votes = self.construct_new_votes(blocks, step);
+        if self.faults.contains(&Fault::NoVotes) {
+            self.stats.votes_withheld += votes.len() as u64;
+            return suspicions;
+        }
+        if !self.is_voting(step) {
+            self.stats.votes_deferred += votes.len() as u64;
+            return suspicions;
+        }
         let our_name = self.our_name;
 
-        let mut to_broadcast = vec![];
-
         for vote in &votes {
             self.add_vote(vote.clone(), Some(our_name));
         }
+        self.stats.votes_proposed += votes.len() as u64;
 
         // Construct vote messages and broadcast.
-        let vote_msgs: Vec<_> = votes.into_iter().map(VoteMsg).collect();
-        to_broadcast.extend(self.broadcast(blocks, vote_msgs, step));
+        let mut messages = if self.params.vote_batch_steps > 0 {
+            self.batch_votes(blocks, votes, step)
+        } else {
+            let vote_msgs: Vec<_> = votes.into_iter().map(VoteMsg).collect();
+            let to_broadcast = self.broadcast(blocks, vote_msgs, step);
+            self.filter_messages(to_broadcast)
+        };
+        self.stats.votes_sent +=
+            messages.iter().map(|message| message.content.plain_votes().len() as u64).sum::<u64>();
+        self.stats.vote_messages_sent += messages.len() as u64;
+        if self.faults.contains(&Fault::Equivocate) {
+            messages = self.equivocate(blocks, messages);
+        }
+        messages.extend(suspicions);
+        messages
+    }
+
+    /// Swap our membership votes to every other recipient for votes for conflicting sibling
+    /// blocks, so that the two halves of our section see us vote for different blocks.
+    fn equivocate(&self, blocks: &mut Blocks, messages: Vec<Message>) -> Vec<Message> {
+        messages
+            .into_iter()
+            .map(|message| {
+                if message.recipient.0 % 2 == 0 {
+                    return message;
+                }
+                let content = match message.content {
+                    VoteMsg(ref vote) => VoteMsg(self.conflicting_vote(blocks, vote)),
+                    VoteBatch(ref votes) => {
+                        VoteBatch(Arc::new(
+                            votes.iter().map(|vote| self.conflicting_vote(blocks, vote)).collect(),
+                        ))
+                    }
+                    _ => return message,
+                };
+                Message { content, ..message }
+            })
+            .collect()
+    }
+
+    /// A vote from the same block as the membership vote `vote`, for a sibling block which
+    /// removes one of the other members instead. Other votes are left as they are.
+    fn conflicting_vote(&self, blocks: &mut Blocks, vote: &Vote) -> Vote {
+        if vote.kind(blocks) != VoteKind::Membership {
+            return vote.clone();
+        }
+        let sibling = {
+            let from = vote.from.into_block(blocks);
+            let to = vote.to.into_block(blocks);
+            from.members
+                .iter()
+                .find(|&&member| member != self.our_name && to.members.contains(&member))
+                .map(|&victim| from.remove_node(victim))
+        };
+        match sibling {
+            Some(sibling) => {
+                Vote {
+                    from: vote.from,
+                    to: blocks.insert(sibling),
+                }
+            }
+            None => vote.clone(),
+        }
+    }
+
+    /// Add `votes` to our batch, and once the oldest vote in it has waited `vote_batch_steps`
+    /// steps, broadcast the whole batch with the votes for each recipient in a single message.
+    /// Votes already sent to a recipient are left out, as the message filter does when votes are
+    /// sent individually.
+    fn batch_votes(&mut self, blocks: &Blocks, votes: Vec<Vote>, step: u64) -> Vec<Message> {
+        self.vote_batch.extend(votes.into_iter().map(|vote| (vote, step)));
+        match self.vote_batch.first() {
+            Some(&(_, cast)) if cast + self.params.vote_batch_steps <= step => (),
+            _ => return vec![],
+        }
+        let batch = mem::take(&mut self.vote_batch);
+        self.stats.vote_batch_wait_steps += batch.iter().map(|&(_, cast)| step - cast).sum::<u64>();
+
+        let vote_msgs = batch.into_iter().map(|(vote, _)| VoteMsg(vote)).collect();
+        let mut batches: BTreeMap<Name, Vec<Vote>> = BTreeMap::new();
+        let to_broadcast = self.broadcast(blocks, vote_msgs, step);
+        for message in self.filter_messages(to_broadcast) {
+            if let VoteMsg(vote) = message.content {
+                batches.entry(message.recipient).or_default().push(vote);
+            }
+        }
+        batches
+            .into_iter()
+            .map(|(recipient, mut votes)| {
+                let content = if votes.len() == 1 {
+                    VoteMsg(votes.remove(0))
+                } else {
+                    VoteBatch(Arc::new(votes))
+                };
+                Message {
+                    sender: self.our_name,
+                    recipient,
+                    content,
+                }
+            })
+            .collect()
+    }
 
-        self.filter_messages(to_broadcast)
+    /// The fault which makes us drop a message with this content, if any.
+    fn fault_dropping(&self, content: &MessageContent) -> Option<Fault> {
+        let fault = match *content {
+            Connect | ConnectWithVotes(_) => Fault::IgnoreConnects,
+            BootstrapMsg(_) | SnapshotBootstrapMsg(_) => Fault::DropBootstrap,
+            _ => return None,
+        };
+        if self.faults.contains(&fault) {
+            Some(fault)
+        } else {
+            None
+        }
     }
 
     /// Remove messages that have already been sent from `messages`, and update the filter.
     fn filter_messages(&mut self, messages: Vec<Message>) -> Vec<Message> {
         let mut filtered = vec![];
         for message in messages {
-            let mut hasher = DefaultHasher::new();
-            message.hash(&mut hasher);
-            let hash = hasher.finish();
-            if message.content == Connect || message.content == Disconnect ||
-                !self.message_filter.contains(&hash)
+            let hash = stable_hash(&message);
+            // Joining nodes may be asked to try later more than once, and candidates approved
+            // again until they're added.
+            let repeatable = matches!(
+                message.content,
+                Connect | ConnectWithVotes(_) | Disconnect | TryLater(_) | ApproveCandidate(_)
+            );
+            if repeatable || !self.message_filter.contains(&hash)
             {
                 filtered.push(message);
                 if self.message_filter.len() == MESSAGE_FILTER_LEN {
@@ -515,136 +1649,706 @@ impl Node {
     }
 
     /// Create a message with all our votes to send to a new node.
-    fn construct_bootstrap_msg(&self, joining_node: Name) -> Message {
-        Message {
-            sender: self.our_name,
-            recipient: joining_node,
-            content: BootstrapMsg(self.vote_counts.clone()),
-        }
+    pub fn construct_bootstrap_msg(&self, joining_node: Name) -> Message {
+        let snapshots = self.snapshots.values().cloned().collect();
+        self.bootstrap_msg(joining_node, snapshots, self.vote_counts.clone())
     }
 
-    /// Apply a bootstrap message received from another node.
-    fn apply_bootstrap_msg(&mut self, vote_counts: VoteCounts) {
-        for (from, map) in vote_counts {
-            for (to, voters) in map {
-                let vote = Vote {
-                    from: from.clone(),
-                    to,
-                };
-                self.add_vote(vote, voters);
-            }
+    /// Bootstrap message for a candidate. With partial bootstraps, only the history of the
+    /// candidate's section and its neighbours is included.
+    fn candidate_bootstrap_msg(&mut self, blocks: &Blocks, joining_node: Name) -> Message {
+        if !self.params.partial_bootstrap {
+            let message = self.construct_bootstrap_msg(joining_node);
+            self.stats.bootstrap_votes_sent += num_votes(&self.vote_counts) as u64;
+            return message;
         }
-    }
-
-    /// Construct a RequestProof message
-    fn request_proof(&self, blocks: &Blocks, block: BlockId, node: Name) -> Vec<Message> {
-        let max_version = blocks
+        let prefixes: Vec<Prefix> = blocks
             .block_contents(&self.current_blocks)
             .into_iter()
-            .map(|b| b.version)
-            .max();
-        // Request proof if the `from` block isn't valid and it's version is less than the
-        // max version we have - 10
-        // FIXME: this tries to prevent bootstrapping issues - find a less hacky way to do this
-        if self.valid_blocks.contains(&block) ||
-            max_version.map_or(true, |ver| block.into_block(blocks).version > ver + 10)
-        {
-            vec![]
-        } else {
-            vec![
-                Message {
-                    sender: self.our_name,
-                    recipient: node,
-                    content: RequestProof(block, self.current_blocks.clone()),
-                },
-            ]
-        }
+            .map(|block| block.prefix)
+            .collect();
+        let relevant: Vec<Prefix> = match prefixes.iter().find(|p| p.matches(joining_node)) {
+            Some(section) => {
+                prefixes
+                    .iter()
+                    .filter(|p| *p == section || p.is_neighbour(section))
+                    .cloned()
+                    .collect()
+            }
+            None => prefixes.clone(),
+        };
+        self.partial_bootstrap_msg(blocks, joining_node, &relevant)
     }
 
-    fn check_path(blocks: &Blocks, current_blocks: &CurrentBlocks, p: &[BlockId]) -> bool {
-        let current_blocks_objs = blocks.block_contents(current_blocks);
-        let plast = &p[p.len() - 1];
-        let b0 = plast.into_block(blocks);
-        current_blocks.contains(plast) ||
-            current_blocks_objs.iter().any(|b| {
-                b.prefix.is_compatible(&b0.prefix) && b.version > b0.version
-            })
+    /// Bootstrap message with only the history of the sections covered by `prefixes`.
+    fn partial_bootstrap_msg(
+        &mut self,
+        blocks: &Blocks,
+        recipient: Name,
+        prefixes: &[Prefix],
+    ) -> Message {
+        let relevant = |prefix: &Prefix| prefixes.iter().any(|p| p.is_compatible(prefix));
+        let mut vote_counts = VoteCounts::new();
+        for (from, map) in &self.vote_counts {
+            let map: BTreeMap<_, _> = map.iter()
+                .filter(|&(to, _)| relevant(&to.into_block(blocks).prefix))
+                .map(|(to, voters)| (*to, voters.clone()))
+                .collect();
+            if !map.is_empty() {
+                let _ = vote_counts.insert(*from, map);
+            }
+        }
+        let snapshots = self.snapshots
+            .values()
+            .filter(|snapshot| relevant(&snapshot.prefix))
+            .cloned()
+            .collect();
+        self.stats.bootstrap_votes_sent += num_votes(&vote_counts) as u64;
+        self.bootstrap_msg(recipient, snapshots, vote_counts)
     }
 
-    fn bundle_predecessors(&self, blocks: &Blocks, block: BlockId, node: Name) -> Message {
-        let bundle = VoteBundle(
-            blocks
-                .predecessors(&block, &self.rev_vote_counts)
-                .into_iter()
-                .map(|(b, _, voters)| (Vote { from: b, to: block }, voters))
-                .collect::<Vec<_>>(),
-        );
+    fn bootstrap_msg(
+        &self,
+        recipient: Name,
+        snapshots: Vec<Snapshot>,
+        vote_counts: VoteCounts,
+    ) -> Message {
+        let content = if snapshots.is_empty() {
+            BootstrapMsg(Arc::new(vote_counts))
+        } else {
+            SnapshotBootstrapMsg(Arc::new((snapshots, vote_counts)))
+        };
         Message {
             sender: self.our_name,
-            recipient: node,
-            content: bundle,
+            recipient,
+            content,
         }
     }
 
-    /// Constructs a message with a vote bundle proving the given block
-    fn construct_proof(
-        &self,
-        blocks: &Blocks,
-        block: BlockId,
-        current_blocks: CurrentBlocks,
-        node: Name,
-    ) -> Message {
-        if !self.valid_blocks.contains(&block) {
-            return Message {
-                sender: self.our_name,
-                recipient: node,
-                content: NoProof(block),
-            };
-        }
-        if Self::check_path(blocks, &current_blocks, &[block]) {
-            return self.bundle_predecessors(blocks, block, node);
+    /// Ask the node which bootstrapped us for the history of any sections our current blocks
+    /// don't cover, once our bootstrap has been applied.
+    fn request_missing_chains(&mut self, blocks: &Blocks) -> Vec<Message> {
+        let peer = match self.bootstrap_peer {
+            Some(peer) if !self.chains_requested && !self.current_blocks.is_empty() => peer,
+            _ => return vec![],
+        };
+        self.chains_requested = true;
+        let prefixes: Vec<Prefix> = blocks
+            .block_contents(&self.current_blocks)
+            .into_iter()
+            .map(|block| block.prefix)
+            .collect();
+        let missing = Prefix::empty().uncovered_by(&prefixes);
+        if missing.is_empty() {
+            return vec![];
         }
+        debug!("{}: requesting history for {:?} from {}", self, missing, peer);
+        self.stats.chain_requests += 1;
+        vec![
+            Message {
+                sender: self.our_name,
+                recipient: peer,
+                content: RequestChains(missing),
+            },
+        ]
+    }
 
-        let mut paths = BTreeSet::new();
-        paths.insert(vec![block]);
-
-        let mut had_predecessors = true;
+    /// Sign a message to each neighbour of our section for every split of our section in `votes`.
+    fn notify_neighbours_of_splits(
+        &mut self,
+        blocks: &Blocks,
+        votes: &BTreeSet<(Vote, BTreeSet<Name>)>,
+        step: u64,
+    ) -> Vec<Message> {
+        let mut section_messages = vec![];
+        for (vote, _) in votes {
+            let from = vote.from.into_block(blocks);
+            if vote.kind(blocks) != VoteKind::Split || !from.members.contains(&self.our_name) {
+                continue;
+            }
+            for block in blocks.block_contents(&self.current_blocks) {
+                if block.prefix.is_neighbour(&from.prefix) {
+                    section_messages.push(SectionMessage {
+                        src: vote.from,
+                        dst: block.prefix,
+                        payload: SectionPayload::Split(vote.to),
+                    });
+                }
+            }
+        }
+        section_messages
+            .into_iter()
+            .flat_map(|message| self.sign_section_message(blocks, message, step))
+            .collect()
+    }
 
-        while had_predecessors &&
-            !paths.iter().any(
-                |p| Self::check_path(blocks, &current_blocks, p),
-            )
-        {
-            had_predecessors = false;
-            let mut new_paths = BTreeSet::new();
-            for path in paths {
-                let plast = path.last().unwrap();
-                let predecessor_blocks = blocks
-                    .predecessors(plast, &self.rev_vote_counts)
+    /// Sign the relocation of every node newly added to our section in `votes`, except those
+    /// relocated to us, to the section covering the name it's relocated under. The name is
+    /// derived from the block adding the node, so that every member relocates it to the same
+    /// one.
+    fn relocate_joiners(
+        &mut self,
+        blocks: &Blocks,
+        votes: &BTreeSet<(Vote, BTreeSet<Name>)>,
+        step: u64,
+    ) -> Vec<Message> {
+        let mut section_messages = vec![];
+        for (vote, _) in votes {
+            let from = vote.from.into_block(blocks);
+            let to = vote.to.into_block(blocks);
+            if vote.kind(blocks) != VoteKind::Membership || vote.is_witnessing(blocks) ||
+                !to.members.contains(&self.our_name)
+            {
+                continue;
+            }
+            for joiner in to.members.difference(&from.members) {
+                if *joiner == self.our_name || self.relocated_in.contains(joiner) {
+                    continue;
+                }
+                let new_name = Name(stable_hash(&(joiner, vote.to)));
+                let target = blocks
+                    .block_contents(&self.current_blocks)
                     .into_iter()
-                    .map(|(b, _, _)| b);
-
-                for prev_block in predecessor_blocks.filter(|&b| !path.iter().any(|b2| *b2 == b)) {
-                    had_predecessors = true;
-                    let mut new_path = path.clone();
-                    new_path.push(prev_block);
-                    new_paths.insert(new_path);
+                    .find(|block| block.prefix.matches(new_name))
+                    .map(|block| block.prefix);
+                if let Some(dst) = target {
+                    debug!("{}: relocating {} to {} in {:?}", self, joiner, new_name, dst);
+                    section_messages.push(SectionMessage {
+                        src: vote.to,
+                        dst,
+                        payload: SectionPayload::Relocate {
+                            node: *joiner,
+                            to: new_name,
+                        },
+                    });
                 }
             }
-            paths = new_paths;
         }
+        section_messages
+            .into_iter()
+            .flat_map(|message| self.sign_section_message(blocks, message, step))
+            .collect()
+    }
 
-        if !had_predecessors {
-            return Message {
-                sender: self.our_name,
-                recipient: node,
-                content: NoProof(block),
-            };
+    /// Sign the relocations we've been told about, from the current block listing both us and the
+    /// relocated member to the section covering its new name. Members both sections have lost
+    /// track of can't be relocated, so their relocations are dropped.
+    fn sign_relocations(&mut self, blocks: &Blocks, step: u64) -> Vec<Message> {
+        let mut section_messages = vec![];
+        for (node, to) in mem::take(&mut self.pending_relocations) {
+            let current = blocks.block_contents(&self.current_blocks);
+            let our_name = self.our_name;
+            let src = current
+                .iter()
+                .find(|block| block.members.contains(&node) && block.members.contains(&our_name))
+                .map(|block| block.get_id());
+            let dst = current
+                .iter()
+                .find(|block| block.prefix.matches(to))
+                .map(|block| block.prefix);
+            match (src, dst) {
+                (Some(src), Some(dst)) => {
+                    debug!("{}: relocating {} to {} in {:?}", self, node, to, dst);
+                    section_messages.push(SectionMessage {
+                        src,
+                        dst,
+                        payload: SectionPayload::Relocate { node, to },
+                    });
+                }
+                _ => debug!("{}: can't relocate {} to {}", self, node, to),
+            }
         }
+        section_messages
+            .into_iter()
+            .flat_map(|message| self.sign_section_message(blocks, message, step))
+            .collect()
+    }
 
-        let mut path = paths
-            .iter()
-            .find(|&p| Self::check_path(blocks, &current_blocks, p))
+    /// Look for peers signing a membership vote from one of our blocks which they don't send us
+    /// within `grace` steps, while they've sent us a vote from the same block for a sibling
+    /// block. Honest members send all their membership votes to the whole section, so an honest
+    /// peer only looks like an equivocator if one of its votes to us is lost or held up.
+    ///
+    /// The two votes are sent to the rest of our section as evidence, as the members the
+    /// equivocator sent the same votes to as us can't catch it themselves.
+    fn detect_equivocation(&mut self, blocks: &Blocks, step: u64, grace: u64) -> Vec<Message> {
+        for vote in &self.recent_votes {
+            if !vote.kind(blocks).changes_members() ||
+                !vote.from.into_block(blocks).members.contains(&self.our_name)
+            {
+                continue;
+            }
+            let voters = match self.vote_counts.get(&vote.from).and_then(|counts| {
+                counts.get(&vote.to)
+            }) {
+                Some(voters) => voters,
+                None => continue,
+            };
+            for voter in voters {
+                let sent = self.direct_votes.get(voter).is_some_and(
+                    |sent| sent.contains(vote),
+                );
+                if *voter != self.our_name && !sent && !self.equivocators.contains(voter) {
+                    let _ = self.unsent_votes.entry((*voter, vote.clone())).or_insert(step);
+                }
+            }
+        }
+
+        let mut proofs = vec![];
+        let due: Vec<(Name, Vote)> = self.unsent_votes
+            .iter()
+            .filter(|&(_, &since)| since + grace <= step)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in due {
+            let _ = self.unsent_votes.remove(&key);
+            let (voter, vote) = key;
+            let sent = match self.direct_votes.get(&voter) {
+                Some(sent) => sent,
+                None => continue,
+            };
+            if sent.contains(&vote) {
+                continue;
+            }
+            let to = vote.to.into_block(blocks);
+            let sibling = sent.iter().find(|other| {
+                let other_to = other.to.into_block(blocks);
+                other.from == vote.from && other.to != vote.to && other_to.prefix == to.prefix &&
+                    other_to.version == to.version
+            });
+            if let Some(sibling) = sibling {
+                if self.equivocators.insert(voter) {
+                    debug!(
+                        "{}: caught {} equivocating: it sent us {:?}, but signed {:?}",
+                        self,
+                        voter,
+                        sibling.as_debug(blocks),
+                        vote.as_debug(blocks)
+                    );
+                    let _ = self.quarantined.insert(voter);
+                    proofs.push(EquivocationProof(Arc::new((voter, sibling.clone(), vote))));
+                }
+            }
+        }
+
+        let our_name = self.our_name;
+        let mut messages = vec![];
+        for content in proofs {
+            let equivocator = match content {
+                EquivocationProof(ref proof) => proof.0,
+                _ => continue,
+            };
+            messages.extend(
+                content
+                    .recipients(blocks, &self.current_blocks, our_name)
+                    .into_iter()
+                    .filter(|recipient| *recipient != our_name && *recipient != equivocator)
+                    .map(|recipient| {
+                        Message {
+                            sender: our_name,
+                            recipient,
+                            content: content.clone(),
+                        }
+                    }),
+            );
+        }
+        messages
+    }
+
+    /// Check evidence from `sender` that a member of our section equivocated, and vote it out if
+    /// the two votes it signed are from the same block of ours and for conflicting siblings.
+    fn add_equivocation_proof(
+        &mut self,
+        blocks: &Blocks,
+        proof: &(Name, Vote, Vote),
+        sender: Name,
+    ) {
+        let (equivocator, ref first, ref second) = *proof;
+        if equivocator == self.our_name || self.equivocators.contains(&equivocator) {
+            return;
+        }
+        let conflicting = {
+            let from = first.from.into_block(blocks);
+            let first_to = first.to.into_block(blocks);
+            let second_to = second.to.into_block(blocks);
+            from.members.contains(&self.our_name) && first.from == second.from &&
+                first.to != second.to && first_to.prefix == second_to.prefix &&
+                first_to.version == second_to.version &&
+                first.kind(blocks).changes_members() &&
+                second.kind(blocks).changes_members()
+        };
+        let signed = !self.verify_voters(blocks, first, btreeset!{equivocator}).is_empty() &&
+            !self.verify_voters(blocks, second, btreeset!{equivocator}).is_empty();
+        if !conflicting || !signed {
+            debug!(
+                "{}: rejected evidence from {} that {} equivocated",
+                self,
+                sender,
+                equivocator
+            );
+            return;
+        }
+        if self.equivocators.insert(equivocator) {
+            debug!(
+                "{}: {} showed us {} equivocating: it signed {:?} and {:?}",
+                self,
+                sender,
+                equivocator,
+                first.as_debug(blocks),
+                second.as_debug(blocks)
+            );
+            let _ = self.quarantined.insert(equivocator);
+        }
+    }
+
+    /// Send our share of a message from our section to the rest of the section.
+    fn sign_section_message(
+        &mut self,
+        blocks: &Blocks,
+        message: SectionMessage,
+        step: u64,
+    ) -> Vec<Message> {
+        self.stats.section_shares_sent += 1;
+        let content = SectionShare(Arc::new(message.clone()));
+        let mut messages: Vec<Message> = message
+            .src
+            .into_block(blocks)
+            .members
+            .iter()
+            .filter(|&&member| member != self.our_name)
+            .map(|&member| {
+                Message {
+                    sender: self.our_name,
+                    recipient: member,
+                    content: content.clone(),
+                }
+            })
+            .collect();
+        messages.extend(self.add_section_share(blocks, &message, self.our_name, step));
+        messages
+    }
+
+    /// Add a share of a message from our section, sending the message to the members of its
+    /// destination section once a quorum has signed it.
+    fn add_section_share(
+        &mut self,
+        blocks: &Blocks,
+        message: &SectionMessage,
+        signer: Name,
+        step: u64,
+    ) -> Vec<Message> {
+        let signers = match self.section_shares.add(blocks, message, signer) {
+            Some(signers) => signers,
+            None => return vec![],
+        };
+        debug!(
+            "{}: sending {:?} signed by {} members to {:?}",
+            self,
+            message.payload,
+            signers.len(),
+            message.dst
+        );
+        self.stats.section_messages_sent += 1;
+        if let SectionPayload::Relocate { node, to } = message.payload {
+            if node == self.our_name {
+                self.relocated_to = Some(to);
+            } else if self.relocating_out.insert(node, to).is_none() {
+                debug!("{}: voting out {}, relocated to {}", self, node, to);
+            }
+            // We don't send the message to ourselves, so take note of a node relocated within
+            // our own section here.
+            if message.dst.matches(self.our_name) {
+                self.relocate_in(node, to, step);
+            }
+        }
+        let content = SectionMsg(Arc::new((message.clone(), signers)));
+        blocks
+            .block_contents(&self.current_blocks)
+            .into_iter()
+            .filter(|block| block.prefix.is_compatible(&message.dst))
+            .flat_map(|block| block.members.iter().cloned())
+            .filter(|&member| member != self.our_name)
+            .map(|member| {
+                Message {
+                    sender: self.our_name,
+                    recipient: member,
+                    content: content.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Take note of `node` being relocated to our section under the name `to`, to let it join
+    /// and vote it in.
+    fn relocate_in(&mut self, node: Name, to: Name, step: u64) {
+        if self.relocated_in.insert(to) {
+            debug!("{}: voting in {}, relocated from {}", self, to, node);
+            let _ = self.relocating_in.insert(to, (node, step));
+        }
+    }
+
+    /// Compact the history of our section that's older than we keep into a snapshot, once
+    /// enough of it has built up.
+    fn compact_history(&mut self, blocks: &Blocks) {
+        let keep_versions = match self.params.compaction {
+            Some(keep_versions) => keep_versions,
+            None => return,
+        };
+        let our_blocks: Vec<(BlockId, Prefix, u64)> = self.our_current_section_blocks(blocks)
+            .into_iter()
+            .map(|block| (block.get_id(), block.prefix, block.version))
+            .collect();
+        for (block, prefix, version) in our_blocks {
+            // Compact in batches, once twice as much history as we keep has built up.
+            let previous = self.snapshots.get(&prefix).cloned();
+            let compacted_version = previous.as_ref().map_or(0, |snapshot| snapshot.version);
+            if compacted_version + 2 * keep_versions > version {
+                continue;
+            }
+            let (snapshot, compacted) = match Snapshot::compact(
+                blocks,
+                &self.rev_vote_counts,
+                block,
+                keep_versions,
+                previous.as_ref(),
+            ) {
+                Some(compaction) => compaction,
+                None => continue,
+            };
+
+            let full = if self.params.verify_compaction {
+                Some(self.vote_counts.clone())
+            } else {
+                None
+            };
+            let num_removed = compaction::remove_votes(
+                &mut self.vote_counts,
+                &mut self.rev_vote_counts,
+                &compacted,
+            );
+            // Forgotten votes which have been compacted away are no longer worth hearing about.
+            self.forgotten_votes.retain(|vote| {
+                !compacted.contains(&vote.from) && !compacted.contains(&vote.to)
+            });
+            let candidates = &self.current_candidate_blocks;
+            self.valid_blocks.retain(
+                |id| !compacted.contains(id) || candidates.contains(id),
+            );
+            if let Some(full) = full {
+                if !snapshot.verify(blocks, &self.vote_counts, &full) {
+                    warn!("{}: compaction into {:?} changed the agreed history", self, snapshot);
+                    self.stats.compaction_mismatches += 1;
+                }
+            }
+
+            debug!(
+                "{}: compacted {} votes into a snapshot at version {}",
+                self,
+                num_removed,
+                snapshot.version
+            );
+            self.stats.compactions += 1;
+            self.stats.votes_compacted += num_removed as u64;
+            self.add_snapshot(snapshot);
+        }
+    }
+
+    /// Keep a snapshot, replacing any older ones of compatible sections.
+    fn add_snapshot(&mut self, snapshot: Snapshot) {
+        self.snapshots.retain(|prefix, existing| {
+            !prefix.is_compatible(&snapshot.prefix) || existing.version > snapshot.version
+        });
+        let _ = self.snapshots.insert(snapshot.prefix, snapshot);
+    }
+
+    /// Apply a bootstrap message received from another node.
+    fn apply_bootstrap_msg(&mut self, blocks: &Blocks, vote_counts: &VoteCounts) {
+        for (from, map) in vote_counts {
+            for (to, voters) in map {
+                let vote = Vote {
+                    from: *from,
+                    to: *to,
+                };
+                let voters = self.verify_voters(blocks, &vote, voters.clone());
+                self.add_vote(vote, voters);
+            }
+        }
+    }
+
+    /// Construct a RequestProof message
+    fn request_proof(&self, blocks: &Blocks, block: BlockId, node: Name) -> Vec<Message> {
+        let max_version = blocks
+            .block_contents(&self.current_blocks)
+            .into_iter()
+            .map(|b| b.version)
+            .max();
+        // Request proof if the `from` block isn't valid and it's version is less than the
+        // max version we have - 10
+        // FIXME: this tries to prevent bootstrapping issues - find a less hacky way to do this
+        if self.valid_blocks.contains(&block) ||
+            max_version.map_or(true, |ver| block.into_block(blocks).version > ver + 10)
+        {
+            vec![]
+        } else {
+            vec![
+                Message {
+                    sender: self.our_name,
+                    recipient: node,
+                    content: RequestProof(block, self.current_blocks.clone()),
+                },
+            ]
+        }
+    }
+
+    fn check_path(blocks: &Blocks, current_blocks: &CurrentBlocks, p: &[BlockId]) -> bool {
+        let current_blocks_objs = blocks.block_contents(current_blocks);
+        let plast = &p[p.len() - 1];
+        let b0 = plast.into_block(blocks);
+        current_blocks.contains(plast) ||
+            current_blocks_objs.iter().any(|b| {
+                b.prefix.is_compatible(&b0.prefix) && b.version > b0.version
+            })
+    }
+
+    /// Welcome messages for the nodes added to our section by newly valid votes, each with the
+    /// last `depth` agreed votes of our section's chain up to the block adding it.
+    fn welcome_joiners(
+        &mut self,
+        blocks: &Blocks,
+        new_valid_votes: &BTreeSet<(Vote, BTreeSet<Name>)>,
+        depth: usize,
+    ) -> Vec<Message> {
+        let mut messages = vec![];
+        for (vote, _) in new_valid_votes {
+            let from = vote.from.into_block(blocks);
+            let to = vote.to.into_block(blocks);
+            if !vote.kind(blocks).changes_members() || !to.members.contains(&self.our_name) {
+                continue;
+            }
+            let joiners: Vec<Name> = to.members
+                .difference(&from.members)
+                .filter(|joiner| **joiner != self.our_name)
+                .cloned()
+                .collect();
+            if joiners.is_empty() {
+                continue;
+            }
+            let segment = Arc::new(self.agreed_chain(blocks, vote.to, depth));
+            for joiner in joiners {
+                debug!("{}: welcoming {} with {} votes", self, joiner, segment.len());
+                self.stats.welcome_msgs_sent += 1;
+                messages.push(Message {
+                    sender: self.our_name,
+                    recipient: joiner,
+                    content: WelcomeMsg(segment.clone()),
+                });
+            }
+        }
+        messages
+    }
+
+    /// Up to `depth` agreed votes leading to `block`, oldest first, following the most recent
+    /// valid predecessor of each block.
+    fn agreed_chain(
+        &self,
+        blocks: &Blocks,
+        block: BlockId,
+        depth: usize,
+    ) -> Vec<(Vote, BTreeSet<Name>)> {
+        let mut chain = vec![];
+        let mut block = block;
+        while chain.len() < depth {
+            let latest = blocks
+                .predecessors(&block, &self.rev_vote_counts)
+                .into_iter()
+                .filter(|&(from, _, _)| self.valid_blocks.contains(&from))
+                .max_by_key(|&(from, _, _)| from.into_block(blocks).version);
+            match latest {
+                Some((from, vote, voters)) => {
+                    chain.push((vote, voters));
+                    block = from;
+                }
+                None => break,
+            }
+        }
+        chain.reverse();
+        chain
+    }
+
+    fn bundle_predecessors(&self, blocks: &Blocks, block: BlockId, node: Name) -> Message {
+        let bundle = VoteBundle(Arc::new(
+            blocks
+                .predecessors(&block, &self.rev_vote_counts)
+                .into_iter()
+                .map(|(b, _, voters)| (Vote { from: b, to: block }, voters))
+                .collect::<Vec<_>>(),
+        ));
+        Message {
+            sender: self.our_name,
+            recipient: node,
+            content: bundle,
+        }
+    }
+
+    /// Constructs a message with a vote bundle proving the given block
+    fn construct_proof(
+        &self,
+        blocks: &Blocks,
+        block: BlockId,
+        current_blocks: CurrentBlocks,
+        node: Name,
+    ) -> Message {
+        if !self.valid_blocks.contains(&block) {
+            return Message {
+                sender: self.our_name,
+                recipient: node,
+                content: NoProof(block),
+            };
+        }
+        if Self::check_path(blocks, &current_blocks, &[block]) {
+            return self.bundle_predecessors(blocks, block, node);
+        }
+
+        let mut paths = BTreeSet::new();
+        paths.insert(vec![block]);
+
+        let mut had_predecessors = true;
+
+        while had_predecessors &&
+            !paths.iter().any(
+                |p| Self::check_path(blocks, &current_blocks, p),
+            )
+        {
+            had_predecessors = false;
+            let mut new_paths = BTreeSet::new();
+            for path in paths {
+                let plast = path.last().unwrap();
+                let predecessor_blocks = blocks
+                    .predecessors(plast, &self.rev_vote_counts)
+                    .into_iter()
+                    .map(|(b, _, _)| b);
+
+                for prev_block in predecessor_blocks.filter(|&b| !path.iter().any(|b2| *b2 == b)) {
+                    had_predecessors = true;
+                    let mut new_path = path.clone();
+                    new_path.push(prev_block);
+                    new_paths.insert(new_path);
+                }
+            }
+            paths = new_paths;
+        }
+
+        if !had_predecessors {
+            return Message {
+                sender: self.our_name,
+                recipient: node,
+                content: NoProof(block),
+            };
+        }
+
+        let mut path = paths
+            .iter()
+            .find(|&p| Self::check_path(blocks, &current_blocks, p))
             .unwrap()
             .clone();
 
@@ -679,115 +2383,600 @@ impl Node {
                     from: vote[0],
                     to: vote[1],
                 },
-                names,
-            ));
+                names,
+            ));
+        }
+
+        Message {
+            sender: self.our_name,
+            recipient: node,
+            content: VoteBundle(Arc::new(bundle)),
+        }
+    }
+
+    /// Returns true if the peer is known and its state is `Disconnected`.
+    pub fn is_disconnected_from(&self, name: &Name) -> bool {
+        !self.connections.is_connected(name)
+    }
+
+    /// Returns true if this node should shutdown because it has failed to join a section.
+    pub fn should_shutdown(&self, blocks: &Blocks, step: u64) -> bool {
+        let timeout_elapsed = step >= self.step_created + self.params.self_shutdown_timeout;
+
+        let (no_blocks, insufficient_connections) = match self.our_current_blocks(blocks).first() {
+            Some(block) => (false, self.connections.num_connected() * 2 < block.members.len()),
+            None => (true, true),
+        };
+
+        timeout_elapsed && (no_blocks || insufficient_connections)
+    }
+
+    pub fn step_created(&self) -> u64 {
+        self.step_created
+    }
+
+    /// Our notion of the current step, which is what all our timeouts go by.
+    pub fn local_step(&self, step: u64) -> u64 {
+        clock_step(step, self.clock_offset)
+    }
+
+    /// Let our clock drift by up to a step, staying within the allowed skew.
+    fn drift_clock(&mut self) {
+        if self.params.max_clock_skew == 0 {
+            return;
+        }
+        let max_skew = self.params.max_clock_skew as i64;
+        let drift = (random::<u8>() % 3) as i64 - 1;
+        self.clock_offset = (self.clock_offset + drift).clamp(-max_skew, max_skew);
+    }
+
+    /// Add the votes of a bundle from `sender`, asking it for proof of the blocks they start from.
+    fn apply_vote_bundle(
+        &mut self,
+        blocks: &Blocks,
+        sender: Name,
+        bundle: &[(Vote, BTreeSet<Name>)],
+    ) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for block in self.bundle_base(blocks, bundle) {
+            messages.extend(self.request_proof(blocks, block, sender));
+        }
+        self.stats.votes_received += bundle.len() as u64;
+        for (vote, voters) in bundle {
+            let voters = self.verify_voters(blocks, vote, voters.clone());
+            self.add_vote(vote.clone(), voters);
+        }
+        messages
+    }
+
+    fn bundle_base(&self, blocks: &Blocks, bundle: &[(Vote, BTreeSet<Name>)]) -> Vec<BlockId> {
+        let mut block_ids = BTreeSet::new();
+        for &(ref vote, _) in bundle {
+            block_ids.insert(vote.from);
+            block_ids.insert(vote.to);
+        }
+        block_ids
+            .into_iter()
+            .filter(|&b| {
+                !bundle.into_iter().any(|&(ref vote, ref voters)| {
+                    vote.to == b && vote.is_quorum(blocks, voters)
+                })
+            })
+            .collect()
+    }
+
+    fn should_be_connected(&self, node: Name, blocks: &Blocks, step: u64) -> bool {
+        let neighbours = nodes_in_any(blocks, &self.current_blocks);
+        // A candidate connects back to us as soon as it's bootstrapped, which can be before we see
+        // it added, so keep accepting connections from it until it's added or times out.
+        let recent_candidate = self.candidates
+            .get(&node)
+            .map(|candidate| candidate.is_recent(self.params.join_timeout, step))
+            .unwrap_or(false);
+        // Our section has agreed to take nodes relocated to it, which may try to connect before
+        // we've seen them added.
+        neighbours.contains(&node) || recent_candidate || self.relocated_in.contains(&node)
+    }
+
+    /// Accept or reject a connection request from `peer`.
+    fn handle_connect(&mut self, blocks: &Blocks, peer: Name, step: u64) -> Vec<Message> {
+        let mut messages = vec![];
+        let mut accept = self.should_be_connected(peer, blocks, step);
+        if accept && !self.make_room(blocks, peer, &mut messages) {
+            debug!("{}: no room for a connection to {}", self, peer);
+            self.stats.connections_refused += 1;
+            accept = false;
+        }
+        if accept {
+            if self.connections.accept(peer) {
+                debug!("{}: obtained a connection to {}", self, peer);
+            }
+            if !self.connections.is_requested(&peer) {
+                trace!("{}: connecting back to {}", self, peer);
+                self.connections.request(peer, step);
+                self.stats.connects_initiated += 1;
+                messages.push(self.connect_msg(peer));
+            }
+        } else {
+            trace!("{}: rejecting connection request from {}", self, peer);
+            self.connections.disconnect(&peer);
+            messages.push(Message {
+                sender: self.our_name,
+                recipient: peer,
+                content: Disconnect,
+            });
+        }
+        messages
+    }
+
+    /// Messages initiating a connection to `peer`.
+    ///
+    /// If we block inbound connections, a request the peer already sent us may have been lost,
+    /// leaving it waiting on that instead of answering ours. A disconnect first clears it.
+    fn initiate_connection(&mut self, peer: Name) -> Vec<Message> {
+        let mut messages = vec![];
+        if self.inbound_blocked {
+            messages.push(Message {
+                sender: self.our_name,
+                recipient: peer,
+                content: Disconnect,
+            });
+        }
+        messages.push(self.connect_msg(peer));
+        messages
+    }
+
+    /// A connection request to `peer`, carrying our pending votes if piggybacking is enabled.
+    fn connect_msg(&mut self, peer: Name) -> Message {
+        let votes = if self.params.piggyback_votes {
+            self.pending_votes()
+        } else {
+            vec![]
+        };
+        let content = if votes.is_empty() {
+            Connect
+        } else {
+            self.stats.votes_piggybacked += votes.len() as u64;
+            ConnectWithVotes(Arc::new(votes))
+        };
+        Message {
+            sender: self.our_name,
+            recipient: peer,
+            content,
+        }
+    }
+
+    /// Votes succeeding our current blocks which haven't become valid yet, with every voter we
+    /// know of.
+    fn pending_votes(&self) -> Vec<(Vote, BTreeSet<Name>)> {
+        self.current_blocks
+            .iter()
+            .filter_map(|from| self.vote_counts.get(from).map(|votes| (from, votes)))
+            .flat_map(|(from, votes)| {
+                votes
+                    .iter()
+                    .filter(|&(to, _)| !self.valid_blocks.contains(to))
+                    .map(move |(to, voters)| {
+                        (
+                            Vote {
+                                from: *from,
+                                to: *to,
+                            },
+                            voters.clone(),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Add a vote cast by `sender`, requesting proof of the block it's from if we need it.
+    fn handle_vote(&mut self, blocks: &Blocks, vote: Vote, sender: Name, step: u64) -> Vec<Message> {
+        trace!("{}: received {:?} from {}", self, vote.as_debug(blocks), sender);
+        self.stats.votes_received += 1;
+        let messages = self.request_proof(blocks, vote.from, sender);
+        let voters = self.verify_voters(blocks, &vote, btreeset!{sender});
+        if !voters.is_empty() {
+            if self.params.equivocation_grace.is_some() {
+                let _ = self.direct_votes.entry(sender).or_default().insert(vote.clone());
+            }
+            if self.params.validate_ancestry && !self.valid_blocks.contains(&vote.from) {
+                trace!(
+                    "{}: holding back {:?} from {} until its ancestry is valid",
+                    self,
+                    vote.as_debug(blocks),
+                    sender
+                );
+                self.stats.votes_unknown_ancestry += 1;
+                self.orphan_votes.entry(vote.from).or_default().push((vote, sender, step));
+                return messages;
+            }
+            self.add_vote(vote, voters);
+        }
+        messages
+    }
+
+    /// Count the votes held back for lack of a valid block to follow, now that their blocks are
+    /// valid, and give up on those from blocks which have fallen behind our current blocks, as
+    /// on a losing branch, or have been held back for longer than `orphan_vote_timeout`.
+    fn release_orphan_votes(&mut self, blocks: &Blocks, step: u64) {
+        let ready: Vec<BlockId> = self.orphan_votes
+            .keys()
+            .filter(|block| self.valid_blocks.contains(block))
+            .cloned()
+            .collect();
+        for block in ready {
+            for (vote, voter, received) in self.orphan_votes.remove(&block).unwrap_or_default() {
+                self.stats.unknown_ancestry_wait_steps += step.saturating_sub(received);
+                self.add_vote(vote, Some(voter));
+            }
+        }
+
+        let current = blocks.block_contents(&self.current_blocks);
+        let timeout = self.params.orphan_vote_timeout;
+        let mut dropped = 0;
+        self.orphan_votes.retain(|from, held| {
+            let from = from.into_block(blocks);
+            let behind = current.iter().any(|block| {
+                block.prefix.is_compatible(&from.prefix) && block.version > from.version
+            });
+            let before = held.len();
+            if behind {
+                held.clear();
+            } else {
+                held.retain(|&(_, _, received)| received + timeout > step);
+            }
+            dropped += before - held.len();
+            !held.is_empty()
+        });
+        if dropped > 0 {
+            debug!("{}: gave up on {} votes held back for their ancestry", self, dropped);
+            self.stats.orphan_votes_dropped += dropped as u64;
+        }
+    }
+
+    /// Add the votes attached to a connection request from `peer`.
+    fn apply_piggybacked_votes(
+        &mut self,
+        blocks: &Blocks,
+        peer: Name,
+        votes: &[(Vote, BTreeSet<Name>)],
+    ) -> Vec<Message> {
+        trace!("{}: received {} votes with a connection from {}", self, votes.len(), peer);
+        self.stats.votes_received += votes.len() as u64;
+
+        let mut messages = vec![];
+        let froms: BTreeSet<BlockId> = votes.iter().map(|(vote, _)| vote.from).collect();
+        for from in froms {
+            messages.extend(self.request_proof(blocks, from, peer));
+        }
+        for (vote, voters) in votes {
+            let is_new = self.vote_counts
+                .get(&vote.from)
+                .and_then(|counts| counts.get(&vote.to))
+                .is_none_or(|known| !voters.is_subset(known));
+            if is_new {
+                self.stats.piggybacked_votes_new += 1;
+            }
+            let voters = self.verify_voters(blocks, vote, voters.clone());
+            if !voters.is_empty() {
+                self.add_vote(vote.clone(), voters);
+            }
+        }
+        messages
+    }
+
+    /// Whether the section `joining_node` would join already has as many candidates as we allow.
+    fn section_full(&self, blocks: &Blocks, joining_node: Name, step: u64) -> bool {
+        let max = match self.params.max_candidates_per_section {
+            Some(max) => max,
+            None => return false,
+        };
+        let block = match blocks
+            .block_contents(&self.current_blocks)
+            .into_iter()
+            .find(|block| block.prefix.matches(joining_node)) {
+            Some(block) => block,
+            None => return false,
+        };
+        // Candidates we've seen added are kept a while in case that block loses out, but they
+        // no longer take up room.
+        let num_candidates = self.candidates
+            .iter()
+            .filter(|&(name, candidate)| {
+                block.prefix.matches(*name) && !block.members.contains(name) &&
+                    candidate.is_recent(self.params.join_timeout, step)
+            })
+            .count();
+        num_candidates >= max
+    }
+
+    /// Blocks succeeding our section's current blocks which haven't become valid yet.
+    fn our_pending_blocks(&self, blocks: &Blocks) -> BTreeSet<BlockId> {
+        let ours: BTreeSet<BlockId> = self.our_current_blocks(blocks)
+            .into_iter()
+            .map(Block::get_id)
+            .collect();
+        self.pending_votes()
+            .into_iter()
+            .filter(|(vote, _)| ours.contains(&vote.from))
+            .map(|(vote, _)| vote.to)
+            .collect()
+    }
+
+    /// Send our join again to the members of busy sections whose delay is up, until we're a
+    /// member of a section.
+    fn retry_joins(&mut self, step: u64) -> Vec<Message> {
+        if self.voting_from.is_some() {
+            self.join_retries.clear();
+            return vec![];
+        }
+        let due: Vec<Name> = self.join_retries
+            .iter()
+            .filter(|&(_, &retry_step)| retry_step <= step)
+            .map(|(name, _)| *name)
+            .collect();
+        due.into_iter()
+            .map(|member| {
+                let _ = self.join_retries.remove(&member);
+                trace!("{}: sending our join to {} again", self, member);
+                self.stats.join_retries += 1;
+                Message {
+                    sender: self.our_name,
+                    recipient: member,
+                    content: NodeJoined,
+                }
+            })
+            .collect()
+    }
+
+    /// Start adding a node which has asked to join, and get it up to date.
+    fn handle_join(&mut self, blocks: &Blocks, joining_node: Name, step: u64) -> Vec<Message> {
+        debug!("{}: received join message for: {}", self, joining_node);
+
+        // A node joining again under the name of a member we haven't agreed to remove yet has to
+        // wait out the whole cooldown, or it would be bootstrapped with blocks listing it. Once
+        // other members have voted to add it back, or agreed to, it's let through.
+        let cooldown = self.params.rejoin_cooldown;
+        let cooldown_left = if cooldown == 0 {
+            0
+        } else {
+            let lists_joiner = |block: &BlockId| {
+                block.into_block(blocks).members.contains(&joining_node)
+            };
+            match self.recently_removed.get(&joining_node) {
+                Some(&removed) => {
+                    let added_back = self.current_blocks.iter().any(&lists_joiner) ||
+                        self.our_pending_blocks(blocks).iter().any(&lists_joiner);
+                    if added_back {
+                        0
+                    } else {
+                        (removed + cooldown).saturating_sub(step)
+                    }
+                }
+                None if self.current_blocks.iter().any(&lists_joiner) => cooldown,
+                None => 0,
+            }
+        };
+        if cooldown_left > 0 {
+            debug!("{}: {} was only just removed, asking it to try later", self, joining_node);
+            self.stats.rejoins_deferred += 1;
+            return vec![
+                Message {
+                    sender: self.our_name,
+                    recipient: joining_node,
+                    content: TryLater(cooldown_left),
+                },
+            ];
         }
 
-        Message {
-            sender: self.our_name,
-            recipient: node,
-            content: VoteBundle(bundle),
+        // Our section has agreed to take nodes relocated to it, so they're let through however
+        // busy we are.
+        let relocated = self.relocated_in.contains(&joining_node);
+        if !self.candidates.contains_key(&joining_node) && !relocated &&
+            self.section_full(blocks, joining_node, step)
+        {
+            debug!("{}: too many candidates to accept {}", self, joining_node);
+            self.stats.candidates_refused += 1;
+            return vec![];
         }
-    }
 
-    /// Returns true if the peer is known and its state is `Disconnected`.
-    pub fn is_disconnected_from(&self, name: &Name) -> bool {
-        !self.connections.contains(name)
-    }
+        if let Some(backpressure) = self.params.join_backpressure {
+            // Joining nodes other members have already voted for are let through, or those
+            // votes could never be agreed and we'd stay busy.
+            let pending = self.our_pending_blocks(blocks);
+            let voted_for = pending
+                .iter()
+                .any(|block| block.into_block(blocks).members.contains(&joining_node));
+            if !self.candidates.contains_key(&joining_node) && !voted_for && !relocated &&
+                pending.len() >= backpressure.max_pending_blocks
+            {
+                debug!("{}: too busy to accept {}, asking it to try later", self, joining_node);
+                self.stats.joins_deferred += 1;
+                return vec![
+                    Message {
+                        sender: self.our_name,
+                        recipient: joining_node,
+                        content: TryLater(backpressure.retry_delay),
+                    },
+                ];
+            }
+        }
 
-    /// Returns true if this node should shutdown because it has failed to join a section.
-    pub fn should_shutdown(&self, blocks: &Blocks, step: u64) -> bool {
-        let timeout_elapsed = step >= self.step_created + self.params.self_shutdown_timeout;
+        let mut messages = vec![];
+        if !self.make_room(blocks, joining_node, &mut messages) {
+            debug!("{}: no room for a connection to candidate {}", self, joining_node);
+            self.stats.connections_refused += 1;
+            return messages;
+        }
 
-        let (no_blocks, insufficient_connections) = match self.our_current_blocks(blocks).first() {
-            Some(block) => (false, self.connections.len() * 2 < block.members.len()),
-            None => (true, true),
-        };
+        // Mark the peer as having joined so that we vote to keep adding it.
+        self.candidates
+            .entry(joining_node)
+            .or_insert_with(|| {
+                Candidate {
+                    step_added: step,
+                    approvals: BTreeSet::new(),
+                    approval_due: None,
+                    added: false,
+                }
+            })
+            .step_added = step;
+        let _ = self.connections.accept(joining_node);
+        self.connections.request(joining_node, step);
 
-        timeout_elapsed && (no_blocks || insufficient_connections)
-    }
+        let connect_msg = self.connect_msg(joining_node);
 
-    pub fn step_created(&self) -> u64 {
-        self.step_created
+        // Send a bootstrap message to the joining node.
+        messages.push(connect_msg);
+        messages.push(self.candidate_bootstrap_msg(blocks, joining_node));
+        if self.params.candidate_approval {
+            messages.extend(self.approve_candidate(blocks, joining_node, step));
+        }
+        messages
     }
 
-    fn bundle_base(&self, blocks: &Blocks, bundle: &[(Vote, BTreeSet<Name>)]) -> Vec<BlockId> {
-        let mut block_ids = BTreeSet::new();
-        for &(ref vote, _) in bundle {
-            block_ids.insert(vote.from);
-            block_ids.insert(vote.to);
+    /// Handle a message intended for us and return messages we'd like to send.
+    ///
+    /// With a processing cost model, the message joins the back of our inbox, and we only handle
+    /// as many messages from the front as this step's budget covers.
+    pub fn handle_message(&mut self, message: Message, blocks: &Blocks, step: u64) -> Vec<Message> {
+        if self.params.processing.is_none() {
+            return self.process_message(message, blocks, step);
         }
-        block_ids
-            .into_iter()
-            .filter(|&b| {
-                !bundle.into_iter().any(|&(ref vote, ref voters)| {
-                    vote.to == b && vote.is_quorum(blocks, voters)
-                })
-            })
-            .collect()
+        self.inbox.push_back((message, step));
+        self.process_inbox(blocks, step)
     }
 
-    fn should_be_connected(&self, node: Name, blocks: &Blocks) -> bool {
-        let neighbours = nodes_in_any(blocks, &self.current_blocks);
-        neighbours.contains(&node)
+    /// Handle messages from our inbox until we run out of budget for this step.
+    fn process_inbox(&mut self, blocks: &Blocks, step: u64) -> Vec<Message> {
+        let processing = match self.params.processing {
+            Some(processing) => processing,
+            None => return vec![],
+        };
+        if self.processed.0 != step {
+            self.processed = (step, 0);
+        }
+        let mut to_send = vec![];
+        while let Some((message, received)) = self.inbox.pop_front() {
+            let cost = processing.cost(&message.content);
+            // The first message of a step is handled whatever it costs.
+            if self.processed.1 > 0 && self.processed.1 + cost > processing.budget_per_step {
+                self.inbox.push_front((message, received));
+                break;
+            }
+            self.processed.1 += cost;
+            if received < step {
+                self.stats.messages_backlogged += 1;
+                self.stats.backlog_wait_steps += step - received;
+                if message.content.carries_votes() {
+                    self.stats.votes_backlogged += 1;
+                }
+            }
+            to_send.extend(self.process_message(message, blocks, step));
+        }
+        if !self.inbox.is_empty() {
+            trace!("{}: {} messages left in inbox", self, self.inbox.len());
+        }
+        to_send
     }
 
-    /// Handle a message intended for us and return messages we'd like to send.
-    pub fn handle_message(&mut self, message: Message, blocks: &Blocks, step: u64) -> Vec<Message> {
+    /// Handle a message, as soon as we have the capacity to.
+    fn process_message(&mut self, message: Message, blocks: &Blocks, step: u64) -> Vec<Message> {
+        if let Some(fault) = self.fault_dropping(&message.content) {
+            trace!("{}: dropping message from {} due to {}", self, message.sender, fault);
+            self.stats.messages_dropped_by_faults += 1;
+            return vec![];
+        }
         let to_send = match message.content {
-            NodeJoined => {
+            NodeJoined => self.handle_join(blocks, message.sender, step),
+            JoinRequest => {
                 let joining_node = message.sender;
-                debug!("{}: received join message for: {}", self, joining_node);
-
-                // Mark the peer as having joined so that we vote to keep adding it.
-                self.candidates.insert(
-                    joining_node,
-                    Candidate { step_added: step },
-                );
-                self.connections.insert(joining_node);
-                self.connect_requests.insert(joining_node);
-
-                let connect_msg = Message {
-                    sender: self.our_name,
-                    recipient: joining_node,
-                    content: Connect,
-                };
+                debug!("{}: forwarding join of {} to its section", self, joining_node);
 
-                // Send a bootstrap message to the joining node.
-                vec![connect_msg, self.construct_bootstrap_msg(joining_node)]
+                let section: BTreeSet<Name> = blocks
+                    .block_contents(&self.current_blocks)
+                    .into_iter()
+                    .filter(|block| block.prefix.matches(joining_node))
+                    .flat_map(|block| block.members.iter().cloned())
+                    .collect();
+                let mut messages: Vec<Message> = section
+                    .iter()
+                    .filter(|&&member| member != self.our_name)
+                    .map(|&member| {
+                        Message {
+                            sender: self.our_name,
+                            recipient: member,
+                            content: ForwardedJoin(joining_node),
+                        }
+                    })
+                    .collect();
+                if section.contains(&self.our_name) {
+                    messages.extend(self.handle_join(blocks, joining_node, step));
+                }
+                messages
+            }
+            ForwardedJoin(joining_node) => self.handle_join(blocks, joining_node, step),
+            TryLater(delay) => {
+                if self.voting_from.is_none() {
+                    debug!("{}: {} asked us to try joining again later", self, message.sender);
+                    let _ = self.join_retries.insert(message.sender, step + delay);
+                }
+                vec![]
             }
-            VoteMsg(vote) => {
+            ApproveCandidate(joining_node) => {
                 trace!(
-                    "{}: received {:?} from {}",
+                    "{}: {} approved candidate {}",
                     self,
-                    vote.as_debug(blocks),
-                    message.sender
+                    message.sender,
+                    joining_node
                 );
-                let messages = self.request_proof(blocks, vote.from, message.sender);
-                self.add_vote(vote, Some(message.sender));
-                messages
+                // Approvals can overtake the candidate's own join message.
+                self.candidates
+                    .entry(joining_node)
+                    .or_insert_with(|| {
+                        Candidate {
+                            step_added: step,
+                            approvals: BTreeSet::new(),
+                            approval_due: None,
+                            added: false,
+                        }
+                    })
+                    .approvals
+                    .insert(message.sender);
+                vec![]
+            }
+            VoteMsg(vote) => self.handle_vote(blocks, vote, message.sender, step),
+            VoteBatch(votes) => {
+                let sender = message.sender;
+                votes
+                    .iter()
+                    .flat_map(|vote| self.handle_vote(blocks, vote.clone(), sender, step))
+                    .collect()
             }
-            VoteAgreedMsg((vote, voters)) => {
+            VoteAgreedMsg(agreed) => {
+                let (ref vote, ref voters) = *agreed;
                 trace!(
                     "{}: received agreement msg for {:?} from {}",
                     self,
                     vote.as_debug(blocks),
                     message.sender
                 );
+                self.stats.votes_received += 1;
+                if !self.verify_agreement(blocks, vote, voters) {
+                    return vec![];
+                }
                 let messages = self.request_proof(blocks, vote.from, message.sender);
-                self.add_vote(vote, voters);
+                let voters = self.verify_voters(blocks, vote, voters.clone());
+                self.add_vote(vote.clone(), voters);
                 messages
             }
             VoteBundle(bundle) => {
                 trace!("{}: received a vote bundle from {}", self, message.sender);
-                let mut messages = Vec::new();
-                for block in self.bundle_base(blocks, &bundle) {
-                    messages.extend(self.request_proof(blocks, block, message.sender));
-                }
-                for (vote, voters) in bundle {
-                    self.add_vote(vote, voters);
-                }
-                messages
+                self.apply_vote_bundle(blocks, message.sender, &bundle)
+            }
+            WelcomeMsg(bundle) => {
+                debug!("{}: applying welcome message from {}", self, message.sender);
+                self.apply_vote_bundle(blocks, message.sender, &bundle)
             }
             BootstrapMsg(vote_counts) => {
                 debug!(
@@ -795,45 +2984,83 @@ impl Node {
                     self,
                     message.sender
                 );
-                self.apply_bootstrap_msg(vote_counts);
+                self.apply_bootstrap_msg(blocks, &vote_counts);
+                self.bootstrap_peer.get_or_insert(message.sender);
                 vec![]
             }
-            Disconnect => {
-                debug!("{}: lost our connection to {}", self, message.sender);
-                self.connections.remove(&message.sender);
-                self.connect_requests.remove(&message.sender);
+            SnapshotBootstrapMsg(bootstrap) => {
+                debug!(
+                    "{}: applying bootstrap message with snapshots from {}",
+                    self,
+                    message.sender
+                );
+                let (ref snapshots, ref vote_counts) = *bootstrap;
+                let heads = snapshots.iter().map(|snapshot| snapshot.head).collect();
+                self.learn_blocks(&heads);
+                for snapshot in snapshots {
+                    self.add_snapshot(snapshot.clone());
+                }
+                self.apply_bootstrap_msg(blocks, vote_counts);
+                self.bootstrap_peer.get_or_insert(message.sender);
                 vec![]
             }
-            Connect => {
-                if self.should_be_connected(message.sender, blocks) {
-                    if self.connections.insert(message.sender) {
-                        debug!("{}: obtained a connection to {}", self, message.sender);
-                    }
-                    if !self.connect_requests.contains(&message.sender) {
-                        trace!("{}: connecting back to {}", self, message.sender);
-                        self.connect_requests.insert(message.sender);
-                        vec![
-                            Message {
-                                sender: self.our_name,
-                                recipient: message.sender,
-                                content: MessageContent::Connect,
-                            },
-                        ]
-                    } else {
-                        vec![]
+            RequestChains(prefixes) => {
+                debug!("{}: sending history for {:?} to {}", self, prefixes, message.sender);
+                vec![self.partial_bootstrap_msg(blocks, message.sender, &prefixes)]
+            }
+            SectionShare(section_message) => {
+                self.add_section_share(blocks, &section_message, message.sender, step)
+            }
+            SectionMsg(signed) => {
+                let (ref section_message, ref signers) = *signed;
+                if section_message.is_signed_by(blocks, signers) {
+                    debug!(
+                        "{}: received {:?} from section {:?}",
+                        self,
+                        section_message.payload,
+                        section_message.src.into_block(blocks).prefix
+                    );
+                    self.stats.section_messages_received += 1;
+                    if let SectionPayload::Relocate { node, to } = section_message.payload {
+                        self.relocate_in(node, to, step);
                     }
                 } else {
-                    trace!("{}: rejecting connection request from {}", self, message.sender);
-                    self.connections.remove(&message.sender);
-                    self.connect_requests.remove(&message.sender);
-                    vec![
-                        Message {
-                            sender: self.our_name,
-                            recipient: message.sender,
-                            content: Disconnect,
-                        }
-                    ]
+                    debug!("{}: rejected unsigned {:?}", self, section_message);
+                    self.stats.section_messages_rejected += 1;
+                }
+                vec![]
+            }
+            NodeSuspected(suspect) => {
+                trace!("{}: {} suspects {}", self, message.sender, suspect);
+                self.add_suspicion(blocks, suspect, message.sender);
+                vec![]
+            }
+            EquivocationProof(proof) => {
+                self.add_equivocation_proof(blocks, &proof, message.sender);
+                vec![]
+            }
+            SuspicionWithdrawn(suspect) => {
+                trace!("{}: {} no longer suspects {}", self, message.sender, suspect);
+                if let Some(suspecters) = self.suspicions.get_mut(&suspect) {
+                    let _ = suspecters.remove(&message.sender);
+                }
+                vec![]
+            }
+            Disconnect => {
+                debug!("{}: lost our connection to {}", self, message.sender);
+                self.connections.dropped_by(
+                    &message.sender,
+                    self.params.max_connections.is_some(),
+                );
+                vec![]
+            }
+            Connect => self.handle_connect(blocks, message.sender, step),
+            ConnectWithVotes(votes) => {
+                let mut messages = self.handle_connect(blocks, message.sender, step);
+                if self.connections.is_connected(&message.sender) {
+                    messages.extend(self.apply_piggybacked_votes(blocks, message.sender, &votes));
                 }
+                messages
             }
             RequestProof(block, current_blocks) => {
                 trace!(
@@ -866,6 +3093,219 @@ impl Node {
     }
 }
 
+impl NodeTrait for Node {
+    fn new(
+        name: Name,
+        blocks: &Blocks,
+        current_blocks: CurrentBlocks,
+        params: NodeParams,
+        step: u64,
+    ) -> Self {
+        Node::new(name, blocks, current_blocks, params, step)
+    }
+
+    fn handle_message(&mut self, message: Message, blocks: &Blocks, step: u64) -> Vec<Message> {
+        let step = self.local_step(step);
+        Node::handle_message(self, message, blocks, step)
+    }
+
+    fn update_state(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message> {
+        self.drift_clock();
+        let step = self.local_step(step);
+        Node::update_state(self, blocks, step)
+    }
+
+    fn broadcast_new_votes(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message> {
+        let step = self.local_step(step);
+        Node::broadcast_new_votes(self, blocks, step)
+    }
+
+    fn current_blocks(&self) -> &CurrentBlocks {
+        &self.current_blocks
+    }
+
+    fn name(&self) -> Name {
+        self.our_name
+    }
+
+    fn is_disconnected_from(&self, name: &Name) -> bool {
+        Node::is_disconnected_from(self, name)
+    }
+
+    fn block_inbound(&mut self) {
+        self.inbound_blocked = true;
+    }
+
+    fn set_fault(&mut self, fault: Fault, enabled: bool) {
+        if enabled {
+            debug!("{}: starting to behave with fault {}", self, fault);
+            let _ = self.faults.insert(fault);
+        } else {
+            debug!("{}: recovered from fault {}", self, fault);
+            let _ = self.faults.remove(&fault);
+        }
+    }
+
+    fn has_fault(&self, fault: Fault) -> bool {
+        self.faults.contains(&fault)
+    }
+
+    fn force_split(&mut self, prefix: Prefix) {
+        debug!("{}: forced to split {:?}", self, prefix);
+        let _ = self.forced_splits.insert(prefix);
+    }
+
+    fn force_merge(&mut self, prefix: Prefix) {
+        debug!("{}: forced to merge into {:?}", self, prefix);
+        let _ = self.forced_merges.insert(prefix);
+    }
+
+    fn relocate(&mut self, node: Name, to: Name) {
+        let _ = self.pending_relocations.insert(node, to);
+    }
+
+    fn set_params(&mut self, params: NodeParams) {
+        self.params = params;
+    }
+
+    fn forget_votes(&mut self, fraction: f64, step: u64) {
+        let votes = self.vote_counts.iter().flat_map(|(from, map)| {
+            map.keys().map(move |to| Vote { from: *from, to: *to })
+        });
+        let amount = (fraction * num_votes(&self.vote_counts) as f64).round() as usize;
+        let forgotten = sample(votes, amount);
+        debug!("{}: forgetting {} votes", self, forgotten.len());
+        for vote in forgotten {
+            if let Some(map) = self.vote_counts.get_mut(&vote.from) {
+                let _ = map.remove(&vote.to);
+            }
+            if let Some(map) = self.rev_vote_counts.get_mut(&vote.to) {
+                let _ = map.remove(&vote.from);
+            }
+            let _ = self.recent_votes.remove(&vote);
+            let _ = self.forgotten_votes.insert(vote);
+            self.stats.votes_forgotten += 1;
+        }
+        self.vote_counts.retain(|_, map| !map.is_empty());
+        self.rev_vote_counts.retain(|_, map| !map.is_empty());
+        if !self.forgotten_votes.is_empty() {
+            let _ = self.forgotten_at.get_or_insert(step);
+        }
+    }
+
+    fn quarantine(&mut self, peer: Name) {
+        if self.quarantined.insert(peer) {
+            debug!("{}: quarantining {}", self, peer);
+        }
+    }
+
+    fn relocation(&self) -> Option<Name> {
+        self.relocated_to
+    }
+
+    fn equivocators(&self) -> Vec<Name> {
+        self.equivocators.iter().cloned().collect()
+    }
+
+    fn is_voting(&self, step: u64) -> bool {
+        Node::is_voting(self, self.local_step(step))
+    }
+
+    fn should_shutdown(&self, blocks: &Blocks, step: u64) -> bool {
+        Node::should_shutdown(self, blocks, self.local_step(step))
+    }
+
+    fn construct_bootstrap_msg(&self, joining_node: Name) -> Message {
+        Node::construct_bootstrap_msg(self, joining_node)
+    }
+
+    fn learn_blocks(&mut self, new_blocks: &BTreeSet<BlockId>) {
+        Node::learn_blocks(self, new_blocks)
+    }
+
+    fn valid_blocks(&self) -> &ValidBlocks {
+        &self.valid_blocks
+    }
+
+    fn pending_blocks(&self) -> BTreeSet<BlockId> {
+        self.pending_votes().into_iter().map(|(vote, _)| vote.to).collect()
+    }
+
+    fn conflicting_blocks(&self, blocks: &Blocks) -> BTreeMap<(Prefix, u64), usize> {
+        Node::conflicting_blocks(self, blocks)
+    }
+
+    fn referenced_blocks(&self) -> BTreeSet<BlockId> {
+        Node::referenced_blocks(self)
+    }
+
+    fn debug_state(&self, blocks: &Blocks) -> String {
+        format!("{:?}\n{:#?}", self.as_debug(blocks), self.connections.connected())
+    }
+
+    fn dump_state(&self, blocks: &Blocks) -> String {
+        let recent_votes: Vec<_> = self.recent_votes
+            .iter()
+            .map(|vote| vote.as_debug(blocks))
+            .collect();
+        format!(
+            "{:?}\nvalid blocks:\n{}pending votes: {:#?}\ncandidates: {:?}\n\
+             connections: {:?}",
+            self.as_debug(blocks),
+            Chain::new(blocks, Some(&self.valid_blocks), &self.current_blocks).pretty(),
+            recent_votes,
+            self.candidates.keys().collect::<Vec<_>>(),
+            self.connections
+        )
+    }
+
+    fn stats(&self) -> NodeStats {
+        self.stats.clone()
+    }
+
+    fn step_created(&self) -> u64 {
+        self.step_created
+    }
+
+    fn num_connections(&self) -> usize {
+        self.connections.num_connected()
+    }
+
+    fn backlog(&self) -> usize {
+        self.inbox.len()
+    }
+
+    fn stale_candidates(&self, step: u64, grace: u64) -> Vec<Name> {
+        let step = self.local_step(step);
+        self.candidates
+            .iter()
+            .filter(|&(_, candidate)| {
+                candidate.step_added + self.params.join_timeout + grace < step
+            })
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    fn ghost_connections(&self, routable: &BTreeSet<Name>, step: u64) -> Vec<Name> {
+        let step = self.local_step(step);
+        self.connections
+            .connected()
+            .into_iter()
+            .filter(|name| !routable.contains(name) && !self.is_candidate(name, step))
+            .collect()
+    }
+
+    fn purge(&mut self, candidates: &[Name], connections: &[Name]) {
+        for name in candidates {
+            let _ = self.candidates.remove(name);
+        }
+        for name in connections {
+            debug!("{}: dropping ghost connection to {}", self, name);
+            self.connections.disconnect(name);
+        }
+    }
+}
+
 pub struct DebugNode<'a, 'b> {
     blocks: &'a Blocks,
     node: &'b Node,
@@ -876,13 +3316,122 @@ impl<'a, 'b> fmt::Debug for DebugNode<'a, 'b> {
         write!(
             f,
             "Node({}): {} valid blocks;   {} vote counts with max \"to\" blocks of {:?};   {} \
-               current blocks: {:#?}",
+               current blocks:\n{}",
             self.node.our_name,
             self.node.valid_blocks.len(),
             self.node.vote_counts.len(),
             self.node.vote_counts.values().map(BTreeMap::len).max(),
             self.node.current_blocks.len(),
-            self.blocks.block_contents(&self.node.current_blocks)
+            RoutingTable::of(self.node, self.blocks).pretty()
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn forged_agreements_rejected() {
+        let names: Vec<_> = (0..4).map(Name).collect();
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: names[..3].iter().cloned().collect(),
+        });
+        let next = blocks.insert(genesis.into_block(&blocks).add_node(names[3]));
+        let vote = Vote {
+            from: genesis,
+            to: next,
+        };
+        let mut node = Node::new(names[0], &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+        let agreement = |node: &mut Node, voters: BTreeSet<Name>| {
+            let message = Message {
+                sender: names[1],
+                recipient: names[0],
+                content: VoteAgreedMsg(Arc::new((vote.clone(), voters))),
+            };
+            let _ = node.handle_message(message, &blocks, 1);
+        };
+
+        // Node 3 wasn't a member of the genesis block, and node 1 alone isn't a quorum.
+        agreement(&mut node, btreeset!{names[1], names[3]});
+        agreement(&mut node, btreeset!{names[1]});
+        assert_eq!(node.stats.agreements_rejected, 2);
+        assert!(node.vote_counts.is_empty());
+
+        agreement(&mut node, btreeset!{names[1], names[2]});
+        assert_eq!(node.stats.agreements_rejected, 2);
+        assert_eq!(node.vote_counts[&genesis][&next], btreeset!{names[1], names[2]});
+    }
+
+    // A joining node connects back to the members as soon as it's bootstrapped, which can be
+    // before they see it added. They accept it, or would vote it out again as soon as it's added.
+    #[test]
+    fn candidates_connect_before_added() {
+        let names: Vec<_> = (0..4).map(Name).collect();
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: names[..3].iter().cloned().collect(),
+        });
+        let mut node = Node::new(names[0], &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+        let from_candidate = |content| {
+            Message {
+                sender: names[3],
+                recipient: names[0],
+                content,
+            }
+        };
+
+        let _ = node.handle_message(from_candidate(NodeJoined), &blocks, 1);
+        let replies = node.handle_message(from_candidate(Connect), &blocks, 2);
+        assert!(replies.iter().all(|reply| reply.content != Disconnect));
+        assert!(!node.is_disconnected_from(&names[3]));
+    }
+
+    // With ancestry validation, votes held back for a block which falls behind our current
+    // blocks are given up on straight away, and the rest once they've waited too long.
+    #[test]
+    fn orphan_votes_given_up() {
+        let names: Vec<_> = (0..5).map(Name).collect();
+        let mut blocks = Blocks::new();
+        let block = |version, members: &[Name]| {
+            Block {
+                prefix: Prefix::empty(),
+                version,
+                members: members.iter().cloned().collect(),
+            }
+        };
+        let current = blocks.insert(block(1, &names[..4]));
+        // A block our section has moved on from, and one it hasn't got to yet.
+        let stale = blocks.insert(block(0, &names[1..4]));
+        let ahead = blocks.insert(block(2, &names[..3]));
+        let ahead_next = blocks.insert(block(3, &names[..2]));
+        let params = NodeParams {
+            validate_ancestry: true,
+            orphan_vote_timeout: 10,
+            ..NodeParams::default()
+        };
+        let mut node = Node::new(names[0], &blocks, btreeset!{current}, params, 0);
+        for &(from, to) in &[(stale, current), (ahead, ahead_next)] {
+            let message = Message {
+                sender: names[1],
+                recipient: names[0],
+                content: VoteMsg(Vote { from, to }),
+            };
+            let _ = node.handle_message(message, &blocks, 1);
+        }
+        assert_eq!(node.orphan_votes.len(), 2);
+
+        let _ = node.update_state(&mut blocks, 2);
+        assert_eq!(node.orphan_votes.keys().collect::<Vec<_>>(), vec![&ahead]);
+        let _ = node.update_state(&mut blocks, 10);
+        assert_eq!(node.orphan_votes.len(), 1);
+        let _ = node.update_state(&mut blocks, 11);
+        assert!(node.orphan_votes.is_empty());
+        assert_eq!(node.stats.orphan_votes_dropped, 2);
+    }
+}
@@ -0,0 +1,162 @@
+//! Whole-section outages: every member of a section killed within a short window, as if the
+//! section's data were lost in a disaster.
+//!
+//! With no members left to vote the dead nodes out, nothing in the model defines what becomes of
+//! the section's namespace. The outage is only recovered from once the survivors agree on
+//! sections covering the orphaned namespace again, e.g. by the sibling merging with it after it
+//! lost quorum, and none of them still has the dead section's block as current.
+
+use blocks::Blocks;
+use event::Event;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use random::sample;
+
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SectionOutage {
+    /// Prefix whose nodes are all killed.
+    pub prefix: Prefix,
+    /// Step at which the first nodes are killed.
+    pub start_step: u64,
+    /// Number of steps over which the nodes are killed; at least 1.
+    pub window: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutageReport {
+    /// Number of nodes killed.
+    pub nodes_killed: usize,
+    /// Step at which the last node under the prefix was killed.
+    pub completed_step: Option<u64>,
+    /// First step at which the survivors agreed on sections covering the orphaned namespace, and
+    /// those sections' prefixes.
+    pub recovered: Option<(u64, Vec<Prefix>)>,
+}
+
+/// Kills the nodes of a section and follows whether the rest of the network recovers.
+#[derive(Clone)]
+pub struct OutageTracker {
+    outage: SectionOutage,
+    report: OutageReport,
+}
+
+impl OutageTracker {
+    pub fn new(outage: SectionOutage) -> Self {
+        OutageTracker {
+            outage,
+            report: OutageReport::default(),
+        }
+    }
+
+    pub fn outage(&self) -> &SectionOutage {
+        &self.outage
+    }
+
+    pub fn report(&self) -> &OutageReport {
+        &self.report
+    }
+
+    /// Whether all the nodes have been killed, but the survivors haven't recovered yet.
+    pub fn is_orphaned(&self) -> bool {
+        self.report.completed_step.is_some() && self.report.recovered.is_none()
+    }
+
+    /// Removals of nodes under the prefix due at `step`. The nodes still running are spread evenly
+    /// over the rest of the window, so that any which join in the meantime are killed too.
+    pub fn get_events<N>(&mut self, step: u64, nodes: &BTreeMap<Name, N>) -> Vec<Event> {
+        let end_step = self.outage.start_step + self.outage.window;
+        if step < self.outage.start_step || step >= end_step {
+            return vec![];
+        }
+        let prefix = self.outage.prefix;
+        let victims: Vec<Name> = nodes
+            .keys()
+            .filter(|&&name| prefix.matches(name))
+            .cloned()
+            .collect();
+        let steps_left = (end_step - step) as usize;
+        let amount = victims.len().div_ceil(steps_left);
+        let events: Vec<_> = sample(victims, amount).into_iter().map(Event::RemoveNode).collect();
+        self.report.nodes_killed += events.len();
+        if step + 1 == end_step {
+            debug!("Outage: all {} nodes of {:?} killed", self.report.nodes_killed, prefix);
+            self.report.completed_step = Some(step);
+        }
+        events
+    }
+
+    /// Check whether the survivors have recovered from the outage, once it's complete.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        if !self.is_orphaned() {
+            return;
+        }
+        let prefix = self.outage.prefix;
+        let mut covering = vec![];
+        for node in nodes.values() {
+            for block in node.our_current_blocks(blocks) {
+                if block.prefix.is_compatible(&prefix) && !covering.contains(&block.prefix) {
+                    covering.push(block.prefix);
+                }
+            }
+        }
+        // A section is only gone once no survivor still routes to it.
+        let dead_section_current = nodes.values().any(|node| {
+            blocks.block_contents(node.current_blocks()).into_iter().any(|block| {
+                block.prefix.is_compatible(&prefix) &&
+                    !block.members.iter().any(|name| nodes.contains_key(name))
+            })
+        });
+        if prefix.is_covered_by(&covering) && !dead_section_current {
+            info!("Outage: namespace of {:?} taken over by {:?}", prefix, covering);
+            self.report.recovered = Some((step, covering));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kills_spread_over_window() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let mut nodes: BTreeMap<Name, ()> = BTreeMap::new();
+        for i in 0..5 {
+            let _ = nodes.insert(p0.substituted_in(Name(i)), ());
+            let _ = nodes.insert(p1.substituted_in(Name(i)), ());
+        }
+        let mut tracker = OutageTracker::new(SectionOutage {
+            prefix: p1,
+            start_step: 10,
+            window: 3,
+        });
+
+        let mut killed = vec![];
+        for step in 9..14 {
+            let mut removed = vec![];
+            for event in tracker.get_events(step, &nodes) {
+                match event {
+                    Event::RemoveNode(name) => removed.push(name),
+                    event => panic!("unexpected event {:?}", event),
+                }
+            }
+            for name in &removed {
+                assert!(p1.matches(*name));
+                let _ = nodes.remove(name);
+            }
+            killed.push(removed.len());
+            // A node joining mid-outage is killed with the rest.
+            if step == 10 {
+                let _ = nodes.insert(p1.substituted_in(Name(9)), ());
+            }
+        }
+        assert_eq!(killed, vec![0, 2, 2, 2, 0]);
+        assert_eq!(nodes.keys().filter(|&&name| p1.matches(name)).count(), 0);
+        assert_eq!(tracker.report().nodes_killed, 6);
+        assert_eq!(tracker.report().completed_step, Some(12));
+        assert!(tracker.is_orphaned());
+    }
+}
@@ -1,10 +1,23 @@
+use error::{Error, Result};
+use message::MessageContent;
+use message::MessageContent::*;
+use name::{Name, Prefix};
 use simulation::Phase;
 use simulation::Phase::*;
 
+use std::cmp;
+use std::collections::BTreeSet;
+use std::fmt;
+
 #[derive(Clone, Debug)]
 pub struct SimulationParams {
     /// Maximum number of steps a message can be delayed by before it's delivered.
     pub max_delay: u64,
+    /// Number of steps after which a message still in flight is dropped, if any. Only has an
+    /// effect if less than `max_delay`.
+    pub message_ttl: Option<u64>,
+    /// How the delay of each message is chosen.
+    pub delay_model: DelayModel,
     /// Probability of a node joining on a given step during the network growth phase.
     pub grow_prob_join: f64,
     /// Probability of a node leaving on a given step during the network growth phase.
@@ -17,17 +30,208 @@ pub struct SimulationParams {
     pub shrink_prob_drop: f64,
     /// Probability that a two-way connection will be lost on any given step.
     pub prob_disconnect: f64,
-    /// Probability that a lost two-way connection will be re-established on any given step.
-    pub prob_reconnect: f64,
+    /// Rule deciding when a lost two-way connection is re-established.
+    pub reconnect: ReconnectModel,
     /// Network starting phase is complete once the size of network reaches this value.
     pub starting_complete: usize,
     /// Network growth phase is complete once the size of network reaches this value.
     pub grow_complete: usize,
     /// Network stable phase is run for this number of steps.
     pub stable_steps: u64,
+    /// Rule deciding which part of the namespace randomly joining nodes are placed in.
+    pub join_policy: JoinPolicy,
+    /// Rule deciding which nodes are chosen to leave the network at random.
+    pub drop_policy: DropPolicy,
+    /// Rule deciding which nodes a joining node first contacts.
+    pub bootstrap: BootstrapStrategy,
+    /// Order in which nodes handle messages and update their state each step.
+    pub processing_order: ProcessingOrder,
+    /// Members of the genesis section that simulations started with `Simulation::new` begin
+    /// from, fully connected and with the section already agreed.
+    pub genesis_nodes: GenesisNodes,
+    /// Safety limit on the number of live nodes, if any. A run which grows past it stops with
+    /// `Error::LimitReached` rather than running out of memory.
+    pub max_nodes: Option<usize>,
+    /// Safety limit on the number of steps over all phases, if any. A run still going after that
+    /// many steps stops with `Error::LimitReached` rather than running forever.
+    pub max_total_steps: Option<u64>,
+}
+
+impl Default for SimulationParams {
+    fn default() -> SimulationParams {
+        SimulationParams {
+            max_delay: 5,
+            message_ttl: None,
+            delay_model: DelayModel::PerMessage,
+            grow_prob_join: 0.1,
+            grow_prob_drop: 0.02,
+            prob_churn: 0.05,
+            shrink_prob_join: 0.02,
+            shrink_prob_drop: 0.1,
+            prob_disconnect: 0.05,
+            // Gives ~94% chance that a pair will reconnect within 3 steps of noticing the
+            // disconnection, with attempts straight away and after 1 and 3 steps.
+            reconnect: ReconnectModel::Backoff(ReconnectBackoff {
+                prob_success: 0.6,
+                initial_delay: 1,
+                multiplier: 2,
+                max_delay: 32,
+            }),
+            starting_complete: 16,
+            grow_complete: 30,
+            stable_steps: 100,
+            join_policy: JoinPolicy::Uniform,
+            drop_policy: DropPolicy::Uniform,
+            bootstrap: BootstrapStrategy::AllNodes,
+            processing_order: ProcessingOrder::ByName,
+            genesis_nodes: GenesisNodes::Count(1),
+            max_nodes: None,
+            max_total_steps: None,
+        }
+    }
+}
+
+/// A safety limit which stopped a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// More live nodes than `max_nodes`.
+    Nodes(usize),
+    /// As many steps as `max_total_steps`.
+    Steps(u64),
 }
 
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Nodes(max) => write!(f, "more than max_nodes ({}) nodes", max),
+            Limit::Steps(max) => write!(f, "max_total_steps ({}) steps run", max),
+        }
+    }
+}
+
+/// Names of the parameter presets, as accepted by `SimulationParams::preset`.
+pub const PRESETS: &[&str] = &["small-stable", "aggressive-churn", "slow-network", "massive-growth"];
+
 impl SimulationParams {
+    /// The parameter preset with the given name, one of `PRESETS`.
+    pub fn preset(name: &str) -> Result<Self> {
+        match name {
+            "small-stable" => Ok(Self::small_stable()),
+            "aggressive-churn" => Ok(Self::aggressive_churn()),
+            "slow-network" => Ok(Self::slow_network()),
+            "massive-growth" => Ok(Self::massive_growth()),
+            _ => Err(Error::Config(format!(
+                "unknown preset {:?}, expected one of {}",
+                name,
+                PRESETS.join(", ")
+            ))),
+        }
+    }
+
+    /// A small network with little churn and reliable connections, run for a long stable phase.
+    pub fn small_stable() -> Self {
+        SimulationParams {
+            grow_prob_drop: 0.0,
+            prob_churn: 0.01,
+            prob_disconnect: 0.01,
+            grow_complete: 20,
+            stable_steps: 200,
+            ..Self::default()
+        }
+    }
+
+    /// Nodes joining and leaving on roughly a third of the steps of a long stable phase.
+    pub fn aggressive_churn() -> Self {
+        SimulationParams {
+            prob_churn: 0.3,
+            stable_steps: 200,
+            ..Self::default()
+        }
+    }
+
+    /// Messages delayed by up to three times as many steps as usual.
+    pub fn slow_network() -> Self {
+        SimulationParams {
+            max_delay: 15,
+            ..Self::default()
+        }
+    }
+
+    /// Rapid growth to a hundred nodes, splitting repeatedly along the way. Takes far longer to
+    /// run than the other presets.
+    pub fn massive_growth() -> Self {
+        SimulationParams {
+            grow_prob_join: 0.3,
+            grow_prob_drop: 0.01,
+            grow_complete: 100,
+            stable_steps: 50,
+            ..Self::default()
+        }
+    }
+
+    /// Check that all probabilities and policy weights are in range.
+    pub fn validate(&self) -> Result<()> {
+        check_probability("grow_prob_join", self.grow_prob_join)?;
+        check_probability("grow_prob_drop", self.grow_prob_drop)?;
+        check_probability("prob_churn", self.prob_churn)?;
+        check_probability("shrink_prob_join", self.shrink_prob_join)?;
+        check_probability("shrink_prob_drop", self.shrink_prob_drop)?;
+        check_probability("prob_disconnect", self.prob_disconnect)?;
+        match self.reconnect {
+            ReconnectModel::Constant(prob) => check_probability("reconnect", prob)?,
+            ReconnectModel::Backoff(ref backoff) => {
+                check_probability("reconnect.prob_success", backoff.prob_success)?;
+                if backoff.initial_delay == 0 || backoff.multiplier == 0 {
+                    return Err(Error::Config(
+                        "reconnect backoff needs a delay and multiplier of at least 1".to_string(),
+                    ));
+                }
+            }
+        }
+        if self.message_ttl == Some(0) {
+            return Err(Error::Config("message_ttl must be at least 1".to_string()));
+        }
+        if self.max_nodes == Some(0) || self.max_total_steps == Some(0) {
+            return Err(Error::Config(
+                "max_nodes and max_total_steps must be at least 1".to_string(),
+            ));
+        }
+        if let JoinPolicy::Weighted(ref prefixes) = self.join_policy {
+            check_weights("join_policy", prefixes)?;
+        }
+        if let DropPolicy::Weighted(ref prefixes) = self.drop_policy {
+            check_weights("drop_policy", prefixes)?;
+        }
+        match self.genesis_nodes {
+            GenesisNodes::Count(0) => {
+                return Err(Error::Config("genesis section needs at least one node".to_string()));
+            }
+            GenesisNodes::Names(ref names) => {
+                if names.is_empty() {
+                    return Err(Error::Config(
+                        "genesis section needs at least one node".to_string(),
+                    ));
+                }
+                let mut distinct = BTreeSet::new();
+                if let Some(name) = names.iter().find(|name| !distinct.insert(**name)) {
+                    return Err(Error::Config(
+                        format!("genesis section lists {:?} twice", name),
+                    ));
+                }
+            }
+            GenesisNodes::Count(_) => (),
+        }
+        match self.bootstrap {
+            BootstrapStrategy::RandomContacts(0) => {
+                Err(Error::Config("bootstrap needs at least one contact".to_string()))
+            }
+            BootstrapStrategy::BootstrapList(ref names) if names.is_empty() => {
+                Err(Error::Config("bootstrap list is empty".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn prob_join(&self, phase: Phase) -> f64 {
         match phase {
             Starting => 0.1,
@@ -54,10 +258,203 @@ impl SimulationParams {
         }
     }
 
-    pub fn prob_reconnect(&self, phase: Phase) -> f64 {
+    /// Whether disconnected pairs try to reconnect in this phase.
+    pub fn reconnects(&self, phase: Phase) -> bool {
         match phase {
-            Starting | Finishing { .. } => 0.0,
-            Growth | Stable { .. } | Shrinking => self.prob_reconnect,
+            Starting | Finishing { .. } => false,
+            Growth | Stable { .. } | Shrinking => true,
+        }
+    }
+}
+
+/// Rule deciding which section a randomly joining node targets.
+#[derive(Clone, Debug)]
+pub enum JoinPolicy {
+    /// Pick a uniformly random name.
+    Uniform,
+    /// Pick a name in the section with the fewest members.
+    SmallestSection,
+    /// Pick a name in one of the given prefixes, each chosen with probability proportional to its
+    /// weight.
+    Weighted(Vec<(Prefix, f64)>),
+}
+
+/// Rule deciding which node is removed when a random node leaves the network.
+///
+/// Nodes are only ever removed from sections that can spare them, so the policy is a preference
+/// rather than a guarantee.
+#[derive(Clone, Debug)]
+pub enum DropPolicy {
+    /// Pick any node with equal probability.
+    Uniform,
+    /// Prefer the nodes which have been running longest.
+    OldestFirst,
+    /// Prefer the nodes which joined most recently.
+    YoungestFirst,
+    /// Prefer the nodes with the most connections.
+    HighestDegree,
+    /// Prefer nodes in one of the given prefixes, each chosen with probability proportional to its
+    /// weight.
+    Weighted(Vec<(Prefix, f64)>),
+}
+
+/// Rule deciding when a disconnected pair of nodes reconnects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReconnectModel {
+    /// Reconnect with the same probability on every step, however long the pair has been
+    /// disconnected for. Disconnections then last for geometrically distributed numbers of steps,
+    /// with few much longer than the mean.
+    Constant(f64),
+    /// Retry with exponential backoff, so that most pairs reconnect soon after noticing the
+    /// disconnection, while those that don't are left disconnected for longer and longer.
+    Backoff(ReconnectBackoff),
+}
+
+/// Exponential backoff between attempts to reconnect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectBackoff {
+    /// Probability that each attempt succeeds.
+    pub prob_success: f64,
+    /// Number of steps between the first attempt, made as soon as both nodes notice the
+    /// disconnection, and the second.
+    pub initial_delay: u64,
+    /// Factor the delay grows by after each failed attempt.
+    pub multiplier: u64,
+    /// Maximum number of steps between attempts.
+    pub max_delay: u64,
+}
+
+impl ReconnectBackoff {
+    /// Number of steps between the given attempt, counting from 0, and the next.
+    pub fn delay(&self, attempt: u32) -> u64 {
+        let growth = self.multiplier.saturating_pow(attempt);
+        cmp::min(self.initial_delay.saturating_mul(growth), self.max_delay)
+    }
+}
+
+/// Rule deciding how long each message spends in the network before being delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelayModel {
+    /// Every message is delayed independently, by up to `max_delay` steps.
+    PerMessage,
+    /// Every pair of nodes is given a latency of between 1 and `max_delay` steps the first time
+    /// they exchange a message, and all messages between them take exactly that long.
+    PerConnection,
+}
+
+/// Order in which nodes are processed within a step.
+///
+/// The network keeps each sender's messages to each recipient apart and delivers them by step, so
+/// the order doesn't decide whose votes arrive first. It decides the order in which the nodes, and
+/// the network on their behalf, draw from the random streams, e.g. for message delays, so a fixed
+/// order hands the same nodes the same draws every step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessingOrder {
+    /// In order of name, except that messages are handled in the order they're delivered.
+    ByName,
+    /// Shuffled afresh every step.
+    Random,
+    /// In order of name, starting one node further along each step.
+    RoundRobin,
+}
+
+/// Rule deciding which nodes a joining node first contacts.
+///
+/// Except with `AllNodes`, the contacts act as proxies: each forwards the join to the members of
+/// the joining node's section, as far as it knows them.
+#[derive(Clone, Debug)]
+pub enum BootstrapStrategy {
+    /// Tell every node in the network directly.
+    AllNodes,
+    /// Contact a single random node.
+    SingleProxy,
+    /// Contact this many random nodes.
+    RandomContacts(usize),
+    /// Contact whichever of these nodes are still running.
+    BootstrapList(Vec<Name>),
+}
+
+/// Members of the genesis section.
+#[derive(Clone, Debug)]
+pub enum GenesisNodes {
+    /// This many nodes with random names.
+    Count(usize),
+    /// Nodes with these names.
+    Names(Vec<Name>),
+}
+
+/// Rule deciding when a section is large enough to split.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitPolicy {
+    /// Split once both resulting sections would have at least `min_section_size +
+    /// split_buffer` members.
+    Balanced,
+    /// Split once the section has at least `2 * (min_section_size + split_buffer)` members,
+    /// however they're distributed between the two halves. This can create a section below the
+    /// minimum size, which will then try to merge again.
+    TotalSize,
+}
+
+/// Model of the handshake needed to establish a connection between two nodes.
+#[derive(Clone, Debug)]
+pub struct HandshakeParams {
+    /// Maximum number of steps a handshake takes before our connection request is sent. The
+    /// actual delay is chosen uniformly at random.
+    pub max_delay: u64,
+    /// Probability that a handshake fails, withdrawing our connection request.
+    pub prob_fail: f64,
+    /// Number of steps to wait after a handshake fails before trying to connect to the peer again.
+    pub retry_timeout: u64,
+}
+
+/// Backpressure on joins to a busy section.
+#[derive(Clone, Copy, Debug)]
+pub struct JoinBackpressure {
+    /// Number of blocks succeeding our section's current block which haven't become valid yet,
+    /// at which we consider our section busy.
+    pub max_pending_blocks: usize,
+    /// Number of steps we ask a joining node to wait before trying again.
+    pub retry_delay: u64,
+}
+
+/// Model of the work a node does handling messages, with a limit on how much it can do each step.
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessingParams {
+    /// Total cost of the messages a node can handle in a step, including that of verifying the
+    /// signatures they carry. The rest wait for later steps, in the order they arrived. A message
+    /// costing more than this is handled alone on a step.
+    pub budget_per_step: u64,
+    /// Cost of handling a vote or a vote agreement, and of each vote in a bundle or attached to a
+    /// connection request.
+    pub vote_cost: u64,
+    /// Cost of handling a bootstrap message, which carries the history of the network.
+    pub bootstrap_cost: u64,
+    /// Cost of handling any other message.
+    pub other_cost: u64,
+}
+
+impl Default for ProcessingParams {
+    fn default() -> ProcessingParams {
+        ProcessingParams {
+            budget_per_step: 100,
+            vote_cost: 1,
+            bootstrap_cost: 50,
+            other_cost: 1,
+        }
+    }
+}
+
+impl ProcessingParams {
+    /// Cost of handling a message with the given content.
+    pub fn cost(&self, content: &MessageContent) -> u64 {
+        match *content {
+            VoteMsg(_) | VoteAgreedMsg(_) => self.vote_cost,
+            VoteBatch(ref votes) => self.vote_cost * votes.len() as u64,
+            VoteBundle(ref bundle) |
+            WelcomeMsg(ref bundle) => self.vote_cost * bundle.len() as u64,
+            ConnectWithVotes(ref bundle) => self.other_cost + self.vote_cost * bundle.len() as u64,
+            BootstrapMsg(_) | SnapshotBootstrapMsg(_) => self.bootstrap_cost,
+            _ => self.other_cost,
         }
     }
 }
@@ -68,13 +465,113 @@ pub struct NodeParams {
     pub min_section_size: usize,
     /// Number of nodes past the minimum that must be present in all sections when splitting.
     pub split_buffer: usize,
+    /// Rule for deciding when our own section should split.
+    pub split_policy: SplitPolicy,
     /// Number of steps to wait for a candidate to appear in at least one current section.
     pub join_timeout: u64,
     /// Number of steps to wait before shutting down if we fail to join.
     pub self_shutdown_timeout: u64,
-    /// The maximum number of permissible valid blocks for a single prefix and version pair.
-    /// Exceeding this will cause the process to panic.
+    /// Number of valid blocks a node can hold for a single prefix and version pair before a
+    /// warning is logged.
+    pub warn_conflicting_blocks: usize,
+    /// Number of valid blocks a node can hold for a single prefix and version pair before the run
+    /// is aborted.
     pub max_conflicting_blocks: usize,
+    /// Whether votes carry simulated signatures which are checked on receipt. Votes signed by
+    /// nodes that aren't members of the block being voted from are rejected.
+    pub verify_signatures: bool,
+    /// Cost of verifying a single signature, in arbitrary units of work. With a processing model,
+    /// it's in the same units as the message costs, and counts towards the budget for the step.
+    pub signature_cost: u64,
+    /// Whether a quorum of our section must approve a candidate before we vote to add it.
+    /// Otherwise we vote to add any candidate that contacts us.
+    pub candidate_approval: bool,
+    /// Handshake model for new connections. `None` establishes connections as soon as the
+    /// request arrives.
+    pub handshake: Option<HandshakeParams>,
+    /// Maximum number of steps by which a node's clock can be ahead of or behind the true step.
+    /// Each node starts with a random offset within this bound, which then drifts by at most one
+    /// step per step. Zero gives every node a perfect clock.
+    pub max_clock_skew: u64,
+    /// Whether to attach our pending votes to connection requests, so that newly connected
+    /// peers catch up without waiting for the votes to be resent.
+    pub piggyback_votes: bool,
+    /// Maximum number of candidates for a single section that we'll accept at once, if any.
+    /// Further joins to that section are ignored until one of the candidates is added or times
+    /// out.
+    pub max_candidates_per_section: Option<usize>,
+    /// Number of versions of our section's history to keep behind its current block before
+    /// compacting older history into a snapshot, if any.
+    pub compaction: Option<u64>,
+    /// Whether to check each compaction against the uncompacted votes.
+    pub verify_compaction: bool,
+    /// Whether to bootstrap candidates with only the history of their section and its
+    /// neighbours, leaving them to request the rest.
+    pub partial_bootstrap: bool,
+    /// Number of agreed votes leading up to a candidate's addition which members push to it
+    /// once the block adding it is valid, if any, rather than leaving it to catch up from its
+    /// bootstrap and later votes.
+    pub welcome_joiners: Option<usize>,
+    /// Whether to only hold connections to the members of our section and its neighbours, rather
+    /// than to every node we know of.
+    pub prune_connections: bool,
+    /// Maximum number of connections we can hold at once, as with a limit on open sockets, if
+    /// any. Beyond it we refuse connections, unless we can drop one to a more distant section to
+    /// make room.
+    pub max_connections: Option<usize>,
+    /// Number of steps we wait for a peer to answer our connection request before giving it up
+    /// and asking again, if any. Otherwise we wait for as long as it takes.
+    pub connect_timeout: Option<u64>,
+    /// Whether sections notify their neighbours of splits with messages signed by a quorum of
+    /// the section.
+    pub section_messages: bool,
+    /// Number of consecutive steps a member of our section must be disconnected from us before
+    /// we vote to remove it. With 0, we vote to remove it as soon as it disconnects.
+    pub drop_grace_steps: u64,
+    /// Number of steps after we agree to remove a member of our section during which we ask it
+    /// to try again later if it joins under the same name, so that the votes removing it are
+    /// agreed before any adding it back. With 0, it can rejoin straight away.
+    pub rejoin_cooldown: u64,
+    /// Number of members of our section, counting us, which must suspect a disconnected member
+    /// of having left before we vote to remove it, if any. Members announce their suspicions to
+    /// the rest of the section, and withdraw them when they reconnect.
+    pub suspicion_confirmations: Option<usize>,
+    /// Number of steps a node waits after becoming a member of a section before it starts
+    /// voting, modelling the cost of handing over to a newly promoted voter. Every member votes,
+    /// so this applies to each node added to a section, including relocated nodes.
+    pub handover_steps: u64,
+    /// Maximum number of members of a section, if any. Once a section reaches it, we stop voting
+    /// to add nodes to it, and vote to split it as soon as both halves would have at least
+    /// `min_section_size` members, whatever the split policy.
+    pub max_section_size: Option<usize>,
+    /// Cost model limiting how many messages we handle each step, if any. Otherwise we handle
+    /// every message as soon as it's delivered.
+    pub processing: Option<ProcessingParams>,
+    /// Whether, and when, we ask joining nodes to try again later because our section is busy
+    /// agreeing on blocks. Otherwise we accept every join straight away.
+    pub join_backpressure: Option<JoinBackpressure>,
+    /// Number of steps to hold the votes we cast before broadcasting them, so that votes cast
+    /// over several steps go to each recipient in a single message. With 0, we broadcast each
+    /// vote as soon as we cast it.
+    pub vote_batch_steps: u64,
+    /// Whether joining nodes are relocated before becoming members for good: the section a new
+    /// node's name falls in accepts it as usual, then relocates it under a name derived from the
+    /// block adding it, to whichever section that name falls in, with a message signed by a
+    /// quorum of the section. Nodes relocated to a section aren't relocated again.
+    pub secure_join: bool,
+    /// Steps we give a peer to send us a vote we've only seen its signature on from others. If
+    /// it hasn't by then, but sent us a vote from the same block for a sibling block, we take the
+    /// two votes as proof that it's equivocating, show them to the rest of our section, and vote
+    /// it out. No detection if `None`.
+    pub equivocation_grace: Option<u64>,
+    /// Whether we hold back the votes peers send us from blocks we don't consider valid yet,
+    /// asking for proof of those blocks, and only count the votes once their blocks become
+    /// valid. Otherwise we count every vote as soon as it arrives.
+    pub validate_ancestry: bool,
+    /// Steps we hold back a vote for, with ancestry validation, before giving up on the block
+    /// it's from becoming valid. Votes from blocks behind our current blocks are given up on
+    /// straight away.
+    pub orphan_vote_timeout: u64,
 }
 
 impl Default for NodeParams {
@@ -82,14 +579,89 @@ impl Default for NodeParams {
         NodeParams {
             min_section_size: 8,
             split_buffer: 1,
+            split_policy: SplitPolicy::Balanced,
             join_timeout: 20,
             self_shutdown_timeout: 100,
+            warn_conflicting_blocks: 10,
             max_conflicting_blocks: 20,
+            verify_signatures: false,
+            signature_cost: 1,
+            candidate_approval: false,
+            handshake: None,
+            max_clock_skew: 0,
+            piggyback_votes: false,
+            max_candidates_per_section: None,
+            compaction: None,
+            verify_compaction: false,
+            partial_bootstrap: false,
+            welcome_joiners: None,
+            prune_connections: false,
+            max_connections: None,
+            connect_timeout: None,
+            section_messages: false,
+            drop_grace_steps: 0,
+            rejoin_cooldown: 0,
+            suspicion_confirmations: None,
+            handover_steps: 0,
+            max_section_size: None,
+            processing: None,
+            join_backpressure: None,
+            vote_batch_steps: 0,
+            secure_join: false,
+            equivocation_grace: None,
+            validate_ancestry: false,
+            orphan_vote_timeout: 100,
         }
     }
 }
 
 impl NodeParams {
+    /// Check that all probabilities are in range.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ref handshake) = self.handshake {
+            check_probability("handshake.prob_fail", handshake.prob_fail)?;
+        }
+        if self.max_candidates_per_section == Some(0) {
+            return Err(Error::Config(
+                "max_candidates_per_section must be at least 1".to_string(),
+            ));
+        }
+        if self.suspicion_confirmations == Some(0) {
+            return Err(Error::Config(
+                "suspicion_confirmations must be at least 1".to_string(),
+            ));
+        }
+        if self.welcome_joiners == Some(0) {
+            return Err(Error::Config("welcome_joiners must be at least 1".to_string()));
+        }
+        if self.max_connections == Some(0) {
+            return Err(Error::Config("max_connections must be at least 1".to_string()));
+        }
+        if self.connect_timeout == Some(0) {
+            return Err(Error::Config("connect_timeout must be at least 1".to_string()));
+        }
+        if self.join_backpressure.is_some_and(|backpressure| backpressure.retry_delay == 0) {
+            return Err(Error::Config(
+                "join_backpressure.retry_delay must be at least 1".to_string(),
+            ));
+        }
+        if self.processing.is_some_and(|processing| processing.budget_per_step == 0) {
+            return Err(Error::Config(
+                "processing.budget_per_step must be at least 1".to_string(),
+            ));
+        }
+        if let Some(max) = self.max_section_size {
+            if max < 2 * self.min_section_size {
+                return Err(Error::Config(format!(
+                    "max_section_size must be at least twice min_section_size ({}), not {}",
+                    self.min_section_size,
+                    max
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub fn max_timeout(&self) -> u64 {
         vec![self.join_timeout, self.self_shutdown_timeout]
             .into_iter()
@@ -105,9 +677,37 @@ pub fn quorum(num_nodes: usize) -> usize {
     (num_nodes / 2) + 1
 }
 
+fn check_probability(name: &str, p: f64) -> Result<()> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(())
+    } else {
+        Err(Error::Config(
+            format!("{} must be between 0 and 1, not {}", name, p),
+        ))
+    }
+}
+
+fn check_weights(name: &str, prefixes: &[(Prefix, f64)]) -> Result<()> {
+    let valid = |weight: f64| weight.is_finite() && weight >= 0.0;
+    if prefixes.iter().all(|&(_, weight)| valid(weight)) &&
+        prefixes.iter().any(|&(_, weight)| weight > 0.0)
+    {
+        Ok(())
+    } else {
+        Err(Error::Config(format!(
+            "{} needs non-negative weights, at least one of them positive",
+            name
+        )))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use block::{Block, Vote};
+    use blocks::Blocks;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::Arc;
 
     #[test]
     fn test_quorum() {
@@ -118,4 +718,80 @@ mod test {
         assert_eq!(2, quorum(3));
         assert_eq!(2, quorum(2));
     }
+
+    #[test]
+    fn presets_valid() {
+        for name in PRESETS {
+            assert!(SimulationParams::preset(name).unwrap().validate().is_ok());
+        }
+        assert!(SimulationParams::preset("tiny").is_err());
+    }
+
+    #[test]
+    fn invalid_params_rejected() {
+        assert!(check_probability("p", 0.5).is_ok());
+        assert!(check_probability("p", 1.5).is_err());
+        assert!(check_weights("w", &[(Prefix::empty(), 1.0)]).is_ok());
+        assert!(check_weights("w", &[(Prefix::empty(), -1.0)]).is_err());
+        assert!(check_weights("w", &[]).is_err());
+        let node_params = NodeParams {
+            suspicion_confirmations: Some(0),
+            ..NodeParams::default()
+        };
+        assert!(node_params.validate().is_err());
+        let node_params = NodeParams {
+            max_section_size: Some(15),
+            ..NodeParams::default()
+        };
+        assert!(node_params.validate().is_err());
+        let node_params = NodeParams {
+            processing: Some(ProcessingParams {
+                budget_per_step: 0,
+                ..ProcessingParams::default()
+            }),
+            ..NodeParams::default()
+        };
+        assert!(node_params.validate().is_err());
+    }
+
+    #[test]
+    fn reconnect_backoff_delays() {
+        let backoff = ReconnectBackoff {
+            prob_success: 0.5,
+            initial_delay: 1,
+            multiplier: 2,
+            max_delay: 32,
+        };
+        let delays: Vec<_> = (0..8).map(|attempt| backoff.delay(attempt)).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 32, 32]);
+        assert_eq!(backoff.delay(100), 32);
+
+        let params = SimulationParams {
+            reconnect: ReconnectModel::Backoff(ReconnectBackoff {
+                multiplier: 0,
+                ..backoff
+            }),
+            ..SimulationParams::default()
+        };
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn processing_costs() {
+        let processing = ProcessingParams::default();
+        let mut blocks = Blocks::new();
+        let genesis = Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: BTreeSet::new(),
+        };
+        let from = blocks.insert(genesis.clone());
+        let to = blocks.insert(genesis.add_node(Name(1)));
+        let vote = (Vote { from, to }, BTreeSet::new());
+        assert_eq!(processing.cost(&VoteMsg(vote.0.clone())), 1);
+        assert_eq!(processing.cost(&VoteBundle(Arc::new(vec![vote.clone(); 3]))), 3);
+        assert_eq!(processing.cost(&ConnectWithVotes(Arc::new(vec![vote; 2]))), 3);
+        assert_eq!(processing.cost(&BootstrapMsg(Arc::new(BTreeMap::new()))), 50);
+        assert_eq!(processing.cost(&Connect), 1);
+    }
 }
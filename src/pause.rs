@@ -0,0 +1,98 @@
+//! Pausing a running simulation at a step boundary when the process receives SIGINT or SIGUSR1,
+//! rather than killing it and losing the network's state.
+//!
+//! The signal handlers only record which signal arrived. The simulation checks for it before
+//! each step and writes its chain to a file: only the agreed blocks, from which another run can
+//! start, not a checkpoint of the whole network's state. It then either hands control to a
+//! debugger or waits for another signal: SIGUSR1 resumes the run, and SIGINT stops it.
+
+use std::io;
+#[cfg(unix)]
+use std::{mem, ptr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    User1,
+}
+
+/// What to do once a debugger is done with a paused simulation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseAction {
+    Resume,
+    /// Stop the run, which then fails with `Error::Stopped`.
+    Stop,
+}
+
+/// Number of the signal received since the last call to `take_signal`, or 0 if none was.
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+extern "C" fn record_signal(signum: libc::c_int) {
+    RECEIVED.store(signum as usize, Ordering::SeqCst);
+}
+
+/// Install the handlers for SIGINT and SIGUSR1. Once installed, neither signal kills the
+/// process any more.
+#[cfg(unix)]
+pub fn install_handlers() -> io::Result<()> {
+    for &signum in &[libc::SIGINT, libc::SIGUSR1] {
+        let result = unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            let _ = libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signum, &action, ptr::null_mut())
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install_handlers() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "pausing on signals is only supported on Unix",
+    ))
+}
+
+/// Pause a run at its next step, as SIGUSR1 would, without sending a signal. Works whether or not
+/// the handlers are installed.
+#[cfg(unix)]
+pub fn request_pause() {
+    RECEIVED.store(libc::SIGUSR1 as usize, Ordering::SeqCst);
+}
+
+#[cfg(not(unix))]
+pub fn request_pause() {}
+
+/// The signal received since the last call, if any.
+#[cfg(unix)]
+pub fn take_signal() -> Option<Signal> {
+    match RECEIVED.swap(0, Ordering::SeqCst) as libc::c_int {
+        libc::SIGINT => Some(Signal::Interrupt),
+        libc::SIGUSR1 => Some(Signal::User1),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn take_signal() -> Option<Signal> {
+    None
+}
+
+/// Block until the next signal arrives.
+pub fn wait_for_signal() -> Signal {
+    loop {
+        if let Some(signal) = take_signal() {
+            return signal;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,157 @@
+//! History of the prefix tree, with the sections as its leaves, to show how sections split and
+//! merge over a run without working it out from the graph of blocks.
+//!
+//! Each step, every member counts towards the size of the section it considers itself in, and the
+//! sections are recorded whenever they differ from those last recorded. The history is written as
+//! a series of graphs in the DOT language, one per change, which `dot -Tsvg -O <file>` renders to
+//! a file each.
+
+use blocks::Blocks;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use run_id;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+#[derive(Clone, Default)]
+pub struct PrefixTreeHistory {
+    /// The steps at which the sections changed, with the size of each section from then on.
+    snapshots: Vec<(u64, BTreeMap<Prefix, usize>)>,
+}
+
+impl PrefixTreeHistory {
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        let mut sections = BTreeMap::new();
+        for node in nodes.values() {
+            if let Some(block) = node.our_current_blocks(blocks).first() {
+                *sections.entry(block.prefix).or_insert(0) += 1;
+            }
+        }
+        if self.snapshots.last().is_none_or(|(_, last)| *last != sections) {
+            self.snapshots.push((step, sections));
+        }
+    }
+
+    pub fn snapshots(&self) -> &[(u64, BTreeMap<Prefix, usize>)] {
+        &self.snapshots
+    }
+
+    /// Write one graph per change to the sections, with an edge from each prefix to those one bit
+    /// longer and the sections as leaves labelled with their sizes. Sections which weren't in the
+    /// previous graph are drawn in bold.
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if let Some(run_id) = run_id::current() {
+            writeln!(writer, "// run {}", run_id)?;
+        }
+        let mut previous = BTreeMap::new();
+        for &(step, ref sections) in &self.snapshots {
+            writeln!(writer, "digraph step_{} {{", step)?;
+            writeln!(writer, "label=\"step {}\"; node [fontsize=10];", step)?;
+            let mut inner = BTreeSet::new();
+            for prefix in sections.keys() {
+                let mut child = *prefix;
+                while child.bit_count() > 0 {
+                    let parent = child.popped();
+                    writeln!(
+                        writer,
+                        "p{}->p{} [label={}];",
+                        bits(&parent),
+                        bits(&child),
+                        bits(&child).pop().unwrap_or('0')
+                    )?;
+                    if !inner.insert(parent) {
+                        break;
+                    }
+                    child = parent;
+                }
+            }
+            for prefix in &inner {
+                writeln!(writer, "p{} [label=\"\"; shape=point];", bits(prefix))?;
+            }
+            for (prefix, size) in sections {
+                let style = if previous.contains_key(prefix) {
+                    "solid"
+                } else {
+                    "bold"
+                };
+                writeln!(
+                    writer,
+                    "p{} [label=\"{}\\n{} nodes\"; shape=box; style={}];",
+                    bits(prefix),
+                    if prefix.bit_count() == 0 {
+                        "()".to_string()
+                    } else {
+                        bits(prefix)
+                    },
+                    size,
+                    style
+                )?;
+            }
+            writeln!(writer, "}}")?;
+            previous = sections.clone();
+        }
+        Ok(())
+    }
+}
+
+/// The bits of `prefix`, as a string of 0s and 1s.
+fn bits(prefix: &Prefix) -> String {
+    let name = prefix.lower_bound();
+    (0..prefix.bit_count())
+        .map(|i| if name.bit(i) { '1' } else { '0' })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn split_recorded_and_drawn() {
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: (0..4).map(|i| Name(i << 62)).collect(),
+        });
+        let mut nodes = BTreeMap::new();
+        for i in 0..4 {
+            let name = Name(i << 62);
+            let node = Node::new(name, &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+            let _ = nodes.insert(name, node);
+        }
+        let mut history = PrefixTreeHistory::default();
+        history.observe(0, &blocks, &nodes);
+        history.observe(1, &blocks, &nodes);
+        assert_eq!(history.snapshots().len(), 1);
+
+        // Split into sections 0 and 1, with nodes 0 and 1 in the first and 2 and 3 in the second.
+        for &(bit, ref names) in &[(false, [0, 1]), (true, [2, 3])] {
+            let id = blocks.insert(Block {
+                prefix: Prefix::empty().pushed(bit),
+                version: 1,
+                members: names.iter().map(|&i| Name(i << 62)).collect(),
+            });
+            for &i in names {
+                nodes.get_mut(&Name(i << 62)).unwrap().current_blocks = btreeset!{id};
+            }
+        }
+        history.observe(5, &blocks, &nodes);
+        let snapshots = history.snapshots();
+        assert_eq!(snapshots[0], (0, btreemap!{Prefix::empty() => 4}));
+        let (p0, p1) = (Prefix::empty().pushed(false), Prefix::empty().pushed(true));
+        assert_eq!(snapshots[1], (5, btreemap!{p0 => 2, p1 => 2}));
+
+        let mut dot = vec![];
+        history.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert_eq!(dot.matches("digraph").count(), 2);
+        assert!(dot.contains("p [label=\"()\\n4 nodes\"; shape=box; style=bold];"));
+        assert!(dot.contains("p->p1 [label=1];"));
+        assert!(dot.contains("p1 [label=\"1\\n2 nodes\"; shape=box; style=bold];"));
+    }
+}
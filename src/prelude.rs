@@ -0,0 +1,14 @@
+//! The types needed to embed ewok in other code: configure a simulation, schedule events, run it
+//! and read its report.
+//!
+//! These are kept stable between releases, unlike the internals of the model, so code using ewok
+//! should import them from here with `use ewok::prelude::*;`.
+
+pub use builder::SimulationBuilder;
+pub use error::{Error, Result};
+pub use event::Event;
+pub use event_schedule::EventSchedule;
+pub use name::{Name, Prefix};
+pub use params::{GenesisNodes, NodeParams, SimulationParams};
+pub use scenario::SimulationReport as Report;
+pub use simulation::Simulation;
@@ -0,0 +1,46 @@
+//! Plain-text tables, for human-readable summaries of chains and routing tables in the debugger,
+//! failure dumps and logs.
+
+use std::cmp;
+
+/// A table with the given column headers and rows, each column padded to its widest cell. Every
+/// line, including the last, ends in a newline.
+pub fn table<S: AsRef<str>>(headers: &[&str], rows: &[Vec<S>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = cmp::max(*width, cell.as_ref().len());
+        }
+    }
+    let mut output = String::new();
+    let mut push_line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<1$}", cell, width))
+            .collect();
+        output.push_str(padded.join("  ").trim_end());
+        output.push('\n');
+    };
+    push_line(headers.to_vec());
+    for row in rows {
+        push_line(row.iter().map(AsRef::as_ref).collect());
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn columns_aligned() {
+        let rows = vec![vec!["Prefix(0)", "v12", ""], vec!["Prefix(10)", "v3", "v4"]];
+        assert_eq!(
+            table(&["prefix", "latest", "pending"], &rows),
+            "prefix      latest  pending\n\
+             Prefix(0)   v12\n\
+             Prefix(10)  v3      v4\n"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Serving soak metrics in the Prometheus text format, so long runs can be watched on standard
+//! dashboards.
+//!
+//! The exporter is a minimal HTTP server on its own thread. It answers every request with the
+//! most recent metrics, whatever the path.
+
+use soak::SoakMetrics;
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Serves the latest metrics over HTTP until dropped.
+pub struct MetricsExporter {
+    addr: SocketAddr,
+    latest: Arc<Mutex<String>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MetricsExporter {
+    /// Start serving on `port` on all interfaces. Port 0 picks any free port.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::new(0, 0, 0, 0), port))?;
+        let addr = listener.local_addr()?;
+        let latest = Arc::new(Mutex::new(String::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let thread_latest = Arc::clone(&latest);
+        let thread_stopped = Arc::clone(&stopped);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let body = thread_latest.lock().unwrap().clone();
+                    if let Err(err) = respond(stream, &body) {
+                        debug!("Metrics exporter: failed to respond: {}", err);
+                    }
+                }
+            }
+        });
+
+        info!("Serving Prometheus metrics on port {}", addr.port());
+        Ok(MetricsExporter {
+            addr,
+            latest,
+            stopped,
+        })
+    }
+
+    /// The address being served on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replace the metrics served.
+    pub fn update(&self, metrics: &SoakMetrics, num_reports: u64, blocks_pruned: usize) {
+        *self.latest.lock().unwrap() = render(metrics, num_reports, blocks_pruned);
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the server thread up so that it sees it's been stopped.
+        let _ = TcpStream::connect((Ipv4Addr::new(127, 0, 0, 1), self.addr.port()));
+    }
+}
+
+fn respond(mut stream: TcpStream, body: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    // Only one response is ever sent, so the request itself doesn't matter.
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Format metrics in the Prometheus text exposition format.
+pub fn render(metrics: &SoakMetrics, num_reports: u64, blocks_pruned: usize) -> String {
+    let gauges = [
+        ("ewok_step", "Last step reported.", metrics.step),
+        ("ewok_nodes", "Number of live nodes.", metrics.num_nodes as u64),
+        ("ewok_sections", "Sections current at any node.", metrics.num_sections as u64),
+        ("ewok_messages_in_queue", "Messages in flight.", metrics.messages_in_queue as u64),
+        ("ewok_blocks_stored", "Blocks in the block store.", metrics.blocks_stored as u64),
+        ("ewok_valid_blocks", "Valid blocks summed over nodes.", metrics.valid_blocks as u64),
+        (
+            "ewok_graph_components",
+            "Connected components of the connection graph.",
+            metrics.connectivity.components as u64,
+        ),
+        (
+            "ewok_graph_largest_component",
+            "Nodes in the largest component of the connection graph.",
+            metrics.connectivity.largest_component as u64,
+        ),
+        (
+            "ewok_graph_diameter_estimate",
+            "Lower bound on the diameter of the largest component, in hops.",
+            metrics.connectivity.diameter_estimate as u64,
+        ),
+        (
+            "ewok_graph_articulation_points",
+            "Section members whose loss would split the connection graph.",
+            metrics.connectivity.articulation_points.len() as u64,
+        ),
+    ];
+    let counters = [
+        ("ewok_reports_total", "Metric reports written.", num_reports),
+        ("ewok_blocks_pruned_total", "Blocks pruned from the store.", blocks_pruned as u64),
+    ];
+
+    let mut text = String::new();
+    for &(kind, metrics) in &[("gauge", &gauges[..]), ("counter", &counters[..])] {
+        for &(name, help, value) in metrics {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} {}", name, kind);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serves_latest_metrics() {
+        let exporter = MetricsExporter::bind(0).unwrap();
+        let metrics = SoakMetrics {
+            step: 42,
+            num_nodes: 17,
+            ..SoakMetrics::default()
+        };
+        exporter.update(&metrics, 3, 0);
+
+        let port = exporter.local_addr().port();
+        let mut stream = TcpStream::connect((Ipv4Addr::new(127, 0, 0, 1), port)).unwrap();
+        write!(stream, "GET /metrics HTTP/1.0\r\n\r\n").unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.0 200 OK"));
+        assert!(response.contains("\n# TYPE ewok_nodes gauge\newok_nodes 17\n"));
+        assert!(response.contains("\newok_step 42\n"));
+        assert!(response.contains("\newok_reports_total 3\n"));
+        assert!(response.contains("\n# TYPE ewok_graph_components gauge\n"));
+    }
+}
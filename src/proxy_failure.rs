@@ -0,0 +1,102 @@
+//! Fault injection for bootstrap proxies: killing a proxy after it has forwarded a join, but
+//! before the joining node has been brought up to date.
+//!
+//! Every joining node which loses its proxy this way must still either join via the members the
+//! join was forwarded to, or shut itself down.
+
+use blocks::Blocks;
+use event::Event;
+use message::Message;
+use message::MessageContent::JoinRequest;
+use name::Name;
+use node::NodeTrait;
+use random::do_with_probability;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProxyFailureReport {
+    /// Number of proxies killed mid-bootstrap.
+    pub proxies_killed: usize,
+    /// Number of joining nodes which lost their proxy but still joined.
+    pub joined: usize,
+    /// Number of joining nodes which lost their proxy and then shut down.
+    pub shut_down: usize,
+}
+
+/// Kills proxies and follows what becomes of the nodes they were bootstrapping.
+#[derive(Clone)]
+pub struct ProxyFailures {
+    /// Probability of a proxy being killed after handling a join request.
+    prob_fail: f64,
+    /// Proxies to kill at the start of the next step, with the nodes they were bootstrapping.
+    doomed: BTreeMap<Name, BTreeSet<Name>>,
+    /// Joining nodes which lost their proxy, and haven't yet joined or shut down.
+    orphaned: BTreeSet<Name>,
+    report: ProxyFailureReport,
+}
+
+impl ProxyFailures {
+    pub fn new(prob_fail: f64) -> Self {
+        ProxyFailures {
+            prob_fail,
+            doomed: BTreeMap::new(),
+            orphaned: BTreeSet::new(),
+            report: ProxyFailureReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &ProxyFailureReport {
+        &self.report
+    }
+
+    /// Joining nodes which lost their proxy and have neither joined nor shut down.
+    pub fn stranded(&self) -> &BTreeSet<Name> {
+        &self.orphaned
+    }
+
+    /// Pick which of the proxies receiving these messages will die once they've handled them.
+    pub fn on_delivered(&mut self, messages: &[Message]) {
+        for message in messages.iter().filter(|message| message.content == JoinRequest) {
+            if self.doomed.contains_key(&message.recipient) ||
+                do_with_probability(self.prob_fail)
+            {
+                self.doomed.entry(message.recipient).or_default().insert(message.sender);
+            }
+        }
+    }
+
+    /// Removals of the proxies picked to die, which have forwarded their joins by now.
+    pub fn get_events<N>(&mut self, nodes: &BTreeMap<Name, N>) -> Vec<Event> {
+        let mut events = vec![];
+        for (proxy, joining) in mem::take(&mut self.doomed) {
+            if !nodes.contains_key(&proxy) {
+                continue;
+            }
+            debug!("killing proxy {} while bootstrapping {:?}", proxy, joining);
+            self.report.proxies_killed += 1;
+            self.orphaned.extend(joining);
+            events.push(Event::RemoveNode(proxy));
+        }
+        events
+    }
+
+    /// Note which of the nodes that lost their proxy have since joined or shut down.
+    pub fn observe<N: NodeTrait>(&mut self, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        let report = &mut self.report;
+        self.orphaned.retain(|name| match nodes.get(name) {
+            Some(node) => {
+                if node.our_current_blocks(blocks).is_empty() {
+                    return true;
+                }
+                report.joined += 1;
+                false
+            }
+            None => {
+                report.shut_down += 1;
+                false
+            }
+        });
+    }
+}
@@ -0,0 +1,298 @@
+//! Vote races: cases where two or more blocks competed to follow the same block, with the
+//! arrivals of the votes for each of them at each node over time.
+//!
+//! Competing successors are those with compatible prefixes, so that the two halves of a split
+//! don't count as competing with each other, but either half does with a membership change to
+//! the section being split. These races are where conflicting blocks come from: a node adds
+//! whichever votes reach it, and which successor reaches a quorum there first depends on the
+//! order they arrive in.
+//!
+//! The report has a timeline for each race, with a row for each node and competitor, and a
+//! column for each step from the first vote for any competitor arriving to the last. Each cell
+//! gives the number of votes for the competitor which arrived at the node on that step:
+//!
+//! ```text
+//! Race to follow Prefix(01) v4, steps 120 to 131:
+//!   A: Prefix(01) v5 +3f2a0c.., agreed at step 128
+//!   B: Prefix(01) v5 -112233..
+//!   8a5c01.. A 12..3.......
+//!   8a5c01.. B .1..2.......
+//! ```
+
+use block::{BlockId, Vote, VoteKind};
+use blocks::Blocks;
+use message::Message;
+use name::Name;
+use node::NodeTrait;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// Maximum number of columns in a race's timeline. Longer races have several steps per column.
+pub const MAX_COLUMNS: u64 = 60;
+
+/// Number of votes for a block which arrived at a node, by step.
+pub type Arrivals = BTreeMap<u64, usize>;
+
+#[derive(Clone, Default)]
+pub struct VoteRaces {
+    /// For each block voted from, the arrivals of the votes for each successor at each node.
+    arrivals: BTreeMap<BlockId, BTreeMap<BlockId, BTreeMap<Name, Arrivals>>>,
+    /// Every successor voted for so far.
+    successors: BTreeSet<BlockId>,
+    /// Step at which each successor first became current at any node.
+    agreed: BTreeMap<BlockId, u64>,
+}
+
+/// Successors competing to follow a block.
+#[derive(Debug)]
+pub struct VoteRace<'a> {
+    pub from: BlockId,
+    /// Each competitor, with the arrivals of the votes for it at each node.
+    pub competitors: Vec<(BlockId, &'a BTreeMap<Name, Arrivals>)>,
+}
+
+impl<'a> VoteRace<'a> {
+    /// The first and last steps at which a vote for any competitor arrived.
+    pub fn steps(&self) -> (u64, u64) {
+        let steps = self.competitors
+            .iter()
+            .flat_map(|&(_, nodes)| nodes.values())
+            .flat_map(|arrivals| arrivals.keys().cloned());
+        steps.fold((u64::MAX, 0), |(first, last), step| {
+            (cmp::min(first, step), cmp::max(last, step))
+        })
+    }
+}
+
+impl VoteRaces {
+    /// Record the arrivals of the votes among the messages delivered at `step`.
+    pub fn record_delivered(&mut self, step: u64, messages: &[Message]) {
+        for message in messages {
+            for vote in message.content.plain_votes() {
+                *self.arrivals
+                    .entry(vote.from)
+                    .or_default()
+                    .entry(vote.to)
+                    .or_default()
+                    .entry(message.recipient)
+                    .or_default()
+                    .entry(step)
+                    .or_insert(0) += 1;
+                let _ = self.successors.insert(vote.to);
+            }
+        }
+    }
+
+    /// Record successors which are current at any node for the first time.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, nodes: &BTreeMap<Name, N>) {
+        for node in nodes.values() {
+            for id in node.current_blocks() {
+                if self.successors.contains(id) && !self.agreed.contains_key(id) {
+                    let _ = self.agreed.insert(*id, step);
+                }
+            }
+        }
+    }
+
+    /// The blocks `races` and `write_report` look up: those with more than one successor, and
+    /// their successors.
+    pub fn referenced_blocks(&self) -> BTreeSet<BlockId> {
+        let mut referenced = BTreeSet::new();
+        for (from, successors) in &self.arrivals {
+            if successors.len() < 2 {
+                continue;
+            }
+            let _ = referenced.insert(*from);
+            referenced.extend(successors.keys().cloned());
+        }
+        referenced
+    }
+
+    /// The step at which `block` first became current at any node, if it has.
+    pub fn agreed_at(&self, block: &BlockId) -> Option<u64> {
+        self.agreed.get(block).cloned()
+    }
+
+    /// Every race so far, with only the successors that competed with another, in the order
+    /// they started.
+    pub fn races(&self, blocks: &Blocks) -> Vec<VoteRace<'_>> {
+        let mut races: Vec<VoteRace<'_>> = self.arrivals
+            .iter()
+            // A lone successor has nothing to race, and its blocks may have been pruned since.
+            .filter(|&(_, successors)| successors.len() > 1)
+            .filter_map(|(from, successors)| {
+                let competitors: Vec<_> = successors
+                    .iter()
+                    .filter(|&(to, _)| {
+                        let prefix = to.into_block(blocks).prefix;
+                        successors.keys().any(|other| {
+                            other != to && other.into_block(blocks).prefix.is_compatible(&prefix)
+                        })
+                    })
+                    .map(|(to, nodes)| (*to, nodes))
+                    .collect();
+                if competitors.is_empty() {
+                    None
+                } else {
+                    Some(VoteRace {
+                        from: *from,
+                        competitors,
+                    })
+                }
+            })
+            .collect();
+        races.sort_by_key(VoteRace::steps);
+        races
+    }
+
+    /// Write a timeline of every race.
+    pub fn write_report<W: Write>(&self, blocks: &Blocks, writer: &mut W) -> io::Result<()> {
+        for race in self.races(blocks) {
+            let from = race.from.into_block(blocks);
+            let (first, last) = race.steps();
+            let steps_per_column = (last - first) / MAX_COLUMNS + 1;
+            writeln!(
+                writer,
+                "Race to follow {:?} v{}, steps {} to {}{}:",
+                from.prefix,
+                from.version,
+                first,
+                last,
+                if steps_per_column > 1 {
+                    format!(" ({} steps per column)", steps_per_column)
+                } else {
+                    String::new()
+                }
+            )?;
+            for (label, &(to, _)) in labels().zip(&race.competitors) {
+                let block = to.into_block(blocks);
+                let members_changed = || {
+                    let added = block.members.difference(&from.members).map(|name| {
+                        format!("+{}", name)
+                    });
+                    let removed = from.members.difference(&block.members).map(|name| {
+                        format!("-{}", name)
+                    });
+                    added.chain(removed).collect::<Vec<_>>().join(" ")
+                };
+                let change = match (Vote { from: race.from, to }).kind(blocks) {
+                    VoteKind::Membership => members_changed(),
+                    VoteKind::Relocation => format!("relocation {}", members_changed()),
+                    VoteKind::Split => "split".to_string(),
+                    VoteKind::Merge => "merge".to_string(),
+                };
+                write!(writer, "  {}: {:?} v{} {}", label, block.prefix, block.version, change)?;
+                match self.agreed_at(&to) {
+                    Some(step) => writeln!(writer, ", agreed at step {}", step)?,
+                    None => writeln!(writer)?,
+                }
+            }
+            let nodes: BTreeSet<Name> = race.competitors
+                .iter()
+                .flat_map(|&(_, nodes)| nodes.keys().cloned())
+                .collect();
+            for node in nodes {
+                for (label, &(_, arrivals)) in labels().zip(&race.competitors) {
+                    let arrivals = match arrivals.get(&node) {
+                        Some(arrivals) => arrivals,
+                        None => continue,
+                    };
+                    let mut columns = vec![0; ((last - first) / steps_per_column + 1) as usize];
+                    for (step, count) in arrivals {
+                        columns[((step - first) / steps_per_column) as usize] += count;
+                    }
+                    let cells: String = columns
+                        .into_iter()
+                        .map(|count| match count {
+                            0 => '.',
+                            1..=9 => (b'0' + count as u8) as char,
+                            _ => '+',
+                        })
+                        .collect();
+                    writeln!(writer, "  {} {} {}", node, label, cells)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Labels for the competitors in a race: A to Z, then numbers.
+fn labels() -> impl Iterator<Item = String> {
+    (0..).map(|i| if i < 26 {
+        ((b'A' + i as u8) as char).to_string()
+    } else {
+        i.to_string()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use message::MessageContent::VoteMsg;
+    use name::Prefix;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn competing_successors_reported() {
+        let mut blocks = Blocks::new();
+        let genesis = Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: (0..4).map(|i| Name(i << 62)).collect(),
+        };
+        let from = blocks.insert(genesis.clone());
+        let added = blocks.insert(genesis.add_node(Name(1)));
+        let removed = blocks.insert(genesis.remove_node(Name(3 << 62)));
+        let split = blocks.insert(Block {
+            prefix: Prefix::empty().pushed(false),
+            version: 1,
+            members: btreeset!{Name(0), Name(1 << 62)},
+        });
+        let split_sibling = blocks.insert(Block {
+            prefix: Prefix::empty().pushed(true),
+            version: 1,
+            members: btreeset!{Name(2 << 62), Name(3 << 62)},
+        });
+        let vote = |sender: u64, recipient: u64, to| Message {
+            sender: Name(sender << 62),
+            recipient: Name(recipient << 62),
+            content: VoteMsg(Vote { from, to }),
+        };
+
+        // The halves of a split don't compete with each other.
+        let mut races = VoteRaces::default();
+        races.record_delivered(10, &[vote(0, 1, split), vote(2, 1, split_sibling)]);
+        assert!(races.races(&blocks).is_empty());
+
+        races.record_delivered(11, &[vote(0, 1, added), vote(1, 0, removed)]);
+        races.record_delivered(13, &[vote(2, 1, added), vote(3, 1, added)]);
+        let mut nodes = BTreeMap::new();
+        let node = Node::new(Name(1 << 62), &blocks, btreeset!{added}, NodeParams::default(), 0);
+        let _ = nodes.insert(Name(1 << 62), node);
+        races.observe(14, &nodes);
+        assert_eq!(races.agreed_at(&added), Some(14));
+
+        let found = races.races(&blocks);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].competitors.len(), 4);
+        assert_eq!(found[0].steps(), (10, 13));
+
+        let mut report = vec![];
+        races.write_report(&blocks, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("Race to follow Prefix() v0, steps 10 to 13:\n"));
+        assert!(report.contains("Prefix() v1 +000000.., agreed at step 14\n"));
+        assert!(report.contains("Prefix(0) v1 split\n"));
+        let label = |block| {
+            let position = found[0].competitors.iter().position(|&(to, _)| to == block);
+            labels().nth(position.unwrap()).unwrap()
+        };
+        assert!(report.contains(&format!("  400000.. {} .1.2\n", label(added))));
+        assert!(report.contains(&format!("  000000.. {} .1..\n", label(removed))));
+    }
+}
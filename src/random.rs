@@ -1,9 +1,16 @@
+//! Seeded random number generation, in separate streams for separate parts of the simulation.
+//!
+//! Every stream is derived from the one seed, but draws from one don't affect the others, so that
+//! e.g. changing the delay model doesn't change which nodes join and leave. Draws come from the
+//! general stream unless made within `in_stream`.
+
+use name::Name;
 use rand::{self, thread_rng, XorShiftRng, Rand, Rng, SeedableRng};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::env;
 
 thread_local! {
-    static SEED: [u32; 4] = match env::var("EWOK_SEED") {
+    static SEED: Cell<[u32; 4]> = Cell::new(match env::var("EWOK_SEED") {
         Ok(value) => {
             let nums: Vec<u32> = value.split(|c| c == '[' || c == ']' || c == ' ' || c == ',')
                                       .filter_map(|s| s.parse().ok())
@@ -18,24 +25,121 @@ thread_local! {
              rng.next_u32().wrapping_add(rng.next_u32()),
              rng.next_u32().wrapping_add(rng.next_u32())]
         }
-    };
+    });
 
-    static WEAK_RNG: RefCell<XorShiftRng> = RefCell::new(
+    static WEAK_RNG: RefCell<Streams> = RefCell::new(
         SEED.with(|seed| {
-            println!("Seed: {:?}", seed);
-            XorShiftRng::from_seed(*seed)
+            println!("Seed: {:?}", seed.get());
+            Streams::from_seed(seed.get())
         })
     );
+
+    static CURRENT: Cell<Stream> = const { Cell::new(Stream::General) };
+
+    static FORBIDDEN: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The parts of the simulation with their own random number generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// Everything not in another stream, such as the behaviour of nodes and disconnections.
+    General,
+    /// Which nodes join and leave, and when.
+    Events,
+    /// Message delays and losses.
+    Delays,
+    /// Names of new nodes.
+    Names,
+}
+
+const NUM_STREAMS: usize = 4;
+
+/// A generator for each stream.
+#[derive(Clone)]
+struct Streams([XorShiftRng; NUM_STREAMS]);
+
+impl Streams {
+    fn from_seed(seed: [u32; 4]) -> Self {
+        let stream = |index: u32| {
+            // The general stream uses the seed as is. The others mix in their index, taking care
+            // not to produce the all-zero seed the generator rejects.
+            let mut derived = seed;
+            for (i, word) in derived.iter_mut().enumerate() {
+                *word ^= index.wrapping_mul(0x9e37_79b9).rotate_left(i as u32 * 8);
+            }
+            if derived == [0; 4] {
+                derived[0] = index;
+            }
+            XorShiftRng::from_seed(derived)
+        };
+        Streams([stream(0), stream(1), stream(2), stream(3)])
+    }
+
+    fn current(&mut self) -> &mut XorShiftRng {
+        &mut self.0[CURRENT.with(Cell::get) as usize]
+    }
+}
+
+/// Make the draws in `f` from `stream`.
+pub fn in_stream<T, F: FnOnce() -> T>(stream: Stream, f: F) -> T {
+    let previous = CURRENT.with(|current| current.replace(stream));
+    let result = f();
+    CURRENT.with(|current| current.set(previous));
+    result
+}
+
+/// Run `f`, which mustn't draw random numbers: other threads don't share this thread's streams,
+/// so their draws wouldn't follow the seed. Checked in debug builds.
+pub fn without_draws<T, F: FnOnce() -> T>(f: F) -> T {
+    let previous = FORBIDDEN.with(|forbidden| forbidden.replace(true));
+    let result = f();
+    FORBIDDEN.with(|forbidden| forbidden.set(previous));
+    result
+}
+
+/// Draw from the current stream.
+fn with_rng<T, F: FnOnce(&mut XorShiftRng) -> T>(f: F) -> T {
+    debug_assert!(
+        !FORBIDDEN.with(Cell::get),
+        "random number drawn where draws aren't allowed"
+    );
+    WEAK_RNG.with(|rng| f(rng.borrow_mut().current()))
 }
 
 /// Get the seed used for the random number generator.
 pub fn seed() -> [u32; 4] {
-    SEED.with(|seed| *seed)
+    SEED.with(|seed| seed.get())
+}
+
+/// Restart the random number generators from the given seed, so that a run can be repeated
+/// exactly.
+pub fn reseed(new_seed: [u32; 4]) {
+    SEED.with(|seed| seed.set(new_seed));
+    WEAK_RNG.with(|rng| *rng.borrow_mut() = Streams::from_seed(new_seed));
+}
+
+/// Saved state of the thread-local random number generators.
+#[derive(Clone)]
+pub struct RngState(Streams);
+
+/// Save the current state of the random number generators, to replay a run from this point.
+pub fn rng_state() -> RngState {
+    WEAK_RNG.with(|rng| RngState(rng.borrow().clone()))
+}
+
+/// Restore the random number generators to a saved state.
+pub fn restore_rng(state: &RngState) {
+    WEAK_RNG.with(|rng| *rng.borrow_mut() = state.0.clone());
 }
 
 /// Random value from the thread-local weak RNG.
 pub fn random<T: Rand>() -> T {
-    WEAK_RNG.with(|rng| rng.borrow_mut().gen())
+    with_rng(|rng| rng.gen())
+}
+
+/// Random name for a new node, from the names stream.
+pub fn random_name() -> Name {
+    in_stream(Stream::Names, random)
 }
 
 /// Sample values from an iterator.
@@ -43,7 +147,7 @@ pub fn sample<T, I>(iterable: I, amount: usize) -> Vec<T>
 where
     I: IntoIterator<Item = T>,
 {
-    WEAK_RNG.with(|rng| rand::sample(&mut *rng.borrow_mut(), iterable, amount))
+    with_rng(|rng| rand::sample(rng, iterable, amount))
 }
 
 /// Sample a single value from an iterator.
@@ -61,5 +165,39 @@ pub fn do_with_probability(p: f64) -> bool {
 
 /// Shuffle the mutable slice in place.
 pub fn shuffle<T>(values: &mut [T]) {
-    WEAK_RNG.with(|rng| rng.borrow_mut().shuffle(values))
+    with_rng(|rng| rng.shuffle(values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn streams_independent() {
+        let names = |draws_elsewhere: usize| {
+            reseed([1, 2, 3, 4]);
+            let first = random_name();
+            for _ in 0..draws_elsewhere {
+                let _ = random::<u64>();
+                let _ = in_stream(Stream::Delays, random::<u64>);
+            }
+            (first, random_name())
+        };
+        assert_eq!(names(0), names(10));
+
+        reseed([1, 2, 3, 4]);
+        let general = random::<u64>();
+        reseed([1, 2, 3, 4]);
+        let events = in_stream(Stream::Events, || in_stream(Stream::Names, random_name));
+        assert_eq!(events, names(0).0);
+        // Draws go back to the general stream once `in_stream` returns.
+        assert_eq!(random::<u64>(), general);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "random number drawn where draws aren't allowed")]
+    fn draws_forbidden() {
+        let _ = without_draws(random::<u64>);
+    }
 }
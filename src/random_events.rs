@@ -1,14 +1,15 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::cmp;
 use itertools::Itertools;
-use params::{SimulationParams, NodeParams, quorum};
+use params::{SimulationParams, NodeParams, JoinPolicy, DropPolicy, quorum};
 use blocks::Blocks;
-use name::Name;
-use node::Node;
+use name::{Name, Prefix};
+use node::NodeTrait;
 use event::Event;
-use random::{random, do_with_probability, shuffle};
+use random::{random, random_name, do_with_probability, shuffle};
 use simulation::Phase;
 
+#[derive(Clone)]
 pub struct RandomEvents {
     params: SimulationParams,
     node_params: NodeParams,
@@ -22,17 +23,22 @@ impl RandomEvents {
         }
     }
 
-    pub fn get_events(
+    pub fn get_events<N: NodeTrait>(
         &self,
         phase: Phase,
         blocks: &Blocks,
-        nodes: &BTreeMap<Name, Node>,
+        nodes: &BTreeMap<Name, N>,
     ) -> Vec<Event> {
         let mut events = vec![];
 
-        // Random join.
-        if do_with_probability(self.params.prob_join(phase)) {
-            events.push(self.random_add());
+        // Random join. A network set up at its starting size or above has nothing left to start,
+        // though the phase only moves on at the end of the first step.
+        let prob_join = match phase {
+            Phase::Starting if nodes.len() >= self.params.starting_complete => 0.0,
+            _ => self.params.prob_join(phase),
+        };
+        if do_with_probability(prob_join) {
+            events.push(self.random_add(blocks, nodes));
         }
 
         // Random remove.
@@ -45,23 +51,59 @@ impl RandomEvents {
         events
     }
 
-    fn random_add(&self) -> Event {
-        Event::AddNode(random())
+    /// A join of a node named according to the join policy.
+    pub fn random_add<N: NodeTrait>(&self, blocks: &Blocks, nodes: &BTreeMap<Name, N>) -> Event {
+        let target = match self.params.join_policy {
+            JoinPolicy::Uniform => None,
+            JoinPolicy::SmallestSection => smallest_section(blocks, nodes),
+            JoinPolicy::Weighted(ref prefixes) => weighted_prefix(prefixes),
+        };
+        let name = match target {
+            Some(prefix) => prefix.substituted_in(random_name()),
+            None => random_name(),
+        };
+        Event::AddNode(name)
     }
 
-    fn random_remove(&self, blocks: &Blocks, nodes: &BTreeMap<Name, Node>) -> Option<Event> {
+    /// A leave of a node chosen by the drop policy, if any can leave.
+    pub fn random_remove<N: NodeTrait>(
+        &self,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> Option<Event> {
         self.find_node_to_remove(blocks, nodes).map(
             Event::RemoveNode,
         )
     }
 
-    // Remove a randomly-selected node which is in a section with at least quorum + 2 members. The
-    // section's member count is calculated by removing any dead nodes from the node's own current
-    // block's member list. If no suitable node can be found, the function returns `None`.
-    fn find_node_to_remove(&self, blocks: &Blocks, nodes: &BTreeMap<Name, Node>) -> Option<Name> {
+    // Remove a node which is in a section with at least quorum + 2 members, chosen according to the
+    // drop policy. The section's member count is calculated by removing any dead nodes from the
+    // node's own current block's member list. If no suitable node can be found, the function
+    // returns `None`.
+    fn find_node_to_remove<N: NodeTrait>(
+        &self,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> Option<Name> {
         let names_sorted: BTreeSet<_> = nodes.keys().cloned().collect();
         let mut names = nodes.keys().cloned().collect_vec();
         shuffle(&mut names);
+        // Stable sorts, so that ties stay in random order.
+        match self.params.drop_policy {
+            DropPolicy::Uniform => (),
+            DropPolicy::OldestFirst => names.sort_by_key(|name| nodes[name].step_created()),
+            DropPolicy::YoungestFirst => {
+                names.sort_by_key(|name| cmp::Reverse(nodes[name].step_created()))
+            }
+            DropPolicy::HighestDegree => {
+                names.sort_by_key(|name| cmp::Reverse(nodes[name].num_connections()))
+            }
+            DropPolicy::Weighted(ref prefixes) => {
+                if let Some(prefix) = weighted_prefix(prefixes) {
+                    names.sort_by_key(|name| !prefix.matches(*name));
+                }
+            }
+        }
         for name in names {
             if let Some(our_current_block) = nodes[&name].our_current_blocks(blocks).first() {
                 let num_live = our_current_block
@@ -87,3 +129,145 @@ impl RandomEvents {
         None
     }
 }
+
+/// The prefix of the section with the fewest members, going by the most recent block each live
+/// member considers its own.
+fn smallest_section<N: NodeTrait>(blocks: &Blocks, nodes: &BTreeMap<Name, N>) -> Option<Prefix> {
+    let mut sections = BTreeMap::new();
+    for node in nodes.values() {
+        for block in node.our_current_blocks(blocks) {
+            let latest = sections.entry(block.prefix).or_insert(block);
+            if block.version > latest.version {
+                *latest = block;
+            }
+        }
+    }
+    sections
+        .values()
+        .min_by_key(|block| block.members.len())
+        .map(|block| block.prefix)
+}
+
+/// Pick one of the prefixes with probability proportional to its weight.
+fn weighted_prefix(prefixes: &[(Prefix, f64)]) -> Option<Prefix> {
+    let total: f64 = prefixes.iter().map(|&(_, weight)| weight).sum();
+    let mut target = random::<f64>() * total;
+    for &(prefix, weight) in prefixes {
+        if target < weight {
+            return Some(prefix);
+        }
+        target -= weight;
+    }
+    prefixes.last().map(|&(prefix, _)| prefix)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use node::Node;
+    use params::{BootstrapStrategy, DelayModel, GenesisNodes, ProcessingOrder, ReconnectModel};
+
+    fn test_params(drop_policy: DropPolicy) -> SimulationParams {
+        SimulationParams {
+            max_delay: 5,
+            message_ttl: None,
+            delay_model: DelayModel::PerMessage,
+            grow_prob_join: 0.0,
+            grow_prob_drop: 0.0,
+            prob_churn: 0.0,
+            shrink_prob_join: 0.0,
+            shrink_prob_drop: 0.0,
+            prob_disconnect: 0.0,
+            reconnect: ReconnectModel::Constant(0.0),
+            starting_complete: 0,
+            grow_complete: 0,
+            stable_steps: 0,
+            join_policy: JoinPolicy::Uniform,
+            drop_policy,
+            bootstrap: BootstrapStrategy::AllNodes,
+            processing_order: ProcessingOrder::ByName,
+            genesis_nodes: GenesisNodes::Count(1),
+            max_nodes: None,
+            max_total_steps: None,
+        }
+    }
+
+    #[test]
+    fn age_based_drops() {
+        let node_params = NodeParams::default();
+        let block = Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: (0..10).map(Name).collect(),
+        };
+        let mut blocks = Blocks::new();
+        let current_blocks = btreeset!{ blocks.insert(block.clone()) };
+        // Each node was created at the step given by its name.
+        let nodes: BTreeMap<Name, Node> = block
+            .members
+            .iter()
+            .map(|&name| {
+                let params = node_params.clone();
+                let node = Node::new(name, &blocks, current_blocks.clone(), params, name.0);
+                (name, node)
+            })
+            .collect();
+
+        let oldest = RandomEvents::new(test_params(DropPolicy::OldestFirst), node_params.clone());
+        assert_eq!(oldest.find_node_to_remove(&blocks, &nodes), Some(Name(0)));
+
+        let youngest = RandomEvents::new(test_params(DropPolicy::YoungestFirst), node_params);
+        assert_eq!(youngest.find_node_to_remove(&blocks, &nodes), Some(Name(9)));
+    }
+
+    #[test]
+    fn weighted_join_targets_prefixes() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let weights = vec![(p0, 0.0), (p1, 1.0)];
+        for _ in 0..100 {
+            assert_eq!(weighted_prefix(&weights), Some(p1));
+        }
+        assert_eq!(weighted_prefix(&[]), None);
+    }
+
+    #[test]
+    fn smallest_section_is_targeted() {
+        let node_params = NodeParams::default();
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let b0 = Block {
+            prefix: p0,
+            version: 0,
+            members: (0..3).map(|i| p0.substituted_in(Name(i))).collect(),
+        };
+        let b1 = Block {
+            prefix: p1,
+            version: 0,
+            members: (0..2).map(|i| p1.substituted_in(Name(i))).collect(),
+        };
+        let mut blocks = Blocks::new();
+        let current_blocks = btreeset!{ blocks.insert(b0.clone()), blocks.insert(b1.clone()) };
+        let nodes: BTreeMap<Name, Node> = b0.members
+            .iter()
+            .chain(&b1.members)
+            .map(|&name| {
+                let node = Node::new(name, &blocks, current_blocks.clone(), node_params.clone(), 0);
+                (name, node)
+            })
+            .collect();
+
+        assert_eq!(smallest_section(&blocks, &nodes), Some(p1));
+    }
+
+    #[test]
+    fn no_starting_joins_past_starting_size() {
+        let params = test_params(DropPolicy::Uniform);
+        let random_events = RandomEvents::new(params, NodeParams::default());
+        let nodes: BTreeMap<Name, Node> = BTreeMap::new();
+        for _ in 0..100 {
+            assert!(random_events.get_events(Phase::Starting, &Blocks::new(), &nodes).is_empty());
+        }
+    }
+}
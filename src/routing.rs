@@ -0,0 +1,308 @@
+//! Nodes' routing tables, and comparisons between them which pinpoint where two nodes' views of
+//! the network diverge, rather than leaving the reader to spot it in two full tables.
+
+use block::{Block, BlockId};
+use blocks::{Blocks, CurrentBlocks};
+use name::{Name, Prefix};
+use node::NodeTrait;
+use pretty;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A node's view of the network: the latest valid block for each section, and the blocks it has
+/// seen votes for which aren't valid yet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoutingTable {
+    latest: BTreeMap<Prefix, Block>,
+    pending: BTreeMap<Prefix, BTreeSet<Block>>,
+}
+
+impl RoutingTable {
+    pub fn new(blocks: &Blocks, current: &CurrentBlocks, pending: &BTreeSet<BlockId>) -> Self {
+        let mut table = RoutingTable::default();
+        for block in blocks.block_contents(current) {
+            let _ = table.latest.insert(block.prefix, block.clone());
+        }
+        for block in blocks.block_contents(pending) {
+            let _ = table.pending.entry(block.prefix).or_default().insert(block.clone());
+        }
+        table
+    }
+
+    /// The routing table of `node`.
+    pub fn of<N: NodeTrait>(node: &N, blocks: &Blocks) -> Self {
+        Self::new(blocks, node.current_blocks(), &node.pending_blocks())
+    }
+
+    /// The latest valid block for each section.
+    pub fn latest(&self) -> &BTreeMap<Prefix, Block> {
+        &self.latest
+    }
+
+    /// A table with a row for each prefix: the version and size of its latest valid block, and
+    /// the versions of the blocks pending for it.
+    pub fn pretty(&self) -> String {
+        let mut prefixes = BTreeSet::new();
+        for prefix in self.latest.keys().chain(self.pending.keys()) {
+            let _ = prefixes.insert(*prefix);
+        }
+        let rows: Vec<_> = prefixes
+            .iter()
+            .map(|prefix| {
+                let (version, members) = match self.latest.get(prefix) {
+                    Some(block) => (format!("v{}", block.version), block.members.len().to_string()),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                let pending: Vec<_> = self.pending
+                    .get(prefix)
+                    .into_iter()
+                    .flatten()
+                    .map(|block| format!("v{}", block.version))
+                    .collect();
+                vec![format!("{:?}", prefix), version, members, pending.join(", ")]
+            })
+            .collect();
+        pretty::table(&["prefix", "latest", "members", "pending"], &rows)
+    }
+
+    /// The differences between this table and `other`, for each prefix they differ in.
+    pub fn diff(&self, other: &RoutingTable) -> TableDiff {
+        // Prefixes are inserted one at a time, as collecting them sorts them by `Prefix`'s
+        // partial order, which disagrees with its total order once a section has split.
+        let mut prefixes = BTreeSet::new();
+        for prefix in self.latest.keys().chain(self.pending.keys()) {
+            let _ = prefixes.insert(*prefix);
+        }
+        for prefix in other.latest.keys().chain(other.pending.keys()) {
+            let _ = prefixes.insert(*prefix);
+        }
+
+        let empty = BTreeSet::new();
+        let mut diff = TableDiff::default();
+        for prefix in prefixes {
+            let ours = self.latest.get(&prefix);
+            let theirs = other.latest.get(&prefix);
+            let members = |block: Option<&Block>| {
+                block.map(|block| block.members.clone()).unwrap_or_default()
+            };
+            let (our_members, their_members) = (members(ours), members(theirs));
+            let our_pending = self.pending.get(&prefix).unwrap_or(&empty);
+            let their_pending = other.pending.get(&prefix).unwrap_or(&empty);
+            let prefix_diff = PrefixDiff {
+                versions: (ours.map(|block| block.version), theirs.map(|block| block.version)),
+                members_only_ours: our_members.difference(&their_members).cloned().collect(),
+                members_only_theirs: their_members.difference(&our_members).cloned().collect(),
+                pending_only_ours: our_pending.difference(their_pending).cloned().collect(),
+                pending_only_theirs: their_pending.difference(our_pending).cloned().collect(),
+            };
+            if !prefix_diff.is_empty() {
+                let _ = diff.prefixes.insert(prefix, prefix_diff);
+            }
+        }
+        diff
+    }
+}
+
+/// How two routing tables differ for one prefix. "Ours" is the table `diff` was called on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixDiff {
+    /// Version of the latest valid block in each table, if it has one for the prefix.
+    pub versions: (Option<u64>, Option<u64>),
+    pub members_only_ours: BTreeSet<Name>,
+    pub members_only_theirs: BTreeSet<Name>,
+    pub pending_only_ours: BTreeSet<Block>,
+    pub pending_only_theirs: BTreeSet<Block>,
+}
+
+impl PrefixDiff {
+    fn is_empty(&self) -> bool {
+        !self.latest_differs() && self.pending_only_ours.is_empty() &&
+            self.pending_only_theirs.is_empty()
+    }
+
+    /// Whether the tables hold different latest blocks for the prefix.
+    pub fn latest_differs(&self) -> bool {
+        self.versions.0 != self.versions.1 || !self.members_only_ours.is_empty() ||
+            !self.members_only_theirs.is_empty()
+    }
+}
+
+impl fmt::Display for PrefixDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let version = |version: Option<u64>| match version {
+            Some(version) => format!("v{}", version),
+            None => "none".to_string(),
+        };
+        write!(
+            f,
+            "latest {} vs {}",
+            version(self.versions.0),
+            version(self.versions.1)
+        )?;
+        if !self.members_only_ours.is_empty() || !self.members_only_theirs.is_empty() {
+            write!(
+                f,
+                ", members only ours {:?}, only theirs {:?}",
+                self.members_only_ours,
+                self.members_only_theirs
+            )?;
+        }
+        let versions = |pending: &BTreeSet<Block>| -> String {
+            let versions: Vec<_> = pending
+                .iter()
+                .map(|block| format!("v{}", block.version))
+                .collect();
+            versions.join(", ")
+        };
+        if !self.pending_only_ours.is_empty() {
+            write!(f, ", pending only ours [{}]", versions(&self.pending_only_ours))?;
+        }
+        if !self.pending_only_theirs.is_empty() {
+            write!(f, ", pending only theirs [{}]", versions(&self.pending_only_theirs))?;
+        }
+        Ok(())
+    }
+}
+
+/// Differences between two routing tables, by prefix.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableDiff {
+    pub prefixes: BTreeMap<Prefix, PrefixDiff>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+impl fmt::Display for TableDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no differences");
+        }
+        for (i, (prefix, diff)) in self.prefixes.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{:?}: {}", prefix, diff)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prefixes for which the tables don't all hold the same latest block.
+pub fn disputed_prefixes<'a, I>(tables: I) -> Vec<Prefix>
+where
+    I: IntoIterator<Item = &'a RoutingTable>,
+{
+    let mut tables = tables.into_iter();
+    let first = match tables.next() {
+        Some(first) => first,
+        None => return vec![],
+    };
+    let mut disputed = vec![];
+    for table in tables {
+        for (prefix, diff) in first.diff(table).prefixes {
+            if diff.latest_differs() && !disputed.contains(&prefix) {
+                disputed.push(prefix);
+            }
+        }
+    }
+    disputed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_pinpoints_divergence() {
+        let p0 = Prefix::short(1, 0);
+        let p1 = Prefix::short(1, 0b10000000);
+        let (a, b) = (p0.substituted_in(Name(1)), p0.substituted_in(Name(2)));
+        let mut blocks = Blocks::new();
+        let section0 = Block {
+            prefix: p0,
+            version: 1,
+            members: btreeset!{a},
+        };
+        let v1 = blocks.insert(section0.clone());
+        let v2 = blocks.insert(section0.add_node(b));
+        let other = blocks.insert(Block {
+            prefix: p1,
+            version: 1,
+            members: btreeset!{p1.substituted_in(Name(3))},
+        });
+
+        // One node has added b, while the other is still voting to.
+        let ahead = RoutingTable::new(&blocks, &btreeset!{v2, other}, &BTreeSet::new());
+        let behind = RoutingTable::new(&blocks, &btreeset!{v1, other}, &btreeset!{v2});
+        assert!(ahead.diff(&ahead).is_empty());
+        assert_eq!(
+            behind.pretty(),
+            format!(
+                "prefix     latest  members  pending\n\
+                 {:?}  v1      1        v2\n\
+                 {:?}  v1      1\n",
+                p0,
+                p1
+            )
+        );
+
+        assert_eq!(disputed_prefixes(vec![&ahead, &ahead]), vec![]);
+        assert_eq!(disputed_prefixes(vec![&ahead, &ahead, &behind]), vec![p0]);
+
+        let diff = ahead.diff(&behind);
+        assert_eq!(diff.prefixes.keys().cloned().collect::<Vec<_>>(), vec![p0]);
+        let prefix_diff = &diff.prefixes[&p0];
+        assert_eq!(prefix_diff.versions, (Some(2), Some(1)));
+        assert_eq!(prefix_diff.members_only_ours, btreeset!{b});
+        assert!(prefix_diff.members_only_theirs.is_empty());
+        assert_eq!(prefix_diff.pending_only_theirs.len(), 1);
+        assert_eq!(
+            diff.to_string(),
+            format!(
+                "{:?}: latest v2 vs v1, members only ours {{{:?}}}, only theirs {{}}, pending only \
+                 theirs [v2]",
+                p0,
+                b
+            )
+        );
+    }
+
+    #[test]
+    fn diff_across_a_split() {
+        let p0 = Prefix::short(1, 0);
+        let p00 = Prefix::short(2, 0);
+        let p01 = Prefix::short(2, 0b01000000);
+        let p1 = Prefix::short(1, 0b10000000);
+        let block = |prefix: Prefix, version| {
+            Block {
+                prefix,
+                version,
+                members: btreeset!{prefix.substituted_in(Name(1))},
+            }
+        };
+        let mut blocks = Blocks::new();
+        let merged = blocks.insert(block(p0, 1));
+        let left = blocks.insert(block(p00, 2));
+        let right = blocks.insert(block(p01, 2));
+        let other = blocks.insert(block(p1, 1));
+
+        let split = RoutingTable::new(&blocks, &btreeset!{left, right, other}, &BTreeSet::new());
+        let behind = RoutingTable::new(&blocks, &btreeset!{merged, other}, &btreeset!{left});
+        let diff = split.diff(&behind);
+        assert_eq!(
+            diff.prefixes.keys().cloned().collect::<Vec<_>>(),
+            vec![p0, p00, p01]
+        );
+        assert_eq!(diff.prefixes[&p0].versions, (None, Some(1)));
+        assert_eq!(diff.prefixes[&p00].versions, (Some(2), None));
+        assert_eq!(diff.prefixes[&p00].pending_only_theirs.len(), 1);
+        assert_eq!(diff.prefixes[&p01].versions, (Some(2), None));
+        assert_eq!(behind.diff(&split).prefixes.len(), 3);
+        assert_eq!(disputed_prefixes(vec![&split, &behind]), vec![p0, p00, p01]);
+    }
+}
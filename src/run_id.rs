@@ -0,0 +1,85 @@
+//! Short identifiers for runs, written into the logs, metrics files, checkpoints and graphs of a
+//! run so that the outputs of runs sharing a directory, as in parallel sweeps, can't be mixed up.
+//!
+//! An identifier is eight hex digits hashed from the run's seed, the time it was created and the
+//! process creating it, so runs with the same seed still get different identifiers. Like the
+//! seed, the identifier of the run in progress is kept per thread.
+
+use hash::StableHasher;
+
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A new identifier for a run with the given seed.
+pub fn generate(seed: [u32; 4]) -> String {
+    let mut hasher = StableHasher::default();
+    seed.hash(&mut hasher);
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    elapsed.as_nanos().hash(&mut hasher);
+    process::id().hash(&mut hasher);
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Generate an identifier for a new run with the given seed, and make it the run in progress on
+/// this thread.
+pub fn start(seed: [u32; 4]) -> String {
+    let run_id = generate(seed);
+    set_current(Some(run_id.clone()));
+    run_id
+}
+
+/// The identifier of the run in progress on this thread, if any.
+pub fn current() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Set the identifier of the run in progress on this thread.
+pub fn set_current(run_id: Option<String>) {
+    CURRENT.with(|current| *current.borrow_mut() = run_id);
+}
+
+/// A log line, starting with the identifier of the run in progress in brackets if there is one.
+pub fn tag_line<D: Display>(line: D) -> String {
+    CURRENT.with(|current| match *current.borrow() {
+        Some(ref run_id) => format!("[{}] {}", run_id, line),
+        None => line.to_string(),
+    })
+}
+
+/// Strip the identifier of a run from the start of a log line, if it has one.
+pub fn strip_from_line(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let tagged = bytes.len() >= 11 && bytes[0] == b'[' && &bytes[9..11] == b"] " &&
+        bytes[1..9].iter().all(u8::is_ascii_hexdigit);
+    if tagged { &line[11..] } else { line }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identifiers_differ() {
+        let first = generate([1, 2, 3, 4]);
+        assert_eq!(first.len(), 8);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(first, generate([1, 2, 3, 5]));
+
+        set_current(Some(first.clone()));
+        assert_eq!(current(), Some(first.clone()));
+        let line = tag_line("-- step 3");
+        set_current(None);
+        assert_eq!(current(), None);
+        assert_eq!(line, format!("[{}] -- step 3", first));
+
+        assert_eq!(strip_from_line(&line), "-- step 3");
+        assert_eq!(strip_from_line("-- step 3"), "-- step 3");
+    }
+}
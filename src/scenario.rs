@@ -0,0 +1,657 @@
+//! Seeded scenarios with golden reports, for catching changes in convergence behaviour.
+//!
+//! A scenario file describes a starting network, a schedule of events and a seed:
+//!
+//! ```text
+//! # ewok scenario format 1
+//! seed 1 2 3 4
+//! param max_delay 5
+//! section 0 8
+//! section 1 8
+//! at 0 add 0
+//! at 5 remove-from 1
+//! at 9 relocate-from 0 1
+//! at 10 rejoin 0 2
+//! at 12 fault 0 no-votes
+//! at 20 recover 0 no-votes
+//! at 30 outage 1 3
+//! at 40 drop 0 1 VoteMsg
+//! at 50 forget 1 0.5
+//! at 60 partition 0 1
+//! at 70 heal 0 1
+//! at 80 merge -
+//! at 90 split -
+//! at 95 rewire 1
+//! ```
+//!
+//! `assert` lines state what should hold at a given step or eventually, as described in
+//! `assertion`, e.g. `assert eventually: sections == {0,1}`.
+//!
+//! Besides `max_delay`, `min_section_size`, `split_buffer`, `rejoin_cooldown` and
+//! `equivocation_grace`, `param` lines can set any of the parameters tuning searches, so its
+//! output can be pasted into a scenario.
+//!
+//! Prefixes are written as strings of bits, with `-` for the empty prefix. Lines starting with
+//! `#` are comments. A `preset <name>` line replaces the parameters with one of the named presets,
+//! which `param` lines after it can adjust. A `rejoin` event removes a node of the section and
+//! adds it back under the same name after the given number of steps. A `fault` event starts a
+//! fault in a node of the section which doesn't have it yet, and `recover` stops it in one which
+//! does. An `outage` kills every node under the prefix over the given number of steps; a scenario
+//! can have at most one. A `drop` event drops the next message of the given kind from a node of
+//! the first section to a node of the second. A `forget` event makes a node of the section forget
+//! the given fraction of its cached votes. `partition` cuts the connections between two prefixes
+//! until a `heal` for the same prefixes. `split` and `merge` make the members of the sections
+//! concerned vote to split the section with the prefix, or merge its halves into it, whatever
+//! their sizes. `rewire` drops every connection of the nodes under the prefix.
+//!
+//! Running a scenario gives a `SimulationReport`, which is compared against the golden report
+//! stored next to the scenario file. A scenario whose assertions fail doesn't match its golden
+//! report, nor can it be blessed.
+
+use assertion::Assertion;
+use block::Block;
+use error::{Error, Result};
+use event::Event;
+use event_schedule::EventSchedule;
+use fault::Fault;
+use format::FormatKind;
+use name::Prefix;
+use node::{Node, NodeTrait};
+use outage::SectionOutage;
+use params::{BootstrapStrategy, DelayModel, DropPolicy, GenesisNodes, JoinPolicy, NodeParams,
+             ProcessingOrder, ReconnectModel, SimulationParams};
+use random::{random_name, reseed};
+use simulation::Simulation;
+use tune::{self, TUNABLE_PARAMS};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// A seeded run of a fixed network and event schedule.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    pub seed: [u32; 4],
+    pub params: SimulationParams,
+    pub node_params: NodeParams,
+    pub sections: BTreeMap<Prefix, usize>,
+    pub events: BTreeMap<u64, Vec<ScenarioEvent>>,
+    pub outage: Option<SectionOutage>,
+    pub assertions: Vec<Assertion>,
+}
+
+/// An event in a scenario. Names are only picked when the scenario is run, so that they come
+/// from the seeded RNG.
+#[derive(Clone, Debug)]
+pub enum ScenarioEvent {
+    /// Add a node with a random name in the prefix.
+    Add(Prefix),
+    /// Remove a random node from the section with the prefix.
+    RemoveFrom(Prefix),
+    /// Relocate a random node from the first prefix to the second.
+    RelocateFrom(Prefix, Prefix),
+    /// Remove a random node from the section and add it back after the number of steps.
+    Rejoin(Prefix, u64),
+    /// Start or stop a fault in a node of the section with the prefix.
+    SetFault(Prefix, Fault, bool),
+    /// Drop the next message of the kind from a node in the first prefix to one in the second.
+    DropNext(Prefix, Prefix, String),
+    /// A node in the prefix forgets the fraction of its cached votes.
+    Forget(Prefix, f64),
+    /// Start or heal a partition between the two prefixes.
+    Partition(Prefix, Prefix, bool),
+    /// Split the section with the prefix.
+    Split(Prefix),
+    /// Merge the halves of the prefix into it.
+    Merge(Prefix),
+    /// Drop the connections of the nodes under the prefix.
+    Rewire(Prefix),
+}
+
+impl ScenarioEvent {
+    fn to_event(&self) -> Event {
+        match *self {
+            ScenarioEvent::Add(prefix) => Event::AddNode(prefix.substituted_in(random_name())),
+            ScenarioEvent::RemoveFrom(prefix) => Event::RemoveNodeFrom(prefix),
+            ScenarioEvent::RelocateFrom(from, to) => Event::RelocateFrom(from, to),
+            ScenarioEvent::Rejoin(prefix, after) => Event::RejoinFrom(prefix, after),
+            ScenarioEvent::SetFault(prefix, fault, enabled) => {
+                Event::SetFaultIn {
+                    prefix,
+                    fault,
+                    enabled,
+                }
+            }
+            ScenarioEvent::DropNext(from, to, ref content_kind) => {
+                Event::DropNextMessageIn {
+                    from,
+                    to,
+                    content_kind: content_kind.clone(),
+                }
+            }
+            ScenarioEvent::Forget(prefix, fraction) => Event::ForgetVotesIn { prefix, fraction },
+            ScenarioEvent::Partition(a, b, partitioned) => {
+                Event::PartitionPrefixes { a, b, partitioned }
+            }
+            ScenarioEvent::Split(prefix) => Event::SectionSplit(prefix),
+            ScenarioEvent::Merge(prefix) => Event::SectionMerge(prefix),
+            ScenarioEvent::Rewire(prefix) => Event::RewireConnections(prefix),
+        }
+    }
+}
+
+/// Summary of a scenario run, compact enough to keep as a golden file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationReport {
+    /// `None` if the run ended consistently, otherwise a description of the failure.
+    pub failure: Option<String>,
+    /// Step at which the run ended.
+    pub final_step: u64,
+    /// Number of members of each final section.
+    pub sections: BTreeMap<Prefix, usize>,
+    pub joined: u64,
+    pub rejected: u64,
+    pub blocks_agreed: u64,
+    /// Descriptions of the scenario's assertions which failed.
+    pub failed_assertions: Vec<String>,
+}
+
+impl Scenario {
+    /// Parse a scenario from the contents of a scenario file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate().peekable();
+        let version = lines.peek().and_then(
+            |&(_, line)| FormatKind::Scenario.parse_header(line.trim_end()),
+        );
+        let _ = FormatKind::Scenario.check_version(version)?;
+
+        let mut scenario = Scenario {
+            seed: [1, 2, 3, 4],
+            params: base_params(),
+            node_params: NodeParams::default(),
+            sections: BTreeMap::new(),
+            events: BTreeMap::new(),
+            outage: None,
+            assertions: vec![],
+        };
+        for (index, line) in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            scenario.parse_line(&words).map_err(|msg| {
+                Error::Serialization(format!("scenario line {}: {}", index + 1, msg))
+            })?;
+        }
+        Ok(scenario)
+    }
+
+    fn parse_line(&mut self, words: &[&str]) -> ::std::result::Result<(), String> {
+        match *words {
+            ["seed", a, b, c, d] => {
+                self.seed = [parse_num(a)?, parse_num(b)?, parse_num(c)?, parse_num(d)?];
+            }
+            ["preset", name] => {
+                self.params = SimulationParams::preset(name).map_err(|err| err.to_string())?;
+            }
+            ["param", name, value] => self.set_param(name, value)?,
+            ["section", prefix, size] => {
+                let _ = self.sections.insert(parse_prefix(prefix)?, parse_num(size)?);
+            }
+            ["at", step, "outage", prefix, window] => {
+                if self.outage.is_some() {
+                    return Err("only one outage per scenario is supported".to_string());
+                }
+                let window = parse_num(window)?;
+                if window == 0 {
+                    return Err("outage window must be at least 1 step".to_string());
+                }
+                self.outage = Some(SectionOutage {
+                    prefix: parse_prefix(prefix)?,
+                    start_step: parse_num(step)?,
+                    window,
+                });
+            }
+            ["assert", ref assertion @ ..] => {
+                self.assertions.push(Assertion::parse(&assertion.join(" "))?);
+            }
+            ["at", step, ref event @ ..] => {
+                let event = parse_event(event)?;
+                self.events.entry(parse_num(step)?).or_default().push(event);
+            }
+            _ => return Err(format!("unrecognised line {:?}", words)),
+        }
+        Ok(())
+    }
+
+    fn set_param(&mut self, name: &str, value: &str) -> ::std::result::Result<(), String> {
+        match name {
+            "max_delay" => self.params.max_delay = parse_num(value)?,
+            "min_section_size" => self.node_params.min_section_size = parse_num(value)?,
+            "split_buffer" => self.node_params.split_buffer = parse_num(value)?,
+            "join_timeout" => self.node_params.join_timeout = parse_num(value)?,
+            "rejoin_cooldown" => self.node_params.rejoin_cooldown = parse_num(value)?,
+            "equivocation_grace" => {
+                self.node_params.equivocation_grace = Some(parse_num(value)?)
+            }
+            _ if TUNABLE_PARAMS.contains(&name) => {
+                let config = btreemap!{ name.to_string() => parse_num(value)? };
+                tune::apply(&config, &mut self.params, &mut self.node_params)
+                    .map_err(|err| err.to_string())?;
+            }
+            _ => return Err(format!("unknown parameter {}", name)),
+        }
+        Ok(())
+    }
+
+    /// Run the scenario from its seed.
+    pub fn run(&self) -> SimulationReport {
+        reseed(self.seed);
+        let schedule = EventSchedule::new(
+            self.events
+                .iter()
+                .map(|(&step, events)| {
+                    (step, events.iter().map(ScenarioEvent::to_event).collect())
+                })
+                .collect(),
+        );
+        // Scheduled events are only applied in the stable phase, which starts at step 1, so stay
+        // in it until the last of them.
+        let mut params = self.params.clone();
+        if let Some(&last_step) = self.events.keys().next_back() {
+            params.stable_steps = params.stable_steps.max(last_step.saturating_sub(1));
+        }
+        let mut simulation = match Simulation::<Node>::try_from_sections(
+            self.sections.clone(),
+            schedule,
+            params,
+            self.node_params.clone(),
+        ) {
+            Ok(simulation) => simulation,
+            Err(err) => {
+                return SimulationReport {
+                    failure: Some(err.to_string()),
+                    final_step: 0,
+                    sections: BTreeMap::new(),
+                    joined: 0,
+                    rejected: 0,
+                    blocks_agreed: 0,
+                    failed_assertions: vec![],
+                }
+            }
+        };
+
+        if let Some(outage) = self.outage {
+            simulation.section_outage(outage);
+        }
+        if !self.assertions.is_empty() {
+            simulation.check_assertions(self.assertions.clone());
+        }
+        let result = simulation.run();
+        SimulationReport::new(&simulation, &result)
+    }
+}
+
+impl SimulationReport {
+    /// The report of a finished run, given the result `run` returned.
+    pub fn new<N: NodeTrait>(
+        simulation: &Simulation<N>,
+        result: &Result<BTreeMap<Prefix, Block>>,
+    ) -> Self {
+        let (failure, sections) = match *result {
+            Ok(ref blocks) => {
+                let sections = blocks
+                    .iter()
+                    .map(|(prefix, block)| (*prefix, block.members.len()))
+                    .collect();
+                (None, sections)
+            }
+            Err(ref err) => (Some(err.to_string()), BTreeMap::new()),
+        };
+        SimulationReport {
+            failure,
+            final_step: simulation.step(),
+            sections,
+            joined: simulation.join_stats().joined,
+            rejected: simulation.join_stats().rejected,
+            blocks_agreed: simulation.node_stats().blocks_agreed,
+            failed_assertions: simulation.failed_assertions(),
+        }
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", FormatKind::ScenarioReport.header())?;
+        match self.failure {
+            None => writeln!(f, "outcome: ok")?,
+            Some(ref failure) => writeln!(f, "outcome: failed: {}", failure)?,
+        }
+        writeln!(f, "final step: {}", self.final_step)?;
+        for (prefix, size) in &self.sections {
+            writeln!(f, "section {}: {} members", format_prefix(prefix), size)?;
+        }
+        writeln!(f, "joined: {}", self.joined)?;
+        writeln!(f, "rejected: {}", self.rejected)?;
+        writeln!(f, "blocks agreed: {}", self.blocks_agreed)?;
+        for failure in &self.failed_assertions {
+            writeln!(f, "assertion failed: {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of checking a scenario against its golden report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GoldenCheck {
+    Matched,
+    /// The report differs from the golden one. Each entry describes one differing line.
+    Differs(Vec<String>),
+    /// The golden report was (re)written from this run.
+    Blessed,
+    /// Some of the scenario's assertions failed, so the report wasn't compared or blessed.
+    AssertionsFailed(Vec<String>),
+}
+
+/// Path of the golden report for a scenario file.
+pub fn golden_path(scenario_path: &Path) -> PathBuf {
+    scenario_path.with_extension("report")
+}
+
+/// Run the scenario in `path` and compare its report with the golden one. With `bless`, the
+/// golden report is overwritten instead.
+pub fn check_scenario(path: &Path, bless: bool) -> Result<GoldenCheck> {
+    let mut text = String::new();
+    let _ = File::open(path)?.read_to_string(&mut text)?;
+    let report = Scenario::parse(&text)?.run();
+    if !report.failed_assertions.is_empty() {
+        return Ok(GoldenCheck::AssertionsFailed(report.failed_assertions));
+    }
+    let report = report.to_string();
+
+    let golden = golden_path(path);
+    if bless {
+        File::create(golden)?.write_all(report.as_bytes())?;
+        return Ok(GoldenCheck::Blessed);
+    }
+    let expected = fs::read_to_string(golden)?;
+    let differences = diff_lines(&expected, &report);
+    if differences.is_empty() {
+        Ok(GoldenCheck::Matched)
+    } else {
+        Ok(GoldenCheck::Differs(differences))
+    }
+}
+
+/// All scenario files in `dir`, in order of name.
+pub fn scenario_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "scenario") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Describe the lines that differ between two reports.
+fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    (0..expected.len().max(actual.len()))
+        .filter_map(|i| {
+            let (old, new) = (expected.get(i), actual.get(i));
+            if old == new {
+                return None;
+            }
+            Some(format!(
+                "line {}: expected {:?}, got {:?}",
+                i + 1,
+                old.cloned().unwrap_or(""),
+                new.cloned().unwrap_or("")
+            ))
+        })
+        .collect()
+}
+
+/// Parameters for scenarios: no random churn, so that only the scheduled events happen.
+fn base_params() -> SimulationParams {
+    SimulationParams {
+        max_delay: 5,
+        message_ttl: None,
+        delay_model: DelayModel::PerMessage,
+        grow_prob_join: 0.0,
+        grow_prob_drop: 0.0,
+        prob_churn: 0.0,
+        shrink_prob_join: 0.0,
+        shrink_prob_drop: 0.0,
+        prob_disconnect: 0.0,
+        reconnect: ReconnectModel::Constant(0.0),
+        starting_complete: 0,
+        grow_complete: 0,
+        stable_steps: 0,
+        join_policy: JoinPolicy::Uniform,
+        drop_policy: DropPolicy::Uniform,
+        bootstrap: BootstrapStrategy::AllNodes,
+        processing_order: ProcessingOrder::ByName,
+        genesis_nodes: GenesisNodes::Count(1),
+        max_nodes: None,
+        max_total_steps: None,
+    }
+}
+
+/// Parse an event written as in a scenario line without its `at <step>`, e.g. `split 01`, for
+/// the events not tied to a scenario's own schedule.
+pub fn parse_event_line(line: &str) -> Result<Event> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    parse_event(&words).map(|event| event.to_event()).map_err(Error::Serialization)
+}
+
+fn parse_event(words: &[&str]) -> ::std::result::Result<ScenarioEvent, String> {
+    Ok(match *words {
+        ["add", prefix] => ScenarioEvent::Add(parse_prefix(prefix)?),
+        ["remove-from", prefix] => ScenarioEvent::RemoveFrom(parse_prefix(prefix)?),
+        ["relocate-from", from, to] => {
+            ScenarioEvent::RelocateFrom(parse_prefix(from)?, parse_prefix(to)?)
+        }
+        ["rejoin", prefix, after] => {
+            ScenarioEvent::Rejoin(parse_prefix(prefix)?, parse_num(after)?)
+        }
+        ["fault", prefix, fault] => {
+            ScenarioEvent::SetFault(parse_prefix(prefix)?, fault.parse()?, true)
+        }
+        ["recover", prefix, fault] => {
+            ScenarioEvent::SetFault(parse_prefix(prefix)?, fault.parse()?, false)
+        }
+        ["drop", from, to, kind] => {
+            let (from, to) = (parse_prefix(from)?, parse_prefix(to)?);
+            ScenarioEvent::DropNext(from, to, kind.to_string())
+        }
+        ["forget", prefix, fraction] => {
+            let fraction = parse_num(fraction)?;
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(format!("fraction {} not between 0 and 1", fraction));
+            }
+            ScenarioEvent::Forget(parse_prefix(prefix)?, fraction)
+        }
+        ["partition", a, b] => {
+            ScenarioEvent::Partition(parse_prefix(a)?, parse_prefix(b)?, true)
+        }
+        ["heal", a, b] => {
+            ScenarioEvent::Partition(parse_prefix(a)?, parse_prefix(b)?, false)
+        }
+        ["split", prefix] => ScenarioEvent::Split(parse_prefix(prefix)?),
+        ["merge", prefix] => ScenarioEvent::Merge(parse_prefix(prefix)?),
+        ["rewire", prefix] => ScenarioEvent::Rewire(parse_prefix(prefix)?),
+        _ => return Err(format!("unknown event {:?}", words)),
+    })
+}
+
+fn parse_num<T: ::std::str::FromStr>(word: &str) -> ::std::result::Result<T, String> {
+    word.parse().map_err(|_| format!("invalid number {}", word))
+}
+
+/// Parse a prefix written as its bits, or `-` for the empty prefix.
+pub fn parse_prefix(word: &str) -> ::std::result::Result<Prefix, String> {
+    if word == "-" {
+        return Ok(Prefix::empty());
+    }
+    word.chars().try_fold(Prefix::empty(), |prefix, bit| match bit {
+        '0' => Ok(prefix.pushed(false)),
+        '1' => Ok(prefix.pushed(true)),
+        _ => Err(format!("invalid prefix {}", word)),
+    })
+}
+
+/// Write a prefix as its bits, or `-` for the empty prefix.
+pub fn format_prefix(prefix: &Prefix) -> String {
+    if prefix.bit_count() == 0 {
+        return "-".to_string();
+    }
+    let name = prefix.lower_bound();
+    (0..prefix.bit_count())
+        .map(|i| if name.bit(i) { '1' } else { '0' })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prefixes_round_trip() {
+        for word in &["-", "0", "1", "0110"] {
+            assert_eq!(format_prefix(&parse_prefix(word).unwrap()), *word);
+        }
+        assert!(parse_prefix("012").is_err());
+    }
+
+    #[test]
+    fn parse_errors_give_line() {
+        let text = "# ewok scenario format 1\nsection 0 8\nat 3 explode 0\n";
+        let err = Scenario::parse(text).unwrap_err().to_string();
+        assert!(err.contains("line 3"), "{}", err);
+    }
+
+    #[test]
+    fn preset_then_params() {
+        let text = "# ewok scenario format 1\npreset aggressive-churn\nparam max_delay 7\n";
+        let scenario = Scenario::parse(text).unwrap();
+        assert_eq!(scenario.params.max_delay, 7);
+        assert_eq!(scenario.params.prob_churn, 0.3);
+
+        let text = "# ewok scenario format 1\nparam prob_reconnect 0.5\nparam drop_grace_steps 3\n";
+        let scenario = Scenario::parse(text).unwrap();
+        assert!(matches!(scenario.params.reconnect, ReconnectModel::Constant(p) if p == 0.5));
+        assert_eq!(scenario.node_params.drop_grace_steps, 3);
+
+        let text = "# ewok scenario format 1\npreset tiny\n";
+        assert!(Scenario::parse(text).is_err());
+    }
+
+    #[test]
+    fn fault_events() {
+        let text = "# ewok scenario format 1\nat 2 fault 0 no-votes\nat 4 recover 0 no-votes\n";
+        let scenario = Scenario::parse(text).unwrap();
+        match scenario.events[&4][..] {
+            [ScenarioEvent::SetFault(prefix, Fault::NoVotes, false)] => {
+                assert_eq!(prefix, parse_prefix("0").unwrap())
+            }
+            ref events => panic!("unexpected events {:?}", events),
+        }
+
+        let text = "# ewok scenario format 1\nat 2 fault 0 slow\n";
+        assert!(Scenario::parse(text).is_err());
+    }
+
+    #[test]
+    fn drop_and_forget_events() {
+        let text = "# ewok scenario format 1\nat 3 drop 0 1 VoteMsg\n";
+        let scenario = Scenario::parse(text).unwrap();
+        match scenario.events[&3][..] {
+            [ScenarioEvent::DropNext(from, to, ref kind)] => {
+                assert_eq!(from, parse_prefix("0").unwrap());
+                assert_eq!(to, parse_prefix("1").unwrap());
+                assert_eq!(kind, "VoteMsg");
+            }
+            ref events => panic!("unexpected events {:?}", events),
+        }
+
+        let text = "# ewok scenario format 1\nat 3 drop 0 VoteMsg\n";
+        assert!(Scenario::parse(text).is_err());
+
+        let text = "# ewok scenario format 1\nat 5 forget 1 0.5\n";
+        let scenario = Scenario::parse(text).unwrap();
+        match scenario.events[&5][..] {
+            [ScenarioEvent::Forget(prefix, fraction)] => {
+                assert_eq!(prefix, parse_prefix("1").unwrap());
+                assert_eq!(fraction, 0.5);
+            }
+            ref events => panic!("unexpected events {:?}", events),
+        }
+
+        let text = "# ewok scenario format 1\nat 5 forget 1 1.5\n";
+        assert!(Scenario::parse(text).is_err());
+    }
+
+    #[test]
+    fn topology_events() {
+        let text = "# ewok scenario format 1\nat 1 partition 0 1\nat 1 split 1\nat 2 heal 0 1\n\
+                    at 3 merge -\nat 4 rewire 01\n";
+        let scenario = Scenario::parse(text).unwrap();
+        match scenario.events[&1][..] {
+            [ScenarioEvent::Partition(a, b, true), ScenarioEvent::Split(split)] => {
+                assert_eq!((a, b), (parse_prefix("0").unwrap(), parse_prefix("1").unwrap()));
+                assert_eq!(split, b);
+            }
+            ref events => panic!("unexpected events {:?}", events),
+        }
+        assert!(matches!(scenario.events[&2][..], [ScenarioEvent::Partition(_, _, false)]));
+        match scenario.events[&3][..] {
+            [ScenarioEvent::Merge(prefix)] => assert_eq!(prefix, Prefix::empty()),
+            ref events => panic!("unexpected events {:?}", events),
+        }
+        assert!(matches!(scenario.events[&4][..], [ScenarioEvent::Rewire(_)]));
+
+        let text = "# ewok scenario format 1\nat 1 partition 0\n";
+        assert!(Scenario::parse(text).is_err());
+
+        let p01 = parse_prefix("01").unwrap();
+        let split = parse_event_line(" split 01 ").unwrap();
+        assert!(matches!(split, Event::SectionSplit(prefix) if prefix == p01));
+        assert!(parse_event_line("at 1 split 01").is_err());
+    }
+
+    #[test]
+    fn assertions() {
+        let text = "# ewok scenario format 1\nassert at 50: sections == {0, 1}\n\
+                    assert eventually: node 3F2a member of section 01\n";
+        let scenario = Scenario::parse(text).unwrap();
+        let assertions: Vec<_> = scenario.assertions.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            assertions,
+            vec!["at 50: sections == {0,1}", "eventually: node 3f2a member of section 01"]
+        );
+
+        let text = "# ewok scenario format 1\nassert always: sections == {0}\n";
+        assert!(Scenario::parse(text).is_err());
+    }
+
+    #[test]
+    fn rejoin_events() {
+        let text = "# ewok scenario format 1\nparam rejoin_cooldown 15\nat 2 rejoin 10 3\n";
+        let scenario = Scenario::parse(text).unwrap();
+        assert_eq!(scenario.node_params.rejoin_cooldown, 15);
+        match scenario.events[&2][..] {
+            [ScenarioEvent::Rejoin(prefix, after)] => {
+                assert_eq!((prefix, after), (parse_prefix("10").unwrap(), 3));
+            }
+            ref events => panic!("unexpected events {:?}", events),
+        }
+        assert!(parse_event_line("rejoin 10").is_err());
+    }
+}
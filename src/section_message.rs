@@ -0,0 +1,125 @@
+//! Messages sent on behalf of a whole section.
+//!
+//! Every member of the sending section signs a section message by sending its share to the rest
+//! of the section. A member holding shares from a quorum of the section's block sends the message
+//! with its signers to the members of the destination section, who only accept it if the signers
+//! are a quorum of that block. Several members usually reach a quorum, so the network delivers
+//! each section message to a recipient at most once.
+
+use block::BlockId;
+use blocks::Blocks;
+use name::{Name, Prefix};
+use params::quorum;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What a section is telling another section.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SectionPayload {
+    /// The sending section has split, with the given block as one of its successors.
+    Split(BlockId),
+    /// The sending section has accepted `node` and relocates it to the destination section under
+    /// the name `to`.
+    Relocate { node: Name, to: Name },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SectionMessage {
+    /// Block of the sending section, whose members sign the message.
+    pub src: BlockId,
+    /// Prefix of the section the message is for.
+    pub dst: Prefix,
+    pub payload: SectionPayload,
+}
+
+impl SectionMessage {
+    /// All the blocks referred to by this message.
+    pub fn block_ids(&self) -> BTreeSet<BlockId> {
+        match self.payload {
+            SectionPayload::Split(block) => btreeset!{self.src, block},
+            SectionPayload::Relocate { .. } => btreeset!{self.src},
+        }
+    }
+
+    /// Whether `signers` are a quorum of the members of the sending block.
+    pub fn is_signed_by(&self, blocks: &Blocks, signers: &BTreeSet<Name>) -> bool {
+        let members = &self.src.into_block(blocks).members;
+        signers.is_subset(members) && signers.len() >= quorum(members.len())
+    }
+}
+
+/// Shares of section messages collected by one member of the sending section.
+#[derive(Clone, Debug, Default)]
+pub struct SectionAccumulator {
+    /// Signers of each message seen, until they form a quorum.
+    shares: BTreeMap<SectionMessage, BTreeSet<Name>>,
+    /// Messages whose signers have formed a quorum.
+    accumulated: BTreeSet<SectionMessage>,
+}
+
+impl SectionAccumulator {
+    /// Add a share of `message` from `signer`. Returns the signers once they first form a quorum
+    /// of the sending block.
+    pub fn add(
+        &mut self,
+        blocks: &Blocks,
+        message: &SectionMessage,
+        signer: Name,
+    ) -> Option<BTreeSet<Name>> {
+        if self.accumulated.contains(message) ||
+            !message.src.into_block(blocks).members.contains(&signer)
+        {
+            return None;
+        }
+        let complete = {
+            let signers = self.shares.entry(message.clone()).or_default();
+            let _ = signers.insert(signer);
+            message.is_signed_by(blocks, signers)
+        };
+        if !complete {
+            return None;
+        }
+        let _ = self.accumulated.insert(message.clone());
+        self.shares.remove(message)
+    }
+
+    /// Number of messages still short of a quorum of signers.
+    pub fn num_pending(&self) -> usize {
+        self.shares.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+
+    #[test]
+    fn accumulates_once_at_quorum() {
+        let members: BTreeSet<Name> = (0..4).map(Name).collect();
+        let mut blocks = Blocks::new();
+        let src = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: members.clone(),
+        });
+        let message = SectionMessage {
+            src,
+            dst: Prefix::short(1, 0),
+            payload: SectionPayload::Split(src),
+        };
+
+        let mut accumulator = SectionAccumulator::default();
+        // Shares from outside the section don't count.
+        assert_eq!(accumulator.add(&blocks, &message, Name(10)), None);
+        assert_eq!(accumulator.add(&blocks, &message, Name(0)), None);
+        assert_eq!(accumulator.add(&blocks, &message, Name(1)), None);
+        assert_eq!(accumulator.num_pending(), 1);
+
+        let signers = accumulator.add(&blocks, &message, Name(2)).unwrap();
+        assert_eq!(signers, btreeset!{Name(0), Name(1), Name(2)});
+        assert!(message.is_signed_by(&blocks, &signers));
+        assert_eq!(accumulator.add(&blocks, &message, Name(3)), None);
+        assert_eq!(accumulator.num_pending(), 0);
+    }
+}
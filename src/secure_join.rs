@@ -0,0 +1,111 @@
+//! Latency of secure joins, where a joining node is first accepted by its home section and then
+//! relocated by it to another section under a new name, from the node first joining to it
+//! becoming a member of the section it was relocated to.
+
+use name::Name;
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+/// Progress of secure joins.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SecureJoinStats {
+    /// Number of nodes relocated by their home section.
+    pub relocated: u64,
+    /// Number of relocated nodes which became members of the section they were relocated to.
+    pub completed: u64,
+    /// Total number of steps from joining to being relocated, over the relocated nodes.
+    pub total_relocation_latency: u64,
+    /// Total number of steps from joining to becoming a member of the section relocated to, over
+    /// the completed joins.
+    pub total_latency: u64,
+}
+
+impl SecureJoinStats {
+    /// Mean number of steps between a node joining and its home section relocating it.
+    pub fn mean_relocation_latency(&self) -> f64 {
+        self.total_relocation_latency as f64 / cmp::max(self.relocated, 1) as f64
+    }
+
+    /// Mean number of steps between a node joining and it being a member of the section it was
+    /// relocated to.
+    pub fn mean_latency(&self) -> f64 {
+        self.total_latency as f64 / cmp::max(self.completed, 1) as f64
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SecureJoins {
+    /// Nodes joining their home section, or members of it not yet relocated, with the step each
+    /// started joining at.
+    home: BTreeMap<Name, u64>,
+    /// Relocated nodes, by their new names, with the step each started joining at.
+    relocated: BTreeMap<Name, u64>,
+    stats: SecureJoinStats,
+}
+
+impl SecureJoins {
+    /// Record that a node started joining at `step`, unless it's joining after being relocated.
+    pub fn started(&mut self, name: Name, step: u64) {
+        if !self.relocated.contains_key(&name) {
+            let _ = self.home.insert(name, step);
+        }
+    }
+
+    /// Record that the home section of `from` relocated it to `to` at `step`.
+    pub fn relocated(&mut self, from: Name, to: Name, step: u64) {
+        let start_step = match self.home.remove(&from) {
+            Some(start_step) => start_step,
+            None => return,
+        };
+        self.stats.relocated += 1;
+        self.stats.total_relocation_latency += step - start_step;
+        let _ = self.relocated.insert(to, start_step);
+    }
+
+    /// Record that a node became a member at `step`.
+    pub fn joined(&mut self, name: Name, step: u64) {
+        if let Some(start_step) = self.relocated.remove(&name) {
+            self.stats.completed += 1;
+            self.stats.total_latency += step - start_step;
+        }
+    }
+
+    /// Forget a node which left before being relocated, or before joining the section it was
+    /// relocated to.
+    pub fn left(&mut self, name: Name) {
+        let _ = self.home.remove(&name);
+        let _ = self.relocated.remove(&name);
+    }
+
+    pub fn stats(&self) -> &SecureJoinStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latency_from_first_join() {
+        let mut joins = SecureJoins::default();
+        joins.started(Name(1), 10);
+        joins.started(Name(2), 12);
+        joins.joined(Name(1), 15);
+        joins.relocated(Name(1), Name(11), 18);
+        joins.started(Name(11), 18);
+        joins.relocated(Name(2), Name(12), 24);
+        joins.started(Name(12), 24);
+        // Nodes which didn't start joining while being tracked aren't counted.
+        joins.relocated(Name(3), Name(13), 25);
+        joins.joined(Name(11), 30);
+        joins.left(Name(12));
+        joins.joined(Name(12), 31);
+
+        let stats = joins.stats();
+        assert_eq!((stats.relocated, stats.completed), (2, 1));
+        assert_eq!(stats.mean_relocation_latency(), 10.0);
+        assert_eq!(stats.mean_latency(), 20.0);
+    }
+}
@@ -1,28 +1,70 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp;
+use std::fs::{self, File};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, BufWriter, Write};
 use std::mem;
+use std::path::{Path, PathBuf};
 use itertools::Itertools;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rayon::prelude::*;
 
 use network::Network;
-use event::Event;
+use assertion::{Assertion, AssertionChecker};
+use builder::SimulationBuilder;
+use chain::Chain;
+use cohort::{Cohort, Cohorts};
+use event::{relocation_messages, Event, Relocation};
 use event_schedule::EventSchedule;
-use node::Node;
+use format::FormatKind;
+use node::{Node, NodeTrait};
 use name::{Name, Prefix};
 use block::{Block, BlockId};
 use blocks::Blocks;
-use generate::generate_network;
-use consistency::check_consistency;
+use generate::{converged_sections, generate_network, genesis_network, network_from_chain};
+use lifecycle::Lifecycles;
+use complexity::ComplexityTracker;
+use conflicts::{Conflict, ConflictTracker};
+use divergence::{DivergenceMonitor, DivergenceReport};
+use hygiene::{HygieneAudit, HygieneReport};
+use journal::{JournalLayout, Journals, JournalsCheckpoint};
+use drops::DropTracker;
+use connectivity::ConnectivityMetrics;
+use consistency::{check_consistency, unreachable_quorums};
+use coverage::{CoverageChecker, CoverageViolation};
+use dump::FailureDump;
+use error::{Error, Result};
 use message::Message;
 use message::MessageContent::*;
-use params::{NodeParams, SimulationParams, quorum};
-use random::{sample_single, do_with_probability, seed};
+use params::{Limit, NodeParams, ProcessingOrder, ReconnectModel, SimulationParams, quorum};
+use random::{self, sample_single, do_with_probability, seed, shuffle, rng_state, restore_rng,
+             RngState, Stream};
 use random_events::RandomEvents;
-use self::detail::DisconnectedPair;
+use run_id;
+use soak::{Soak, SoakCheckpoint, SoakParams};
+use stats::{CandidateStats, HandoverStats, JoinStats, MessageKindCounts, NodeStats,
+            ReconnectStats, write_node_stats_csv};
+use outage::{OutageReport, OutageTracker, SectionOutage};
+use pause::{self, PauseAction, Signal};
+use proxy_failure::{ProxyFailureReport, ProxyFailures};
+use sybil::{SybilAttack, SybilReport, SybilTracker};
+use size_target::{SizeController, SizeTrajectory, TrackingStats};
+use trace::{ChurnTrace, ReplayStats, TraceReplay};
+use validity::ValidityAudit;
+use membership::MembershipHistory;
+use prefix_tree::PrefixTreeHistory;
+use state_trace::StateTrace;
+use races::VoteRaces;
+use secure_join::{SecureJoinStats, SecureJoins};
+use equivocation::{EquivocationReport, EquivocationTracker};
+use health::{HealthMonitor, LowHealth, SectionHealth};
+use livelock::{Livelock, LivelockWatchdog};
+use self::detail::{DisconnectedPair, Reconnection};
 
 mod detail {
     use name::Name;
 
     /// Holds a pair of names sorted by lowest first.
-    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
     pub struct DisconnectedPair {
         lower: Name,
         higher: Name,
@@ -53,6 +95,26 @@ mod detail {
             self.higher
         }
     }
+
+    /// Progress of a disconnected pair towards reconnecting.
+    #[derive(Clone, Debug)]
+    pub struct Reconnection {
+        /// Step at which the pair disconnected.
+        pub since: u64,
+        /// Number of failed attempts to reconnect.
+        pub failed: u32,
+        /// Step of the next attempt, if one has failed already.
+        pub next_attempt: Option<u64>,
+    }
+}
+
+/// A set of independently bootstrapped networks which are connected to each other at some step.
+#[derive(Clone)]
+struct DisjointNetworks {
+    /// Step at which the networks learn about each other.
+    connect_step: u64,
+    /// Genesis blocks and initial members of each network.
+    networks: Vec<(BTreeSet<BlockId>, BTreeSet<Name>)>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -64,8 +126,130 @@ pub enum Phase {
     Finishing { since_step: u64 },
 }
 
-pub struct Simulation {
-    nodes: BTreeMap<Name, Node>,
+/// Maximum number of steps to run in the finishing phase.
+const MAX_EXTRA_STEPS: u64 = 1000;
+
+/// Summary of the network after a single step of the simulation.
+#[derive(Clone, Debug)]
+pub struct StepSummary {
+    pub step: u64,
+    /// Phase the step was run in.
+    pub phase: Phase,
+    /// Number of live nodes at the end of the step.
+    pub num_nodes: usize,
+    /// Number of messages delivered during the step.
+    pub messages_delivered: usize,
+    /// Number of messages still in flight at the end of the step.
+    pub messages_in_queue: usize,
+    /// Messages sent, delivered and still in flight, by kind of message.
+    pub message_kinds: BTreeMap<&'static str, MessageKindCounts>,
+}
+
+/// Callback run with the summary of every step, added with `Simulation::add_hook`.
+pub type StepHook = Box<dyn FnMut(&StepSummary)>;
+
+/// Debugger handed a paused simulation, added with `Simulation::set_debugger`.
+pub type Debugger<N> = Box<dyn FnMut(&mut Simulation<N>) -> PauseAction>;
+
+/// Iterator over the steps of a simulation, returned by `Simulation::steps`.
+pub struct Steps<'a, N: NodeTrait + 'a> {
+    simulation: &'a mut Simulation<N>,
+}
+
+impl<'a, N: NodeTrait> Steps<'a, N> {
+    /// The simulation being run, for inspection between steps.
+    pub fn simulation(&self) -> &Simulation<N> {
+        self.simulation
+    }
+}
+
+impl<'a, N: NodeTrait> Iterator for Steps<'a, N> {
+    type Item = StepSummary;
+
+    fn next(&mut self) -> Option<StepSummary> {
+        // Other simulations may have been stepped on this thread in between.
+        run_id::set_current(Some(self.simulation.run_id.clone()));
+        self.simulation.run_step()
+    }
+}
+
+/// Everything needed to resume a simulation from the start of a step.
+///
+/// Only the in-memory state of soak mode and journals is included, so their files see the steps
+/// re-run after a rewind a second time.
+#[derive(Clone)]
+struct Checkpoint<N> {
+    step: u64,
+    nodes: BTreeMap<Name, N>,
+    blocks: Blocks,
+    network: Network,
+    phase: Phase,
+    disconnected: BTreeMap<DisconnectedPair, Reconnection>,
+    random_events: RandomEvents,
+    event_schedule: EventSchedule,
+    injected_events: Vec<Event>,
+    disjoint_networks: Option<DisjointNetworks>,
+    agreed_history: Option<Vec<BTreeSet<BlockId>>>,
+    num_churn_events: u64,
+    dead_node_stats: BTreeMap<Name, NodeStats>,
+    sybil: Option<SybilTracker>,
+    proxy_failures: Option<ProxyFailures>,
+    outage: Option<OutageTracker>,
+    coverage: CoverageChecker,
+    conflicts: ConflictTracker,
+    joining: BTreeMap<Name, u64>,
+    join_stats: JoinStats,
+    secure_joins: SecureJoins,
+    equivocations: EquivocationTracker,
+    relocations: Vec<Relocation>,
+    rejoins: BTreeMap<u64, Vec<Name>>,
+    candidate_stats: CandidateStats,
+    handover_stats: HandoverStats,
+    reconnect_stats: ReconnectStats,
+    lifecycles: Option<Lifecycles>,
+    validity: Option<ValidityAudit>,
+    membership: Option<MembershipHistory>,
+    prefix_tree: Option<PrefixTreeHistory>,
+    state_trace: Option<StateTrace>,
+    vote_races: Option<VoteRaces>,
+    assertions: Option<AssertionChecker>,
+    cohorts: Option<Cohorts>,
+    unreachable_shutdowns: u64,
+    livelock: Option<LivelockWatchdog>,
+    health: Option<HealthMonitor>,
+    divergence: Option<DivergenceMonitor>,
+    hygiene: Option<HygieneAudit>,
+    drops: Option<DropTracker>,
+    churn_trace: Option<TraceReplay>,
+    size_controller: Option<SizeController>,
+    complexity: Option<ComplexityTracker>,
+    no_op_step_count: u64,
+    limit_reached: Option<(Limit, u64, usize)>,
+    stopped_at: Option<u64>,
+    journals: Option<JournalsCheckpoint>,
+    soak: Option<SoakCheckpoint>,
+    rng: RngState,
+}
+
+/// The latest `capacity` checkpoints, taken every `interval` steps.
+struct Checkpoints<N: NodeTrait> {
+    interval: u64,
+    capacity: usize,
+    saved: VecDeque<Checkpoint<N>>,
+    /// Directory that the chain of each checkpoint kept is written to, if enabled.
+    chain_dir: Option<PathBuf>,
+    /// Function to take a checkpoint, which is only available if `N: Clone`.
+    capture: fn(&Simulation<N>) -> Checkpoint<N>,
+}
+
+/// The file within `dir` that the chain of the checkpoint at `step` is written to.
+fn checkpoint_chain_path(dir: &Path, step: u64) -> PathBuf {
+    dir.join(format!("checkpoint-step-{}.chain", step))
+}
+
+/// A simulation of a network of nodes of type `N`.
+pub struct Simulation<N: NodeTrait = Node> {
+    nodes: BTreeMap<Name, N>,
     blocks: Blocks,
     network: Network,
     /// Set of blocks that all nodes start from (often just a single genesis block).
@@ -74,29 +258,125 @@ pub struct Simulation {
     params: SimulationParams,
     /// Parameters for nodes.
     node_params: NodeParams,
+    /// Identifier of this run, written into its outputs.
+    run_id: String,
     /// Which phase the simulation is currently in.
     phase: Phase,
     /// Collection of disconnected pairs which should be trying to reconnect.
-    disconnected: BTreeSet<DisconnectedPair>,
+    disconnected: BTreeMap<DisconnectedPair, Reconnection>,
     /// Generator of random events.
     random_events: RandomEvents,
     /// Event schedule - specifying events to happen at various steps.
     event_schedule: EventSchedule,
+    /// Separate networks that haven't been connected to each other yet.
+    disjoint_networks: Option<DisjointNetworks>,
+    /// Soak mode state, if enabled.
+    soak: Option<Soak>,
+    /// Blocks that were current at any node after each step, if being recorded.
+    agreed_history: Option<Vec<BTreeSet<BlockId>>>,
+    /// Number of node additions and removals applied so far.
+    num_churn_events: u64,
+    /// Final statistics of nodes which are no longer part of the simulation.
+    dead_node_stats: BTreeMap<Name, NodeStats>,
+    /// Sybil attack in progress, if any.
+    sybil: Option<SybilTracker>,
+    /// Bootstrap proxies being killed mid-bootstrap, if any.
+    proxy_failures: Option<ProxyFailures>,
+    /// Section being killed off, if any, and whether the network recovered.
+    outage: Option<OutageTracker>,
+    /// Per-step check of namespace coverage.
+    coverage: CoverageChecker,
+    /// Conflicting valid blocks held by nodes, by prefix.
+    conflicts: ConflictTracker,
+    /// Nodes that are trying to join, and the step at which they started.
+    joining: BTreeMap<Name, u64>,
+    /// Outcomes of finished join attempts.
+    join_stats: JoinStats,
+    /// Progress of secure joins, if joining nodes are relocated.
+    secure_joins: SecureJoins,
+    /// Equivocating nodes and those caught equivocating, if nodes look for equivocation.
+    equivocations: EquivocationTracker,
+    /// Relocations applied so far.
+    relocations: Vec<Relocation>,
+    /// Nodes removed to rejoin under the same name, by the step they're added back at.
+    rejoins: BTreeMap<u64, Vec<Name>>,
+    candidate_stats: CandidateStats,
+    /// How often sections were stalled by members waiting for their handover.
+    handover_stats: HandoverStats,
+    /// How long disconnected pairs took to reconnect.
+    reconnect_stats: ReconnectStats,
+    /// Timelines of every node's lifecycle, if being recorded.
+    lifecycles: Option<Lifecycles>,
+    /// Audit trail of blocks becoming valid on each node, if being recorded.
+    validity: Option<ValidityAudit>,
+    /// History of section membership, if being recorded.
+    membership: Option<MembershipHistory>,
+    /// History of the prefix tree, if being recorded.
+    prefix_tree: Option<PrefixTreeHistory>,
+    /// Trace of global states, if being recorded.
+    state_trace: Option<StateTrace>,
+    /// Arrivals of votes for competing blocks, if being recorded.
+    vote_races: Option<VoteRaces>,
+    /// Assertions the run is checked against, if any.
+    assertions: Option<AssertionChecker>,
+    /// Cohorts of nodes with their own parameters, if any.
+    cohorts: Option<Cohorts>,
+    /// Number of nodes with blocked inbound connections which have shut down.
+    unreachable_shutdowns: u64,
+    /// Watchdog stopping the run once a prefix stops making progress, if enabled.
+    livelock: Option<LivelockWatchdog>,
+    /// Health scores of each section, if being computed.
+    health: Option<HealthMonitor>,
+    /// Nodes whose view differs from their section's, if being detected.
+    divergence: Option<DivergenceMonitor>,
+    /// Stale candidates and ghost connections found, if being audited.
+    hygiene: Option<HygieneAudit>,
+    /// Votes to remove members, and how many were against running nodes, if recording.
+    drops: Option<DropTracker>,
+    /// Churn trace replayed in place of random joins and leaves, if any.
+    churn_trace: Option<TraceReplay>,
+    /// Controller steering the network's size in place of random joins and leaves, if any.
+    size_controller: Option<SizeController>,
+    /// Messages sent per block agreed, by kind and section size, if being tracked.
+    complexity: Option<ComplexityTracker>,
+    /// Callbacks run with the summary of every step.
+    hooks: Vec<StepHook>,
+    /// File that nodes' activity counters are written to at the end of the run, if any.
+    metrics_path: Option<PathBuf>,
+    /// Threads handling delivered messages, if there's more than one.
+    thread_pool: Option<ThreadPool>,
+    /// The next step to be run.
+    step: u64,
+    /// Number of steps in the finishing phase with no messages left to deliver.
+    no_op_step_count: u64,
+    /// Saved states to rewind to, if being recorded.
+    checkpoints: Option<Checkpoints<N>>,
+    /// Dump of the state at the first failure, if enabled.
+    failure_dump: Option<FailureDump>,
+    /// Journals of each node's decisions, if being written.
+    journals: Option<Journals>,
+    /// Directory that the chain is written to when pausing on a signal, if enabled.
+    pause_dir: Option<PathBuf>,
+    debugger: Option<Debugger<N>>,
+    /// Step at which the run was stopped while paused, if it was.
+    stopped_at: Option<u64>,
+    /// Safety limit the run was stopped by, if any, with the step it was reached at and the
+    /// number of nodes then.
+    limit_reached: Option<(Limit, u64, usize)>,
+    /// Events to apply at the next step on top of those drawn for it, e.g. from a debugger.
+    injected_events: Vec<Event>,
 }
 
-impl Simulation {
-    /// Create a new simulation with a single seed node.
+impl Simulation<Node> {
+    /// Start building a simulation, with every setting at its default.
+    pub fn builder() -> SimulationBuilder {
+        SimulationBuilder::default()
+    }
+
+    /// Create a new simulation starting from the genesis section given by `params.genesis_nodes`,
+    /// a single seed node by default.
     pub fn new(params: SimulationParams, node_params: NodeParams) -> Self {
-        let single_node_genesis =
-            btreemap! {
-            Prefix::empty() => 1
-        };
-        Self::new_from(
-            single_node_genesis,
-            EventSchedule::empty(),
-            params,
-            node_params,
-        )
+        Self::with_seed_node(params, node_params)
     }
 
     /// Create a new simulation with sections whose prefixes and sizes are specified by `sections`.
@@ -108,10 +388,317 @@ impl Simulation {
         params: SimulationParams,
         node_params: NodeParams,
     ) -> Self {
+        Self::from_sections(sections, event_schedule, params, node_params)
+    }
+
+    /// Create a simulation containing several disjoint networks. See `from_disjoint_sections`.
+    pub fn new_disjoint(
+        networks: Vec<BTreeMap<Prefix, usize>>,
+        connect_step: u64,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Self {
+        Self::from_disjoint_sections(
+            networks,
+            connect_step,
+            event_schedule,
+            params,
+            node_params,
+        )
+    }
+}
+
+impl<N: NodeTrait + Clone> Simulation<N> {
+    /// Save the simulation's state at the start of every `interval` steps, so that it can be
+    /// rewound with `rewind`. Each checkpoint is a full copy of the network, so only the latest
+    /// `capacity` are kept.
+    pub fn record_checkpoints(&mut self, interval: u64, capacity: usize) {
+        assert!(interval > 0, "checkpoint interval must be positive");
+        assert!(capacity > 0, "checkpoint capacity must be positive");
+        self.checkpoints = Some(Checkpoints {
+            interval,
+            capacity,
+            saved: VecDeque::new(),
+            chain_dir: None,
+            capture: Self::checkpoint,
+        });
+    }
+
+    /// Also write the chain of each checkpoint to `checkpoint-step-<step>.chain` within `dir`, for
+    /// `try_from_chain` to start other runs from. The files of checkpoints no longer kept are
+    /// removed. Does nothing unless checkpoints are being recorded.
+    pub fn write_checkpoint_chains(&mut self, dir: PathBuf) {
+        if let Some(ref mut checkpoints) = self.checkpoints {
+            checkpoints.chain_dir = Some(dir);
+        }
+    }
+
+    /// Restore the simulation to the start of `step` and re-run it up to there.
+    ///
+    /// Starts from the latest checkpoint at or before `step`. The random number generator is
+    /// restored along with everything else, so the re-run steps are identical to the original
+    /// ones, as are any steps run afterwards.
+    pub fn rewind(&mut self, step: u64) -> Result<()> {
+        if step > self.step {
+            return Err(Error::Config(format!(
+                "can't rewind to step {}, which is after the current step {}",
+                step,
+                self.step
+            )));
+        }
+        let checkpoint = match self.checkpoints {
+            Some(ref mut checkpoints)
+                if checkpoints.saved.iter().any(|saved| saved.step <= step) => {
+                if let Some(ref dir) = checkpoints.chain_dir {
+                    for saved in checkpoints.saved.iter().filter(|saved| saved.step > step) {
+                        let _ = fs::remove_file(checkpoint_chain_path(dir, saved.step));
+                    }
+                }
+                checkpoints.saved.retain(|saved| saved.step <= step);
+                checkpoints.saved.back().cloned()
+            }
+            Some(_) => None,
+            None => None,
+        };
+        let checkpoint = checkpoint.ok_or_else(|| {
+            Error::Config(format!("no checkpoint at or before step {}", step))
+        })?;
+
+        info!("-- rewinding to step {} from step {} --", step, self.step);
+        self.restore(checkpoint);
+        while self.step < step && self.run_step().is_some() {}
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint<N> {
+        Checkpoint {
+            step: self.step,
+            nodes: self.nodes.clone(),
+            blocks: self.blocks.clone(),
+            network: self.network.clone(),
+            phase: self.phase,
+            disconnected: self.disconnected.clone(),
+            random_events: self.random_events.clone(),
+            event_schedule: self.event_schedule.clone(),
+            injected_events: self.injected_events.clone(),
+            disjoint_networks: self.disjoint_networks.clone(),
+            agreed_history: self.agreed_history.clone(),
+            num_churn_events: self.num_churn_events,
+            dead_node_stats: self.dead_node_stats.clone(),
+            sybil: self.sybil.clone(),
+            proxy_failures: self.proxy_failures.clone(),
+            outage: self.outage.clone(),
+            coverage: self.coverage.clone(),
+            conflicts: self.conflicts.clone(),
+            joining: self.joining.clone(),
+            join_stats: self.join_stats.clone(),
+            secure_joins: self.secure_joins.clone(),
+            equivocations: self.equivocations.clone(),
+            relocations: self.relocations.clone(),
+            rejoins: self.rejoins.clone(),
+            candidate_stats: self.candidate_stats.clone(),
+            handover_stats: self.handover_stats.clone(),
+            reconnect_stats: self.reconnect_stats.clone(),
+            lifecycles: self.lifecycles.clone(),
+            validity: self.validity.clone(),
+            membership: self.membership.clone(),
+            prefix_tree: self.prefix_tree.clone(),
+            state_trace: self.state_trace.clone(),
+            vote_races: self.vote_races.clone(),
+            assertions: self.assertions.clone(),
+            cohorts: self.cohorts.clone(),
+            unreachable_shutdowns: self.unreachable_shutdowns,
+            livelock: self.livelock.clone(),
+            health: self.health.clone(),
+            divergence: self.divergence.clone(),
+            hygiene: self.hygiene.clone(),
+            drops: self.drops.clone(),
+            churn_trace: self.churn_trace.clone(),
+            size_controller: self.size_controller.clone(),
+            complexity: self.complexity.clone(),
+            no_op_step_count: self.no_op_step_count,
+            limit_reached: self.limit_reached,
+            stopped_at: self.stopped_at,
+            journals: self.journals.as_ref().map(Journals::checkpoint),
+            soak: self.soak.as_ref().map(Soak::checkpoint),
+            rng: rng_state(),
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint<N>) {
+        self.step = checkpoint.step;
+        self.nodes = checkpoint.nodes;
+        self.blocks = checkpoint.blocks;
+        self.network = checkpoint.network;
+        self.phase = checkpoint.phase;
+        self.disconnected = checkpoint.disconnected;
+        self.random_events = checkpoint.random_events;
+        self.event_schedule = checkpoint.event_schedule;
+        self.injected_events = checkpoint.injected_events;
+        self.disjoint_networks = checkpoint.disjoint_networks;
+        self.agreed_history = checkpoint.agreed_history;
+        self.num_churn_events = checkpoint.num_churn_events;
+        self.dead_node_stats = checkpoint.dead_node_stats;
+        self.sybil = checkpoint.sybil;
+        self.proxy_failures = checkpoint.proxy_failures;
+        self.outage = checkpoint.outage;
+        self.coverage = checkpoint.coverage;
+        self.conflicts = checkpoint.conflicts;
+        self.joining = checkpoint.joining;
+        self.join_stats = checkpoint.join_stats;
+        self.secure_joins = checkpoint.secure_joins;
+        self.equivocations = checkpoint.equivocations;
+        self.relocations = checkpoint.relocations;
+        self.rejoins = checkpoint.rejoins;
+        self.candidate_stats = checkpoint.candidate_stats;
+        self.handover_stats = checkpoint.handover_stats;
+        self.reconnect_stats = checkpoint.reconnect_stats;
+        self.lifecycles = checkpoint.lifecycles;
+        self.validity = checkpoint.validity;
+        self.membership = checkpoint.membership;
+        self.prefix_tree = checkpoint.prefix_tree;
+        self.state_trace = checkpoint.state_trace;
+        self.vote_races = checkpoint.vote_races;
+        self.assertions = checkpoint.assertions;
+        self.cohorts = checkpoint.cohorts;
+        self.unreachable_shutdowns = checkpoint.unreachable_shutdowns;
+        self.livelock = checkpoint.livelock;
+        self.health = checkpoint.health;
+        self.divergence = checkpoint.divergence;
+        self.hygiene = checkpoint.hygiene;
+        self.drops = checkpoint.drops;
+        self.churn_trace = checkpoint.churn_trace;
+        self.size_controller = checkpoint.size_controller;
+        self.complexity = checkpoint.complexity;
+        self.no_op_step_count = checkpoint.no_op_step_count;
+        self.limit_reached = checkpoint.limit_reached;
+        self.stopped_at = checkpoint.stopped_at;
+        if let (Some(journals), Some(saved)) = (self.journals.as_mut(), checkpoint.journals) {
+            journals.restore(saved);
+        }
+        if let (Some(soak), Some(saved)) = (self.soak.as_mut(), checkpoint.soak) {
+            soak.restore(saved);
+        }
+        restore_rng(&checkpoint.rng);
+    }
+}
+
+impl<N: NodeTrait> Simulation<N> {
+    /// Create a new simulation of nodes of type `N` starting from the genesis section given by
+    /// `params.genesis_nodes`, a single seed node by default.
+    ///
+    /// Panics if the parameters are invalid; see `try_from_genesis`.
+    pub fn with_seed_node(params: SimulationParams, node_params: NodeParams) -> Self {
+        Self::try_from_genesis(EventSchedule::empty(), params, node_params)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Create a simulation which starts from the genesis section described by
+    /// `params.genesis_nodes`. Returns an error if the parameters are invalid.
+    pub fn try_from_genesis(
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Result<Self> {
+        params.validate()?;
+        node_params.validate()?;
+
+        let mut blocks = Blocks::new();
+        let (nodes, genesis_set) =
+            genesis_network(&mut blocks, &params.genesis_nodes, &node_params);
+        Ok(Self::from_network(
+            blocks,
+            nodes,
+            genesis_set,
+            event_schedule,
+            params,
+            node_params,
+        ))
+    }
+
+    /// Create a new simulation of nodes of type `N` with sections whose prefixes and sizes are
+    /// specified by `sections`.
+    ///
+    /// Panics if the parameters or sections are invalid; see `try_from_sections`.
+    pub fn from_sections(
+        sections: BTreeMap<Prefix, usize>,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Self {
+        Self::try_from_sections(sections, event_schedule, params, node_params)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `from_sections`, but returns an error if the parameters are invalid or the sections
+    /// don't cover the whole namespace.
+    pub fn try_from_sections(
+        sections: BTreeMap<Prefix, usize>,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Result<Self> {
+        params.validate()?;
+        node_params.validate()?;
+
+        let mut blocks = Blocks::new();
+        let (nodes, genesis_set) = generate_network(&mut blocks, &sections, &node_params)?;
+        Ok(Self::from_network(
+            blocks,
+            nodes,
+            genesis_set,
+            event_schedule,
+            params,
+            node_params,
+        ))
+    }
+
+    /// Create a simulation which starts from a chain exported by `write_chain`, with a node for
+    /// each member of its current blocks. Returns an error if the parameters are invalid or the
+    /// current blocks aren't a consistent network.
+    pub fn try_from_chain(
+        chain: &Chain,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Result<Self> {
+        params.validate()?;
+        node_params.validate()?;
+
         let mut blocks = Blocks::new();
-        let (nodes, genesis_set) = generate_network(&mut blocks, &sections, &node_params);
-        let network = Network::new(params.max_delay);
+        let (nodes, genesis_set) = network_from_chain(&mut blocks, chain, &node_params)?;
+        let simulation = Self::from_network(
+            blocks,
+            nodes,
+            genesis_set,
+            event_schedule,
+            params,
+            node_params,
+        );
+        check_consistency(
+            &simulation.blocks,
+            &simulation.nodes,
+            simulation.node_params.min_section_size,
+            simulation.node_params.max_section_size,
+        )?;
+        Ok(simulation)
+    }
+
+    fn from_network(
+        blocks: Blocks,
+        nodes: BTreeMap<Name, N>,
+        genesis_set: BTreeSet<BlockId>,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Self {
+        let network = Network::new(params.max_delay, params.message_ttl, params.delay_model);
         let random_events = RandomEvents::new(params.clone(), node_params.clone());
+        let conflicts = ConflictTracker::new(
+            node_params.warn_conflicting_blocks,
+            node_params.max_conflicting_blocks,
+        );
 
         Simulation {
             blocks,
@@ -120,196 +707,1504 @@ impl Simulation {
             network,
             params,
             node_params,
+            run_id: run_id::start(seed()),
             phase: Phase::Starting,
-            disconnected: BTreeSet::new(),
+            disconnected: BTreeMap::new(),
             random_events,
             event_schedule,
+            disjoint_networks: None,
+            soak: None,
+            agreed_history: None,
+            num_churn_events: 0,
+            dead_node_stats: BTreeMap::new(),
+            sybil: None,
+            proxy_failures: None,
+            outage: None,
+            coverage: CoverageChecker::default(),
+            conflicts,
+            joining: BTreeMap::new(),
+            join_stats: JoinStats::default(),
+            secure_joins: SecureJoins::default(),
+            equivocations: EquivocationTracker::default(),
+            relocations: vec![],
+            rejoins: BTreeMap::new(),
+            candidate_stats: CandidateStats::default(),
+            handover_stats: HandoverStats::default(),
+            reconnect_stats: ReconnectStats::default(),
+            lifecycles: None,
+            validity: None,
+            membership: None,
+            prefix_tree: None,
+            state_trace: None,
+            vote_races: None,
+            assertions: None,
+            cohorts: None,
+            unreachable_shutdowns: 0,
+            livelock: None,
+            health: None,
+            divergence: None,
+            hygiene: None,
+            drops: None,
+            churn_trace: None,
+            size_controller: None,
+            complexity: None,
+            hooks: vec![],
+            metrics_path: None,
+            thread_pool: None,
+            step: 0,
+            no_op_step_count: 0,
+            checkpoints: None,
+            failure_dump: None,
+            journals: None,
+            pause_dir: None,
+            debugger: None,
+            stopped_at: None,
+            limit_reached: None,
+            injected_events: vec![],
         }
     }
 
-    fn apply_add_node(&mut self, joining: Name, step: u64) {
-        // Make the node active, and let it build its way up from the genesis block(s).
-        let genesis_set = self.genesis_set.clone();
-        let params = self.node_params.clone();
-        let node = Node::new(joining, &self.blocks, genesis_set, params, step);
-        self.nodes.insert(joining, node);
+    /// Create a simulation which starts from a converged network of `num_nodes` nodes, skipping
+    /// the starting phase.
+    ///
+    /// Panics if the parameters are invalid; see `try_warmed_up`.
+    pub fn warmed_up(
+        num_nodes: usize,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Self {
+        Self::try_warmed_up(num_nodes, event_schedule, params, node_params)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
-    fn apply_remove_node(&mut self, leaving_node: Name) {
-        debug!("Node({}): dying...", leaving_node);
+    /// Like `warmed_up`, but returns an error if the parameters are invalid.
+    ///
+    /// The sections are laid out as the network would have split while growing, and every node
+    /// starts out agreeing on all of them and connected to all their members. The network is
+    /// checked for consistency before being returned.
+    pub fn try_warmed_up(
+        num_nodes: usize,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Result<Self> {
+        let sections = converged_sections(num_nodes, &node_params);
+        let mut simulation =
+            Self::try_from_sections(sections, event_schedule, params, node_params)?;
+        check_consistency(
+            &simulation.blocks,
+            &simulation.nodes,
+            simulation.node_params.min_section_size,
+            simulation.node_params.max_section_size,
+        )?;
 
-        // Remove the node.
-        self.nodes.remove(&leaving_node);
+        simulation.phase = if simulation.params.grow_prob_join > 0.0 {
+            Phase::Growth
+        } else {
+            Phase::Stable { since_step: 0 }
+        };
+        Ok(simulation)
+    }
 
-        // Remove any "disconnections" associated with this node.
-        let disconnected = mem::replace(&mut self.disconnected, BTreeSet::new());
-        self.disconnected = disconnected
-            .into_iter()
-            .filter(|pair| {
-                pair.lower() != leaving_node && pair.higher() != leaving_node
-            })
-            .collect();
+    /// Create a simulation containing several disjoint networks, each with its own genesis
+    /// blocks, which are connected to each other at `connect_step`.
+    ///
+    /// Each entry of `networks` specifies the prefixes and sizes of one network's sections, as
+    /// for `from_sections`. Nodes joining before or after the networks are connected start from
+    /// the genesis blocks of the first network.
+    ///
+    /// Panics if the parameters or sections are invalid; see `try_from_disjoint_sections`.
+    pub fn from_disjoint_sections(
+        networks: Vec<BTreeMap<Prefix, usize>>,
+        connect_step: u64,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Self {
+        Self::try_from_disjoint_sections(
+            networks,
+            connect_step,
+            event_schedule,
+            params,
+            node_params,
+        ).unwrap_or_else(|err| panic!("{}", err))
     }
 
-    fn apply_event(&mut self, event: &Event, step: u64) {
-        match *event {
-            Event::AddNode(name) => self.apply_add_node(name, step),
-            Event::RemoveNode(name) => self.apply_remove_node(name),
-            Event::RemoveNodeFrom(_) => panic!("normalise RemoveNodeFrom before applying"),
+    /// Like `from_disjoint_sections`, but returns an error if the parameters are invalid or any
+    /// network's sections don't cover the whole namespace.
+    pub fn try_from_disjoint_sections(
+        networks: Vec<BTreeMap<Prefix, usize>>,
+        connect_step: u64,
+        event_schedule: EventSchedule,
+        params: SimulationParams,
+        node_params: NodeParams,
+    ) -> Result<Self> {
+        if networks.is_empty() {
+            return Err(Error::Config("at least one network is required".to_string()));
         }
-    }
+        params.validate()?;
+        node_params.validate()?;
 
-    /// Kill a connection between a pair of nodes which aren't already disconnected.
-    fn disconnect_pair(&mut self) -> Vec<Message> {
-        let pair = {
-            let connected_pairs = self.nodes
-                .keys()
-                .cloned()
-                .tuple_combinations()
-                .filter(|&(ref n1, ref n2)| {
-                    !self.nodes[n1].is_disconnected_from(n2) &&
-                        !self.nodes[n2].is_disconnected_from(n1)
-                })
-                .map(|(n1, n2)| DisconnectedPair::new(n1, n2));
+        let mut blocks = Blocks::new();
+        let mut nodes = BTreeMap::new();
+        let mut disjoint = vec![];
 
-            match sample_single(connected_pairs) {
-                Some(x) => x,
-                None => return vec![],
-            }
-        };
+        for sections in &networks {
+            let (network_nodes, network_genesis) =
+                generate_network(&mut blocks, sections, &node_params)?;
+            disjoint.push((network_genesis, network_nodes.keys().cloned().collect()));
+            nodes.extend(network_nodes);
+        }
 
-        debug!(
-            "Node({}) and Node({}) disconnecting from each other...",
-            pair.lower(),
-            pair.higher()
+        let genesis_set = disjoint[0].0.clone();
+        let network = Network::new(params.max_delay, params.message_ttl, params.delay_model);
+        let random_events = RandomEvents::new(params.clone(), node_params.clone());
+        let conflicts = ConflictTracker::new(
+            node_params.warn_conflicting_blocks,
+            node_params.max_conflicting_blocks,
         );
-        let messages = vec![
-            Message {
-                sender: pair.lower(),
-                recipient: pair.higher(),
-                content: Disconnect,
-            },
-            Message {
-                sender: pair.higher(),
-                recipient: pair.lower(),
-                content: Disconnect,
-            },
-        ];
 
-        self.disconnected.insert(pair);
-        messages
+        Ok(Simulation {
+            blocks,
+            nodes,
+            genesis_set,
+            network,
+            params,
+            node_params,
+            run_id: run_id::start(seed()),
+            phase: Phase::Starting,
+            disconnected: BTreeMap::new(),
+            random_events,
+            event_schedule,
+            disjoint_networks: Some(DisjointNetworks {
+                connect_step,
+                networks: disjoint,
+            }),
+            soak: None,
+            agreed_history: None,
+            num_churn_events: 0,
+            dead_node_stats: BTreeMap::new(),
+            sybil: None,
+            proxy_failures: None,
+            outage: None,
+            coverage: CoverageChecker::default(),
+            conflicts,
+            joining: BTreeMap::new(),
+            join_stats: JoinStats::default(),
+            secure_joins: SecureJoins::default(),
+            equivocations: EquivocationTracker::default(),
+            relocations: vec![],
+            rejoins: BTreeMap::new(),
+            candidate_stats: CandidateStats::default(),
+            handover_stats: HandoverStats::default(),
+            reconnect_stats: ReconnectStats::default(),
+            lifecycles: None,
+            validity: None,
+            membership: None,
+            prefix_tree: None,
+            state_trace: None,
+            vote_races: None,
+            assertions: None,
+            cohorts: None,
+            unreachable_shutdowns: 0,
+            livelock: None,
+            health: None,
+            divergence: None,
+            hygiene: None,
+            drops: None,
+            churn_trace: None,
+            size_controller: None,
+            complexity: None,
+            hooks: vec![],
+            metrics_path: None,
+            thread_pool: None,
+            step: 0,
+            no_op_step_count: 0,
+            checkpoints: None,
+            failure_dump: None,
+            journals: None,
+            pause_dir: None,
+            debugger: None,
+            stopped_at: None,
+            limit_reached: None,
+            injected_events: vec![],
+        })
     }
 
-    /// Try to reconnect all pairs of nodes which have previously become disconnected. Each pair
-    /// will only succeed with `SimulationParams::prob_reconnect` probability.
-    fn reconnect_pairs(&mut self) -> Vec<Message> {
-        let disconnected = mem::replace(&mut self.disconnected, BTreeSet::new());
-        let mut messages = vec![];
-        for pair in disconnected {
-            // Ensure both have realised they're disconnected.
-            if self.nodes[&pair.lower()].is_disconnected_from(&pair.higher()) &&
-                self.nodes[&pair.higher()].is_disconnected_from(&pair.lower()) &&
-                do_with_probability(self.params.prob_reconnect(self.phase))
-            {
-                debug!(
-                    "Node({}) and Node({}) reconnecting to each other...",
-                    pair.lower(),
-                    pair.higher()
-                );
-                messages.push(Message {
-                    sender: pair.lower(),
-                    recipient: pair.higher(),
-                    content: Connect,
-                });
-                messages.push(Message {
-                    sender: pair.higher(),
-                    recipient: pair.lower(),
-                    content: Connect,
-                });
-            } else {
-                self.disconnected.insert(pair);
-            }
-        }
-        messages
+    /// Run in soak mode: keep the network in the stable phase until `params.max_steps`,
+    /// periodically writing metrics to disk and dropping unreferenced blocks. Nodes' history is
+    /// only trimmed if they were set up with `NodeParams::compaction`.
+    pub fn enable_soak(&mut self, params: SoakParams) -> io::Result<()> {
+        self.soak = Some(Soak::new(params)?);
+        Ok(())
     }
 
-    /// Generate events to occur at the given step, and send messages for them.
-    pub fn generate_events(&mut self, step: u64) {
-        let mut events = vec![];
-        events.extend(self.event_schedule.get_events(step));
-        if self.event_schedule.is_empty() {
-            events.extend(self.random_events.get_events(
-                self.phase,
-                &self.blocks,
-                &self.nodes,
-            ));
-        }
-        trace!("events: {:?}", events);
+    /// When an invariant first fails, dump the state of the affected nodes and up to
+    /// `max_messages` of each one's most recent messages into a new directory within `dir`. If the
+    /// nodes still disagree at the end of a failed run, a non-convergence bundle is written too.
+    pub fn dump_on_failure(&mut self, dir: PathBuf, max_messages: usize) {
+        self.failure_dump = Some(FailureDump::new(dir, max_messages));
+    }
 
-        let mut ev_messages = vec![];
+    /// Write a journal of the messages each node handles, the votes it casts and the blocks it
+    /// agrees, in `layout`.
+    pub fn write_journals(&mut self, layout: JournalLayout) -> io::Result<()> {
+        self.journals = Some(Journals::new(layout)?);
+        Ok(())
+    }
 
-        for ev in events {
-            if let Some(ev) = ev.normalise(&self.nodes) {
-                ev_messages.extend(ev.broadcast(&self.nodes));
-                self.apply_event(&ev, step);
+    /// The directory the state at the first failure was dumped to, if any.
+    pub fn failure_dump_path(&self) -> Option<&Path> {
+        self.failure_dump.as_ref().and_then(FailureDump::path)
+    }
+
+    fn dump_failure(&mut self, step: u64, description: &str, affected: &[Prefix]) {
+        if let Some(ref mut dump) = self.failure_dump {
+            if let Err(err) = dump.dump(step, description, affected, &self.blocks, &self.nodes) {
+                warn!("failed to write failure dump: {}", err);
             }
         }
+    }
 
-        self.network.send(step, ev_messages);
+    /// The directory the nodes' disagreement at the end of a failed run was dumped to, if any.
+    pub fn non_convergence_dump_path(&self) -> Option<&Path> {
+        self.failure_dump.as_ref().and_then(FailureDump::non_convergence_path)
+    }
 
-        // Kill a connection between two nodes if we're past the stabilisation threshold.
-        if do_with_probability(self.params.prob_disconnect(self.phase)) {
-            let disconnect_messages = self.disconnect_pair();
+    /// Dump the nodes' disagreement at the end of a failed run, if they disagree. Returns whether
+    /// a non-convergence bundle was dumped.
+    fn dump_non_convergence(&mut self, step: u64, description: &str) -> bool {
+        let dump = match self.failure_dump {
+            Some(ref mut dump) => dump,
+            None => return false,
+        };
+        let in_flight = self.network.in_flight();
+        let schedule_tail = self.event_schedule.tail(step);
+        match dump.dump_non_convergence(
+            step,
+            description,
+            &self.blocks,
+            &self.nodes,
+            &in_flight,
+            &schedule_tail,
+        ) {
+            Ok(disagreed) => disagreed,
+            Err(err) => {
+                warn!("failed to write non-convergence dump: {}", err);
+                true
+            }
+        }
+    }
+
+    /// Pause at the next step boundary whenever the process receives SIGINT or SIGUSR1, writing
+    /// the chain to `pause-step-<step>.chain` within `dir`, from which `try_from_chain` can start
+    /// another run. Only the chain is written, not a checkpoint of the network's state. Unless a
+    /// debugger is set, the run then waits for SIGUSR1 to resume or SIGINT to stop, in which case
+    /// it fails with `Error::Stopped`.
+    pub fn pause_on_signal(&mut self, dir: PathBuf) -> io::Result<()> {
+        pause::install_handlers()?;
+        self.pause_on_request(dir);
+        Ok(())
+    }
+
+    /// Pause as `pause_on_signal` does, but only when `pause::request_pause` is called, leaving
+    /// the process's signal handlers alone.
+    pub fn pause_on_request(&mut self, dir: PathBuf) {
+        self.pause_dir = Some(dir);
+    }
+
+    /// Apply `event` at the next step, along with the events drawn for it. A debugger can use
+    /// this to act on the paused network.
+    pub fn inject_event(&mut self, event: Event) {
+        self.injected_events.push(event);
+    }
+
+    /// Hand the simulation to `debugger` whenever it pauses, instead of waiting for a signal.
+    pub fn set_debugger<F>(&mut self, debugger: F)
+    where
+        F: FnMut(&mut Simulation<N>) -> PauseAction + 'static,
+    {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    fn pause_at(&mut self, step: u64) -> PauseAction {
+        if let Some(ref dir) = self.pause_dir {
+            let path = dir.join(format!("pause-step-{}.chain", step));
+            match fs::create_dir_all(dir).and_then(|_| self.write_chain(&path)) {
+                Ok(()) => info!("Paused at step {}, chain written to {}", step, path.display()),
+                Err(err) => warn!("Paused at step {}, but couldn't write the chain: {}", step, err),
+            }
+        }
+        let action = match self.debugger.take() {
+            Some(mut debugger) => {
+                let action = debugger(self);
+                self.debugger = Some(debugger);
+                action
+            }
+            None => {
+                info!("Send SIGUSR1 to resume, or SIGINT to stop");
+                match pause::wait_for_signal() {
+                    Signal::User1 => PauseAction::Resume,
+                    Signal::Interrupt => PauseAction::Stop,
+                }
+            }
+        };
+        // Signals sent while paused were meant for the pause itself.
+        let _ = pause::take_signal();
+        action
+    }
+
+    /// Call `hook` with the summary of every step once it has been run.
+    ///
+    /// Hooks aren't part of checkpoints, so they also see the steps re-run by `rewind`.
+    pub fn add_hook<F: FnMut(&StepSummary) + 'static>(&mut self, hook: F) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Write every node's activity counters to `path` as CSV when the run finishes.
+    pub fn write_metrics_to(&mut self, path: PathBuf) {
+        self.metrics_path = Some(path);
+    }
+
+    /// Track a logical clock for every node and the cause of every message sent, to trace how
+    /// blocks came to be agreed.
+    ///
+    /// Messages are handled in batches when using several threads, so everything a node sends in
+    /// response to a batch is attributed to the last message in it.
+    pub fn track_causality(&mut self) {
+        self.network.track_causality();
+    }
+
+    /// Write the messages which carried votes for `block`, and the messages which led to them, as
+    /// a graph in the DOT language. Fails if causality wasn't being tracked.
+    pub fn write_causal_graph(&self, block: BlockId, path: &Path) -> io::Result<()> {
+        let causality = self.network.causality().ok_or_else(|| {
+            io::Error::other("causality wasn't tracked")
+        })?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        causality.write_agreement_graph(block, &mut writer)?;
+        writer.flush()
+    }
+
+    /// The blocks which the live nodes consider valid, with the blocks current at any of them
+    /// as its routing table.
+    pub fn chain(&self) -> Chain {
+        let mut current = BTreeSet::new();
+        for node in self.nodes.values() {
+            current.extend(node.current_blocks().iter().cloned());
+        }
+        Chain::new(
+            &self.blocks,
+            self.nodes.values().map(NodeTrait::valid_blocks),
+            &current,
+        )
+    }
+
+    /// Write the chain to a file, for `try_from_chain` to start another simulation from.
+    pub fn write_chain(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.chain().write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Record the set of blocks which are current at any node after every step.
+    pub fn record_agreed_blocks(&mut self) {
+        self.agreed_history = Some(vec![]);
+    }
+
+    /// The blocks current at any node after each step, if recording was enabled.
+    pub fn agreed_history(&self) -> Option<&[BTreeSet<BlockId>]> {
+        self.agreed_history.as_ref().map(|history| &history[..])
+    }
+
+    /// Handle the messages delivered at each step on `num_threads` threads.
+    ///
+    /// Each node handles its own messages in the order they were delivered, and the responses are
+    /// sent in order of recipient, so the outcome is the same as for a single thread. This relies
+    /// on nodes not drawing random numbers while handling messages, which debug builds check.
+    pub fn set_num_threads(&mut self, num_threads: usize) -> Result<()> {
+        self.thread_pool = if num_threads > 1 {
+            let pool = ThreadPoolBuilder::new().num_threads(num_threads).build().map_err(
+                |error| {
+                    Error::Config(format!("couldn't start {} threads: {}", num_threads, error))
+                },
+            )?;
+            Some(pool)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// Record the lifecycle state transitions of every node.
+    pub fn record_lifecycles(&mut self) {
+        self.lifecycles = Some(Lifecycles::default());
+    }
+
+    /// Nodes' lifecycle timelines, if recording was enabled.
+    pub fn lifecycles(&self) -> Option<&Lifecycles> {
+        self.lifecycles.as_ref()
+    }
+
+    /// Record the sections' prefixes and sizes whenever they change.
+    pub fn record_prefix_tree(&mut self) {
+        self.prefix_tree = Some(PrefixTreeHistory::default());
+    }
+
+    /// History of the prefix tree, if recording was enabled.
+    pub fn prefix_tree(&self) -> Option<&PrefixTreeHistory> {
+        self.prefix_tree.as_ref()
+    }
+
+    /// Record the membership of every section whenever it changes, as a trace of global states.
+    pub fn record_state_trace(&mut self) {
+        self.state_trace = Some(StateTrace::default());
+    }
+
+    /// Trace of global states, if recording was enabled.
+    pub fn state_trace(&self) -> Option<&StateTrace> {
+        self.state_trace.as_ref()
+    }
+
+    /// Record the arrivals at each node of the votes for blocks competing to follow the same
+    /// block.
+    pub fn record_vote_races(&mut self) {
+        self.vote_races = Some(VoteRaces::default());
+    }
+
+    /// Vote races, if recording was enabled.
+    pub fn vote_races(&self) -> Option<&VoteRaces> {
+        self.vote_races.as_ref()
+    }
+
+    /// Check the run against `assertions` at the end of each step.
+    pub fn check_assertions(&mut self, assertions: Vec<Assertion>) {
+        self.assertions = Some(AssertionChecker::new(assertions));
+    }
+
+    /// Descriptions of the assertions which haven't held so far, or of none if they're not
+    /// being checked.
+    pub fn failed_assertions(&self) -> Vec<String> {
+        self.assertions
+            .as_ref()
+            .map(AssertionChecker::failures)
+            .unwrap_or_default()
+    }
+
+    /// Split the nodes into cohorts running with their own parameters, the rest keeping
+    /// `node_params`. Nodes already running switch to their cohort's parameters straight away,
+    /// though anything set up when they were created, like their clock skew, stays as it was.
+    pub fn assign_cohorts(&mut self, cohorts: Vec<Cohort>) -> Result<()> {
+        let cohorts = Cohorts::new(cohorts)?;
+        for (name, node) in &mut self.nodes {
+            node.set_params(cohorts.params_for(*name, &self.node_params));
+        }
+        self.cohorts = Some(cohorts);
+        Ok(())
+    }
+
+    /// The cohorts nodes are split into, if any.
+    pub fn cohorts(&self) -> Option<&Cohorts> {
+        self.cohorts.as_ref()
+    }
+
+    /// Record every block becoming valid on every node, noting blocks which some nodes still
+    /// haven't accepted `max_disagreement` steps after the first did.
+    pub fn audit_validity(&mut self, max_disagreement: u64) {
+        self.validity = Some(ValidityAudit::new(max_disagreement));
+    }
+
+    /// Audit trail of blocks becoming valid, if recording was enabled.
+    pub fn validity_audit(&self) -> Option<&ValidityAudit> {
+        self.validity.as_ref()
+    }
+
+    /// Record the membership of every section agreed during the run, with the step at which it
+    /// was agreed.
+    pub fn record_membership(&mut self) {
+        self.membership = Some(MembershipHistory::default());
+    }
+
+    /// History of section membership, if recording was enabled.
+    pub fn membership_history(&self) -> Option<&MembershipHistory> {
+        self.membership.as_ref()
+    }
+
+    /// Stop the run once the blocks pending for some prefix have stayed the same for `max_steps`
+    /// steps with no votes in flight, dumping the state if failure dumps are enabled.
+    pub fn watch_for_livelock(&mut self, max_steps: u64) {
+        self.livelock = Some(LivelockWatchdog::new(max_steps));
+    }
+
+    /// The livelock which stopped the run, if any.
+    pub fn livelock(&self) -> Option<&Livelock> {
+        self.livelock.as_ref().and_then(LivelockWatchdog::detected)
+    }
+
+    /// Compute every section's health score each step, logging it at debug level.
+    pub fn monitor_health(&mut self) {
+        if self.health.is_none() {
+            self.health = Some(HealthMonitor::default());
+        }
+    }
+
+    /// Compute every section's health score each step, and fail the run once a section's score
+    /// stays below `threshold` for `max_steps` steps, dumping the state if failure dumps are
+    /// enabled.
+    pub fn alert_on_low_health(&mut self, threshold: f64, max_steps: u64) {
+        self.health = Some(HealthMonitor::with_alert(threshold, max_steps));
+    }
+
+    /// Health of each section after the latest step, if being computed.
+    pub fn section_health(&self) -> Option<&BTreeMap<Prefix, SectionHealth>> {
+        self.health.as_ref().map(HealthMonitor::latest)
+    }
+
+    /// The first section whose health stayed too low, if any.
+    pub fn low_health(&self) -> Option<&LowHealth> {
+        self.health.as_ref().and_then(HealthMonitor::detected)
+    }
+
+    /// Mark nodes whose routing table differs from their section's consensus for more than
+    /// `max_steps` steps as divergent. With `vote_out`, the rest of the section then votes each
+    /// divergent node out.
+    pub fn detect_divergence(&mut self, max_steps: u64, vote_out: bool) {
+        self.divergence = Some(DivergenceMonitor::new(max_steps, vote_out));
+    }
+
+    /// Nodes marked divergent so far, if being detected.
+    pub fn divergence_report(&self) -> Option<&DivergenceReport> {
+        self.divergence.as_ref().map(DivergenceMonitor::report)
+    }
+
+    /// Count the candidates nodes still hold more than `grace` steps after their join timed out,
+    /// and the connections to names no node's routing table lists. With `purge`, nodes forget
+    /// those found.
+    pub fn audit_hygiene(&mut self, grace: u64, purge: bool) {
+        self.hygiene = Some(HygieneAudit::new(grace, purge));
+    }
+
+    pub fn hygiene_report(&self) -> Option<&HygieneReport> {
+        self.hygiene.as_ref().map(HygieneAudit::report)
+    }
+
+    /// Count the votes to remove section members, and how many of them were against nodes which
+    /// were still running.
+    pub fn record_drops(&mut self) {
+        self.drops = Some(DropTracker::default());
+    }
+
+    /// Votes to remove section members so far, if recording was enabled.
+    pub fn drops(&self) -> Option<&DropTracker> {
+        self.drops.as_ref()
+    }
+
+    /// Replay the joins and leaves of `trace` instead of drawing random ones, mapping each unit of
+    /// trace time onto `steps_per_unit` steps from the step the replay starts at. Phases still
+    /// end as usual, so the growth phase only ends if the trace grows the network enough, and
+    /// entries past the end of the stable phase aren't replayed.
+    pub fn replay_churn_trace(&mut self, trace: ChurnTrace, steps_per_unit: f64) -> Result<()> {
+        self.churn_trace = Some(TraceReplay::new(trace, steps_per_unit)?);
+        Ok(())
+    }
+
+    pub fn trace_stats(&self) -> Option<&ReplayStats> {
+        self.churn_trace.as_ref().map(TraceReplay::stats)
+    }
+
+    /// Steer the network's size along `trajectory` during the growth and stable phases, instead of
+    /// drawing random joins and leaves, joining or removing a node each step with probability
+    /// `gain` per node of difference from the target. The trajectory starts with the growth phase,
+    /// which only ends once it reaches `grow_complete` nodes. A churn trace being replayed takes
+    /// precedence.
+    pub fn target_network_size(&mut self, trajectory: SizeTrajectory, gain: f64) -> Result<()> {
+        self.size_controller = Some(SizeController::new(trajectory, gain)?);
+        Ok(())
+    }
+
+    pub fn size_tracking_stats(&self) -> Option<&TrackingStats> {
+        self.size_controller.as_ref().map(SizeController::stats)
+    }
+
+    /// Count the messages sent per block agreed, by kind of message and by section size, in
+    /// buckets of `bucket_width` sizes.
+    pub fn track_message_complexity(&mut self, bucket_width: usize) -> Result<()> {
+        let mut tracker = ComplexityTracker::new(bucket_width)?;
+        tracker.skip_current(&self.nodes);
+        self.complexity = Some(tracker);
+        self.network.count_sent();
+        Ok(())
+    }
+
+    pub fn message_complexity(&self) -> Option<&ComplexityTracker> {
+        self.complexity.as_ref()
+    }
+
+    /// Launch a Sybil attack during the simulation. Attacking joins happen in addition to any
+    /// scheduled or random events.
+    pub fn sybil_attack(&mut self, attack: SybilAttack) {
+        self.sybil = Some(SybilTracker::new(attack));
+    }
+
+    /// Outcome of the Sybil attack so far, if one was launched.
+    pub fn sybil_report(&self) -> Option<&SybilReport> {
+        self.sybil.as_ref().map(SybilTracker::report)
+    }
+
+    /// Kill bootstrap proxies with the given probability after they forward a join, before the
+    /// joining node has been sent any bootstrap messages. Only has an effect with a bootstrap
+    /// strategy which uses proxies.
+    ///
+    /// Running the simulation fails if any node which lost its proxy neither joins nor shuts
+    /// itself down.
+    pub fn fail_proxies(&mut self, prob_fail: f64) {
+        self.proxy_failures = Some(ProxyFailures::new(prob_fail));
+    }
+
+    /// What became of the nodes whose proxies were killed, if proxy failures were enabled.
+    pub fn proxy_failure_report(&self) -> Option<&ProxyFailureReport> {
+        self.proxy_failures.as_ref().map(ProxyFailures::report)
+    }
+
+    /// Kill every node under a prefix within a window of steps, and check that the survivors
+    /// recover by agreeing on sections which cover its namespace. The stable phase lasts at least
+    /// until the last of the nodes has been killed.
+    ///
+    /// Running the simulation fails if the namespace is still orphaned at the end.
+    pub fn section_outage(&mut self, outage: SectionOutage) {
+        self.outage = Some(OutageTracker::new(outage));
+    }
+
+    /// Outcome of the section outage so far, if one was scheduled.
+    pub fn outage_report(&self) -> Option<&OutageReport> {
+        self.outage.as_ref().map(OutageTracker::report)
+    }
+
+    /// Steps at which nodes' own prefixes failed to cover the namespace exactly once, despite
+    /// no messages being in flight.
+    pub fn coverage_violations(&self) -> &[(u64, CoverageViolation)] {
+        &self.coverage.violations
+    }
+
+    /// Conflicting valid blocks held by nodes so far.
+    pub fn conflicts(&self) -> &ConflictTracker {
+        &self.conflicts
+    }
+
+    /// The step the simulation has reached.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    /// Identifier of this run, as written into its logs and other outputs.
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Outcomes of the join attempts made so far.
+    pub fn join_stats(&self) -> &JoinStats {
+        &self.join_stats
+    }
+
+    /// Progress of secure joins, when joining nodes are relocated by their home sections.
+    pub fn secure_join_stats(&self) -> &SecureJoinStats {
+        self.secure_joins.stats()
+    }
+
+    /// How well equivocating nodes have been caught, when nodes look for equivocation.
+    pub fn equivocation_report(&self) -> &EquivocationReport {
+        self.equivocations.report()
+    }
+
+    /// Number of messages dropped by the network for exceeding the message TTL.
+    pub fn messages_expired(&self) -> u64 {
+        self.network.messages_expired()
+    }
+
+    /// Make the named node, or the node joining with that name, reachable only over connections
+    /// it initiates, as if it were behind a NAT.
+    pub fn block_inbound(&mut self, name: Name) {
+        self.network.block_inbound(name);
+        if let Some(node) = self.nodes.get_mut(&name) {
+            node.block_inbound();
+        }
+    }
+
+    /// Number of messages dropped by the network for being sent to a node blocking them.
+    pub fn messages_blocked(&self) -> u64 {
+        self.network.messages_blocked()
+    }
+
+    /// Number of copies of section messages dropped by the network, each recipient having been
+    /// sent the message by another member of the sending section.
+    pub fn section_duplicates(&self) -> u64 {
+        self.network.section_duplicates()
+    }
+
+    /// Number of messages dropped by the network because a scenario forced their drop.
+    pub fn messages_force_dropped(&self) -> u64 {
+        self.network.messages_force_dropped()
+    }
+
+    /// Number of messages dropped by the network for crossing a partition between prefixes.
+    pub fn messages_partitioned(&self) -> u64 {
+        self.network.messages_partitioned()
+    }
+
+    /// Forced drops which no message has matched yet, as (sender, recipient, kind of message).
+    pub fn pending_forced_drops(&self) -> &[(Name, Name, String)] {
+        self.network.pending_forced_drops()
+    }
+
+    /// Number of nodes with blocked inbound connections which shut down, having failed to join
+    /// or been dropped from their section by peers unable to reach them.
+    pub fn unreachable_shutdowns(&self) -> u64 {
+        self.unreachable_shutdowns
+    }
+
+    /// Distribution of the number of joining nodes waiting on each section.
+    pub fn candidate_stats(&self) -> &CandidateStats {
+        &self.candidate_stats
+    }
+
+    /// How often sections were stalled by members waiting for their handover, if it takes any
+    /// steps.
+    pub fn handover_stats(&self) -> &HandoverStats {
+        &self.handover_stats
+    }
+
+    /// How long pairs of nodes took to be reconnected after being disconnected, not counting
+    /// pairs which the nodes reconnected themselves.
+    pub fn reconnect_stats(&self) -> &ReconnectStats {
+        &self.reconnect_stats
+    }
+
+    /// Metrics of the connection graph between the nodes as it stands.
+    pub fn connectivity(&self) -> ConnectivityMetrics {
+        ConnectivityMetrics::of(&self.nodes, &self.blocks)
+    }
+
+    /// Count the sections in which too few members are voting to reach a quorum.
+    fn sample_handovers(&mut self, step: u64) {
+        let mut sections = BTreeSet::new();
+        for node in self.nodes.values() {
+            for block in node.our_current_blocks(&self.blocks) {
+                let _ = sections.insert(block.get_id());
+            }
+        }
+        for block in self.blocks.block_contents(&sections) {
+            let voting = block
+                .members
+                .iter()
+                .filter(|name| self.nodes.get(name).is_some_and(|node| node.is_voting(step)))
+                .count();
+            self.handover_stats.section_steps += 1;
+            if voting < quorum(block.members.len()) {
+                self.handover_stats.stalled_section_steps += 1;
+            }
+        }
+    }
+
+    /// Relocations applied so far, in order.
+    pub fn relocations(&self) -> &[Relocation] {
+        &self.relocations
+    }
+
+    /// Statistics summed over all nodes, including those that have left.
+    pub fn node_stats(&self) -> NodeStats {
+        let mut total = NodeStats::default();
+        for (_, stats) in self.per_node_stats() {
+            total += stats;
+        }
+        total
+    }
+
+    /// The running node with the given name, if any.
+    pub fn node(&self, name: &Name) -> Option<&N> {
+        self.nodes.get(name)
+    }
+
+    /// Statistics of each node, including nodes which are no longer part of the simulation.
+    pub fn per_node_stats(&self) -> BTreeMap<Name, NodeStats> {
+        let mut result = self.dead_node_stats.clone();
+        for (name, node) in &self.nodes {
+            *result.entry(*name).or_default() += node.stats();
+        }
+        result
+    }
+
+    /// All blocks known to the simulation.
+    pub fn blocks(&self) -> &Blocks {
+        &self.blocks
+    }
+
+    /// Blocks which must be kept around even if no node refers to them, including those which
+    /// recorders look up once the run is over.
+    fn root_blocks(&self) -> BTreeSet<BlockId> {
+        let mut roots = self.genesis_set.clone();
+        if let Some(ref disjoint) = self.disjoint_networks {
+            for (genesis, _) in &disjoint.networks {
+                roots.extend(genesis.iter().cloned());
+            }
+        }
+        if let Some(ref vote_races) = self.vote_races {
+            roots.extend(vote_races.referenced_blocks());
+        }
+        roots
+    }
+
+    fn apply_add_node(&mut self, joining: Name, step: u64) {
+        // Make the node active, and let it build its way up from the genesis block(s).
+        let genesis_set = self.genesis_set.clone();
+        let params = match self.cohorts {
+            Some(ref cohorts) => cohorts.params_for(joining, &self.node_params),
+            None => self.node_params.clone(),
+        };
+        // The node starts out connected to the genesis members, so the ones which have left since
+        // tell it so, as they told everyone else when they left.
+        let departed: Vec<Message> = genesis_set
+            .iter()
+            .flat_map(|id| id.into_block(&self.blocks).members.iter())
+            .filter(|member| !self.nodes.contains_key(member))
+            .map(|&member| {
+                Message {
+                    sender: member,
+                    recipient: joining,
+                    content: Disconnect,
+                }
+            })
+            .collect();
+        let mut node = N::new(joining, &self.blocks, genesis_set, params, step);
+        if self.network.is_inbound_blocked(&joining) {
+            node.block_inbound();
+        }
+        self.nodes.insert(joining, node);
+        self.network.send(step, departed);
+        self.joining.insert(joining, step);
+        if self.node_params.secure_join {
+            self.secure_joins.started(joining, step);
+        }
+    }
+
+    /// Record the outcome of any join attempts that have finished.
+    fn update_joins(&mut self, step: u64) {
+        let nodes = &self.nodes;
+        let blocks = &self.blocks;
+        let join_stats = &mut self.join_stats;
+        let secure_joins = &mut self.secure_joins;
+        let secure_join = self.node_params.secure_join;
+        let cohorts = &mut self.cohorts;
+        self.joining.retain(|name, &mut start_step| match nodes.get(name) {
+            Some(node) => {
+                if node.our_current_blocks(blocks).is_empty() {
+                    return true;
+                }
+                join_stats.joined += 1;
+                join_stats.total_latency += step - start_step;
+                if secure_join {
+                    secure_joins.joined(*name, step);
+                }
+                if let Some(ref mut cohorts) = *cohorts {
+                    cohorts.record_join(*name, Some(step - start_step));
+                }
+                false
+            }
+            None => {
+                join_stats.rejected += 1;
+                if let Some(ref mut cohorts) = *cohorts {
+                    cohorts.record_join(*name, None);
+                }
+                false
+            }
+        });
+
+        // Attribute each remaining joining node to the most specific section it matches.
+        let mut num_candidates: BTreeMap<Prefix, usize> = nodes
+            .values()
+            .flat_map(|node| blocks.block_contents(node.current_blocks()))
+            .map(|block| (block.prefix, 0))
+            .collect();
+        for name in self.joining.keys() {
+            let section = num_candidates
+                .keys()
+                .filter(|prefix| prefix.matches(*name))
+                .max_by_key(|prefix| prefix.bit_count())
+                .cloned();
+            if let Some(section) = section {
+                *num_candidates.entry(section).or_insert(0) += 1;
+            }
+        }
+        for count in num_candidates.values() {
+            self.candidate_stats.record(*count);
+        }
+    }
+
+    fn apply_remove_node(&mut self, leaving_node: Name) {
+        debug!("Node({}): dying...", leaving_node);
+
+        // Remove the node.
+        if let Some(node) = self.nodes.remove(&leaving_node) {
+            *self.dead_node_stats.entry(leaving_node).or_default() += node.stats();
+        }
+        self.secure_joins.left(leaving_node);
+
+        // Remove any "disconnections" associated with this node.
+        self.disconnected.retain(|pair, _| {
+            pair.lower() != leaving_node && pair.higher() != leaving_node
+        });
+    }
+
+    fn apply_relocation(&mut self, node: Name, to: Name, step: u64) {
+        debug!("Node({}): relocating to {}", node, to);
+        // A node relocated while still joining hasn't failed to join.
+        let _ = self.joining.remove(&node);
+        self.apply_remove_node(node);
+        self.apply_add_node(to, step);
+        if let Some(ref mut lifecycles) = self.lifecycles {
+            lifecycles.relocated(to, step);
+        }
+        self.relocations.push(Relocation {
+            step,
+            from: node,
+            to,
+        });
+    }
+
+    fn apply_event(&mut self, event: &Event, step: u64) {
+        if event.changes_membership() {
+            self.num_churn_events += 1;
+        }
+        match *event {
+            Event::AddNode(name) => self.apply_add_node(name, step),
+            Event::RemoveNode(name) => self.apply_remove_node(name),
+            Event::Relocate { node, to } => {
+                debug!("Node({}): asking its section to relocate it to {}", node, to);
+                for member in self.nodes.values_mut() {
+                    member.relocate(node, to);
+                }
+            }
+            Event::Rejoin { node, after } => {
+                debug!("Node({}): leaving, to rejoin after {} steps", node, after);
+                self.apply_remove_node(node);
+                self.rejoins.entry(step + after).or_default().push(node);
+            }
+            Event::SetFault {
+                node,
+                fault,
+                enabled,
+            } => {
+                if let Some(node) = self.nodes.get_mut(&node) {
+                    node.set_fault(fault, enabled);
+                }
+            }
+            Event::DropNextMessage {
+                from,
+                to,
+                ref content_kind,
+            } => self.network.drop_next(from, to, content_kind.clone()),
+            Event::ForgetVotes { node, fraction } => {
+                if let Some(node) = self.nodes.get_mut(&node) {
+                    node.forget_votes(fraction, step);
+                }
+            }
+            Event::SectionSplit(prefix) => {
+                for (_, node) in self.nodes.iter_mut().filter(|&(name, _)| prefix.matches(*name)) {
+                    node.force_split(prefix);
+                }
+            }
+            Event::SectionMerge(prefix) => {
+                for (_, node) in self.nodes.iter_mut().filter(|&(name, _)| prefix.matches(*name)) {
+                    node.force_merge(prefix);
+                }
+            }
+            Event::PartitionPrefixes { a, b, partitioned } => {
+                let action = if partitioned { "Starting" } else { "Healing" };
+                debug!("{} the partition between {:?} and {:?}", action, a, b);
+                self.network.set_partition(a, b, partitioned);
+                if partitioned {
+                    let messages = self.disconnect_all(step, |x, y| {
+                        (a.matches(x) && b.matches(y)) || (a.matches(y) && b.matches(x))
+                    });
+                    self.network.send(step, messages);
+                }
+            }
+            Event::RewireConnections(prefix) => {
+                debug!("Rewiring the connections of nodes under {:?}", prefix);
+                let messages =
+                    self.disconnect_all(step, |x, y| prefix.matches(x) || prefix.matches(y));
+                self.network.send(step, messages);
+            }
+            Event::RemoveNodeFrom(_) |
+            Event::RelocateFrom(..) |
+            Event::RejoinFrom(..) |
+            Event::SetFaultIn { .. } |
+            Event::DropNextMessageIn { .. } |
+            Event::ForgetVotesIn { .. } => panic!("normalise {:?} before applying", event),
+        }
+    }
+
+    /// Kill a connection between a pair of nodes which aren't already disconnected.
+    fn disconnect_pair(&mut self, step: u64) -> Vec<Message> {
+        let pair = {
+            let connected_pairs = self.nodes
+                .keys()
+                .cloned()
+                .tuple_combinations()
+                .filter(|&(ref n1, ref n2)| {
+                    !self.nodes[n1].is_disconnected_from(n2) &&
+                        !self.nodes[n2].is_disconnected_from(n1)
+                })
+                .map(|(n1, n2)| DisconnectedPair::new(n1, n2));
+
+            match sample_single(connected_pairs) {
+                Some(x) => x,
+                None => return vec![],
+            }
+        };
+
+        self.disconnect(pair, step)
+    }
+
+    /// Disconnect every connected pair of nodes for which `select` is true.
+    fn disconnect_all<F>(&mut self, step: u64, select: F) -> Vec<Message>
+    where
+        F: Fn(Name, Name) -> bool,
+    {
+        let pairs: Vec<DisconnectedPair> = self.nodes
+            .keys()
+            .cloned()
+            .tuple_combinations()
+            .filter(|&(n1, n2)| {
+                select(n1, n2) && !self.nodes[&n1].is_disconnected_from(&n2) &&
+                    !self.nodes[&n2].is_disconnected_from(&n1)
+            })
+            .map(|(n1, n2)| DisconnectedPair::new(n1, n2))
+            .collect();
+        pairs.into_iter().flat_map(|pair| self.disconnect(pair, step)).collect()
+    }
+
+    /// Break the connection between a pair of nodes, leaving them to reconnect later.
+    fn disconnect(&mut self, pair: DisconnectedPair, step: u64) -> Vec<Message> {
+        debug!(
+            "Node({}) and Node({}) disconnecting from each other...",
+            pair.lower(),
+            pair.higher()
+        );
+        let messages = vec![
+            Message {
+                sender: pair.lower(),
+                recipient: pair.higher(),
+                content: Disconnect,
+            },
+            Message {
+                sender: pair.higher(),
+                recipient: pair.lower(),
+                content: Disconnect,
+            },
+        ];
+
+        let reconnection = Reconnection {
+            since: step,
+            failed: 0,
+            next_attempt: None,
+        };
+        let _ = self.disconnected.insert(pair, reconnection);
+        messages
+    }
+
+    /// Try to reconnect all pairs of nodes which have previously become disconnected, when
+    /// `SimulationParams::reconnect` says they should.
+    fn reconnect_pairs(&mut self, step: u64) -> Vec<Message> {
+        if !self.params.reconnects(self.phase) {
+            return vec![];
+        }
+        let disconnected = mem::take(&mut self.disconnected);
+        let mut messages = vec![];
+        for (pair, mut reconnection) in disconnected {
+            if self.network.is_partitioned(pair.lower(), pair.higher()) {
+                let _ = self.disconnected.insert(pair, reconnection);
+                continue;
+            }
+            // Ensure both have realised they're disconnected.
+            let noticed = self.nodes[&pair.lower()].is_disconnected_from(&pair.higher()) &&
+                self.nodes[&pair.higher()].is_disconnected_from(&pair.lower());
+            let reconnect = noticed && self.attempt_reconnect(&mut reconnection, step);
+            if reconnect {
+                self.reconnect_stats.record(step - reconnection.since);
+                debug!(
+                    "Node({}) and Node({}) reconnecting to each other...",
+                    pair.lower(),
+                    pair.higher()
+                );
+                messages.push(Message {
+                    sender: pair.lower(),
+                    recipient: pair.higher(),
+                    content: Connect,
+                });
+                messages.push(Message {
+                    sender: pair.higher(),
+                    recipient: pair.lower(),
+                    content: Connect,
+                });
+            } else {
+                let _ = self.disconnected.insert(pair, reconnection);
+            }
+        }
+        messages
+    }
+
+    /// Whether a pair which has noticed its disconnection reconnects at `step`.
+    fn attempt_reconnect(&self, reconnection: &mut Reconnection, step: u64) -> bool {
+        let backoff = match self.params.reconnect {
+            ReconnectModel::Constant(prob) => return do_with_probability(prob),
+            ReconnectModel::Backoff(ref backoff) => backoff,
+        };
+        if reconnection.next_attempt.is_some_and(|next_attempt| step < next_attempt) {
+            return false;
+        }
+        if do_with_probability(backoff.prob_success) {
+            return true;
+        }
+        reconnection.next_attempt = Some(step + backoff.delay(reconnection.failed));
+        reconnection.failed += 1;
+        false
+    }
+
+    /// Connect the disjoint networks to each other, if it's time to do so.
+    ///
+    /// Every surviving node learns the genesis blocks of the other networks, and is sent a
+    /// bootstrap message by every surviving node of the other networks. From there on the
+    /// usual rules for computing current blocks decide how the networks reconcile.
+    fn connect_networks(&mut self, step: u64) {
+        match self.disjoint_networks {
+            Some(ref disjoint) if disjoint.connect_step == step => (),
+            _ => return,
+        }
+        let disjoint = self.disjoint_networks.take().unwrap();
+
+        info!("Connecting {} disjoint networks", disjoint.networks.len());
+
+        let mut messages = vec![];
+        for (i, network_i) in disjoint.networks.iter().enumerate() {
+            for (j, network_j) in disjoint.networks.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                let (ref genesis_j, ref members_j) = *network_j;
+                for receiver in &network_i.1 {
+                    if let Some(node) = self.nodes.get_mut(receiver) {
+                        node.learn_blocks(genesis_j);
+                    }
+                    for sender in members_j.iter().filter(|n| self.nodes.contains_key(n)) {
+                        messages.push(self.nodes[sender].construct_bootstrap_msg(*receiver));
+                    }
+                }
+            }
+        }
+
+        self.network.send(step, messages);
+    }
+
+    /// Generate events to occur at the given step, and send messages for them.
+    pub fn generate_events(&mut self, step: u64) {
+        self.connect_networks(step);
+
+        let events = random::in_stream(Stream::Events, || self.draw_events(step));
+        let mut ev_messages = vec![];
+        for ev in events {
+            let normalised = random::in_stream(Stream::Events, || {
+                ev.normalise(&self.nodes).map(|ev| {
+                    let messages = ev.broadcast(&self.nodes, &self.params.bootstrap);
+                    (ev, messages)
+                })
+            });
+            if let Some((ev, messages)) = normalised {
+                ev_messages.extend(messages);
+                self.apply_event(&ev, step);
+            }
+        }
+
+        self.network.send(step, ev_messages);
+
+        // Kill a connection between two nodes if we're past the stabilisation threshold.
+        if do_with_probability(self.params.prob_disconnect(self.phase)) {
+            let disconnect_messages = self.disconnect_pair(step);
             self.network.send(step, disconnect_messages);
         }
 
         // Try to reconnect any previously-disconnected pairs.
-        let reconnect_messages = self.reconnect_pairs();
+        let reconnect_messages = self.reconnect_pairs(step);
         self.network.send(step, reconnect_messages);
     }
 
-    /// Run the simulation, returning Ok iff the network was consistent upon termination.
-    pub fn run(&mut self) -> Result<BTreeMap<Prefix, Block>, [u32; 4]> {
-        let max_extra_steps = 1000;
-        let mut no_op_step_count = 0;
-
-        for step in 0.. {
-            // Generate events unless we're in the finishing phase, in which case we let the event
-            // queue empty out.
-            if let Phase::Finishing { since_step } = self.phase {
-                if step > since_step + max_extra_steps {
-                    break;
+    /// The scheduled, random and injected events for `step`.
+    fn draw_events(&mut self, step: u64) -> Vec<Event> {
+        let mut events = vec![];
+        events.extend(self.event_schedule.get_events(step));
+        events.extend(self.event_schedule.get_triggered_events(
+            step,
+            self.phase,
+            self.nodes.len(),
+        ));
+        if let Some(ref mut replay) = self.churn_trace {
+            if !matches!(self.phase, Phase::Finishing { .. }) {
+                events.extend(replay.get_events(
+                    step,
+                    &self.random_events,
+                    &self.blocks,
+                    &self.nodes,
+                ));
+            }
+        } else if let (Some(controller), Phase::Growth | Phase::Stable { .. }) =
+            (self.size_controller.as_mut(), self.phase)
+        {
+            events.extend(controller.get_events(
+                step,
+                &self.random_events,
+                &self.blocks,
+                &self.nodes,
+            ));
+        } else if self.event_schedule.is_empty() {
+            events.extend(self.random_events.get_events(
+                self.phase,
+                &self.blocks,
+                &self.nodes,
+            ));
+        }
+        if let Some(ref mut sybil) = self.sybil {
+            events.extend(sybil.get_events(step));
+        }
+        if let Some(ref mut proxy_failures) = self.proxy_failures {
+            events.extend(proxy_failures.get_events(&self.nodes));
+        }
+        if let Some(ref mut outage) = self.outage {
+            events.extend(outage.get_events(step, &self.nodes));
+        }
+        events.append(&mut self.injected_events);
+        if let Some(names) = self.rejoins.remove(&step) {
+            events.extend(names.into_iter().map(Event::AddNode));
+        }
+        trace!("events: {:?}", events);
+        events
+    }
+
+    /// Names of the nodes in the order they're to be processed at `step`.
+    fn processing_order(&self, step: u64) -> Vec<Name> {
+        let mut order: Vec<Name> = self.nodes.keys().cloned().collect();
+        match self.params.processing_order {
+            ProcessingOrder::ByName => (),
+            ProcessingOrder::Random => shuffle(&mut order),
+            ProcessingOrder::RoundRobin => {
+                if !order.is_empty() {
+                    let len = order.len();
+                    order.rotate_left((step % len as u64) as usize);
                 }
-                if self.network.queue_is_empty() {
-                    if no_op_step_count > self.node_params.max_timeout() {
-                        break;
-                    } else {
-                        no_op_step_count += 1;
-                    }
+            }
+        }
+        order
+    }
+
+    /// Sort delivered messages by their recipient's place in `order`, keeping messages for the
+    /// same recipient in the order they were delivered.
+    fn in_processing_order(&self, mut delivered: Vec<Message>, order: &[Name]) -> Vec<Message> {
+        if self.params.processing_order == ProcessingOrder::ByName {
+            return delivered;
+        }
+        let rank: BTreeMap<Name, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (*name, index))
+            .collect();
+        delivered.sort_by_key(|message| rank.get(&message.recipient).cloned());
+        delivered
+    }
+
+    /// Have each node handle its delivered messages, spreading the nodes across the threads of
+    /// `pool`. Their responses are sent in `order`, whichever thread handled them.
+    ///
+    /// The threads don't share our random number streams, so nodes mustn't draw from them here.
+    fn handle_messages_parallel(
+        &mut self,
+        pool: &ThreadPool,
+        delivered: Vec<Message>,
+        order: &[Name],
+        step: u64,
+    ) {
+        let mut inboxes: BTreeMap<Name, Vec<Message>> = BTreeMap::new();
+        for message in delivered {
+            if self.nodes.contains_key(&message.recipient) {
+                self.network.handling(&message);
+                inboxes.entry(message.recipient).or_default().push(message);
+            } else {
+                debug!("dropping message for dead node {}", message.recipient);
+            }
+        }
+
+        let mut nodes: BTreeMap<Name, &mut N> = self.nodes
+            .iter_mut()
+            .map(|(name, node)| (*name, node))
+            .collect();
+        let work: Vec<(&mut N, Vec<Message>)> = order
+            .iter()
+            .filter_map(|name| {
+                let inbox = inboxes.remove(name)?;
+                nodes.remove(name).map(|node| (node, inbox))
+            })
+            .collect();
+        let blocks = &self.blocks;
+        let run_id = run_id::current();
+
+        let responses: Vec<Vec<Message>> = pool.install(|| {
+            work.into_par_iter()
+                .map(|(node, inbox)| {
+                    run_id::set_current(run_id.clone());
+                    random::without_draws(|| {
+                        inbox
+                            .into_iter()
+                            .flat_map(|message| node.handle_message(message, blocks, step))
+                            .collect()
+                    })
+                })
+                .collect()
+        });
+
+        for messages in responses {
+            self.network.send(step, messages);
+        }
+    }
+
+    /// Run the simulation one step at a time, yielding a summary of each step.
+    ///
+    /// The iterator ends once the simulation would have terminated. The simulation can be
+    /// stopped early by dropping the iterator; calling `run` afterwards runs any remaining steps
+    /// and checks the final state.
+    pub fn steps(&mut self) -> Steps<'_, N> {
+        Steps { simulation: self }
+    }
+
+    /// Save a checkpoint at the start of `step`, if one is due.
+    fn save_checkpoint(&mut self, step: u64) {
+        let capture = match self.checkpoints {
+            Some(ref checkpoints)
+                if step.is_multiple_of(checkpoints.interval) &&
+                       !checkpoints.saved.iter().any(|saved| saved.step == step) => {
+                checkpoints.capture
+            }
+            _ => return,
+        };
+        let checkpoint = capture(self);
+        let mut dropped = None;
+        let mut chain_dir = None;
+        if let Some(ref mut checkpoints) = self.checkpoints {
+            if checkpoints.saved.len() == checkpoints.capacity {
+                dropped = checkpoints.saved.pop_front().map(|dropped| dropped.step);
+            }
+            checkpoints.saved.push_back(checkpoint);
+            chain_dir = checkpoints.chain_dir.clone();
+        }
+        if let Some(dir) = chain_dir {
+            if let Some(dropped) = dropped {
+                let _ = fs::remove_file(checkpoint_chain_path(&dir, dropped));
+            }
+            let path = checkpoint_chain_path(&dir, step);
+            if let Err(err) = fs::create_dir_all(&dir).and_then(|_| self.write_chain(&path)) {
+                warn!("Couldn't write the chain of the checkpoint at step {}: {}", step, err);
+            }
+        }
+    }
+
+    /// Whether no messages are in flight or waiting to be handled by nodes.
+    fn is_quiet(&self) -> bool {
+        self.network.queue_is_empty() && self.nodes.values().all(|node| node.backlog() == 0)
+    }
+
+    /// Run a single step, or return `None` if the simulation has finished.
+    fn run_step(&mut self) -> Option<StepSummary> {
+        if self.livelock().is_some() || self.conflicts.aborted().is_some() ||
+            self.stopped_at.is_some() || self.limit_reached.is_some()
+        {
+            return None;
+        }
+
+        if self.pause_dir.is_some() && pause::take_signal().is_some() {
+            let step = self.step;
+            if self.pause_at(step) == PauseAction::Stop {
+                info!("Stopped while paused at step {}", step);
+                self.stopped_at = Some(step);
+                return None;
+            }
+        }
+
+        // Read after pausing, as a debugger may have rewound the simulation meanwhile.
+        let step = self.step;
+        self.save_checkpoint(step);
+        if step == 0 {
+            info!("{}", FormatKind::Log.run_header());
+        }
+
+        // Generate events unless we're in the finishing phase, in which case we let the event
+        // queue empty out.
+        if let Phase::Finishing { since_step } = self.phase {
+            if step > since_step + MAX_EXTRA_STEPS {
+                return None;
+            }
+            if self.is_quiet() {
+                if self.no_op_step_count > self.node_params.max_timeout() {
+                    return None;
                 } else {
-                    no_op_step_count = 0;
+                    self.no_op_step_count += 1;
                 }
-                info!(
-                    "-- step {} ({:?}) {} nodes --",
-                    step,
-                    self.phase,
-                    self.nodes.len()
-                );
             } else {
-                info!(
-                    "-- step {} ({:?}) {} nodes --",
-                    step,
-                    self.phase,
-                    self.nodes.len()
-                );
-                self.generate_events(step);
+                self.no_op_step_count = 0;
             }
+            info!(
+                "-- step {} ({:?}) {} nodes --",
+                step,
+                self.phase,
+                self.nodes.len()
+            );
+        } else {
+            info!(
+                "-- step {} ({:?}) {} nodes --",
+                step,
+                self.phase,
+                self.nodes.len()
+            );
+            self.generate_events(step);
+        }
+        let phase = self.phase;
 
-            let delivered = self.network.receive(step);
-            for message in delivered {
+        let delivered = self.network.receive(step);
+        let messages_delivered = delivered.len();
+        let mut message_kinds: BTreeMap<&'static str, MessageKindCounts> = BTreeMap::new();
+        for message in &delivered {
+            message_kinds.entry(message.content.kind()).or_default().delivered += 1;
+        }
+        if let Some(ref mut dump) = self.failure_dump {
+            dump.record_delivered(step, &delivered);
+        }
+        if let Some(ref mut journals) = self.journals {
+            journals.record_handled(step, &delivered, &self.nodes);
+        }
+        if let Some(ref mut vote_races) = self.vote_races {
+            vote_races.record_delivered(step, &delivered);
+        }
+        if let Some(ref mut proxy_failures) = self.proxy_failures {
+            proxy_failures.on_delivered(&delivered);
+        }
+        let order = self.processing_order(step);
+        if let Some(pool) = self.thread_pool.take() {
+            self.handle_messages_parallel(&pool, delivered, &order, step);
+            self.thread_pool = Some(pool);
+        } else {
+            for message in self.in_processing_order(delivered, &order) {
                 match self.nodes.get_mut(&message.recipient) {
                     Some(node) => {
-                        let new_messages = node.handle_message(message, &self.blocks, step);
+                        self.network.handling(&message);
+                        let blocks = &self.blocks;
+                        let new_messages =
+                            random::without_draws(|| node.handle_message(message, blocks, step));
                         self.network.send(step, new_messages);
                     }
                     None => {
@@ -317,71 +2212,829 @@ impl Simulation {
                     }
                 }
             }
+        }
 
-            // Shutdown nodes that have failed to join.
-            let mut to_shutdown = BTreeSet::new();
-            for (name, node) in &self.nodes {
-                if node.should_shutdown(&self.blocks, step) {
-                    to_shutdown.insert(*name);
-                }
+        // Shutdown nodes that have failed to join.
+        let mut to_shutdown = BTreeSet::new();
+        for (name, node) in &self.nodes {
+            if node.should_shutdown(&self.blocks, step) {
+                to_shutdown.insert(*name);
             }
+        }
 
-            for name in to_shutdown {
-                trace!("Node({}): voluntarily shutting down", name);
-                self.apply_remove_node(name);
-                let removal_msgs = Event::RemoveNode(name).broadcast(&self.nodes);
-                self.network.send(step, removal_msgs);
+        for name in to_shutdown {
+            trace!("Node({}): voluntarily shutting down", name);
+            if self.network.is_inbound_blocked(&name) {
+                self.unreachable_shutdowns += 1;
             }
+            self.apply_remove_node(name);
+            let removal_msgs =
+                Event::RemoveNode(name).broadcast(&self.nodes, &self.params.bootstrap);
+            self.network.send(step, removal_msgs);
+        }
 
-            // Update node state (current blocks), and send new votes.
-            for node in self.nodes.values_mut() {
-                match node.our_current_blocks(&self.blocks).into_iter().count() {
-                    0 => (),
-                    1 => node.check_conflicting_block_count(&self.blocks),
-                    count => {
-                        panic!(
-                            "{:?}\nhas {} current blocks for own section.",
-                            node.as_debug(&self.blocks),
-                            count
-                        )
+        // Restart nodes relocated by their section under their new names.
+        let relocated: Vec<(Name, Name)> = self.nodes
+            .iter()
+            .filter_map(|(name, node)| node.relocation().map(|to| (*name, to)))
+            .collect();
+        for (node, to) in relocated {
+            let messages = relocation_messages(node, to, &self.nodes, &self.params.bootstrap);
+            self.secure_joins.relocated(node, to, step);
+            self.apply_relocation(node, to, step);
+            self.network.send(step, messages);
+        }
+
+        // Update node state (current blocks), and send new votes.
+        let mut aborting_conflict: Option<Conflict> = None;
+        for name in &order {
+            let node = match self.nodes.get_mut(name) {
+                Some(node) => node,
+                None => continue,
+            };
+            match node.our_current_blocks(&self.blocks).into_iter().count() {
+                0 => (),
+                1 => {
+                    let counts = node.conflicting_blocks(&self.blocks);
+                    if let Some(conflict) = self.conflicts.observe(step, *name, &counts) {
+                        aborting_conflict = Some(conflict.clone());
                     }
                 }
-                self.network.send(
-                    step,
-                    node.update_state(&mut self.blocks, step),
-                );
-                self.network.send(
-                    step,
-                    node.broadcast_new_votes(&mut self.blocks, step),
-                );
+                count => {
+                    panic!(
+                        "{}\nhas {} current blocks for own section.",
+                        node.debug_state(&self.blocks),
+                        count
+                    )
+                }
+            }
+            self.network.send(
+                step,
+                node.update_state(&mut self.blocks, step),
+            );
+            let votes = node.broadcast_new_votes(&mut self.blocks, step);
+            if let Some(ref mut journals) = self.journals {
+                journals.record_votes(step, *name, &votes, &self.blocks);
+            }
+            if let Some(ref mut drops) = self.drops {
+                drops.observe(&self.blocks, &votes, &self.nodes);
+            }
+            self.network.send(step, votes);
+        }
+
+        if self.node_params.handover_steps > 0 {
+            self.sample_handovers(step);
+        }
+
+        if let Some(conflict) = aborting_conflict {
+            warn!("aborting: {}", conflict);
+            self.dump_failure(step, &conflict.to_string(), &[conflict.prefix]);
+        }
+
+        self.update_joins(step);
+
+        self.phase = self.phase_for_next_step(step);
+
+        if let Some(ref mut history) = self.agreed_history {
+            history.push(
+                self.nodes
+                    .values()
+                    .flat_map(|node| node.current_blocks().iter().cloned())
+                    .collect(),
+            );
+        }
+
+        if let Some(ref mut sybil) = self.sybil {
+            sybil.observe(step, &self.blocks, &self.nodes);
+        }
+
+        if self.node_params.equivocation_grace.is_some() {
+            self.equivocations.observe(step, &self.nodes);
+        }
+
+        if let Some(ref mut proxy_failures) = self.proxy_failures {
+            proxy_failures.observe(&self.blocks, &self.nodes);
+        }
+
+        if let Some(ref mut outage) = self.outage {
+            outage.observe(step, &self.blocks, &self.nodes);
+        }
+
+        if let Some(ref mut lifecycles) = self.lifecycles {
+            lifecycles.observe(step, &self.blocks, &self.nodes);
+        }
+
+        if let Some(ref mut prefix_tree) = self.prefix_tree {
+            prefix_tree.observe(step, &self.blocks, &self.nodes);
+        }
+
+        if let Some(ref mut state_trace) = self.state_trace {
+            state_trace.observe(step, &self.blocks, &self.nodes);
+        }
+
+        if let Some(ref mut vote_races) = self.vote_races {
+            vote_races.observe(step, &self.nodes);
+        }
+
+        if let Some(ref mut assertions) = self.assertions {
+            assertions.observe(step, &self.blocks, &self.nodes);
+        }
+
+        if let Some(ref mut validity) = self.validity {
+            validity.observe(step, &self.nodes);
+        }
+
+        if let Some(ref mut journals) = self.journals {
+            if let Err(err) = journals.observe(step, &self.blocks, &self.nodes) {
+                warn!("failed to write journals: {}", err);
+            }
+        }
+
+        if let Some(ref mut membership) = self.membership {
+            membership.observe(step, &self.blocks, &self.nodes);
+        }
+
+        let mut to_quarantine = vec![];
+        if let Some(ref mut monitor) = self.divergence {
+            to_quarantine = monitor.observe(step, &self.blocks, &self.nodes);
+        }
+        for name in to_quarantine {
+            for node in self.nodes.values_mut().filter(|node| node.name() != name) {
+                node.quarantine(name);
+            }
+        }
+
+        if let Some(ref mut audit) = self.hygiene {
+            audit.observe(step, &self.blocks, &mut self.nodes);
+        }
+
+        let mut livelock = None;
+        if let Some(ref mut watchdog) = self.livelock {
+            let votes_in_flight = self.network.votes_in_flight();
+            livelock = watchdog
+                .observe(step, &self.blocks, &self.nodes, votes_in_flight)
+                .cloned();
+        }
+        if let Some(livelock) = livelock {
+            warn!("{}: {:?}", livelock, livelock.pending);
+            self.dump_failure(step, &livelock.to_string(), &[livelock.prefix]);
+        }
+
+        let mut low_health = None;
+        if let Some(ref mut monitor) = self.health {
+            low_health = monitor.observe(step, &self.blocks, &self.nodes).cloned();
+        }
+        if let Some(low_health) = low_health {
+            warn!("{}", low_health);
+            self.dump_failure(step, &low_health.to_string(), &[low_health.prefix]);
+        }
+
+        let converged = self.is_quiet();
+        let num_violations = self.coverage.violations.len();
+        self.coverage.check_step(
+            step,
+            &self.blocks,
+            &self.nodes,
+            converged,
+        );
+        if self.coverage.violations.len() > num_violations {
+            let mut affected = vec![];
+            for (_, violation) in &self.coverage.violations[num_violations..] {
+                match *violation {
+                    CoverageViolation::Gap(ref prefixes) => affected.extend(prefixes),
+                    CoverageViolation::Overlap(p1, p2) => affected.extend(&[p1, p2]),
+                }
             }
+            let description = format!("namespace coverage violated: {:?}", affected);
+            self.dump_failure(step, &description, &affected);
+        }
 
-            self.phase = self.phase_for_next_step(step);
+        let roots = self.root_blocks();
+        if let Some(ref mut soak) = self.soak {
+            if let Err(err) = soak.on_step(
+                step,
+                &self.nodes,
+                &mut self.blocks,
+                &self.network,
+                &roots,
+            )
+            {
+                warn!("Soak: failed to write report: {}", err);
+            }
+        }
 
+        debug!(
+            "- {} messages still in queue. -",
+            self.network.messages_in_queue()
+        );
+        for (kind, sent) in self.network.take_sent_by_kind() {
+            message_kinds.entry(kind).or_default().sent = sent;
+        }
+        for (kind, in_queue) in self.network.queued_by_kind() {
+            message_kinds.entry(kind).or_default().in_queue = in_queue;
+        }
+        if !message_kinds.is_empty() {
             debug!(
-                "- {} messages still in queue. -",
+                "- messages sent/delivered/queued: {} -",
+                message_kinds
+                    .iter()
+                    .map(|(kind, counts)| {
+                        format!(
+                            "{} {}/{}/{}",
+                            kind,
+                            counts.sent,
+                            counts.delivered,
+                            counts.in_queue
+                        )
+                    })
+                    .join(", ")
+            );
+        }
+
+        self.step += 1;
+        self.check_limits(step);
+        let summary = StepSummary {
+            step,
+            phase,
+            num_nodes: self.nodes.len(),
+            messages_delivered,
+            messages_in_queue: self.network.messages_in_queue(),
+            message_kinds,
+        };
+        if let Some(ref mut complexity) = self.complexity {
+            let sent = self.network.take_sent_counts();
+            complexity.observe(&self.blocks, &self.nodes, &sent);
+        }
+        for hook in &mut self.hooks {
+            hook(&summary);
+        }
+        Some(summary)
+    }
+
+    /// Stop the run if it's over one of the safety limits at the end of `step`.
+    fn check_limits(&mut self, step: u64) {
+        let limit = match (self.params.max_nodes, self.params.max_total_steps) {
+            (Some(max), _) if self.nodes.len() > max => Limit::Nodes(max),
+            (_, Some(max)) if self.step >= max => Limit::Steps(max),
+            _ => return,
+        };
+        warn!("Reached a safety limit at step {}: {}", step, limit);
+        self.limit_reached = Some((limit, step, self.nodes.len()));
+    }
+
+    /// Run the simulation, returning Ok iff the network was consistent upon termination.
+    pub fn run(&mut self) -> Result<BTreeMap<Prefix, Block>> {
+        // Other simulations may have been created on this thread since this one was.
+        run_id::set_current(Some(self.run_id.clone()));
+        while self.run_step().is_some() {}
+        if let Some(step) = self.stopped_at {
+            return Err(Error::Stopped { step });
+        }
+        if let Some((limit, step, num_nodes)) = self.limit_reached {
+            info!(
+                "-- stopped by a safety limit in the {:?} phase, with {} messages in flight --",
+                self.phase,
                 self.network.messages_in_queue()
             );
+            return Err(Error::LimitReached {
+                limit,
+                step,
+                num_nodes,
+            });
         }
 
         debug!("-- final node states --");
         for node in self.nodes.values() {
-            debug!("{:?}", node.as_debug(&self.blocks));
-            trace!("{:#?}", node.connections);
+            debug!("{}", node.debug_state(&self.blocks));
         }
 
-        assert!(
-            no_op_step_count > self.node_params.join_timeout,
-            "Votes were still being sent and received after {} extra steps during which no \
-                 churn was triggered.",
-            max_extra_steps
+        info!(
+            "namespace coverage: {} violations, {} steps with transient violations",
+            self.coverage.violations.len(),
+            self.coverage.transient_steps
         );
 
-        check_consistency(
-            &self.blocks,
-            &self.nodes,
-            self.node_params.min_section_size as usize,
-        ).map_err(|_| seed())
+        if let Some(ref validity) = self.validity {
+            let spreads = validity.acceptance_spreads();
+            let total: u64 = spreads.values().map(|spread| spread.steps()).sum();
+            info!(
+                "{} blocks became valid on {} nodes in total; acceptance spread mean {:.1}, max {} \
+                 steps; {} disagreements",
+                spreads.len(),
+                validity.transitions().len(),
+                total as f64 / cmp::max(spreads.len(), 1) as f64,
+                spreads.values().map(|spread| spread.steps()).max().unwrap_or(0),
+                validity.disagreements().len()
+            );
+        }
+
+        if let Some(report) = self.sybil_report() {
+            info!("Sybil attack outcome: {:?}", report);
+        }
+
+        if self.node_params.equivocation_grace.is_some() {
+            let report = self.equivocation_report();
+            info!(
+                "{} of {} equivocating nodes caught (mean latency {:.1} steps), {} honest nodes \
+                 taken for equivocators",
+                report.detected,
+                report.equivocators,
+                report.mean_detection_latency(),
+                report.false_positives
+            );
+        }
+
+        if let Some(report) = self.proxy_failure_report() {
+            info!("Proxy failure outcome: {:?}", report);
+        }
+
+        if let Some(report) = self.outage_report() {
+            info!("Section outage outcome: {:?}", report);
+        }
+
+        if self.node_params.verify_signatures {
+            let stats = self.node_stats();
+            info!(
+                "{} signatures verified (cost {}), {} rejected, over {} churn events ({:.1} per \
+                 event)",
+                stats.signatures_verified,
+                stats.verification_cost,
+                stats.signatures_rejected,
+                self.num_churn_events,
+                stats.signatures_verified as f64 / cmp::max(self.num_churn_events, 1) as f64
+            );
+        }
+
+        info!(
+            "{} vote agreements rejected",
+            self.node_stats().agreements_rejected
+        );
+
+        info!(
+            "{} nodes joined (mean latency {:.1} steps), {} rejected ({:.1}%)",
+            self.join_stats.joined,
+            self.join_stats.mean_latency(),
+            self.join_stats.rejected,
+            100.0 * self.join_stats.rejection_rate()
+        );
+
+        if self.node_params.secure_join {
+            let stats = self.secure_joins.stats();
+            info!(
+                "{} nodes relocated after joining (mean latency {:.1} steps), {} joined their \
+                 new section (mean latency {:.1} steps from first joining)",
+                stats.relocated,
+                stats.mean_relocation_latency(),
+                stats.completed,
+                stats.mean_latency()
+            );
+        }
+
+        let stats = self.node_stats();
+        info!(
+            "{} votes proposed, {} received; {} blocks agreed, {} expired; {} connects initiated",
+            stats.votes_proposed,
+            stats.votes_received,
+            stats.blocks_agreed,
+            stats.blocks_expired,
+            stats.connects_initiated
+        );
+
+        info!(
+            "candidates per section: mean {:.2}, max {}; distribution {:?}",
+            self.candidate_stats.mean(),
+            self.candidate_stats.max(),
+            self.candidate_stats.distribution
+        );
+
+        if self.reconnect_stats.count() > 0 {
+            info!(
+                "{} disconnected pairs reconnected after a mean of {:.1} steps, max {}",
+                self.reconnect_stats.count(),
+                self.reconnect_stats.mean(),
+                self.reconnect_stats.max()
+            );
+        }
+
+        if let Some(ref replay) = self.churn_trace {
+            let stats = replay.stats();
+            info!(
+                "churn trace: {} joins and {} leaves replayed, {} leaves skipped, {} entries left",
+                stats.joins,
+                stats.leaves,
+                stats.leaves_skipped,
+                replay.remaining()
+            );
+        }
+
+        if let Some(ref controller) = self.size_controller {
+            let stats = controller.stats();
+            info!(
+                "size target: {} joins and {} leaves, mean deviation {:.1} nodes, max {:.1}",
+                stats.joins,
+                stats.leaves,
+                stats.mean_deviation(),
+                stats.max_deviation
+            );
+        }
+
+        if let Some(ref complexity) = self.complexity {
+            for line in complexity.to_string().lines() {
+                info!("{}", line);
+            }
+        }
+
+        if let Some((score, prefix, step)) = self.health.as_ref().and_then(HealthMonitor::lowest) {
+            info!("lowest section health: {:.3} for {:?} at step {}", score, prefix, step);
+        }
+
+        if let Some(report) = self.divergence_report() {
+            info!(
+                "{} nodes marked divergent, {} of them voted out",
+                report.divergent.len(),
+                report.voted_out
+            );
+        }
+
+        if let Some(report) = self.hygiene_report() {
+            info!(
+                "hygiene: at most {} stale candidates and {} ghost connections, {} and {} purged",
+                report.max_stale_candidates,
+                report.max_ghost_connections,
+                report.candidates_purged,
+                report.connections_purged
+            );
+        }
+
+        if let Some(ref drops) = self.drops {
+            info!(
+                "{} removals proposed, {} of running nodes ({:.1}%)",
+                drops.drops_proposed,
+                drops.false_drops,
+                100.0 * drops.false_drop_rate()
+            );
+        }
+
+        let worst_conflicts = self.conflicts.worst_offenders(5);
+        if !worst_conflicts.is_empty() {
+            info!(
+                "most conflicting blocks: {}",
+                worst_conflicts.iter().map(ToString::to_string).join("; ")
+            );
+        }
+
+        if self.node_params.compaction.is_some() {
+            let stats = self.node_stats();
+            info!(
+                "{} compactions dropped {} votes",
+                stats.compactions,
+                stats.votes_compacted
+            );
+        }
+
+        if let Some(max) = self.node_params.max_candidates_per_section {
+            info!(
+                "{} joins refused for exceeding {} candidates per section",
+                self.node_stats().candidates_refused,
+                max
+            );
+        }
+
+        if let Some(max) = self.node_params.max_connections {
+            let stats = self.node_stats();
+            info!(
+                "{} connections refused and {} dropped for exceeding {} per node",
+                stats.connections_refused,
+                stats.connections_evicted,
+                max
+            );
+        }
+
+        if let Some(timeout) = self.node_params.connect_timeout {
+            info!(
+                "{} connection requests timed out after {} steps",
+                self.node_stats().connects_timed_out,
+                timeout
+            );
+        }
+
+        if let Some(ref vote_races) = self.vote_races {
+            info!(
+                "{} blocks had successors racing to follow them",
+                vote_races.races(&self.blocks).len()
+            );
+        }
+
+        if let Some(ref prefix_tree) = self.prefix_tree {
+            info!("The sections changed {} times", prefix_tree.snapshots().len() - 1);
+        }
+
+        if let Some(ref cohorts) = self.cohorts {
+            let no_joins = JoinStats::default();
+            for (cohort, (num_nodes, stats)) in cohorts.node_stats(&self.per_node_stats()) {
+                let joins = cohorts.join_stats().get(&cohort).unwrap_or(&no_joins);
+                info!(
+                    "Cohort {}: {} nodes; {} joined (mean latency {:.1} steps), {} rejected; {} \
+                     votes proposed, {} blocks agreed, {} connects initiated",
+                    cohort,
+                    num_nodes,
+                    joins.joined,
+                    joins.mean_latency(),
+                    joins.rejected,
+                    stats.votes_proposed,
+                    stats.blocks_agreed,
+                    stats.connects_initiated
+                );
+            }
+        }
+
+        if self.node_params.prune_connections {
+            let connections: usize = self.nodes.values().map(N::num_connections).sum();
+            info!(
+                "{} connections pruned, {:.1} connections per node at the end",
+                self.node_stats().connections_pruned,
+                connections as f64 / self.nodes.len().max(1) as f64
+            );
+        }
+
+        if let Some(backpressure) = self.node_params.join_backpressure {
+            let stats = self.node_stats();
+            info!(
+                "{} joins deferred by sections with {} or more pending blocks, {} retried",
+                stats.joins_deferred,
+                backpressure.max_pending_blocks,
+                stats.join_retries
+            );
+        }
+
+        if self.node_params.rejoin_cooldown > 0 {
+            info!(
+                "{} joins deferred for nodes removed less than {} steps before",
+                self.node_stats().rejoins_deferred,
+                self.node_params.rejoin_cooldown
+            );
+        }
+
+        let stats = self.node_stats();
+        if stats.joins_completed > 0 {
+            info!(
+                "Joining nodes took {:.1} steps on average to become members",
+                stats.steps_to_join as f64 / stats.joins_completed as f64
+            );
+        }
+        if stats.votes_forgotten > 0 {
+            info!(
+                "{} votes forgotten, {} heard about again; {} recoveries taking {:.1} steps on \
+                 average",
+                stats.votes_forgotten,
+                stats.votes_relearned,
+                stats.memory_recoveries,
+                stats.steps_to_recover as f64 / cmp::max(stats.memory_recoveries, 1) as f64
+            );
+        }
+        if let Some(depth) = self.node_params.welcome_joiners {
+            info!(
+                "{} welcome messages sent, with up to {} agreed votes each",
+                stats.welcome_msgs_sent,
+                depth
+            );
+        }
+
+        if let Some(ttl) = self.params.message_ttl {
+            info!(
+                "{} messages expired after being in flight for over {} steps",
+                self.messages_expired(),
+                ttl
+            );
+        }
+
+        if self.network.messages_blocked() > 0 {
+            info!(
+                "{} messages blocked by nodes only reachable over their own connections, {} of \
+                 which shut down",
+                self.messages_blocked(),
+                self.unreachable_shutdowns
+            );
+        }
+
+        if self.messages_partitioned() > 0 {
+            info!("{} messages dropped by partitions", self.messages_partitioned());
+        }
+
+        let pending_drops = self.pending_forced_drops().len();
+        if self.messages_force_dropped() > 0 || pending_drops > 0 {
+            info!(
+                "{} messages dropped on purpose, {} forced drops never matched a message",
+                self.messages_force_dropped(),
+                pending_drops
+            );
+        }
+
+        if self.node_params.partial_bootstrap {
+            let stats = self.node_stats();
+            info!(
+                "{} votes sent to bootstrap candidates, {} requests for history left out",
+                stats.bootstrap_votes_sent,
+                stats.chain_requests
+            );
+        }
+
+        if self.node_params.section_messages {
+            let stats = self.node_stats();
+            info!(
+                "{} section messages signed, {} sent and {} received, {} duplicates dropped",
+                stats.section_shares_sent,
+                stats.section_messages_sent,
+                stats.section_messages_received,
+                self.section_duplicates()
+            );
+        }
+
+        if self.node_params.suspicion_confirmations.is_some() {
+            let stats = self.node_stats();
+            info!(
+                "{} suspicions of members raised, {} withdrawn",
+                stats.suspicions_raised,
+                stats.suspicions_withdrawn
+            );
+        }
+
+        if self.node_params.handover_steps > 0 {
+            info!(
+                "{} votes deferred by handovers; sections stalled in {:.1}% of samples",
+                self.node_stats().votes_deferred,
+                100.0 * self.handover_stats.stall_rate()
+            );
+        }
+
+        if let Some(processing) = self.node_params.processing {
+            let stats = self.node_stats();
+            info!(
+                "{} messages handled late for lack of a budget of {} per step, {} of them carrying \
+                 votes; mean wait {:.1} steps",
+                stats.messages_backlogged,
+                processing.budget_per_step,
+                stats.votes_backlogged,
+                stats.backlog_wait_steps as f64 / cmp::max(stats.messages_backlogged, 1) as f64
+            );
+        }
+
+        let stats = self.node_stats();
+        if stats.votes_withheld > 0 || stats.messages_dropped_by_faults > 0 {
+            info!(
+                "faulty nodes withheld {} votes and dropped {} messages",
+                stats.votes_withheld,
+                stats.messages_dropped_by_faults
+            );
+        }
+
+        if self.node_params.validate_ancestry {
+            let stats = self.node_stats();
+            info!(
+                "{} votes held back until the blocks they follow were valid; mean wait {:.1} \
+                 steps; {} given up on",
+                stats.votes_unknown_ancestry,
+                stats.unknown_ancestry_wait_steps as f64 /
+                    cmp::max(stats.votes_unknown_ancestry, 1) as f64,
+                stats.orphan_votes_dropped
+            );
+        }
+
+        if self.node_params.vote_batch_steps > 0 {
+            let stats = self.node_stats();
+            info!(
+                "{} copies of votes sent in {} messages, batched over {} steps; mean wait {:.1} \
+                 steps",
+                stats.votes_sent,
+                stats.vote_messages_sent,
+                self.node_params.vote_batch_steps,
+                stats.vote_batch_wait_steps as f64 / cmp::max(stats.votes_proposed, 1) as f64
+            );
+        }
+
+        if self.node_params.piggyback_votes {
+            let stats = self.node_stats();
+            info!(
+                "{} votes piggybacked on connection requests, {} of them new to the recipient",
+                stats.votes_piggybacked,
+                stats.piggybacked_votes_new
+            );
+        }
+
+        if self.node_params.handshake.is_some() {
+            let stats = self.node_stats();
+            info!(
+                "{} connection handshakes failed, {} retried",
+                stats.handshakes_failed,
+                stats.connect_retries
+            );
+        }
+
+        let compaction_mismatches = self.node_stats().compaction_mismatches;
+        let stranded = self.stranded_joiners();
+        let result = if let Some(livelock) = self.livelock() {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: livelock.to_string(),
+            })
+        } else if let Some(low_health) = self.low_health() {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: low_health.to_string(),
+            })
+        } else if let Some(conflict) = self.conflicts.aborted() {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: format!("too many conflicting blocks: {}", conflict),
+            })
+        } else if self.no_op_step_count <= self.node_params.join_timeout {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: format!(
+                    "votes were still being sent and received after {} extra steps during \
+                     which no churn was triggered",
+                    MAX_EXTRA_STEPS
+                ),
+            })
+        } else if compaction_mismatches > 0 {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: format!(
+                    "{} compactions changed the agreed history",
+                    compaction_mismatches
+                ),
+            })
+        } else if !stranded.is_empty() {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: format!(
+                    "nodes {:?} lost their proxy, but neither joined nor shut down",
+                    stranded
+                ),
+            })
+        } else if let Some(outage) = self.outage.as_ref().filter(|outage| outage.is_orphaned()) {
+            Err(Error::InvariantViolation {
+                seed: seed(),
+                description: format!(
+                    "namespace of {:?} still orphaned after all its nodes were killed",
+                    outage.outage().prefix
+                ),
+            })
+        } else {
+            check_consistency(
+                &self.blocks,
+                &self.nodes,
+                self.node_params.min_section_size as usize,
+                self.node_params.max_section_size,
+            ).and_then(|sections| self.check_connection_capacity(sections))
+        };
+        if let Err(ref err) = result {
+            let step = self.step;
+            if !self.dump_non_convergence(step, &err.to_string()) {
+                self.dump_failure(step, &err.to_string(), &[Prefix::empty()]);
+            }
+        }
+        if let Some(ref mut journals) = self.journals {
+            journals.flush()?;
+        }
+        if let Some(ref path) = self.metrics_path {
+            let mut file = File::create(path)?;
+            write_node_stats_csv(&self.per_node_stats(), self.cohorts.as_ref(), &mut file)?;
+        }
+        result
+    }
+
+    /// With limited connection capacity, check that every section member can still reach a
+    /// quorum of its section.
+    fn check_connection_capacity(
+        &self,
+        sections: BTreeMap<Prefix, Block>,
+    ) -> Result<BTreeMap<Prefix, Block>> {
+        let max = match self.node_params.max_connections {
+            Some(max) => max,
+            None => return Ok(sections),
+        };
+        let unreachable = unreachable_quorums(&sections, &self.nodes);
+        if unreachable.is_empty() {
+            return Ok(sections);
+        }
+        Err(Error::InvariantViolation {
+            seed: seed(),
+            description: format!(
+                "members {:?} can't reach a quorum of their sections with at most {} \
+                 connections",
+                unreachable,
+                max
+            ),
+        })
+    }
+
+    /// Nodes still stuck after their bootstrap proxy was killed.
+    fn stranded_joiners(&self) -> Vec<Name> {
+        self.proxy_failures
+            .as_ref()
+            .map(|proxy_failures| proxy_failures.stranded().iter().cloned().collect())
+            .unwrap_or_default()
     }
 
     fn phase_for_next_step(&self, step: u64) -> Phase {
@@ -407,7 +3060,17 @@ impl Simulation {
                 }
             }
             Stable { since_step } => {
-                if step >= since_step + self.params.stable_steps {
+                let soaking = self.soak
+                    .as_ref()
+                    .map(|soak| !soak.is_finished(step))
+                    .unwrap_or(false);
+                let outage_pending = self.outage
+                    .as_ref()
+                    .is_some_and(|outage| outage.report().completed_step.is_none());
+                let rejoin_pending = !self.rejoins.is_empty();
+                if !soaking && !outage_pending && !rejoin_pending &&
+                    step >= since_step + self.params.stable_steps
+                {
                     if self.params.shrink_prob_drop > 0.0 {
                         Shrinking
                     } else {
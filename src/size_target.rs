@@ -0,0 +1,200 @@
+//! Steering the network's size along a target trajectory, in place of the random joins and leaves
+//! drawn with fixed probabilities, whose realised sizes drift from run to run.
+//!
+//! A trajectory is a list of `<step>:<size>` points, such as `0:16,300:60,600:40`, with steps
+//! counted from the step the controller is first asked for events. The target size is
+//! interpolated linearly between points, and held at the first and last points' sizes before and
+//! after them.
+//!
+//! Each step, the controller compares the number of running nodes with the target, and adds a node
+//! with a probability proportional to the shortfall, or removes one with a probability proportional
+//! to the excess. Like random events, at most one node joins and one leaves per step, so a target
+//! rising or falling faster than that can't be tracked.
+
+use blocks::Blocks;
+use error::{Error, Result};
+use event::Event;
+use name::Name;
+use node::NodeTrait;
+use random::do_with_probability;
+use random_events::RandomEvents;
+
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeTrajectory {
+    /// Points in order of step, with no two at the same step.
+    points: Vec<(u64, usize)>,
+}
+
+impl SizeTrajectory {
+    pub fn new(mut points: Vec<(u64, usize)>) -> Result<Self> {
+        if points.is_empty() {
+            return Err(Error::Config("size trajectory has no points".to_string()));
+        }
+        points.sort_by_key(|&(step, _)| step);
+        if let Some(pair) = points.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::Config(
+                format!("size trajectory has two points at step {}", pair[0].0),
+            ));
+        }
+        Ok(SizeTrajectory { points })
+    }
+
+    /// Parse a trajectory from a list of `<step>:<size>` points separated by commas.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut points = vec![];
+        for point in text.split(',').map(str::trim) {
+            let parsed = match point.find(':') {
+                Some(index) => (point[..index].parse(), point[index + 1..].parse()),
+                None => return Err(Error::Config(format!("expected step:size, not {:?}", point))),
+            };
+            match parsed {
+                (Ok(step), Ok(size)) => points.push((step, size)),
+                _ => return Err(Error::Config(format!("invalid trajectory point {:?}", point))),
+            }
+        }
+        Self::new(points)
+    }
+
+    /// The target size `steps` steps after the start.
+    pub fn target(&self, steps: u64) -> f64 {
+        let after = self.points.iter().position(|&(step, _)| step > steps);
+        match after {
+            Some(0) => self.points[0].1 as f64,
+            Some(index) => {
+                let (step0, size0) = self.points[index - 1];
+                let (step1, size1) = self.points[index];
+                let fraction = (steps - step0) as f64 / (step1 - step0) as f64;
+                size0 as f64 + fraction * (size1 as f64 - size0 as f64)
+            }
+            None => self.points[self.points.len() - 1].1 as f64,
+        }
+    }
+}
+
+/// How closely the network's size tracked the target.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackingStats {
+    pub steps: u64,
+    pub joins: u64,
+    pub leaves: u64,
+    /// Sum over all steps of the absolute difference between the size and the target.
+    pub total_deviation: f64,
+    pub max_deviation: f64,
+}
+
+impl TrackingStats {
+    pub fn mean_deviation(&self) -> f64 {
+        if self.steps == 0 {
+            0.0
+        } else {
+            self.total_deviation / self.steps as f64
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SizeController {
+    trajectory: SizeTrajectory,
+    /// Probability of a join or leave per node of difference from the target.
+    gain: f64,
+    /// Step the controller started at, once it has.
+    start_step: Option<u64>,
+    stats: TrackingStats,
+}
+
+impl SizeController {
+    pub fn new(trajectory: SizeTrajectory, gain: f64) -> Result<Self> {
+        if !(gain.is_finite() && gain > 0.0) {
+            return Err(Error::Config(
+                format!("size controller gain must be positive, not {}", gain),
+            ));
+        }
+        Ok(SizeController {
+            trajectory,
+            gain,
+            start_step: None,
+            stats: TrackingStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> &TrackingStats {
+        &self.stats
+    }
+
+    /// The join or leave, if any, steering the network towards the target at `step`.
+    pub fn get_events<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        random_events: &RandomEvents,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> Vec<Event> {
+        let start_step = *self.start_step.get_or_insert(step);
+        let target = self.trajectory.target(step - start_step);
+        let error = target - nodes.len() as f64;
+        self.stats.steps += 1;
+        self.stats.total_deviation += error.abs();
+        self.stats.max_deviation = self.stats.max_deviation.max(error.abs());
+
+        let prob = (self.gain * error.abs()).min(1.0);
+        if error > 0.0 && do_with_probability(prob) {
+            self.stats.joins += 1;
+            vec![random_events.random_add(blocks, nodes)]
+        } else if error < 0.0 && do_with_probability(prob) {
+            match random_events.random_remove(blocks, nodes) {
+                Some(event) => {
+                    self.stats.leaves += 1;
+                    vec![event]
+                }
+                None => vec![],
+            }
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use node::Node;
+    use params::{NodeParams, SimulationParams};
+
+    #[test]
+    fn trajectory_interpolated() {
+        let trajectory = SizeTrajectory::parse("100:20, 0:10,200:20").unwrap();
+        let targets: Vec<_> = [0, 50, 100, 150, 300]
+            .iter()
+            .map(|&steps| trajectory.target(steps))
+            .collect();
+        assert_eq!(targets, vec![10.0, 15.0, 20.0, 20.0, 20.0]);
+        assert_eq!(SizeTrajectory::parse("5:8").unwrap().target(0), 8.0);
+        assert!(SizeTrajectory::parse("").is_err());
+        assert!(SizeTrajectory::parse("10").is_err());
+        assert!(SizeTrajectory::parse("0:1,0:2").is_err());
+        assert!(SizeTrajectory::parse("0:-1").is_err());
+    }
+
+    #[test]
+    fn joins_below_target() {
+        let trajectory = SizeTrajectory::parse("0:10").unwrap();
+        assert!(SizeController::new(trajectory.clone(), 0.0).is_err());
+
+        // Ten nodes short with a gain of 0.1, a node joins every step.
+        let mut controller = SizeController::new(trajectory, 0.1).unwrap();
+        let random_events = RandomEvents::new(SimulationParams::default(), NodeParams::default());
+        let blocks = Blocks::new();
+        let nodes: BTreeMap<Name, Node> = BTreeMap::new();
+        for step in 5..10 {
+            match controller.get_events(step, &random_events, &blocks, &nodes)[..] {
+                [Event::AddNode(_)] => (),
+                ref events => panic!("expected a join, not {:?}", events),
+            }
+        }
+        let stats = controller.stats();
+        assert_eq!((stats.steps, stats.joins, stats.leaves), (5, 5, 0));
+        assert_eq!(stats.mean_deviation(), 10.0);
+    }
+}
@@ -0,0 +1,332 @@
+//! Soak mode: long-running simulations with periodic, rotated metric reports.
+//!
+//! In soak mode the stable phase lasts until `max_steps` is reached (if ever). Every
+//! `report_interval` steps a line of metrics is appended to the current metrics file, and a
+//! running summary is rewritten, so that a crash loses at most one interval of data. Every
+//! `prune_interval` steps the blocks which nothing refers to any more are dropped from the block
+//! store. Optionally, the latest metrics are also served in the Prometheus text format.
+//!
+//! Pruning alone doesn't bound memory usage. Nodes keep their whole history unless
+//! `NodeParams::compaction` is set, and the simulation keeps per-node records which grow with
+//! churn: the stats of dead nodes, relocations, the latency drawn for every pair of nodes which
+//! has exchanged messages under the per-connection delay model and, if enabled, node lifecycles.
+//!
+//! Each report includes metrics of the connection graph, since consensus results are meaningless
+//! if it has split, and a warning is logged whenever it has.
+
+use block::BlockId;
+use blocks::Blocks;
+use connectivity::ConnectivityMetrics;
+use format::FormatKind;
+use name::Name;
+use network::Network;
+use node::NodeTrait;
+use prometheus::MetricsExporter;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug)]
+pub struct SoakParams {
+    /// Directory that metrics files and the running summary are written to.
+    pub output_dir: PathBuf,
+    /// Number of steps between metric reports.
+    pub report_interval: u64,
+    /// Number of reports written to a metrics file before moving on to a new one.
+    pub reports_per_file: u64,
+    /// Maximum number of metrics files kept on disk. The oldest files are deleted first.
+    pub max_files: usize,
+    /// Number of steps between prunings of blocks which nothing refers to any more. Nodes only
+    /// let go of their old history with `NodeParams::compaction`.
+    pub prune_interval: u64,
+    /// Step at which to stop the soak and let the network settle. `None` runs forever.
+    pub max_steps: Option<u64>,
+    /// Port to serve the latest metrics on for Prometheus to scrape, if any.
+    pub metrics_port: Option<u16>,
+}
+
+impl SoakParams {
+    pub fn new(output_dir: PathBuf) -> Self {
+        SoakParams {
+            output_dir,
+            report_interval: 100,
+            reports_per_file: 10_000,
+            max_files: 10,
+            prune_interval: 1000,
+            max_steps: None,
+            metrics_port: None,
+        }
+    }
+}
+
+/// Metrics sampled at a single step.
+#[derive(Clone, Debug, Default)]
+pub struct SoakMetrics {
+    pub step: u64,
+    pub num_nodes: usize,
+    pub num_sections: usize,
+    pub messages_in_queue: usize,
+    pub blocks_stored: usize,
+    pub valid_blocks: usize,
+    pub connectivity: ConnectivityMetrics,
+}
+
+/// State of an ongoing soak run.
+pub struct Soak {
+    params: SoakParams,
+    /// Index of the metrics file currently being written.
+    file_index: u64,
+    /// Number of reports written to the current metrics file.
+    reports_in_file: u64,
+    writer: Option<BufWriter<File>>,
+    /// Step at which the next report is due.
+    next_report: u64,
+    /// Step at which the next pruning is due.
+    next_prune: u64,
+    /// Total number of reports written.
+    num_reports: u64,
+    min_nodes: usize,
+    max_nodes: usize,
+    blocks_pruned: usize,
+    exporter: Option<MetricsExporter>,
+}
+
+/// Progress of a soak run when a checkpoint was taken. The metrics files aren't part of it, so
+/// reports for steps re-run after a rewind are written again.
+#[derive(Clone)]
+pub struct SoakCheckpoint {
+    next_report: u64,
+    next_prune: u64,
+    num_reports: u64,
+    min_nodes: usize,
+    max_nodes: usize,
+    blocks_pruned: usize,
+}
+
+impl Soak {
+    pub fn new(params: SoakParams) -> io::Result<Self> {
+        fs::create_dir_all(&params.output_dir)?;
+        let exporter = match params.metrics_port {
+            Some(port) => Some(MetricsExporter::bind(port)?),
+            None => None,
+        };
+        Ok(Soak {
+            file_index: 0,
+            reports_in_file: 0,
+            writer: None,
+            next_report: 0,
+            next_prune: params.prune_interval,
+            num_reports: 0,
+            min_nodes: usize::MAX,
+            max_nodes: 0,
+            blocks_pruned: 0,
+            exporter,
+            params,
+        })
+    }
+
+    pub fn checkpoint(&self) -> SoakCheckpoint {
+        SoakCheckpoint {
+            next_report: self.next_report,
+            next_prune: self.next_prune,
+            num_reports: self.num_reports,
+            min_nodes: self.min_nodes,
+            max_nodes: self.max_nodes,
+            blocks_pruned: self.blocks_pruned,
+        }
+    }
+
+    pub fn restore(&mut self, checkpoint: SoakCheckpoint) {
+        self.next_report = checkpoint.next_report;
+        self.next_prune = checkpoint.next_prune;
+        self.num_reports = checkpoint.num_reports;
+        self.min_nodes = checkpoint.min_nodes;
+        self.max_nodes = checkpoint.max_nodes;
+        self.blocks_pruned = checkpoint.blocks_pruned;
+    }
+
+    /// True if the soak has run for as long as it was configured to.
+    pub fn is_finished(&self, step: u64) -> bool {
+        self.params.max_steps.map(|max| step >= max).unwrap_or(false)
+    }
+
+    /// The address metrics are being served on, if enabled.
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.exporter.as_ref().map(MetricsExporter::local_addr)
+    }
+
+    /// Report metrics and prune unreferenced blocks, if it's time to do so.
+    pub fn on_step<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        nodes: &BTreeMap<Name, N>,
+        blocks: &mut Blocks,
+        network: &Network,
+        extra_roots: &BTreeSet<BlockId>,
+    ) -> io::Result<()> {
+        if step >= self.next_prune {
+            self.next_prune = step + self.params.prune_interval;
+            self.prune(nodes, blocks, network, extra_roots);
+        }
+        if step >= self.next_report {
+            self.next_report = step + self.params.report_interval;
+            let metrics = Self::metrics(step, nodes, blocks, network);
+            self.report(&metrics)?;
+        }
+        Ok(())
+    }
+
+    fn metrics<N: NodeTrait>(
+        step: u64,
+        nodes: &BTreeMap<Name, N>,
+        blocks: &Blocks,
+        network: &Network,
+    ) -> SoakMetrics {
+        // Inserted one at a time, as collecting them sorts them by `Prefix`'s partial order,
+        // which disagrees with its total order when a section and its parent are both current.
+        let mut sections = BTreeSet::new();
+        for node in nodes.values() {
+            for block in blocks.block_contents(node.current_blocks()) {
+                let _ = sections.insert(block.prefix);
+            }
+        }
+        SoakMetrics {
+            step,
+            num_nodes: nodes.len(),
+            num_sections: sections.len(),
+            messages_in_queue: network.messages_in_queue(),
+            blocks_stored: blocks.len(),
+            valid_blocks: nodes.values().map(|node| node.num_valid_blocks()).sum(),
+            connectivity: ConnectivityMetrics::of(nodes, blocks),
+        }
+    }
+
+    /// Drop the blocks which no node, message in flight or recorder refers to.
+    ///
+    /// Nodes' own history is left alone, as joining nodes are bootstrapped from it: only
+    /// compaction can replace it with a snapshot they can start from instead.
+    fn prune<N: NodeTrait>(
+        &mut self,
+        nodes: &BTreeMap<Name, N>,
+        blocks: &mut Blocks,
+        network: &Network,
+        extra_roots: &BTreeSet<BlockId>,
+    ) {
+        let mut referenced = extra_roots.clone();
+        for node in nodes.values() {
+            referenced.extend(node.referenced_blocks());
+        }
+        referenced.extend(network.referenced_blocks());
+
+        let num_pruned = blocks.retain(&referenced);
+        self.blocks_pruned += num_pruned;
+        debug!("Soak: pruned {} unreferenced blocks", num_pruned);
+    }
+
+    fn report(&mut self, metrics: &SoakMetrics) -> io::Result<()> {
+        if self.writer.is_none() || self.reports_in_file >= self.params.reports_per_file {
+            self.rotate()?;
+        }
+
+        self.num_reports += 1;
+        self.reports_in_file += 1;
+        self.min_nodes = cmp::min(self.min_nodes, metrics.num_nodes);
+        self.max_nodes = cmp::max(self.max_nodes, metrics.num_nodes);
+        if !metrics.connectivity.is_connected() {
+            warn!(
+                "Soak: connection graph split into {} components at step {}, the largest with {} \
+                 of {} nodes",
+                metrics.connectivity.components,
+                metrics.step,
+                metrics.connectivity.largest_component,
+                metrics.num_nodes
+            );
+        }
+
+        if let Some(ref mut writer) = self.writer {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{}",
+                metrics.step,
+                metrics.num_nodes,
+                metrics.num_sections,
+                metrics.messages_in_queue,
+                metrics.blocks_stored,
+                metrics.valid_blocks,
+                metrics.connectivity.components,
+                metrics.connectivity.largest_component,
+                metrics.connectivity.diameter_estimate,
+                metrics.connectivity.articulation_points.len()
+            )?;
+            writer.flush()?;
+        }
+
+        if let Some(ref exporter) = self.exporter {
+            exporter.update(metrics, self.num_reports, self.blocks_pruned);
+        }
+
+        self.write_summary(metrics)
+    }
+
+    /// Start a new metrics file, deleting the oldest one if we have too many.
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.writer.is_some() {
+            self.file_index += 1;
+        }
+        if self.file_index >= self.params.max_files as u64 {
+            let expired = self.file_index - self.params.max_files as u64;
+            let _ = fs::remove_file(self.metrics_path(expired));
+        }
+
+        let file = File::create(self.metrics_path(self.file_index))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", FormatKind::SoakMetrics.run_header())?;
+        writeln!(
+            writer,
+            "step,nodes,sections,messages_in_queue,blocks_stored,valid_blocks,components,\
+             largest_component,diameter_estimate,articulation_points"
+        )?;
+        self.writer = Some(writer);
+        self.reports_in_file = 0;
+        Ok(())
+    }
+
+    fn metrics_path(&self, index: u64) -> PathBuf {
+        self.params.output_dir.join(
+            format!("metrics-{:06}.csv", index),
+        )
+    }
+
+    /// Rewrite the running summary. Written to a temporary file first so that a crash mid-write
+    /// doesn't leave a truncated summary behind.
+    fn write_summary(&self, latest: &SoakMetrics) -> io::Result<()> {
+        let tmp_path = self.params.output_dir.join("summary.txt.tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            writeln!(file, "last step: {}", latest.step)?;
+            writeln!(file, "reports written: {}", self.num_reports)?;
+            writeln!(file, "current metrics file: {}", self.file_index)?;
+            writeln!(file, "nodes: {}", latest.num_nodes)?;
+            writeln!(file, "min nodes: {}", self.min_nodes)?;
+            writeln!(file, "max nodes: {}", self.max_nodes)?;
+            writeln!(file, "sections: {}", latest.num_sections)?;
+            writeln!(file, "messages in queue: {}", latest.messages_in_queue)?;
+            writeln!(file, "blocks stored: {}", latest.blocks_stored)?;
+            writeln!(file, "blocks pruned: {}", self.blocks_pruned)?;
+            let connectivity = &latest.connectivity;
+            writeln!(file, "connection graph components: {}", connectivity.components)?;
+            writeln!(file, "largest component: {}", connectivity.largest_component)?;
+            writeln!(file, "diameter estimate: {}", connectivity.diameter_estimate)?;
+            writeln!(
+                file,
+                "articulation points: {}",
+                connectivity.articulation_points.len()
+            )?;
+        }
+        fs::rename(tmp_path, self.params.output_dir.join("summary.txt"))
+    }
+}
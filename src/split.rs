@@ -1,13 +1,17 @@
-use name::Name;
+use name::{Name, Prefix};
 use block::{Block, Vote};
 use blocks::{Blocks, CurrentBlocks};
+use params::SplitPolicy;
 use std::collections::BTreeSet;
 
 pub fn split_blocks(
     blocks: &mut Blocks,
     current_blocks: &CurrentBlocks,
     our_name: Name,
+    policy: SplitPolicy,
     min_split_size: usize,
+    max_section_size: Option<usize>,
+    min_section_size: usize,
 ) -> Vec<Vote> {
     // TODO: find a way to satisfy the borrow checker without cloning
     let our_blocks = blocks
@@ -18,51 +22,85 @@ pub fn split_blocks(
     our_blocks
         .into_iter()
         .flat_map(|block| {
-            split_block(blocks, &block, current_blocks, min_split_size)
+            let must_split = block.must_split(max_section_size, min_section_size);
+            split_block(blocks, &block, current_blocks, policy, min_split_size, must_split)
         })
         .collect()
 }
 
-/// If a section as described by `block` can split, return the two blocks it splits into.
+/// If a section as described by `block` can split, or `must_split` because it's at the maximum
+/// size, return the two blocks it splits into.
 /// rule:Split
 fn split_block(
     blocks: &mut Blocks,
     block: &Block,
     current_blocks: &CurrentBlocks,
+    policy: SplitPolicy,
     min_split_size: usize,
+    must_split: bool,
 ) -> Vec<Vote> {
-    if block.should_split(min_split_size) &&
+    if (must_split || block.should_split(policy, min_split_size)) &&
         neighbours_ok(blocks, block, current_blocks, min_split_size)
     {
-        let p0 = block.prefix.pushed(false);
-        let p1 = block.prefix.pushed(true);
-        let (s0, s1): (BTreeSet<_>, _) = block.members.iter().partition(|name| p0.matches(**name));
-        let b0 = blocks.insert(Block {
-            prefix: p0,
-            version: block.version + 1,
-            members: s0,
-        });
-        let b1 = blocks.insert(Block {
-            prefix: p1,
-            version: block.version + 1,
-            members: s1,
-        });
-
-        let v0 = Vote {
-            from: block.get_id(),
-            to: b0,
-        };
-        let v1 = Vote {
-            from: block.get_id(),
-            to: b1,
-        };
-
-        vec![v0, v1]
+        split_votes(blocks, block)
     } else {
         vec![]
     }
 }
 
+/// Votes to split our sections whose prefixes are in `prefixes`, however large they are, as long
+/// as both halves would have at least two members: a vote to a half with all members but one
+/// would be counted as a vote to remove that member.
+pub fn forced_split_blocks(
+    blocks: &mut Blocks,
+    current_blocks: &CurrentBlocks,
+    our_name: Name,
+    prefixes: &BTreeSet<Prefix>,
+) -> Vec<Vote> {
+    let our_blocks = blocks
+        .our_blocks(current_blocks, our_name)
+        .into_iter()
+        .filter(|block| prefixes.contains(&block.prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+    our_blocks
+        .into_iter()
+        .filter(|block| {
+            let (len0, len1) = block.split_sizes();
+            len0 > 1 && len1 > 1
+        })
+        .flat_map(|block| split_votes(blocks, &block))
+        .collect()
+}
+
+/// Votes from `block` to each of its halves.
+fn split_votes(blocks: &mut Blocks, block: &Block) -> Vec<Vote> {
+    let p0 = block.prefix.pushed(false);
+    let p1 = block.prefix.pushed(true);
+    let (s0, s1): (BTreeSet<_>, _) = block.members.iter().partition(|name| p0.matches(**name));
+    let b0 = blocks.insert(Block {
+        prefix: p0,
+        version: block.version + 1,
+        members: s0,
+    });
+    let b1 = blocks.insert(Block {
+        prefix: p1,
+        version: block.version + 1,
+        members: s1,
+    });
+
+    let v0 = Vote {
+        from: block.get_id(),
+        to: b0,
+    };
+    let v1 = Vote {
+        from: block.get_id(),
+        to: b1,
+    };
+
+    vec![v0, v1]
+}
+
 /// True if all neighbouring and compatible blocks of `block` are of `min_split_size`.
 fn neighbours_ok(
     blocks: &Blocks,
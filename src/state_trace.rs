@@ -0,0 +1,163 @@
+//! Traces of the global state of a run, for checking against a TLA+ specification of the
+//! membership protocol, e.g. with Apalache.
+//!
+//! The global state is the membership of every section: each member counts towards the section
+//! it considers itself in, and where members disagree about a section the latest version they
+//! hold is taken. A state is recorded whenever it differs from the last one recorded.
+//!
+//! The trace is written in the Informal Trace Format (ITF), the JSON format that Apalache reads
+//! and writes. Each state has two variables: `step`, the step it was reached at, and `sections`,
+//! a function from each section's prefix, as a string of bits, to the set of its members' names
+//! in hex:
+//!
+//! ```text
+//! {"#meta":{"format":"ITF","source":"ewok","description":"# ewok state-trace format 1"},
+//! "vars":["step","sections"],
+//! "states":[
+//! {"#meta":{"index":0},"step":0,"sections":{"#map":[["",{"#set":["3f2a0c1d9e8b7a65"]}]]}},
+//! ...
+//! ]}
+//! ```
+
+use blocks::Blocks;
+use format::FormatKind;
+use name::{Name, Prefix};
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// Members of each section, by prefix.
+pub type Sections = BTreeMap<Prefix, BTreeSet<Name>>;
+
+#[derive(Clone, Default)]
+pub struct StateTrace {
+    /// The steps at which the global state changed, with the state from then on.
+    states: Vec<(u64, Sections)>,
+}
+
+impl StateTrace {
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        let sections = global_sections(blocks, nodes);
+        if self.states.last().is_none_or(|(_, last)| *last != sections) {
+            self.states.push((step, sections));
+        }
+    }
+
+    pub fn states(&self) -> &[(u64, Sections)] {
+        &self.states
+    }
+
+    /// Write the trace in the Informal Trace Format, one state per line.
+    pub fn write_itf<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{{\"#meta\":{{\"format\":\"ITF\",\"source\":\"ewok\",\"description\":\"{}\"}},",
+            FormatKind::StateTrace.run_header()
+        )?;
+        writeln!(writer, "\"vars\":[\"step\",\"sections\"],")?;
+        writeln!(writer, "\"states\":[")?;
+        for (index, &(step, ref sections)) in self.states.iter().enumerate() {
+            let entries: Vec<String> = sections
+                .iter()
+                .map(|(prefix, members)| {
+                    let names: Vec<String> = members
+                        .iter()
+                        .map(|name| format!("\"{:016x}\"", name.0))
+                        .collect();
+                    format!("[\"{}\",{{\"#set\":[{}]}}]", bits(prefix), names.join(","))
+                })
+                .collect();
+            writeln!(
+                writer,
+                "{{\"#meta\":{{\"index\":{}}},\"step\":{},\"sections\":{{\"#map\":[{}]}}}}{}",
+                index,
+                step,
+                entries.join(","),
+                if index + 1 < self.states.len() { "," } else { "" }
+            )?;
+        }
+        writeln!(writer, "]}}")
+    }
+}
+
+/// The bits of `prefix`, as a string of 0s and 1s.
+fn bits(prefix: &Prefix) -> String {
+    let name = prefix.lower_bound();
+    (0..prefix.bit_count())
+        .map(|i| if name.bit(i) { '1' } else { '0' })
+        .collect()
+}
+
+/// The global state: each member counts towards the section it considers itself in, and where
+/// members disagree about a section the latest version they hold is taken.
+pub fn global_sections<N: NodeTrait>(blocks: &Blocks, nodes: &BTreeMap<Name, N>) -> Sections {
+    let mut latest = BTreeMap::new();
+    for node in nodes.values() {
+        if let Some(block) = node.our_current_blocks(blocks).first() {
+            let version = latest.entry(block.prefix).or_insert((block.version, &block.members));
+            if block.version > version.0 {
+                *version = (block.version, &block.members);
+            }
+        }
+    }
+    latest
+        .into_iter()
+        .map(|(prefix, (_, members))| (prefix, members.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn split_traced() {
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: (0..4).map(|i| Name(i << 62)).collect(),
+        });
+        let mut nodes = BTreeMap::new();
+        for i in 0..4 {
+            let name = Name(i << 62);
+            let node = Node::new(name, &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+            let _ = nodes.insert(name, node);
+        }
+        let mut trace = StateTrace::default();
+        trace.observe(0, &blocks, &nodes);
+        trace.observe(1, &blocks, &nodes);
+        assert_eq!(trace.states().len(), 1);
+
+        // Only node 0 has split off into section 0 so far; the others are still in the genesis
+        // section, which stays in the state alongside it.
+        let p0 = Prefix::empty().pushed(false);
+        let id = blocks.insert(Block {
+            prefix: p0,
+            version: 1,
+            members: btreeset!{Name(0), Name(1 << 62)},
+        });
+        nodes.get_mut(&Name(0)).unwrap().current_blocks = btreeset!{id};
+        trace.observe(5, &blocks, &nodes);
+        let states = trace.states();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[1].0, 5);
+        assert_eq!(states[1].1[&p0], btreeset!{Name(0), Name(1 << 62)});
+        assert_eq!(states[1].1[&Prefix::empty()].len(), 4);
+
+        let mut itf = vec![];
+        trace.write_itf(&mut itf).unwrap();
+        let itf = String::from_utf8(itf).unwrap();
+        assert!(itf.contains("\"vars\":[\"step\",\"sections\"]"));
+        assert!(itf.contains(
+            "{\"#meta\":{\"index\":1},\"step\":5,\"sections\":{\"#map\":[[\"\",{\"#set\":[",
+        ));
+        assert!(itf.contains(
+            "[\"0\",{\"#set\":[\"0000000000000000\",\"4000000000000000\"]}]]}}\n]}",
+        ));
+    }
+}
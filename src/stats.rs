@@ -0,0 +1,334 @@
+//! Counters for node activity, aggregated across the network at the end of a run.
+
+use cohort::{Cohorts, DEFAULT_COHORT};
+use format::FormatKind;
+use name::Name;
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::AddAssign;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeStats {
+    /// Number of simulated vote signatures checked.
+    pub signatures_verified: u64,
+    /// Total cost of the signature checks, in units of `NodeParams::signature_cost`.
+    pub verification_cost: u64,
+    /// Number of signatures rejected because the signer wasn't a member of the voted-from block.
+    pub signatures_rejected: u64,
+    /// Number of vote agreements rejected because their voters weren't a quorum of members of
+    /// the voted-from block.
+    pub agreements_rejected: u64,
+    /// Number of merges we've seen become valid, counting each merged section once.
+    pub merges_agreed: u64,
+    /// Number of those merges which merged a section that was itself the result of a merge.
+    pub cascading_merges_agreed: u64,
+    /// Number of handshakes for our own connection requests that failed.
+    pub handshakes_failed: u64,
+    /// Number of connection attempts made again after a failed handshake.
+    pub connect_retries: u64,
+    /// Number of votes we've cast.
+    pub votes_proposed: u64,
+    /// Number of votes received from other nodes, individually or as part of an agreement.
+    pub votes_received: u64,
+    /// Number of blocks we've seen become valid.
+    pub blocks_agreed: u64,
+    /// Number of blocks which have stopped being current for us.
+    pub blocks_expired: u64,
+    /// Number of connection requests we've sent.
+    pub connects_initiated: u64,
+    /// Number of votes we've attached to connection requests.
+    pub votes_piggybacked: u64,
+    /// Number of votes attached to connection requests to us which were new to us.
+    pub piggybacked_votes_new: u64,
+    /// Number of joining nodes ignored because their section had too many candidates already.
+    pub candidates_refused: u64,
+    /// Number of joining nodes asked to try again later because our section was busy.
+    pub joins_deferred: u64,
+    /// Number of joining nodes asked to try again later because we'd only just removed them.
+    pub rejoins_deferred: u64,
+    /// Number of joins we've sent again after being asked to try later.
+    pub join_retries: u64,
+    /// Number of nodes we know of which we didn't connect to, or disconnected from, for being in
+    /// neither our section nor a neighbouring one.
+    pub connections_pruned: u64,
+    /// Number of connection requests we refused for lack of capacity.
+    pub connections_refused: u64,
+    /// Number of connections we dropped to make room for one to a nearer peer.
+    pub connections_evicted: u64,
+    /// Number of our connection requests given up for going unanswered.
+    pub connects_timed_out: u64,
+    /// Number of snapshots we've compacted our section's history into.
+    pub compactions: u64,
+    /// Number of votes dropped by compaction.
+    pub votes_compacted: u64,
+    /// Number of compactions which changed the agreed history, when verifying compactions.
+    pub compaction_mismatches: u64,
+    /// Number of votes we've sent to other nodes to bootstrap them.
+    pub bootstrap_votes_sent: u64,
+    /// Number of welcome messages we've sent to nodes newly added to our section.
+    pub welcome_msgs_sent: u64,
+    /// Number of times we've become a member of a section after joining it, rather than
+    /// starting out as one.
+    pub joins_completed: u64,
+    /// Total number of steps from our creation to becoming a member, over those joins.
+    pub steps_to_join: u64,
+    /// Number of votes we've forgotten from our cache.
+    pub votes_forgotten: u64,
+    /// Number of forgotten votes we've heard about again since.
+    pub votes_relearned: u64,
+    /// Number of times we've heard again about every vote we forgot.
+    pub memory_recoveries: u64,
+    /// Total number of steps from first forgetting votes to hearing about them all again, over
+    /// those recoveries.
+    pub steps_to_recover: u64,
+    /// Number of requests we've sent for the history of sections left out of our bootstrap.
+    pub chain_requests: u64,
+    /// Number of section messages we've signed.
+    pub section_shares_sent: u64,
+    /// Number of section messages we've sent once a quorum of our section signed them.
+    pub section_messages_sent: u64,
+    /// Number of section messages we've accepted from other sections.
+    pub section_messages_received: u64,
+    /// Number of section messages rejected for lacking a quorum of signers.
+    pub section_messages_rejected: u64,
+    /// Number of blocks we've seen agreed which remove a relocated node from its old section or
+    /// add it to its new one.
+    pub relocations_agreed: u64,
+    /// Number of times we've announced that we suspect a member of our section has left.
+    pub suspicions_raised: u64,
+    /// Number of suspicions we've withdrawn after reconnecting to the member.
+    pub suspicions_withdrawn: u64,
+    /// Number of votes we've withheld due to a fault.
+    pub votes_withheld: u64,
+    /// Number of messages we've dropped due to a fault.
+    pub messages_dropped_by_faults: u64,
+    /// Number of votes we've held back while waiting for our handover to finish.
+    pub votes_deferred: u64,
+    /// Number of messages we handled on a later step than they were delivered on, having run out
+    /// of processing budget.
+    pub messages_backlogged: u64,
+    /// Number of those messages which carried votes.
+    pub votes_backlogged: u64,
+    /// Total number of steps the backlogged messages waited to be handled.
+    pub backlog_wait_steps: u64,
+    /// Number of copies of the votes we cast that we've sent, one per recipient of each vote.
+    pub votes_sent: u64,
+    /// Number of messages we've sent carrying those copies, individually or batched.
+    pub vote_messages_sent: u64,
+    /// Total number of steps the votes we cast waited in a batch before being broadcast.
+    pub vote_batch_wait_steps: u64,
+    /// Number of votes we've received from blocks we didn't consider valid yet, which we held
+    /// back when validating their ancestry.
+    pub votes_unknown_ancestry: u64,
+    /// Total number of steps the held back votes waited for their blocks to become valid.
+    pub unknown_ancestry_wait_steps: u64,
+    /// Number of held back votes we gave up on, as their blocks fell behind our current blocks
+    /// or didn't become valid in time.
+    pub orphan_votes_dropped: u64,
+}
+
+impl AddAssign for NodeStats {
+    fn add_assign(&mut self, other: NodeStats) {
+        self.signatures_verified += other.signatures_verified;
+        self.verification_cost += other.verification_cost;
+        self.signatures_rejected += other.signatures_rejected;
+        self.agreements_rejected += other.agreements_rejected;
+        self.merges_agreed += other.merges_agreed;
+        self.cascading_merges_agreed += other.cascading_merges_agreed;
+        self.handshakes_failed += other.handshakes_failed;
+        self.connect_retries += other.connect_retries;
+        self.votes_proposed += other.votes_proposed;
+        self.votes_received += other.votes_received;
+        self.blocks_agreed += other.blocks_agreed;
+        self.blocks_expired += other.blocks_expired;
+        self.connects_initiated += other.connects_initiated;
+        self.votes_piggybacked += other.votes_piggybacked;
+        self.piggybacked_votes_new += other.piggybacked_votes_new;
+        self.candidates_refused += other.candidates_refused;
+        self.joins_deferred += other.joins_deferred;
+        self.rejoins_deferred += other.rejoins_deferred;
+        self.join_retries += other.join_retries;
+        self.connections_pruned += other.connections_pruned;
+        self.connections_refused += other.connections_refused;
+        self.connections_evicted += other.connections_evicted;
+        self.connects_timed_out += other.connects_timed_out;
+        self.compactions += other.compactions;
+        self.votes_compacted += other.votes_compacted;
+        self.compaction_mismatches += other.compaction_mismatches;
+        self.bootstrap_votes_sent += other.bootstrap_votes_sent;
+        self.welcome_msgs_sent += other.welcome_msgs_sent;
+        self.joins_completed += other.joins_completed;
+        self.steps_to_join += other.steps_to_join;
+        self.votes_forgotten += other.votes_forgotten;
+        self.votes_relearned += other.votes_relearned;
+        self.memory_recoveries += other.memory_recoveries;
+        self.steps_to_recover += other.steps_to_recover;
+        self.chain_requests += other.chain_requests;
+        self.section_shares_sent += other.section_shares_sent;
+        self.section_messages_sent += other.section_messages_sent;
+        self.section_messages_received += other.section_messages_received;
+        self.section_messages_rejected += other.section_messages_rejected;
+        self.relocations_agreed += other.relocations_agreed;
+        self.suspicions_raised += other.suspicions_raised;
+        self.suspicions_withdrawn += other.suspicions_withdrawn;
+        self.votes_withheld += other.votes_withheld;
+        self.messages_dropped_by_faults += other.messages_dropped_by_faults;
+        self.votes_deferred += other.votes_deferred;
+        self.messages_backlogged += other.messages_backlogged;
+        self.votes_backlogged += other.votes_backlogged;
+        self.backlog_wait_steps += other.backlog_wait_steps;
+        self.votes_sent += other.votes_sent;
+        self.vote_messages_sent += other.vote_messages_sent;
+        self.vote_batch_wait_steps += other.vote_batch_wait_steps;
+        self.votes_unknown_ancestry += other.votes_unknown_ancestry;
+        self.unknown_ancestry_wait_steps += other.unknown_ancestry_wait_steps;
+        self.orphan_votes_dropped += other.orphan_votes_dropped;
+    }
+}
+
+/// Write the stats of each node as CSV, one row per node, with the cohort each node is in.
+pub fn write_node_stats_csv<W: Write>(
+    stats: &BTreeMap<Name, NodeStats>,
+    cohorts: Option<&Cohorts>,
+    writer: &mut W,
+) -> io::Result<()> {
+    writeln!(writer, "{}", FormatKind::NodeStats.run_header())?;
+    writeln!(
+        writer,
+        "node,cohort,votes_proposed,votes_received,blocks_agreed,blocks_expired,connects_initiated,\
+         connect_retries,handshakes_failed,signatures_rejected"
+    )?;
+    for (name, stats) in stats {
+        writeln!(
+            writer,
+            "{:016x},{},{},{},{},{},{},{},{},{}",
+            name.0,
+            cohorts.map_or(DEFAULT_COHORT, |cohorts| cohorts.cohort_of(*name)),
+            stats.votes_proposed,
+            stats.votes_received,
+            stats.blocks_agreed,
+            stats.blocks_expired,
+            stats.connects_initiated,
+            stats.connect_retries,
+            stats.handshakes_failed,
+            stats.signatures_rejected
+        )?;
+    }
+    Ok(())
+}
+
+/// Distribution of the number of joining nodes waiting to become members of each section,
+/// sampled for every section on every step.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CandidateStats {
+    /// Map from number of candidates to the number of samples with that many.
+    pub distribution: BTreeMap<usize, u64>,
+}
+
+impl CandidateStats {
+    /// Record that a section had `num_candidates` candidates on a step.
+    pub fn record(&mut self, num_candidates: usize) {
+        *self.distribution.entry(num_candidates).or_insert(0) += 1;
+    }
+
+    /// Largest number of candidates seen for a single section.
+    pub fn max(&self) -> usize {
+        self.distribution.keys().next_back().cloned().unwrap_or(0)
+    }
+
+    /// Mean number of candidates per section.
+    pub fn mean(&self) -> f64 {
+        let samples: u64 = self.distribution.values().sum();
+        let total: u64 = self.distribution
+            .iter()
+            .map(|(&num, &count)| num as u64 * count)
+            .sum();
+        total as f64 / cmp::max(samples, 1) as f64
+    }
+}
+
+/// How often sections were left without a quorum of voting members by members still waiting for
+/// their handover, sampled for every section on every step.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HandoverStats {
+    /// Number of samples.
+    pub section_steps: u64,
+    /// Number of samples in which too few members were voting to reach a quorum.
+    pub stalled_section_steps: u64,
+}
+
+impl HandoverStats {
+    /// Fraction of samples in which a section was stalled.
+    pub fn stall_rate(&self) -> f64 {
+        self.stalled_section_steps as f64 / cmp::max(self.section_steps, 1) as f64
+    }
+}
+
+/// How long pairs of nodes stayed disconnected before reconnecting.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReconnectStats {
+    /// Map from number of steps disconnected to the number of reconnections after that many.
+    pub durations: BTreeMap<u64, u64>,
+}
+
+impl ReconnectStats {
+    /// Record that a pair reconnected after `steps` steps.
+    pub fn record(&mut self, steps: u64) {
+        *self.durations.entry(steps).or_insert(0) += 1;
+    }
+
+    /// Number of reconnections.
+    pub fn count(&self) -> u64 {
+        self.durations.values().sum()
+    }
+
+    /// Longest disconnection which ended in a reconnection.
+    pub fn max(&self) -> u64 {
+        self.durations.keys().next_back().cloned().unwrap_or(0)
+    }
+
+    /// Mean number of steps disconnected.
+    pub fn mean(&self) -> f64 {
+        let total: u64 = self.durations
+            .iter()
+            .map(|(&steps, &count)| steps * count)
+            .sum();
+        total as f64 / cmp::max(self.count(), 1) as f64
+    }
+}
+
+/// Numbers of messages of one kind over a single step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MessageKindCounts {
+    /// Sent during the step, including any the network dropped.
+    pub sent: u64,
+    /// Delivered during the step.
+    pub delivered: u64,
+    /// Still in flight at the end of the step.
+    pub in_queue: u64,
+}
+
+/// Outcomes of nodes' attempts to join the network.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JoinStats {
+    /// Number of joining nodes which became members of a section.
+    pub joined: u64,
+    /// Number of joining nodes which shut down or left before becoming members.
+    pub rejected: u64,
+    /// Total number of steps taken by the successful joins.
+    pub total_latency: u64,
+}
+
+impl JoinStats {
+    /// Mean number of steps between a node joining and it being part of a section.
+    pub fn mean_latency(&self) -> f64 {
+        self.total_latency as f64 / cmp::max(self.joined, 1) as f64
+    }
+
+    /// Fraction of finished join attempts which failed.
+    pub fn rejection_rate(&self) -> f64 {
+        self.rejected as f64 / cmp::max(self.joined + self.rejected, 1) as f64
+    }
+}
@@ -0,0 +1,398 @@
+//! Repeated runs across seeds and parameter settings, with statistical summaries.
+//!
+//! A sweep is a set of cells, one per combination of parameters being compared. Each cell is run
+//! once per seed, and the summary gives each metric's mean with a 95% confidence interval, and
+//! each cell's failure rate with a test of whether it differs from a baseline cell.
+
+use error::Result;
+use format::FormatKind;
+use random::reseed;
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// Two-sided 95% critical values of Student's t distribution, for 1 to 30 degrees of freedom.
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// Two-sided 95% critical value of the normal distribution.
+const Z_95: f64 = 1.96;
+
+/// Outcome of a single run.
+#[derive(Clone, Debug)]
+pub struct RunOutcome {
+    pub seed: [u32; 4],
+    /// Why the run failed, if it did.
+    pub error: Option<String>,
+    /// Metrics reported by a successful run.
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// Mean of a metric over a cell's successful runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetricSummary {
+    pub runs: usize,
+    pub mean: f64,
+    /// Sample standard deviation.
+    pub std_dev: f64,
+    /// Half-width of the 95% confidence interval for the mean.
+    pub ci95: f64,
+}
+
+/// Summary of all runs of a cell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellSummary {
+    pub runs: usize,
+    pub failures: usize,
+    /// 95% confidence interval for the failure rate (Wilson score interval).
+    pub failure_rate_ci: (f64, f64),
+    pub metrics: BTreeMap<String, MetricSummary>,
+}
+
+impl CellSummary {
+    pub fn failure_rate(&self) -> f64 {
+        self.failures as f64 / cmp::max(self.runs, 1) as f64
+    }
+}
+
+/// Comparison of a cell's failure rate against a baseline's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateComparison {
+    /// Failure rate of the cell minus that of the baseline.
+    pub difference: f64,
+    /// Probability of a difference at least this large if the rates were equal (two-proportion
+    /// z-test).
+    pub p_value: f64,
+}
+
+/// Outcomes of a sweep, grouped by cell.
+#[derive(Clone, Debug, Default)]
+pub struct Sweep {
+    cells: BTreeMap<String, Vec<RunOutcome>>,
+}
+
+impl Sweep {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `run` once for each seed, reseeding the random number generator beforehand, and
+    /// record the outcomes under `cell`.
+    pub fn run_cell<F>(&mut self, cell: &str, seeds: &[[u32; 4]], mut run: F)
+    where
+        F: FnMut() -> Result<BTreeMap<String, f64>>,
+    {
+        for &seed in seeds {
+            reseed(seed);
+            let outcome = match run() {
+                Ok(metrics) => {
+                    RunOutcome {
+                        seed,
+                        error: None,
+                        metrics,
+                    }
+                }
+                Err(err) => {
+                    RunOutcome {
+                        seed,
+                        error: Some(err.to_string()),
+                        metrics: BTreeMap::new(),
+                    }
+                }
+            };
+            self.record(cell, outcome);
+        }
+    }
+
+    /// Record the outcome of a run made elsewhere.
+    pub fn record(&mut self, cell: &str, outcome: RunOutcome) {
+        self.cells.entry(cell.to_string()).or_default().push(outcome);
+    }
+
+    /// Outcomes of every run of a cell.
+    pub fn outcomes(&self, cell: &str) -> &[RunOutcome] {
+        self.cells.get(cell).map(|outcomes| &outcomes[..]).unwrap_or(&[])
+    }
+
+    /// Summary of every cell.
+    pub fn summaries(&self) -> BTreeMap<String, CellSummary> {
+        self.cells
+            .iter()
+            .map(|(cell, outcomes)| (cell.clone(), summarise(outcomes)))
+            .collect()
+    }
+
+    /// Summary of a cell, if it has been run.
+    pub fn summary(&self, cell: &str) -> Option<CellSummary> {
+        self.cells.get(cell).map(|outcomes| summarise(outcomes))
+    }
+
+    /// Compare the failure rate of `cell` against that of `baseline`, if both have been run.
+    pub fn compare_failure_rates(&self, baseline: &str, cell: &str) -> Option<RateComparison> {
+        let baseline = summarise(self.cells.get(baseline)?);
+        let cell = summarise(self.cells.get(cell)?);
+        Some(compare_rates(
+            (baseline.failures, baseline.runs),
+            (cell.failures, cell.runs),
+        ))
+    }
+
+    /// Write the summary as a Markdown table, one row per cell, comparing failure rates against
+    /// `baseline` if given.
+    pub fn write_markdown<W: Write>(
+        &self,
+        baseline: Option<&str>,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let summaries = self.summaries();
+        let metrics: BTreeSet<&String> =
+            summaries.values().flat_map(|summary| summary.metrics.keys()).collect();
+
+        write!(writer, "| cell | runs | failure rate (95% CI) |")?;
+        if baseline.is_some() {
+            write!(writer, " p vs baseline |")?;
+        }
+        for metric in &metrics {
+            write!(writer, " {} |", metric)?;
+        }
+        writeln!(writer)?;
+        let num_columns = 3 + baseline.iter().count() + metrics.len();
+        writeln!(writer, "|{}", "---|".repeat(num_columns))?;
+
+        for (cell, summary) in &summaries {
+            let (low, high) = summary.failure_rate_ci;
+            write!(
+                writer,
+                "| {} | {} | {:.1}% ({:.1}%-{:.1}%) |",
+                cell,
+                summary.runs,
+                100.0 * summary.failure_rate(),
+                100.0 * low,
+                100.0 * high
+            )?;
+            if let Some(baseline) = baseline {
+                match self.compare_failure_rates(baseline, cell) {
+                    Some(ref comparison) if baseline != cell => {
+                        write!(writer, " {:.3} |", comparison.p_value)?
+                    }
+                    _ => write!(writer, " - |")?,
+                }
+            }
+            for metric in &metrics {
+                match summary.metrics.get(*metric) {
+                    Some(stats) => write!(writer, " {:.2} ± {:.2} |", stats.mean, stats.ci95)?,
+                    None => write!(writer, " - |")?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write the summary as CSV, one row per metric of each cell. Cells without metrics get a
+    /// single row with the metric columns empty.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", FormatKind::SweepSummary.header())?;
+        writeln!(
+            writer,
+            "cell,runs,failures,failure_rate,failure_rate_low,failure_rate_high,metric,metric_runs,\
+             mean,std_dev,ci95"
+        )?;
+        for (cell, summary) in self.summaries() {
+            let (low, high) = summary.failure_rate_ci;
+            let prefix = format!(
+                "{},{},{},{},{},{}",
+                cell,
+                summary.runs,
+                summary.failures,
+                summary.failure_rate(),
+                low,
+                high
+            );
+            if summary.metrics.is_empty() {
+                writeln!(writer, "{},,,,,", prefix)?;
+            }
+            for (metric, stats) in &summary.metrics {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{}",
+                    prefix,
+                    metric,
+                    stats.runs,
+                    stats.mean,
+                    stats.std_dev,
+                    stats.ci95
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn summarise(outcomes: &[RunOutcome]) -> CellSummary {
+    let failures = outcomes.iter().filter(|outcome| outcome.error.is_some()).count();
+
+    let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for outcome in outcomes.iter().filter(|outcome| outcome.error.is_none()) {
+        for (metric, value) in &outcome.metrics {
+            values.entry(metric.clone()).or_default().push(*value);
+        }
+    }
+
+    CellSummary {
+        runs: outcomes.len(),
+        failures,
+        failure_rate_ci: wilson_interval(failures, outcomes.len()),
+        metrics: values
+            .into_iter()
+            .map(|(metric, values)| (metric, summarise_metric(&values)))
+            .collect(),
+    }
+}
+
+fn summarise_metric(values: &[f64]) -> MetricSummary {
+    let n = values.len();
+    let mean = values.iter().sum::<f64>() / n as f64;
+    if n < 2 {
+        return MetricSummary {
+            runs: n,
+            mean,
+            std_dev: 0.0,
+            ci95: 0.0,
+        };
+    }
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() /
+        (n - 1) as f64;
+    let std_dev = variance.sqrt();
+    let critical = T_95.get(n - 2).cloned().unwrap_or(Z_95);
+    MetricSummary {
+        runs: n,
+        mean,
+        std_dev,
+        ci95: critical * std_dev / (n as f64).sqrt(),
+    }
+}
+
+/// 95% Wilson score interval for a proportion of `successes` out of `trials`.
+fn wilson_interval(successes: usize, trials: usize) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let denominator = 1.0 + z2 / n;
+    let centre = (p + z2 / (2.0 * n)) / denominator;
+    let half_width = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+    ((centre - half_width).max(0.0), (centre + half_width).min(1.0))
+}
+
+/// Two-proportion z-test of `(failures, runs)` pairs.
+fn compare_rates(baseline: (usize, usize), other: (usize, usize)) -> RateComparison {
+    let (x1, n1) = (baseline.0 as f64, cmp::max(baseline.1, 1) as f64);
+    let (x2, n2) = (other.0 as f64, cmp::max(other.1, 1) as f64);
+    let difference = x2 / n2 - x1 / n1;
+    let pooled = (x1 + x2) / (n1 + n2);
+    let std_err = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2)).sqrt();
+    let p_value = if std_err == 0.0 {
+        1.0
+    } else {
+        2.0 * (1.0 - normal_cdf((difference / std_err).abs()))
+    };
+    RateComparison {
+        difference,
+        p_value,
+    }
+}
+
+/// Cumulative distribution function of the standard normal distribution.
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / 2f64.sqrt()))
+}
+
+/// Error function, to within about 1.5e-7 (Abramowitz and Stegun 7.1.26).
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let poly = t *
+        (0.254_829_592 +
+             t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+    use random::random;
+
+    fn outcome(error: bool, value: f64) -> RunOutcome {
+        RunOutcome {
+            seed: [0; 4],
+            error: if error { Some("failed".to_string()) } else { None },
+            metrics: btreemap!{ "steps".to_string() => value },
+        }
+    }
+
+    #[test]
+    fn cell_summary() {
+        let mut sweep = Sweep::new();
+        for &value in &[2.0, 4.0, 6.0] {
+            sweep.record("a", outcome(false, value));
+        }
+        sweep.record("a", outcome(true, 100.0));
+
+        let summary = &sweep.summaries()["a"];
+        assert_eq!(summary.runs, 4);
+        assert_eq!(summary.failures, 1);
+        let (low, high) = summary.failure_rate_ci;
+        assert!(low < 0.25 && 0.25 < high);
+
+        let steps = summary.metrics["steps"];
+        assert_eq!(steps.runs, 3);
+        assert!((steps.mean - 4.0).abs() < 1e-9);
+        assert!((steps.std_dev - 2.0).abs() < 1e-9);
+        assert!((steps.ci95 - 4.303 * 2.0 / 3f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn failure_rate_comparison() {
+        let mut sweep = Sweep::new();
+        for i in 0..100 {
+            sweep.record("baseline", outcome(i < 5, 0.0));
+            sweep.record("same", outcome(i < 6, 0.0));
+            sweep.record("worse", outcome(i < 30, 0.0));
+        }
+        assert!(sweep.compare_failure_rates("baseline", "same").unwrap().p_value > 0.5);
+        assert!(sweep.compare_failure_rates("baseline", "worse").unwrap().p_value < 0.001);
+        assert!(sweep.compare_failure_rates("baseline", "missing").is_none());
+
+        let mut markdown = vec![];
+        sweep.write_markdown(Some("baseline"), &mut markdown).unwrap();
+        assert_eq!(String::from_utf8(markdown).unwrap().lines().count(), 5);
+    }
+
+    #[test]
+    fn runs_are_reseeded() {
+        let seeds = [[1, 2, 3, 4], [5, 6, 7, 8], [1, 2, 3, 4]];
+        let mut sweep = Sweep::new();
+        sweep.run_cell("cell", &seeds, || {
+            let value = random::<u32>();
+            if value % 2 == 0 {
+                Ok(btreemap!{ "value".to_string() => value as f64 })
+            } else {
+                Err(Error::Config(value.to_string()))
+            }
+        });
+
+        let outcomes = sweep.outcomes("cell");
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[0].error, outcomes[2].error);
+        assert_eq!(outcomes[0].metrics, outcomes[2].metrics);
+    }
+}
@@ -0,0 +1,111 @@
+//! Sybil attacks: flooding a victim section with joining nodes controlled by an attacker.
+
+use blocks::Blocks;
+use event::Event;
+use name::{Name, Prefix};
+use node::NodeTrait;
+use params::quorum;
+use random::{random_name, do_with_probability};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug)]
+pub struct SybilAttack {
+    /// Prefix that the attacker's node names are generated within.
+    pub target: Prefix,
+    /// Probability of an attacking node joining on any given step of the attack.
+    pub prob_join: f64,
+    /// Step at which the attack begins.
+    pub start_step: u64,
+    /// Step at which the attack stops. `None` to keep attacking until the simulation ends.
+    pub end_step: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SybilReport {
+    /// Number of attacking nodes that have tried to join.
+    pub join_attempts: usize,
+    /// Number of attacking nodes that have been members of a section, as agreed by honest nodes.
+    pub attackers_joined: usize,
+    /// Greatest fraction of a section under the target prefix held by the attacker.
+    pub max_share: f64,
+    /// First step at which the attacker held a quorum of a section, and that section's prefix.
+    pub quorum_reached: Option<(u64, Prefix)>,
+}
+
+/// Generates attacking joins and tracks the attacker's share of the victim sections.
+#[derive(Clone)]
+pub struct SybilTracker {
+    attack: SybilAttack,
+    attackers: BTreeSet<Name>,
+    /// Attacking nodes that honest nodes have agreed are members of a section.
+    joined: BTreeSet<Name>,
+    report: SybilReport,
+}
+
+impl SybilTracker {
+    pub fn new(attack: SybilAttack) -> Self {
+        SybilTracker {
+            attack,
+            attackers: BTreeSet::new(),
+            joined: BTreeSet::new(),
+            report: SybilReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &SybilReport {
+        &self.report
+    }
+
+    fn is_active(&self, step: u64) -> bool {
+        step >= self.attack.start_step && self.attack.end_step.map(|end| step < end).unwrap_or(true)
+    }
+
+    /// Attacking joins to occur at the given step.
+    pub fn get_events(&mut self, step: u64) -> Vec<Event> {
+        if !self.is_active(step) || !do_with_probability(self.attack.prob_join) {
+            return vec![];
+        }
+        let name = self.attack.target.substituted_in(random_name());
+        self.attackers.insert(name);
+        self.report.join_attempts += 1;
+        vec![Event::AddNode(name)]
+    }
+
+    /// Update the attacker's share of sections under the target prefix, as agreed by honest nodes.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, blocks: &Blocks, nodes: &BTreeMap<Name, N>) {
+        // Inserted one at a time, as collecting them sorts them by `Block`'s derived partial
+        // order, which goes through `Prefix`'s and so disagrees with the total order across a
+        // split.
+        let mut sections = BTreeSet::new();
+        for (_, node) in nodes.iter().filter(|&(name, _)| !self.attackers.contains(name)) {
+            for block in blocks.block_contents(node.current_blocks()) {
+                if block.prefix.is_compatible(&self.attack.target) {
+                    let _ = sections.insert(block);
+                }
+            }
+        }
+
+        for block in sections {
+            let attackers: Vec<_> = block.members.intersection(&self.attackers).collect();
+            let num_attackers = attackers.len();
+            self.joined.extend(attackers);
+            self.report.attackers_joined = self.joined.len();
+            let share = num_attackers as f64 / block.members.len() as f64;
+            if share > self.report.max_share {
+                self.report.max_share = share;
+            }
+            if self.report.quorum_reached.is_none() &&
+                num_attackers >= quorum(block.members.len())
+            {
+                warn!(
+                    "Sybil attacker holds {} of {} members of {:?}",
+                    num_attackers,
+                    block.members.len(),
+                    block.prefix
+                );
+                self.report.quorum_reached = Some((step, block.prefix));
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Replay of churn traces, such as joins and leaves measured in real peer-to-peer networks, in
+//! place of the random joins and leaves drawn each step.
+//!
+//! A trace is a CSV file with a line for each join or leave: `<time>,join|leave[,<peer>]`. Blank
+//! lines, lines starting with `#` and a `time,...` header line are skipped. Times are in any
+//! unit, and are mapped onto steps by a scale factor, counting from the step the replay starts
+//! at and the earliest time in the trace.
+//!
+//! Joining nodes are named according to the join policy. A leave naming a peer which joined
+//! earlier in the trace removes that peer's node, if it's still running; other leaves remove a
+//! node chosen by the drop policy, as random leaves do.
+
+use blocks::Blocks;
+use error::{Error, Result};
+use event::Event;
+use name::Name;
+use node::NodeTrait;
+use random_events::RandomEvents;
+
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceAction {
+    Join,
+    Leave,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub time: f64,
+    pub action: TraceAction,
+    /// Identifier of the peer in the trace, if given.
+    pub peer: Option<String>,
+}
+
+/// Joins and leaves in the order they occurred.
+#[derive(Clone, Debug, Default)]
+pub struct ChurnTrace {
+    entries: Vec<TraceEntry>,
+}
+
+impl ChurnTrace {
+    /// Parse a trace from the contents of a CSV file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("time,") {
+                continue;
+            }
+            let entry = parse_entry(line).map_err(|msg| {
+                Error::Serialization(format!("trace line {}: {}", index + 1, msg))
+            })?;
+            entries.push(entry);
+        }
+        // Stable, so that entries at the same time keep their order.
+        entries.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(ChurnTrace { entries })
+    }
+
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+}
+
+fn parse_entry(line: &str) -> ::std::result::Result<TraceEntry, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let (time, action, peer) = match *fields {
+        [time, action] => (time, action, None),
+        [time, action, peer] if !peer.is_empty() => (time, action, Some(peer.to_string())),
+        _ => return Err(format!("expected time,join|leave[,peer], not {:?}", line)),
+    };
+    let time = time.parse::<f64>().ok().filter(|time| time.is_finite()).ok_or_else(|| {
+        format!("invalid time {}", time)
+    })?;
+    let action = match action {
+        "join" => TraceAction::Join,
+        "leave" => TraceAction::Leave,
+        _ => return Err(format!("unknown action {}, expected join or leave", action)),
+    };
+    Ok(TraceEntry { time, action, peer })
+}
+
+/// Counts of the trace entries replayed so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub joins: u64,
+    pub leaves: u64,
+    /// Leaves which couldn't be replayed, as their peer had already gone or no node could be
+    /// removed without leaving its section too small.
+    pub leaves_skipped: u64,
+}
+
+/// Replays a trace, turning the entries due at each step into events.
+#[derive(Clone)]
+pub struct TraceReplay {
+    trace: ChurnTrace,
+    /// Number of steps per unit of trace time.
+    steps_per_unit: f64,
+    /// Step that the earliest entry in the trace is mapped to, once the replay has started.
+    start_step: Option<u64>,
+    /// Index of the next entry to replay.
+    next: usize,
+    /// Names of the nodes created for peers in the trace.
+    peers: BTreeMap<String, Name>,
+    stats: ReplayStats,
+}
+
+impl TraceReplay {
+    pub fn new(trace: ChurnTrace, steps_per_unit: f64) -> Result<Self> {
+        if !(steps_per_unit.is_finite() && steps_per_unit > 0.0) {
+            return Err(Error::Config(format!(
+                "trace scale must be a positive number of steps, not {}",
+                steps_per_unit
+            )));
+        }
+        Ok(TraceReplay {
+            trace,
+            steps_per_unit,
+            start_step: None,
+            next: 0,
+            peers: BTreeMap::new(),
+            stats: ReplayStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> &ReplayStats {
+        &self.stats
+    }
+
+    /// Number of entries not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.trace.entries.len() - self.next
+    }
+
+    /// The step an entry is due at.
+    fn step_of(&self, entry: &TraceEntry, start_step: u64) -> u64 {
+        let first_time = self.trace.entries[0].time;
+        start_step + ((entry.time - first_time) * self.steps_per_unit) as u64
+    }
+
+    /// The joins and leaves due at `step`, or before it if no events were fetched for a while.
+    pub fn get_events<N: NodeTrait>(
+        &mut self,
+        step: u64,
+        random_events: &RandomEvents,
+        blocks: &Blocks,
+        nodes: &BTreeMap<Name, N>,
+    ) -> Vec<Event> {
+        let start_step = *self.start_step.get_or_insert(step);
+        let mut events = vec![];
+        while self.next < self.trace.entries.len() &&
+            self.step_of(&self.trace.entries[self.next], start_step) <= step
+        {
+            let entry = self.trace.entries[self.next].clone();
+            self.next += 1;
+            match entry.action {
+                TraceAction::Join => {
+                    let event = random_events.random_add(blocks, nodes);
+                    if let (Some(peer), &Event::AddNode(name)) = (entry.peer, &event) {
+                        let _ = self.peers.insert(peer, name);
+                    }
+                    self.stats.joins += 1;
+                    events.push(event);
+                }
+                TraceAction::Leave => {
+                    let event = match entry.peer.and_then(|peer| self.peers.remove(&peer)) {
+                        Some(name) if nodes.contains_key(&name) => Some(Event::RemoveNode(name)),
+                        Some(_) => None,
+                        None => random_events.random_remove(blocks, nodes),
+                    };
+                    match event {
+                        Some(event) => {
+                            self.stats.leaves += 1;
+                            events.push(event);
+                        }
+                        None => self.stats.leaves_skipped += 1,
+                    }
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use node::Node;
+    use params::{NodeParams, SimulationParams};
+
+    #[test]
+    fn parse_and_scale() {
+        let trace = ChurnTrace::parse(
+            "time,event,peer\n\
+             # measured in seconds\n\
+             2.5,leave\n\
+             0.0,join,a\n\
+             1.0,join,b\n\
+             1.5,leave,a\n",
+        ).unwrap();
+        let actions: Vec<_> = trace.entries().iter().map(|entry| entry.action).collect();
+        assert_eq!(
+            actions,
+            vec![TraceAction::Join, TraceAction::Join, TraceAction::Leave, TraceAction::Leave]
+        );
+        assert!(ChurnTrace::parse("1.0,jump").is_err());
+        assert!(ChurnTrace::parse("soon,join").is_err());
+        assert!(TraceReplay::new(trace.clone(), 0.0).is_err());
+
+        // With 4 steps a second, starting at step 10, entries are due at steps 10, 14, 16 and 20.
+        let mut replay = TraceReplay::new(trace, 4.0).unwrap();
+        let random_events = RandomEvents::new(SimulationParams::default(), NodeParams::default());
+        let blocks = Blocks::new();
+        let nodes: BTreeMap<Name, Node> = BTreeMap::new();
+        let due: Vec<_> = (10..16)
+            .map(|step| replay.get_events(step, &random_events, &blocks, &nodes).len())
+            .collect();
+        assert_eq!(due, vec![1, 0, 0, 0, 1, 0]);
+        assert_eq!(replay.remaining(), 2);
+
+        // The nodes never started running, so peer a's leave is skipped, and there are no nodes
+        // to remove for the anonymous leave either.
+        assert!(replay.get_events(20, &random_events, &blocks, &nodes).is_empty());
+        let stats = replay.stats();
+        assert_eq!((stats.joins, stats.leaves, stats.leaves_skipped), (2, 0, 2));
+        assert_eq!(replay.remaining(), 0);
+    }
+}
@@ -0,0 +1,209 @@
+//! Audit trail of blocks becoming valid on each node.
+//!
+//! Useful for seeing how long agreement takes to spread through the network, and for spotting
+//! nodes whose view lags behind the rest.
+
+use block::BlockId;
+use name::Name;
+use node::NodeTrait;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A block becoming valid on a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidityTransition {
+    pub node: Name,
+    pub block: BlockId,
+    pub step: u64,
+}
+
+/// A block which was valid on some nodes but still pending on others for too long.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disagreement {
+    pub block: BlockId,
+    /// Step at which the first node accepted the block.
+    pub first_step: u64,
+    /// Step at which the disagreement was detected.
+    pub step: u64,
+    /// Live nodes which still didn't consider the block valid.
+    pub lagging: BTreeSet<Name>,
+}
+
+/// Steps between the first and last node to accept a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AcceptanceSpread {
+    pub first_step: u64,
+    pub last_step: u64,
+    /// Number of nodes which accepted the block.
+    pub num_nodes: usize,
+}
+
+impl AcceptanceSpread {
+    pub fn steps(&self) -> u64 {
+        self.last_step - self.first_step
+    }
+}
+
+#[derive(Clone)]
+struct NodeView {
+    /// Step at which the node was first observed.
+    since: u64,
+    /// Blocks the node has considered valid at some point.
+    accepted: BTreeSet<BlockId>,
+}
+
+/// Record of every block becoming valid on every node.
+///
+/// Blocks a node already holds when it's first observed, e.g. genesis blocks, aren't counted
+/// as transitions.
+#[derive(Clone)]
+pub struct ValidityAudit {
+    /// Maximum number of steps node views may disagree before it's recorded.
+    max_disagreement: u64,
+    views: BTreeMap<Name, NodeView>,
+    transitions: Vec<ValidityTransition>,
+    /// Blocks by the step at which they were first accepted by any node.
+    first_accepted: BTreeMap<BlockId, u64>,
+    disagreements: Vec<Disagreement>,
+}
+
+impl ValidityAudit {
+    pub fn new(max_disagreement: u64) -> Self {
+        ValidityAudit {
+            max_disagreement,
+            views: BTreeMap::new(),
+            transitions: vec![],
+            first_accepted: BTreeMap::new(),
+            disagreements: vec![],
+        }
+    }
+
+    /// Record blocks which have become valid on any node by the end of `step`.
+    pub fn observe<N: NodeTrait>(&mut self, step: u64, nodes: &BTreeMap<Name, N>) {
+        for (name, node) in nodes {
+            let valid = node.valid_blocks();
+            let view = self.views.entry(*name).or_insert_with(|| {
+                NodeView {
+                    since: step,
+                    accepted: valid.clone(),
+                }
+            });
+            for block in valid {
+                if view.accepted.insert(*block) {
+                    self.transitions.push(ValidityTransition {
+                        node: *name,
+                        block: *block,
+                        step,
+                    });
+                    self.first_accepted.entry(*block).or_insert(step);
+                }
+            }
+        }
+
+        if step < self.max_disagreement {
+            return;
+        }
+        let first_step = step - self.max_disagreement;
+        for (block, _) in self.first_accepted.iter().filter(|&(_, &s)| s == first_step) {
+            let lagging: BTreeSet<Name> = nodes
+                .keys()
+                .filter(|name| {
+                    let view = &self.views[name];
+                    view.since <= first_step && !view.accepted.contains(block)
+                })
+                .cloned()
+                .collect();
+            if !lagging.is_empty() {
+                self.disagreements.push(Disagreement {
+                    block: *block,
+                    first_step,
+                    step,
+                    lagging,
+                });
+            }
+        }
+    }
+
+    /// Every block becoming valid on a node, in the order they were observed.
+    pub fn transitions(&self) -> &[ValidityTransition] {
+        &self.transitions
+    }
+
+    /// Blocks which some live nodes still didn't accept `max_disagreement` steps after the
+    /// first node did.
+    pub fn disagreements(&self) -> &[Disagreement] {
+        &self.disagreements
+    }
+
+    /// For each block, the spread between the first and last node to accept it.
+    ///
+    /// Only nodes which were already running when the block was first accepted are counted, so
+    /// that nodes catching up on history when they join don't distort the spread.
+    pub fn acceptance_spreads(&self) -> BTreeMap<BlockId, AcceptanceSpread> {
+        let mut spreads: BTreeMap<BlockId, AcceptanceSpread> = BTreeMap::new();
+        for transition in &self.transitions {
+            let first_step = self.first_accepted[&transition.block];
+            if self.views[&transition.node].since > first_step {
+                continue;
+            }
+            let spread = spreads.entry(transition.block).or_insert(AcceptanceSpread {
+                first_step,
+                last_step: first_step,
+                num_nodes: 0,
+            });
+            spread.last_step = transition.step;
+            spread.num_nodes += 1;
+        }
+        spreads
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use block::Block;
+    use blocks::Blocks;
+    use name::Prefix;
+    use node::Node;
+    use params::NodeParams;
+
+    #[test]
+    fn spread_and_disagreement() {
+        let mut blocks = Blocks::new();
+        let genesis = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 0,
+            members: btreeset!{Name(0), Name(1)},
+        });
+        let next = blocks.insert(Block {
+            prefix: Prefix::empty(),
+            version: 1,
+            members: btreeset!{Name(0), Name(1), Name(2)},
+        });
+
+        let mut nodes = BTreeMap::new();
+        for name in &[Name(0), Name(1)] {
+            let node = Node::new(*name, &blocks, btreeset!{genesis}, NodeParams::default(), 0);
+            nodes.insert(*name, node);
+        }
+
+        let mut audit = ValidityAudit::new(2);
+        audit.observe(0, &nodes);
+        assert!(audit.transitions().is_empty());
+
+        nodes.get_mut(&Name(0)).unwrap().learn_blocks(&btreeset!{next});
+        audit.observe(1, &nodes);
+        audit.observe(2, &nodes);
+        audit.observe(3, &nodes);
+        assert_eq!(audit.disagreements().len(), 1);
+        assert_eq!(audit.disagreements()[0].lagging, btreeset!{Name(1)});
+
+        nodes.get_mut(&Name(1)).unwrap().learn_blocks(&btreeset!{next});
+        audit.observe(4, &nodes);
+        assert_eq!(audit.transitions().len(), 2);
+
+        let spread = audit.acceptance_spreads()[&next];
+        assert_eq!(spread.steps(), 3);
+        assert_eq!(spread.num_nodes, 2);
+    }
+}
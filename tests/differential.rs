@@ -0,0 +1,385 @@
+extern crate ewok;
+#[macro_use]
+extern crate maplit;
+
+use ewok::block::BlockId;
+use ewok::blocks::{Blocks, CurrentBlocks};
+use ewok::chain::Chain;
+use ewok::differential::run_differential;
+use ewok::event::Event::*;
+use ewok::event_schedule::EventSchedule;
+use ewok::logging::init_logging;
+use ewok::message::Message;
+use ewok::name::{Name, Prefix};
+use ewok::node::{Node, NodeTrait};
+use ewok::params::{SimulationParams, NodeParams, JoinPolicy, DropPolicy, BootstrapStrategy,
+                   DelayModel, GenesisNodes, ProcessingOrder, ReconnectModel};
+use ewok::random::reseed;
+use ewok::simulation::Simulation;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::process;
+
+fn default_params() -> SimulationParams {
+    SimulationParams {
+        max_delay: 5,
+        message_ttl: None,
+        delay_model: DelayModel::PerMessage,
+        grow_prob_join: 0.0,
+        grow_prob_drop: 0.0,
+        prob_churn: 0.0,
+        shrink_prob_join: 0.0,
+        shrink_prob_drop: 0.0,
+        prob_disconnect: 0.0,
+        reconnect: ReconnectModel::Constant(0.0),
+        starting_complete: 0,
+        grow_complete: 0,
+        stable_steps: 1000,
+        join_policy: JoinPolicy::Uniform,
+        drop_policy: DropPolicy::Uniform,
+        bootstrap: BootstrapStrategy::AllNodes,
+        processing_order: ProcessingOrder::ByName,
+        genesis_nodes: GenesisNodes::Count(1),
+        max_nodes: None,
+        max_total_steps: None,
+    }
+}
+
+// Running the same implementation twice from the same seed must never diverge.
+#[test]
+fn same_implementation_never_diverges() {
+    init_logging();
+
+    let node_params = NodeParams::default();
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size,
+    };
+    let schedule = EventSchedule::new(btreemap! {
+        0 => vec![RemoveNodeFrom(Prefix::short(1, 0))],
+        5 => vec![AddNode(Prefix::short(1, 0).substituted_in(Name(12345)))],
+    });
+
+    let result = run_differential::<Node, Node>(
+        [1, 2, 3, 4],
+        &sections,
+        &schedule,
+        &default_params(),
+        &node_params,
+    );
+
+    assert!(result.a_consistent && result.b_consistent);
+    assert_eq!(result.a_steps, result.b_steps);
+    assert!(result.first_divergence.is_none());
+}
+
+/// Behaves like `Node`, except that it doesn't send anything carrying votes if `MUTE`, and keeps
+/// its simulation from finishing before step `BUSY_UNTIL` by never looking idle until then.
+struct TestNode<const MUTE: bool, const BUSY_UNTIL: u64> {
+    node: Node,
+    step: u64,
+}
+
+impl<const MUTE: bool, const BUSY_UNTIL: u64> TestNode<MUTE, BUSY_UNTIL> {
+    fn filter(&self, messages: Vec<Message>) -> Vec<Message> {
+        messages
+            .into_iter()
+            .filter(|message| !MUTE || !message.content.carries_votes())
+            .collect()
+    }
+}
+
+impl<const MUTE: bool, const BUSY_UNTIL: u64> fmt::Display for TestNode<MUTE, BUSY_UNTIL> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+impl<const MUTE: bool, const BUSY_UNTIL: u64> NodeTrait for TestNode<MUTE, BUSY_UNTIL> {
+    fn new(
+        name: Name,
+        blocks: &Blocks,
+        current_blocks: CurrentBlocks,
+        params: NodeParams,
+        step: u64,
+    ) -> Self {
+        TestNode {
+            node: Node::new(name, blocks, current_blocks, params, step),
+            step,
+        }
+    }
+
+    fn handle_message(&mut self, message: Message, blocks: &Blocks, step: u64) -> Vec<Message> {
+        let messages = self.node.handle_message(message, blocks, step);
+        self.filter(messages)
+    }
+
+    fn update_state(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message> {
+        self.step = step;
+        let messages = self.node.update_state(blocks, step);
+        self.filter(messages)
+    }
+
+    fn broadcast_new_votes(&mut self, blocks: &mut Blocks, step: u64) -> Vec<Message> {
+        let messages = self.node.broadcast_new_votes(blocks, step);
+        self.filter(messages)
+    }
+
+    fn current_blocks(&self) -> &CurrentBlocks {
+        self.node.current_blocks()
+    }
+
+    fn name(&self) -> Name {
+        NodeTrait::name(&self.node)
+    }
+
+    fn is_disconnected_from(&self, name: &Name) -> bool {
+        self.node.is_disconnected_from(name)
+    }
+
+    fn should_shutdown(&self, blocks: &Blocks, step: u64) -> bool {
+        self.node.should_shutdown(blocks, step)
+    }
+
+    fn construct_bootstrap_msg(&self, joining_node: Name) -> Message {
+        self.node.construct_bootstrap_msg(joining_node)
+    }
+
+    fn learn_blocks(&mut self, new_blocks: &BTreeSet<BlockId>) {
+        self.node.learn_blocks(new_blocks)
+    }
+
+    fn backlog(&self) -> usize {
+        if self.step < BUSY_UNTIL { 1 } else { 0 }
+    }
+}
+
+// An implementation which never votes is caught diverging as soon as the other agrees a block.
+#[test]
+fn differing_blocks_diverge() {
+    init_logging();
+
+    let node_params = NodeParams::default();
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size + 1,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size,
+    };
+    let schedule = EventSchedule::new(btreemap! {
+        0 => vec![RemoveNodeFrom(Prefix::short(1, 0))],
+    });
+
+    let result = run_differential::<Node, TestNode<true, 0>>(
+        [1, 2, 3, 4],
+        &sections,
+        &schedule,
+        &default_params(),
+        &node_params,
+    );
+
+    let divergence = result.first_divergence.unwrap();
+    assert!(!divergence.a_finished && !divergence.b_finished);
+    assert!(divergence.step < 20);
+    assert_eq!(divergence.only_in_a.len(), 1);
+    assert!(divergence.only_in_b.is_empty());
+}
+
+// Runs agreeing the same blocks diverge when one of them finishes while the other carries on.
+#[test]
+fn finishing_early_diverges() {
+    init_logging();
+
+    let node_params = NodeParams::default();
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size,
+    };
+    let params = SimulationParams {
+        stable_steps: 50,
+        ..default_params()
+    };
+
+    let result = run_differential::<Node, TestNode<false, 100>>(
+        [1, 2, 3, 4],
+        &sections,
+        &EventSchedule::empty(),
+        &params,
+        &node_params,
+    );
+
+    assert!(result.a_consistent && result.b_consistent);
+    assert!(result.a_steps < result.b_steps);
+    let divergence = result.first_divergence.unwrap();
+    assert_eq!(divergence.step, result.a_steps);
+    assert!(divergence.a_finished && !divergence.b_finished);
+    assert!(divergence.only_in_a.is_empty() && divergence.only_in_b.is_empty());
+}
+
+// Handling messages on several threads must give exactly the same run as a single thread, whatever
+// order the nodes are processed in.
+#[test]
+fn parallel_message_handling_matches_sequential() {
+    init_logging();
+
+    let node_params = NodeParams::default();
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size,
+    };
+    let schedule = EventSchedule::new(btreemap! {
+        0 => vec![RemoveNodeFrom(Prefix::short(1, 0))],
+        5 => vec![AddNode(Prefix::short(1, 0).substituted_in(Name(12345)))],
+    });
+
+    let run = |num_threads, processing_order| {
+        reseed([5, 6, 7, 8]);
+        let params = SimulationParams {
+            processing_order,
+            ..default_params()
+        };
+        let mut simulation =
+            Simulation::new_from(sections.clone(), schedule.clone(), params, node_params.clone());
+        simulation.set_num_threads(num_threads).unwrap();
+        simulation.record_agreed_blocks();
+        assert!(simulation.run().is_ok());
+        simulation.agreed_history().unwrap().to_vec()
+    };
+
+    for &order in &[ProcessingOrder::ByName, ProcessingOrder::Random, ProcessingOrder::RoundRobin] {
+        assert_eq!(run(1, order), run(4, order));
+    }
+}
+
+// Rewinding to an earlier step and re-running must repeat the original steps exactly.
+#[test]
+fn rewind_replays_identically() {
+    init_logging();
+
+    reseed([9, 10, 11, 12]);
+    let node_params = NodeParams::default();
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size + 2,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size + 2,
+    };
+    let params = SimulationParams {
+        prob_churn: 0.2,
+        stable_steps: 100,
+        ..default_params()
+    };
+
+    let mut simulation =
+        Simulation::new_from(sections, EventSchedule::empty(), params, node_params);
+    simulation.record_checkpoints(10, 3);
+    simulation.record_agreed_blocks();
+    let original: Vec<_> = simulation.steps().take(50).map(|s| s.num_nodes).collect();
+    let original_history = simulation.agreed_history().unwrap().to_vec();
+
+    simulation.rewind(25).unwrap();
+    assert_eq!(simulation.agreed_history().unwrap(), &original_history[..25]);
+
+    let replayed: Vec<_> = simulation.steps().take(25).map(|s| s.num_nodes).collect();
+    assert_eq!(replayed, &original[25..]);
+    assert_eq!(simulation.agreed_history().unwrap(), &original_history[..]);
+
+    assert!(simulation.rewind(100).is_err());
+    // Only the checkpoints at steps 20, 30 and 40 are kept.
+    assert!(simulation.rewind(15).is_err());
+    simulation.rewind(20).unwrap();
+}
+
+// With ancestry validation, the votes nodes hold back are part of each checkpoint, so rewinding
+// replays them exactly.
+#[test]
+fn rewind_replays_held_back_votes() {
+    init_logging();
+
+    reseed([13, 14, 15, 16]);
+    let node_params = NodeParams {
+        validate_ancestry: true,
+        ..NodeParams::default()
+    };
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size + 2,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size + 2,
+    };
+    let params = SimulationParams {
+        prob_churn: 0.2,
+        stable_steps: 100,
+        ..default_params()
+    };
+
+    let mut simulation =
+        Simulation::new_from(sections, EventSchedule::empty(), params, node_params);
+    simulation.record_checkpoints(10, 3);
+    simulation.record_agreed_blocks();
+    let _ = simulation.steps().take(50).count();
+    let original_history = simulation.agreed_history().unwrap().to_vec();
+    let original_stats = simulation.node_stats();
+    assert!(original_stats.votes_unknown_ancestry > 0);
+
+    simulation.rewind(25).unwrap();
+    let _ = simulation.steps().take(25).count();
+    assert_eq!(simulation.agreed_history().unwrap(), &original_history[..]);
+    let stats = simulation.node_stats();
+    assert_eq!(stats.votes_unknown_ancestry, original_stats.votes_unknown_ancestry);
+    assert_eq!(stats.unknown_ancestry_wait_steps, original_stats.unknown_ancestry_wait_steps);
+}
+
+// The chain of each checkpoint kept is written out, as the network was at the start of the
+// checkpoint's step, and the chains of checkpoints dropped or rewound past are removed.
+#[test]
+fn checkpoint_chains_written() {
+    init_logging();
+
+    reseed([17, 18, 19, 20]);
+    let node_params = NodeParams::default();
+    let sections =
+        btreemap! {
+        Prefix::short(1, 0) => node_params.min_section_size + 2,
+        Prefix::short(1, 0b10000000) => node_params.min_section_size + 2,
+    };
+    let params = SimulationParams {
+        prob_churn: 0.2,
+        stable_steps: 100,
+        ..default_params()
+    };
+
+    let dir = env::temp_dir().join(format!("ewok-checkpoint-test-{}", process::id()));
+    let files = || {
+        let mut files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        files
+    };
+    let mut simulation =
+        Simulation::new_from(sections, EventSchedule::empty(), params, node_params);
+    simulation.record_checkpoints(10, 3);
+    simulation.write_checkpoint_chains(dir.clone());
+    let _ = simulation.steps().take(40).count();
+    let chain_at_40 = simulation.chain();
+    let _ = simulation.steps().take(10).count();
+
+    let kept = files();
+    let file = File::open(dir.join("checkpoint-step-40.chain")).unwrap();
+    let chain = Chain::read(&mut BufReader::new(file));
+    simulation.rewind(35).unwrap();
+    let kept_after_rewind = files();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert_eq!(
+        kept,
+        vec!["checkpoint-step-20.chain", "checkpoint-step-30.chain", "checkpoint-step-40.chain"]
+    );
+    assert_eq!(chain.unwrap(), chain_at_40);
+    assert_eq!(kept_after_rewind, vec!["checkpoint-step-20.chain", "checkpoint-step-30.chain"]);
+}
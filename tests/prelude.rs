@@ -0,0 +1,36 @@
+//! Embeds ewok using only the prelude, as downstream code would.
+
+extern crate ewok;
+
+use ewok::prelude::*;
+
+#[test]
+fn run_through_prelude() {
+    let params = SimulationParams {
+        grow_prob_join: 0.0,
+        grow_prob_drop: 0.0,
+        prob_churn: 0.0,
+        shrink_prob_join: 0.0,
+        shrink_prob_drop: 0.0,
+        prob_disconnect: 0.0,
+        starting_complete: 0,
+        grow_complete: 0,
+        stable_steps: 20,
+        genesis_nodes: GenesisNodes::Count(5),
+        ..SimulationParams::default()
+    };
+    // A node sometimes joins at step 0 regardless of the parameters, so fix the seed.
+    let mut simulation = Simulation::builder()
+        .seed([3, 2, 3, 4])
+        .params(params)
+        .node_params(NodeParams::default())
+        .schedule(EventSchedule::empty())
+        .build()
+        .unwrap();
+    let result = simulation.run();
+    let report = Report::new(&simulation, &result);
+
+    assert_eq!(report.failure, None);
+    let sections: Vec<(Prefix, usize)> = report.sections.into_iter().collect();
+    assert_eq!(sections, vec![(Prefix::empty(), 5)]);
+}
@@ -0,0 +1,39 @@
+//! Runs every scenario in `scenarios/` and compares the results with the golden reports.
+//!
+//! Set `EWOK_BLESS` to rewrite the golden reports after an intended change in behaviour.
+
+extern crate ewok;
+
+use ewok::logging::init_logging;
+use ewok::scenario::{check_scenario, scenario_files, GoldenCheck};
+use std::env;
+use std::path::Path;
+
+#[test]
+fn golden_reports() {
+    init_logging();
+
+    let bless = env::var("EWOK_BLESS").is_ok();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+    let mut failures = vec![];
+    for path in scenario_files(&dir).unwrap() {
+        match check_scenario(&path, bless).unwrap() {
+            GoldenCheck::Matched | GoldenCheck::Blessed => (),
+            GoldenCheck::Differs(differences) => {
+                failures.push(format!("{}:\n  {}", path.display(), differences.join("\n  ")));
+            }
+            GoldenCheck::AssertionsFailed(assertions) => {
+                let assertions: Vec<_> = assertions
+                    .iter()
+                    .map(|assertion| format!("assertion failed: {}", assertion))
+                    .collect();
+                failures.push(format!("{}:\n  {}", path.display(), assertions.join("\n  ")));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "reports differ from the golden ones (set EWOK_BLESS to update them):\n{}",
+        failures.join("\n")
+    );
+}